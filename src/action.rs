@@ -1,12 +1,25 @@
+use std::sync::Arc;
+use std::time::Duration;
+
 use twilight_http::{
+    error::ErrorType,
     request::{channel::reaction::RequestReactionType, AuditLogReason},
     Client,
 };
+use twilight_http_ratelimiting::request::{Method, Path};
 use twilight_mention::Mention;
 use twilight_model::{
-    channel::message::ReactionType,
+    channel::{
+        message::{AllowedMentions, Embed, ReactionType},
+        permission_overwrite::{
+            PermissionOverwrite as ChannelPermissionOverwrite,
+            PermissionOverwriteType as ChannelPermissionOverwriteType,
+        },
+    },
+    guild::Permissions,
+    http::permission_overwrite::{PermissionOverwrite, PermissionOverwriteType},
     id::{
-        marker::{ChannelMarker, GuildMarker, MessageMarker, UserMarker},
+        marker::{ChannelMarker, GuildMarker, MessageMarker, RoleMarker, UserMarker},
         Id,
     },
     util::Timestamp,
@@ -15,6 +28,378 @@ use twilight_util::builder::embed::{EmbedBuilder, EmbedFieldBuilder};
 
 use eyre::Result;
 
+use crate::case::{spawn_open_case, CaseDetails};
+use crate::config::{parse_webhook_url, LogConfig};
+use crate::executor::DelayedActions;
+
+/// The longest Discord lets a member be timed out for, in seconds.
+const MAX_TIMEOUT_SECONDS: i64 = 28 * 24 * 60 * 60;
+
+/// When a timeout of `duration` seconds starting at `now` ends, clamped to
+/// Discord's 28-day maximum.
+fn timeout_expiry(now: i64, duration: i64) -> Result<Timestamp> {
+    if duration > MAX_TIMEOUT_SECONDS {
+        tracing::warn!(
+            duration,
+            max = MAX_TIMEOUT_SECONDS,
+            "Timeout is longer than Discord allows; shortening it to 28 days"
+        );
+    }
+
+    Ok(Timestamp::from_secs(
+        now.saturating_add(duration.min(MAX_TIMEOUT_SECONDS)),
+    )?)
+}
+
+/// Sends `content` to `user_id` in a DM. Users who have DMs closed are
+/// skipped, since there's nothing we can do about it.
+async fn dm_user(http: &Client, user_id: Id<UserMarker>, content: &str) -> Result<()> {
+    let forbidden = |err: &twilight_http::Error| matches!(err.kind(), ErrorType::Response { status, .. } if status.get() == 403);
+
+    let result = match http.create_private_channel(user_id).await {
+        Ok(response) => {
+            let channel = response.model().await?;
+            http.create_message(channel.id)
+                .allowed_mentions(Some(&AllowedMentions::default()))
+                .content(content)?
+                .await
+        }
+        Err(err) => Err(err),
+    };
+
+    match result {
+        Ok(_) => Ok(()),
+        Err(err) if forbidden(&err) => {
+            tracing::debug!(%user_id, "User has DMs closed; not sending DM");
+            Ok(())
+        }
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// The form of `emoji` twilight takes in reaction requests.
+fn request_reaction_type(emoji: &ReactionType) -> RequestReactionType<'_> {
+    match emoji {
+        ReactionType::Custom { id, name, .. } => RequestReactionType::Custom {
+            id: *id,
+            name: name.as_deref(),
+        },
+        ReactionType::Unicode { name } => RequestReactionType::Unicode { name },
+    }
+}
+
+/// Reacts to a message with `emoji`. Messages that are already gone, such as
+/// ones another action deleted, are skipped.
+async fn react(
+    http: &Client,
+    channel_id: Id<ChannelMarker>,
+    message_id: Id<MessageMarker>,
+    emoji: &ReactionType,
+) -> Result<()> {
+    let result = http
+        .create_reaction(channel_id, message_id, &request_reaction_type(emoji))
+        .await;
+
+    match result {
+        Ok(_) => Ok(()),
+        Err(err) if matches!(err.kind(), ErrorType::Response { status, .. } if status.get() == 404) =>
+        {
+            tracing::debug!(%channel_id, %message_id, "Message is gone; not reacting");
+            Ok(())
+        }
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// A webhook URL. Its token is left out of `Debug` output, so it doesn't end
+/// up in logs.
+#[derive(Clone, PartialEq, Eq)]
+pub(crate) struct WebhookUrl(pub(crate) String);
+
+impl std::fmt::Debug for WebhookUrl {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match parse_webhook_url(&self.0) {
+            Some((id, _)) => write!(f, "WebhookUrl({})", id),
+            None => f.write_str("WebhookUrl(<invalid>)"),
+        }
+    }
+}
+
+/// Posts `content` through the webhook at `url`.
+async fn send_webhook(http: &Client, url: &WebhookUrl, content: &str) -> Result<()> {
+    let (id, token) =
+        parse_webhook_url(&url.0).ok_or_else(|| eyre::eyre!("not a Discord webhook URL"))?;
+    http.execute_webhook(id, &token)
+        .allowed_mentions(Some(&AllowedMentions::default()))
+        .content(content)?
+        .await?;
+    Ok(())
+}
+
+/// Discord's limits on embeds. Each part is limited in characters, but
+/// twilight counts the total in bytes.
+const EMBED_TITLE_LENGTH: usize = 256;
+const EMBED_DESCRIPTION_LENGTH: usize = 4096;
+const EMBED_FIELD_COUNT: usize = 25;
+const EMBED_FIELD_NAME_LENGTH: usize = 256;
+const EMBED_FIELD_VALUE_LENGTH: usize = 1024;
+const EMBED_FOOTER_LENGTH: usize = 2048;
+const EMBED_AUTHOR_LENGTH: usize = 256;
+const EMBED_TOTAL_LENGTH: usize = 6000;
+
+const ELLIPSIS: &str = "…";
+
+/// `text` cut to at most `max` characters, ending in an ellipsis if it had
+/// to be cut.
+fn truncate(text: &str, max: usize) -> String {
+    match text.char_indices().nth(max) {
+        Some(_) => {
+            let end = text
+                .char_indices()
+                .nth(max.saturating_sub(1))
+                .map_or(0, |(end, _)| end);
+            format!("{}{}", &text[..end], ELLIPSIS)
+        }
+        None => text.to_owned(),
+    }
+}
+
+/// `text` cut to at most `max` bytes, ending in an ellipsis if it had to be
+/// cut.
+fn truncate_bytes(text: &str, max: usize) -> String {
+    if text.len() <= max {
+        return text.to_owned();
+    }
+
+    let mut end = max.saturating_sub(ELLIPSIS.len());
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}{}", &text[..end], ELLIPSIS)
+}
+
+/// `content` in a code block at most `max` bytes long, and so at most `max`
+/// characters long too.
+fn code_block(content: &str, max: usize) -> String {
+    format!("```{}```", truncate_bytes(content, max - "``````".len()))
+}
+
+/// Trims every part of `embed` to fit Discord's limits, so sending it can't
+/// fail validation however long the content it quotes is. If the whole embed
+/// is still too long, the description is shortened, then the last fields are
+/// dropped.
+fn fit_embed(mut embed: Embed) -> Embed {
+    embed.title = embed.title.map(|t| truncate(&t, EMBED_TITLE_LENGTH));
+    embed.fields.truncate(EMBED_FIELD_COUNT);
+    for field in &mut embed.fields {
+        field.name = truncate(&field.name, EMBED_FIELD_NAME_LENGTH);
+        field.value = truncate(&field.value, EMBED_FIELD_VALUE_LENGTH);
+    }
+    if let Some(footer) = embed.footer.as_mut() {
+        footer.text = truncate(&footer.text, EMBED_FOOTER_LENGTH);
+    }
+    if let Some(author) = embed.author.as_mut() {
+        author.name = truncate(&author.name, EMBED_AUTHOR_LENGTH);
+    }
+
+    let length_without_description = |embed: &Embed| {
+        embed.title.as_ref().map_or(0, String::len)
+            + embed.footer.as_ref().map_or(0, |f| f.text.len())
+            + embed.author.as_ref().map_or(0, |a| a.name.len())
+            + embed
+                .fields
+                .iter()
+                .map(|f| f.name.len() + f.value.len())
+                .sum::<usize>()
+    };
+
+    if let Some(description) = embed.description.take() {
+        // Limiting bytes rather than characters keeps within both limits.
+        let budget = EMBED_TOTAL_LENGTH
+            .saturating_sub(length_without_description(&embed))
+            .min(EMBED_DESCRIPTION_LENGTH);
+        embed.description = if description.len() <= budget {
+            Some(description)
+        } else if budget <= "``````".len() + ELLIPSIS.len() {
+            None
+        } else if let Some(code) = description
+            .strip_prefix("```")
+            .and_then(|d| d.strip_suffix("```"))
+        {
+            Some(code_block(code, budget))
+        } else {
+            Some(truncate_bytes(&description, budget))
+        };
+    }
+
+    while length_without_description(&embed) + embed.description.as_ref().map_or(0, String::len)
+        > EMBED_TOTAL_LENGTH
+    {
+        if embed.fields.pop().is_none() {
+            break;
+        }
+    }
+
+    embed
+}
+
+/// Renders a log embed from a guild's log template, substituting each
+/// `(placeholder, value)` pair into the title and fields.
+fn render_log_embed(
+    log_config: &LogConfig,
+    default_title: &str,
+    placeholders: &[(&str, &str)],
+    content: Option<&str>,
+) -> Embed {
+    let render = |template: &str| {
+        placeholders
+            .iter()
+            .fold(template.to_owned(), |rendered, (placeholder, value)| {
+                rendered.replace(placeholder, value)
+            })
+    };
+
+    let title = log_config
+        .title
+        .as_deref()
+        .map(render)
+        .unwrap_or_else(|| default_title.to_owned());
+    let mut builder = EmbedBuilder::new().title(title);
+
+    if let Some(color) = log_config.color {
+        builder = builder.color(color);
+    }
+
+    for field in &log_config.fields {
+        let mut field_builder = EmbedFieldBuilder::new(render(&field.name), render(&field.value));
+        if field.inline {
+            field_builder = field_builder.inline();
+        }
+
+        builder = builder.field(field_builder.build());
+    }
+
+    if let Some(content) = content {
+        if log_config.include_content && !content.is_empty() {
+            builder = builder.description(code_block(content, EMBED_DESCRIPTION_LENGTH));
+        }
+    }
+
+    builder.build()
+}
+
+/// Adds what an edited message said before to its log embed, trimmed to fit in
+/// a field.
+fn with_previous_content(mut embed: Embed, previous_content: Option<&str>) -> Embed {
+    let previous_content = match previous_content {
+        Some(previous_content) if !previous_content.is_empty() => previous_content,
+        _ => return embed,
+    };
+
+    embed.fields.push(
+        EmbedFieldBuilder::new(
+            "Before edit",
+            code_block(previous_content, EMBED_FIELD_VALUE_LENGTH),
+        )
+        .build(),
+    );
+    embed
+}
+
+/// The `@everyone` overwrites to apply when locking a channel and to restore
+/// when the lock expires.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct ChannelLock {
+    pub(crate) locked: PermissionOverwrite,
+    /// The `@everyone` overwrite from before the lock. If there wasn't one, the
+    /// overwrite is deleted when the lock expires.
+    pub(crate) original: Option<PermissionOverwrite>,
+}
+
+/// Works out how to lock a channel given its current permission overwrites.
+/// Returns `None` if `@everyone` already can't send messages there, in which
+/// case the channel is already locked and we shouldn't touch it.
+pub(crate) fn plan_channel_lock(
+    guild_id: Id<GuildMarker>,
+    overwrites: &[ChannelPermissionOverwrite],
+) -> Option<ChannelLock> {
+    // The @everyone role shares its ID with the guild.
+    let everyone = guild_id.cast();
+    let original = overwrites
+        .iter()
+        .find(|o| o.id == everyone && o.kind == ChannelPermissionOverwriteType::Role);
+
+    let (allow, deny) = original
+        .map(|o| (o.allow, o.deny))
+        .unwrap_or((Permissions::empty(), Permissions::empty()));
+
+    if deny.contains(Permissions::SEND_MESSAGES) {
+        return None;
+    }
+
+    Some(ChannelLock {
+        locked: PermissionOverwrite {
+            allow: Some(allow - Permissions::SEND_MESSAGES),
+            deny: Some(deny | Permissions::SEND_MESSAGES),
+            id: everyone,
+            kind: PermissionOverwriteType::Role,
+        },
+        original: original.map(|o| PermissionOverwrite {
+            allow: Some(o.allow),
+            deny: Some(o.deny),
+            id: everyone,
+            kind: PermissionOverwriteType::Role,
+        }),
+    })
+}
+
+/// Stops `@everyone` sending messages in a channel, and schedules the original
+/// permissions to be restored after `duration_seconds`.
+async fn lock_channel(
+    http: &Arc<Client>,
+    delayed: &DelayedActions,
+    guild_id: Id<GuildMarker>,
+    channel_id: Id<ChannelMarker>,
+    duration_seconds: u64,
+) -> Result<()> {
+    let channel = http.channel(channel_id).await?.model().await?;
+    let lock = match plan_channel_lock(
+        guild_id,
+        channel.permission_overwrites.as_deref().unwrap_or(&[]),
+    ) {
+        Some(lock) => lock,
+        None => {
+            tracing::debug!(%channel_id, "Channel is already locked");
+            return Ok(());
+        }
+    };
+
+    http.update_channel_permission(channel_id, &lock.locked)
+        .reason("Channel locked by Chrysanthemum")?
+        .await?;
+
+    let http = http.clone();
+    delayed.schedule_uncapped(Duration::from_secs(duration_seconds), async move {
+        let result = match &lock.original {
+            Some(original) => http
+                .update_channel_permission(channel_id, original)
+                .await
+                .map(|_| ()),
+            None => http
+                .delete_channel_permission(channel_id)
+                .role(guild_id.cast())
+                .await
+                .map(|_| ()),
+        };
+
+        if let Err(err) = result {
+            tracing::warn!(?err, %channel_id, "Error restoring locked channel");
+        }
+    });
+
+    Ok(())
+}
+
 #[derive(Debug, PartialEq, Eq)]
 pub(crate) enum MessageAction {
     Delete {
@@ -25,6 +410,17 @@ pub(crate) enum MessageAction {
         to: Id<ChannelMarker>,
         content: String,
         requires_armed: bool,
+        /// How long to hold back repeats of this message for, if at all.
+        coalesce_seconds: Option<u64>,
+    },
+    DmUser {
+        user_id: Id<UserMarker>,
+        content: String,
+        requires_armed: bool,
+    },
+    SendWebhook {
+        url: WebhookUrl,
+        content: String,
     },
     Ban {
         user_id: Id<UserMarker>,
@@ -48,15 +444,97 @@ pub(crate) enum MessageAction {
         filter_name: String,
         message_channel: Id<ChannelMarker>,
         content: String,
+        /// What the message said before the edit being logged, if known.
+        previous_content: Option<String>,
         filter_reason: String,
         author: Id<UserMarker>,
         context: &'static str,
+        /// For a log combining several filters' logs, the other actions
+        /// taken on the message. Combined logs are sent once those actions
+        /// have run, and only list the ones that did.
+        actions_taken: Option<Vec<&'static str>>,
+        /// How long to hold back repeats of this log for, if at all.
+        coalesce_seconds: Option<u64>,
+    },
+    LockChannel {
+        guild_id: Id<GuildMarker>,
+        channel_id: Id<ChannelMarker>,
+        duration_seconds: u64,
+    },
+    CreateCase {
+        endpoint: String,
+        authorization: Option<String>,
+        log_channel: Option<Id<ChannelMarker>>,
+        details: CaseDetails,
+    },
+    /// Change the topic of the stage running in a channel.
+    SetStageTopic {
+        channel_id: Id<ChannelMarker>,
+        topic: String,
+    },
+    AddRole {
+        user_id: Id<UserMarker>,
+        guild_id: Id<GuildMarker>,
+        role_id: Id<RoleMarker>,
+        requires_armed: bool,
+    },
+    RemoveRole {
+        user_id: Id<UserMarker>,
+        guild_id: Id<GuildMarker>,
+        role_id: Id<RoleMarker>,
+    },
+    React {
+        message_id: Id<MessageMarker>,
+        channel_id: Id<ChannelMarker>,
+        emoji: ReactionType,
+    },
+    /// Run `action`, or `on_failure` if it fails.
+    WithFallback {
+        action: Box<MessageAction>,
+        on_failure: Box<MessageAction>,
     },
 }
 
 impl MessageAction {
-    #[tracing::instrument(skip(http))]
-    pub(crate) async fn execute(&self, http: &Client) -> Result<()> {
+    /// Runs the action, trying each of its fallbacks in turn until one
+    /// succeeds.
+    pub(crate) async fn execute(
+        &self,
+        http: &Arc<Client>,
+        log_config: Option<&LogConfig>,
+        delayed: &DelayedActions,
+    ) -> Result<()> {
+        let mut attempts = self.attempts().into_iter().peekable();
+        loop {
+            let attempt = attempts.next().expect("every action has an attempt");
+            match attempt.execute_single(http, log_config, delayed).await {
+                Err(err) if attempts.peek().is_some() => {
+                    tracing::warn!(?err, action = ?attempt, "Action failed; trying its fallback");
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// This action followed by its fallbacks, in the order to try them.
+    fn attempts(&self) -> Vec<&Self> {
+        match self {
+            Self::WithFallback { action, on_failure } => {
+                let mut attempts = action.attempts();
+                attempts.extend(on_failure.attempts());
+                attempts
+            }
+            action => vec![action],
+        }
+    }
+
+    #[tracing::instrument(skip(http, log_config, delayed))]
+    async fn execute_single(
+        &self,
+        http: &Arc<Client>,
+        log_config: Option<&LogConfig>,
+        delayed: &DelayedActions,
+    ) -> Result<()> {
         match self {
             Self::Delete {
                 message_id,
@@ -65,7 +543,18 @@ impl MessageAction {
                 http.delete_message(*channel_id, *message_id).await?;
             }
             Self::SendMessage { to, content, .. } => {
-                http.create_message(*to).content(content)?.await?;
+                http.create_message(*to)
+                    .allowed_mentions(Some(&AllowedMentions::default()))
+                    .content(content)?
+                    .await?;
+            }
+            Self::DmUser {
+                user_id, content, ..
+            } => {
+                dm_user(http, *user_id, content).await?;
+            }
+            Self::SendWebhook { url, content } => {
+                send_webhook(http, url, content).await?;
             }
             Self::Ban {
                 user_id,
@@ -93,8 +582,7 @@ impl MessageAction {
                 duration,
                 reason,
             } => {
-                let timeout_expires_at =
-                    Timestamp::from_secs(chrono::Utc::now().timestamp() + *duration)?;
+                let timeout_expires_at = timeout_expiry(chrono::Utc::now().timestamp(), *duration)?;
 
                 http.update_guild_member(*guild_id, *user_id)
                     .communication_disabled_until(Some(timeout_expires_at))?
@@ -106,45 +594,250 @@ impl MessageAction {
                 filter_name,
                 message_channel,
                 content,
+                previous_content,
                 filter_reason,
                 author,
                 context,
+                actions_taken,
+                ..
             } => {
-                let mut embed_builder = EmbedBuilder::new()
-                    .title("Message filtered")
-                    .field(EmbedFieldBuilder::new("Filter", filter_name))
-                    .field(EmbedFieldBuilder::new("Author", author.mention().to_string()).build())
-                    .field(
-                        EmbedFieldBuilder::new("Channel", message_channel.mention().to_string())
-                            .build(),
-                    )
-                    .field(EmbedFieldBuilder::new("Reason", filter_reason).build())
-                    .field(EmbedFieldBuilder::new("Context", *context).build());
-
-                if !content.is_empty() {
-                    embed_builder = embed_builder.description(format!("```{}```", content));
-                }
+                let actions_taken = actions_taken.as_deref().unwrap_or_default().join(", ");
+                let embed = match log_config {
+                    Some(log_config) => render_log_embed(
+                        log_config,
+                        "Message filtered",
+                        &[
+                            ("$FILTER_NAME", filter_name),
+                            ("$USER_ID", &author.to_string()),
+                            ("$CHANNEL_ID", &message_channel.to_string()),
+                            ("$FILTER_REASON", filter_reason),
+                            ("$CONTEXT", context),
+                            ("$ACTIONS", &actions_taken),
+                        ],
+                        Some(content),
+                    ),
+                    None => {
+                        let mut embed_builder = EmbedBuilder::new()
+                            .title("Message filtered")
+                            .field(EmbedFieldBuilder::new("Filter", filter_name))
+                            .field(
+                                EmbedFieldBuilder::new("Author", author.mention().to_string())
+                                    .build(),
+                            )
+                            .field(
+                                EmbedFieldBuilder::new(
+                                    "Channel",
+                                    message_channel.mention().to_string(),
+                                )
+                                .build(),
+                            )
+                            .field(EmbedFieldBuilder::new("Reason", filter_reason).build())
+                            .field(EmbedFieldBuilder::new("Context", *context).build());
+
+                        if !actions_taken.is_empty() {
+                            embed_builder = embed_builder
+                                .field(EmbedFieldBuilder::new("Actions", &actions_taken).build());
+                        }
+
+                        if !content.is_empty() {
+                            embed_builder = embed_builder
+                                .description(code_block(content, EMBED_DESCRIPTION_LENGTH));
+                        }
+
+                        embed_builder.build()
+                    }
+                };
+                let embed = with_previous_content(embed, previous_content.as_deref());
 
                 http.create_message(*to)
-                    .embeds(&[embed_builder.build()])
-                    .unwrap()
+                    .allowed_mentions(Some(&AllowedMentions::default()))
+                    .embeds(&[fit_embed(embed)])?
+                    .await?;
+            }
+            Self::LockChannel {
+                guild_id,
+                channel_id,
+                duration_seconds,
+            } => {
+                lock_channel(http, delayed, *guild_id, *channel_id, *duration_seconds).await?;
+            }
+            Self::CreateCase {
+                endpoint,
+                authorization,
+                log_channel,
+                details,
+            } => {
+                spawn_open_case(
+                    http.clone(),
+                    endpoint.clone(),
+                    authorization.clone(),
+                    *log_channel,
+                    details.clone(),
+                );
+            }
+            Self::SetStageTopic { channel_id, topic } => {
+                http.update_stage_instance(*channel_id)
+                    .topic(topic)?
+                    .await?;
+            }
+            Self::AddRole {
+                user_id,
+                guild_id,
+                role_id,
+                ..
+            } => {
+                http.add_guild_member_role(*guild_id, *user_id, *role_id)
+                    .reason("Filtered by Chrysanthemum")?
                     .await?;
             }
+            Self::RemoveRole {
+                user_id,
+                guild_id,
+                role_id,
+            } => {
+                http.remove_guild_member_role(*guild_id, *user_id, *role_id)
+                    .reason("Filtered by Chrysanthemum")?
+                    .await?;
+            }
+            Self::React {
+                message_id,
+                channel_id,
+                emoji,
+            } => {
+                react(http, *channel_id, *message_id, emoji).await?;
+            }
+            Self::WithFallback { .. } => unreachable!("execute unwraps fallbacks"),
         };
 
         Ok(())
     }
 
+    /// How severe this action is, for choosing between filters that matched
+    /// the same message. Higher is more severe.
+    pub(crate) fn severity(&self) -> u8 {
+        match self {
+            MessageAction::SendMessage { .. }
+            | MessageAction::DmUser { .. }
+            | MessageAction::SendWebhook { .. }
+            | MessageAction::SendLog { .. }
+            | MessageAction::CreateCase { .. }
+            | MessageAction::React { .. } => 0,
+            MessageAction::Delete { .. } | MessageAction::SetStageTopic { .. } => 1,
+            MessageAction::LockChannel { .. }
+            | MessageAction::AddRole { .. }
+            | MessageAction::RemoveRole { .. } => 2,
+            MessageAction::Timeout { .. } => 3,
+            MessageAction::Kick { .. } => 4,
+            MessageAction::Ban { .. } => 5,
+            MessageAction::WithFallback { action, .. } => action.severity(),
+        }
+    }
+
+    /// The name of this action, as written in the configuration.
+    pub(crate) fn kind(&self) -> &'static str {
+        match self {
+            MessageAction::Delete { .. } => "delete",
+            MessageAction::SendMessage { .. } => "send_message",
+            MessageAction::DmUser { .. } => "dm_user",
+            MessageAction::SendWebhook { .. } => "send_webhook",
+            MessageAction::SendLog { .. } => "send_log",
+            MessageAction::Ban { .. } => "ban",
+            MessageAction::Kick { .. } => "kick",
+            MessageAction::Timeout { .. } => "timeout",
+            MessageAction::LockChannel { .. } => "lock_channel",
+            MessageAction::CreateCase { .. } => "create_case",
+            MessageAction::SetStageTopic { .. } => "set_stage_topic",
+            MessageAction::AddRole { .. } => "add_role",
+            MessageAction::RemoveRole { .. } => "remove_role",
+            MessageAction::React { .. } => "react",
+            MessageAction::WithFallback { action, .. } => action.kind(),
+        }
+    }
+
+    /// Whether this is a log combining several filters' logs, which is sent
+    /// once the message's other actions have run.
+    pub(crate) fn is_combined_log(&self) -> bool {
+        matches!(
+            self,
+            MessageAction::SendLog {
+                actions_taken: Some(_),
+                ..
+            }
+        )
+    }
+
+    /// Lists `taken` as the other actions taken on the message, if this is a
+    /// combined log.
+    pub(crate) fn set_actions_taken(&mut self, taken: &[&'static str]) {
+        if let MessageAction::SendLog {
+            actions_taken: Some(actions_taken),
+            ..
+        } = self
+        {
+            *actions_taken = taken.to_vec();
+        }
+    }
+
     pub(crate) fn requires_armed(&self) -> bool {
         match self {
             MessageAction::Delete { .. } => true,
             MessageAction::Ban { .. } => true,
             MessageAction::Kick { .. } => true,
             MessageAction::Timeout { .. } => true,
+            MessageAction::LockChannel { .. } => true,
+            MessageAction::CreateCase { .. } => true,
+            MessageAction::SetStageTopic { .. } => true,
             MessageAction::SendMessage { requires_armed, .. } => *requires_armed,
+            MessageAction::DmUser { requires_armed, .. } => *requires_armed,
+            MessageAction::AddRole { requires_armed, .. } => *requires_armed,
+            MessageAction::RemoveRole { .. } => true,
+            MessageAction::React { .. } => true,
+            MessageAction::WithFallback { action, on_failure } => {
+                action.requires_armed() || on_failure.requires_armed()
+            }
             _ => false,
         }
     }
+
+    /// The rate limit bucket this action's request falls in, if it makes one
+    /// to Discord.
+    pub(crate) fn rate_limit_path(&self) -> Option<Path> {
+        match self {
+            MessageAction::Delete { channel_id, .. } => {
+                Some(Path::ChannelsIdMessagesId(Method::Delete, channel_id.get()))
+            }
+            MessageAction::SendMessage { to, .. } | MessageAction::SendLog { to, .. } => {
+                Some(Path::ChannelsIdMessages(to.get()))
+            }
+            MessageAction::Ban { guild_id, .. } => Some(Path::GuildsIdBansUserId(guild_id.get())),
+            MessageAction::Kick { guild_id, .. } | MessageAction::Timeout { guild_id, .. } => {
+                Some(Path::GuildsIdMembersId(guild_id.get()))
+            }
+            MessageAction::LockChannel { channel_id, .. } => {
+                Some(Path::ChannelsIdPermissionsOverwriteId(channel_id.get()))
+            }
+            MessageAction::CreateCase { .. } => None,
+            MessageAction::DmUser { .. } => Some(Path::UsersIdChannels),
+            MessageAction::SendWebhook { url, .. } => {
+                parse_webhook_url(&url.0).map(|(id, token)| Path::WebhooksIdToken(id.get(), token))
+            }
+            MessageAction::AddRole { guild_id, .. }
+            | MessageAction::RemoveRole { guild_id, .. } => {
+                Some(Path::GuildsIdMembersIdRolesId(guild_id.get()))
+            }
+            MessageAction::SetStageTopic { .. } => Some(Path::StageInstances),
+            MessageAction::React { channel_id, .. } => Some(
+                Path::ChannelsIdMessagesIdReactionsUserIdType(channel_id.get()),
+            ),
+            MessageAction::WithFallback { action, .. } => action.rate_limit_path(),
+        }
+    }
+}
+
+/// Moves reactions ahead of the other actions, keeping each group's order,
+/// since a message another action deletes can't be reacted to.
+pub(crate) fn react_first(actions: &mut [MessageAction]) {
+    actions.sort_by_key(|action| !matches!(action, MessageAction::React { .. }));
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -158,6 +851,17 @@ pub(crate) enum ReactionAction {
         to: Id<ChannelMarker>,
         content: String,
         requires_armed: bool,
+        /// How long to hold back repeats of this message for, if at all.
+        coalesce_seconds: Option<u64>,
+    },
+    DmUser {
+        user_id: Id<UserMarker>,
+        content: String,
+        requires_armed: bool,
+    },
+    SendWebhook {
+        url: WebhookUrl,
+        content: String,
     },
     Ban {
         user_id: Id<UserMarker>,
@@ -184,31 +888,130 @@ pub(crate) enum ReactionAction {
         filter_reason: String,
         author: Id<UserMarker>,
         reaction: ReactionType,
+        /// How long to hold back repeats of this log for, if at all.
+        coalesce_seconds: Option<u64>,
+    },
+    LockChannel {
+        guild_id: Id<GuildMarker>,
+        channel_id: Id<ChannelMarker>,
+        duration_seconds: u64,
+    },
+    CreateCase {
+        endpoint: String,
+        authorization: Option<String>,
+        log_channel: Option<Id<ChannelMarker>>,
+        details: CaseDetails,
+    },
+    AddRole {
+        user_id: Id<UserMarker>,
+        guild_id: Id<GuildMarker>,
+        role_id: Id<RoleMarker>,
+        requires_armed: bool,
+    },
+    RemoveRole {
+        user_id: Id<UserMarker>,
+        guild_id: Id<GuildMarker>,
+        role_id: Id<RoleMarker>,
+    },
+    /// React to the message that was reacted to.
+    React {
+        message_id: Id<MessageMarker>,
+        channel_id: Id<ChannelMarker>,
+        emoji: ReactionType,
+    },
+    /// Run `action`, or `on_failure` if it fails.
+    WithFallback {
+        action: Box<ReactionAction>,
+        on_failure: Box<ReactionAction>,
     },
 }
 
 impl ReactionAction {
-    #[tracing::instrument(skip(http))]
-    pub(crate) async fn execute(&self, http: &Client) -> Result<()> {
+    /// The name of this action, as written in the configuration.
+    pub(crate) fn kind(&self) -> &'static str {
+        match self {
+            ReactionAction::Delete { .. } => "delete",
+            ReactionAction::SendMessage { .. } => "send_message",
+            ReactionAction::DmUser { .. } => "dm_user",
+            ReactionAction::SendWebhook { .. } => "send_webhook",
+            ReactionAction::Ban { .. } => "ban",
+            ReactionAction::Kick { .. } => "kick",
+            ReactionAction::Timeout { .. } => "timeout",
+            ReactionAction::SendLog { .. } => "send_log",
+            ReactionAction::LockChannel { .. } => "lock_channel",
+            ReactionAction::CreateCase { .. } => "create_case",
+            ReactionAction::AddRole { .. } => "add_role",
+            ReactionAction::RemoveRole { .. } => "remove_role",
+            ReactionAction::React { .. } => "react",
+            ReactionAction::WithFallback { action, .. } => action.kind(),
+        }
+    }
+
+    /// Runs the action, trying each of its fallbacks in turn until one
+    /// succeeds.
+    pub(crate) async fn execute(
+        &self,
+        http: &Arc<Client>,
+        log_config: Option<&LogConfig>,
+        delayed: &DelayedActions,
+    ) -> Result<()> {
+        let mut attempts = self.attempts().into_iter().peekable();
+        loop {
+            let attempt = attempts.next().expect("every action has an attempt");
+            match attempt.execute_single(http, log_config, delayed).await {
+                Err(err) if attempts.peek().is_some() => {
+                    tracing::warn!(?err, action = ?attempt, "Action failed; trying its fallback");
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// This action followed by its fallbacks, in the order to try them.
+    fn attempts(&self) -> Vec<&Self> {
+        match self {
+            Self::WithFallback { action, on_failure } => {
+                let mut attempts = action.attempts();
+                attempts.extend(on_failure.attempts());
+                attempts
+            }
+            action => vec![action],
+        }
+    }
+
+    #[tracing::instrument(skip(http, log_config, delayed))]
+    async fn execute_single(
+        &self,
+        http: &Arc<Client>,
+        log_config: Option<&LogConfig>,
+        delayed: &DelayedActions,
+    ) -> Result<()> {
         match self {
             Self::Delete {
                 message_id,
                 channel_id,
                 reaction,
             } => {
-                let request_emoji = match reaction {
-                    ReactionType::Custom { id, name, .. } => RequestReactionType::Custom {
-                        id: *id,
-                        name: name.as_deref(),
-                    },
-                    ReactionType::Unicode { name } => RequestReactionType::Unicode { name },
-                };
-
-                http.delete_all_reaction(*channel_id, *message_id, &request_emoji)
-                    .await?;
+                http.delete_all_reaction(
+                    *channel_id,
+                    *message_id,
+                    &request_reaction_type(reaction),
+                )
+                .await?;
             }
             Self::SendMessage { to, content, .. } => {
-                http.create_message(*to).content(content)?.await?;
+                http.create_message(*to)
+                    .allowed_mentions(Some(&AllowedMentions::default()))
+                    .content(content)?
+                    .await?;
+            }
+            Self::DmUser {
+                user_id, content, ..
+            } => {
+                dm_user(http, *user_id, content).await?;
+            }
+            Self::SendWebhook { url, content } => {
+                send_webhook(http, url, content).await?;
             }
             Self::Ban {
                 user_id,
@@ -236,8 +1039,7 @@ impl ReactionAction {
                 duration,
                 reason,
             } => {
-                let timeout_expires_at =
-                    Timestamp::from_secs(chrono::Utc::now().timestamp() + *duration)?;
+                let timeout_expires_at = timeout_expiry(chrono::Utc::now().timestamp(), *duration)?;
 
                 http.update_guild_member(*guild_id, *user_id)
                     .communication_disabled_until(Some(timeout_expires_at))?
@@ -252,14 +1054,31 @@ impl ReactionAction {
                 filter_reason,
                 author,
                 reaction,
+                ..
             } => {
                 let rxn_string = match reaction {
                     ReactionType::Custom { id, .. } => id.mention().to_string(),
                     ReactionType::Unicode { name } => name.clone(),
                 };
 
-                http.create_message(*to)
-                    .embeds(&[EmbedBuilder::new()
+                let message_link = format!("https://discordapp.com/{}/{}", channel, message);
+
+                let embed = match log_config {
+                    Some(log_config) => render_log_embed(
+                        log_config,
+                        "Reaction filtered",
+                        &[
+                            ("$FILTER_NAME", filter_name),
+                            ("$USER_ID", &author.to_string()),
+                            ("$CHANNEL_ID", &channel.to_string()),
+                            ("$FILTER_REASON", filter_reason),
+                            ("$CONTEXT", "reaction"),
+                            ("$REACTION", &rxn_string),
+                            ("$MESSAGE_LINK", &message_link),
+                        ],
+                        None,
+                    ),
+                    None => EmbedBuilder::new()
                         .title("Reaction filtered")
                         .field(EmbedFieldBuilder::new("Filter", filter_name))
                         .field(
@@ -269,19 +1088,65 @@ impl ReactionAction {
                             EmbedFieldBuilder::new("Channel", channel.mention().to_string())
                                 .build(),
                         )
-                        .field(
-                            EmbedFieldBuilder::new(
-                                "Message",
-                                format!("https://discordapp.com/{}/{}", channel, message),
-                            )
-                            .build(),
-                        )
+                        .field(EmbedFieldBuilder::new("Message", message_link).build())
                         .field(EmbedFieldBuilder::new("Reason", filter_reason).build())
                         .field(EmbedFieldBuilder::new("Reaction", rxn_string).build())
-                        .build()])
-                    .unwrap()
+                        .build(),
+                };
+
+                http.create_message(*to)
+                    .allowed_mentions(Some(&AllowedMentions::default()))
+                    .embeds(&[fit_embed(embed)])?
+                    .await?;
+            }
+            Self::LockChannel {
+                guild_id,
+                channel_id,
+                duration_seconds,
+            } => {
+                lock_channel(http, delayed, *guild_id, *channel_id, *duration_seconds).await?;
+            }
+            Self::CreateCase {
+                endpoint,
+                authorization,
+                log_channel,
+                details,
+            } => {
+                spawn_open_case(
+                    http.clone(),
+                    endpoint.clone(),
+                    authorization.clone(),
+                    *log_channel,
+                    details.clone(),
+                );
+            }
+            Self::AddRole {
+                user_id,
+                guild_id,
+                role_id,
+                ..
+            } => {
+                http.add_guild_member_role(*guild_id, *user_id, *role_id)
+                    .reason("Filtered by Chrysanthemum")?
+                    .await?;
+            }
+            Self::RemoveRole {
+                user_id,
+                guild_id,
+                role_id,
+            } => {
+                http.remove_guild_member_role(*guild_id, *user_id, *role_id)
+                    .reason("Filtered by Chrysanthemum")?
                     .await?;
             }
+            Self::React {
+                message_id,
+                channel_id,
+                emoji,
+            } => {
+                react(http, *channel_id, *message_id, emoji).await?;
+            }
+            Self::WithFallback { .. } => unreachable!("execute unwraps fallbacks"),
         };
 
         Ok(())
@@ -293,8 +1158,643 @@ impl ReactionAction {
             ReactionAction::Ban { .. } => true,
             ReactionAction::Kick { .. } => true,
             ReactionAction::Timeout { .. } => true,
+            ReactionAction::LockChannel { .. } => true,
+            ReactionAction::CreateCase { .. } => true,
             ReactionAction::SendMessage { requires_armed, .. } => *requires_armed,
+            ReactionAction::DmUser { requires_armed, .. } => *requires_armed,
+            ReactionAction::AddRole { requires_armed, .. } => *requires_armed,
+            ReactionAction::RemoveRole { .. } => true,
+            ReactionAction::React { .. } => true,
+            ReactionAction::WithFallback { action, on_failure } => {
+                action.requires_armed() || on_failure.requires_armed()
+            }
             _ => false,
         }
     }
+
+    /// The rate limit bucket this action's request falls in, if it makes one
+    /// to Discord.
+    pub(crate) fn rate_limit_path(&self) -> Option<Path> {
+        match self {
+            ReactionAction::Delete { channel_id, .. } => {
+                Some(Path::ChannelsIdMessagesIdReactions(channel_id.get()))
+            }
+            ReactionAction::SendMessage { to, .. } | ReactionAction::SendLog { to, .. } => {
+                Some(Path::ChannelsIdMessages(to.get()))
+            }
+            ReactionAction::Ban { guild_id, .. } => Some(Path::GuildsIdBansUserId(guild_id.get())),
+            ReactionAction::Kick { guild_id, .. } | ReactionAction::Timeout { guild_id, .. } => {
+                Some(Path::GuildsIdMembersId(guild_id.get()))
+            }
+            ReactionAction::LockChannel { channel_id, .. } => {
+                Some(Path::ChannelsIdPermissionsOverwriteId(channel_id.get()))
+            }
+            ReactionAction::CreateCase { .. } => None,
+            ReactionAction::DmUser { .. } => Some(Path::UsersIdChannels),
+            ReactionAction::SendWebhook { url, .. } => {
+                parse_webhook_url(&url.0).map(|(id, token)| Path::WebhooksIdToken(id.get(), token))
+            }
+            ReactionAction::AddRole { guild_id, .. }
+            | ReactionAction::RemoveRole { guild_id, .. } => {
+                Some(Path::GuildsIdMembersIdRolesId(guild_id.get()))
+            }
+            ReactionAction::React { channel_id, .. } => Some(
+                Path::ChannelsIdMessagesIdReactionsUserIdType(channel_id.get()),
+            ),
+            ReactionAction::WithFallback { action, .. } => action.rate_limit_path(),
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use std::sync::{Arc, Mutex};
+
+    use pretty_assertions::assert_eq;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+    use twilight_http::Client;
+    use twilight_model::{
+        channel::{
+            message::ReactionType,
+            permission_overwrite::{
+                PermissionOverwrite as ChannelPermissionOverwrite,
+                PermissionOverwriteType as ChannelPermissionOverwriteType,
+            },
+        },
+        guild::Permissions,
+        http::permission_overwrite::{PermissionOverwrite, PermissionOverwriteType},
+        id::Id,
+    };
+    use twilight_util::builder::embed::{EmbedBuilder, EmbedFieldBuilder};
+
+    use crate::config::{LogConfig, LogField};
+    use crate::executor::DelayedActions;
+
+    use super::{ChannelLock, MessageAction};
+
+    #[test]
+    fn timeouts_are_clamped_to_28_days() {
+        let day = 24 * 60 * 60;
+
+        assert_eq!(super::timeout_expiry(1_000, 60).unwrap().as_secs(), 1_060);
+        assert_eq!(
+            super::timeout_expiry(1_000, 90 * day).unwrap().as_secs(),
+            1_000 + 28 * day
+        );
+    }
+
+    #[test]
+    fn lock_channel_without_everyone_overwrite() {
+        let lock = super::plan_channel_lock(crate::model::test::GUILD_ID, &[]);
+
+        assert_eq!(
+            lock,
+            Some(ChannelLock {
+                locked: PermissionOverwrite {
+                    allow: Some(Permissions::empty()),
+                    deny: Some(Permissions::SEND_MESSAGES),
+                    id: crate::model::test::GUILD_ID.cast(),
+                    kind: PermissionOverwriteType::Role,
+                },
+                original: None,
+            })
+        );
+    }
+
+    #[test]
+    fn lock_channel_keeps_original_overwrite() {
+        let overwrites = [
+            ChannelPermissionOverwrite {
+                allow: Permissions::SEND_MESSAGES,
+                deny: Permissions::empty(),
+                id: Id::new(99),
+                kind: ChannelPermissionOverwriteType::Role,
+            },
+            ChannelPermissionOverwrite {
+                allow: Permissions::SEND_MESSAGES | Permissions::ADD_REACTIONS,
+                deny: Permissions::ATTACH_FILES,
+                id: crate::model::test::GUILD_ID.cast(),
+                kind: ChannelPermissionOverwriteType::Role,
+            },
+        ];
+
+        let lock = super::plan_channel_lock(crate::model::test::GUILD_ID, &overwrites).unwrap();
+
+        assert_eq!(
+            lock.locked,
+            PermissionOverwrite {
+                allow: Some(Permissions::ADD_REACTIONS),
+                deny: Some(Permissions::ATTACH_FILES | Permissions::SEND_MESSAGES),
+                id: crate::model::test::GUILD_ID.cast(),
+                kind: PermissionOverwriteType::Role,
+            }
+        );
+        assert_eq!(
+            lock.original,
+            Some(PermissionOverwrite {
+                allow: Some(Permissions::SEND_MESSAGES | Permissions::ADD_REACTIONS),
+                deny: Some(Permissions::ATTACH_FILES),
+                id: crate::model::test::GUILD_ID.cast(),
+                kind: PermissionOverwriteType::Role,
+            })
+        );
+    }
+
+    #[test]
+    fn lock_channel_skips_locked_channels() {
+        let overwrites = [ChannelPermissionOverwrite {
+            allow: Permissions::empty(),
+            deny: Permissions::SEND_MESSAGES,
+            id: crate::model::test::GUILD_ID.cast(),
+            kind: ChannelPermissionOverwriteType::Role,
+        }];
+
+        assert_eq!(
+            super::plan_channel_lock(crate::model::test::GUILD_ID, &overwrites),
+            None
+        );
+    }
+
+    #[test]
+    fn edit_log_shows_previous_content() {
+        let embed = super::with_previous_content(
+            EmbedBuilder::new().title("Message filtered").build(),
+            Some("an innocent message"),
+        );
+        assert_eq!(
+            embed.fields,
+            vec![EmbedFieldBuilder::new("Before edit", "```an innocent message```").build()]
+        );
+
+        let long = "a".repeat(2_000);
+        let embed = super::with_previous_content(EmbedBuilder::new().build(), Some(&long));
+        assert!(embed.fields[0].value.len() <= 1024);
+        assert!(embed.fields[0].value.ends_with("…```"));
+
+        let embed = super::with_previous_content(EmbedBuilder::new().build(), None);
+        assert!(embed.fields.is_empty());
+    }
+
+    #[test]
+    fn render_custom_log_template() {
+        let log_config = LogConfig {
+            title: Some("Case: $FILTER_NAME".to_owned()),
+            color: Some(0xff_00_00),
+            fields: vec![
+                LogField {
+                    name: "Offender".to_owned(),
+                    value: "<@$USER_ID>".to_owned(),
+                    inline: true,
+                },
+                LogField {
+                    name: "Why".to_owned(),
+                    value: "$FILTER_REASON ($CONTEXT)".to_owned(),
+                    inline: false,
+                },
+            ],
+            include_content: false,
+        };
+
+        let embed = super::render_log_embed(
+            &log_config,
+            "Message filtered",
+            &[
+                ("$FILTER_NAME", "links"),
+                ("$USER_ID", "3"),
+                ("$FILTER_REASON", "contains denied domain `example.com`"),
+                ("$CONTEXT", "message create"),
+            ],
+            Some("https://example.com/"),
+        );
+
+        assert_eq!(embed.title.as_deref(), Some("Case: links"));
+        assert_eq!(embed.color, Some(0xff_00_00));
+        assert_eq!(embed.description, None);
+
+        let fields: Vec<_> = embed
+            .fields
+            .iter()
+            .map(|f| (f.name.as_str(), f.value.as_str(), f.inline))
+            .collect();
+        assert_eq!(
+            fields,
+            vec![
+                ("Offender", "<@3>", true),
+                (
+                    "Why",
+                    "contains denied domain `example.com` (message create)",
+                    false
+                ),
+            ]
+        );
+    }
+
+    /// Answers one request per connection, with the status and body for the
+    /// first request line containing `route`, recording each request line
+    /// and body, separated by a newline.
+    pub(crate) async fn serve_discord(
+        routes: &'static [(&'static str, &'static str, &'static str)],
+    ) -> (String, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+
+        let recorded = requests.clone();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let request = read_request(&mut socket).await;
+                let request_line = request.lines().next().unwrap_or_default().to_owned();
+                let request_body = request.split_once("\r\n\r\n").map_or("", |(_, body)| body);
+
+                let (status, body) = routes
+                    .iter()
+                    .find(|(route, _, _)| request_line.contains(route))
+                    .map_or(("404 Not Found", "{}"), |(_, status, body)| {
+                        (*status, *body)
+                    });
+                recorded
+                    .lock()
+                    .unwrap()
+                    .push(format!("{}\n{}", request_line, request_body));
+
+                let response = format!(
+                    "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                );
+                let _ = socket.write_all(response.as_bytes()).await;
+            }
+        });
+
+        (addr.to_string(), requests)
+    }
+
+    /// Reads a request's headers and as much body as its `Content-Length`
+    /// says there is.
+    async fn read_request(socket: &mut TcpStream) -> String {
+        let mut request = Vec::new();
+        let mut buffer = [0; 4096];
+        loop {
+            let read = socket.read(&mut buffer).await.unwrap_or(0);
+            if read == 0 {
+                break;
+            }
+            request.extend_from_slice(&buffer[..read]);
+
+            let text = String::from_utf8_lossy(&request);
+            if let Some((headers, body)) = text.split_once("\r\n\r\n") {
+                let length = headers
+                    .lines()
+                    .find_map(|line| {
+                        let (name, value) = line.split_once(':')?;
+                        name.eq_ignore_ascii_case("content-length")
+                            .then(|| value.trim().parse::<usize>().ok())?
+                    })
+                    .unwrap_or(0);
+                if body.len() >= length {
+                    break;
+                }
+            }
+        }
+
+        String::from_utf8_lossy(&request).into_owned()
+    }
+
+    #[tokio::test]
+    async fn failed_ban_falls_back_to_timeout() {
+        let (addr, requests) = serve_discord(&[
+            ("PUT /api/v10/guilds/4/bans/3", "403 Forbidden", "{}"),
+            ("PATCH /api/v10/guilds/4/members/3", "200 OK", "{}"),
+        ])
+        .await;
+        let http = Arc::new(
+            Client::builder()
+                .proxy(addr, true)
+                .ratelimiter(None)
+                .build(),
+        );
+
+        let action = MessageAction::WithFallback {
+            action: Box::new(MessageAction::Ban {
+                user_id: crate::model::test::USER_ID,
+                guild_id: crate::model::test::GUILD_ID,
+                delete_message_seconds: 0,
+                reason: "spam".to_owned(),
+            }),
+            on_failure: Box::new(MessageAction::Timeout {
+                user_id: crate::model::test::USER_ID,
+                guild_id: crate::model::test::GUILD_ID,
+                reason: "spam".to_owned(),
+                duration: 60,
+            }),
+        };
+
+        action
+            .execute(&http, None, &DelayedActions::default())
+            .await
+            .expect("fallback didn't succeed");
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert!(requests[0].starts_with("PUT /api/v10/guilds/4/bans/3"));
+        assert!(requests[1].starts_with("PATCH /api/v10/guilds/4/members/3"));
+    }
+
+    fn lock_channel_action() -> MessageAction {
+        MessageAction::LockChannel {
+            guild_id: crate::model::test::GUILD_ID,
+            channel_id: Id::new(2),
+            duration_seconds: 600,
+        }
+    }
+
+    #[tokio::test]
+    async fn unlocking_restores_original_overwrite() {
+        let (addr, requests) = serve_discord(&[
+            (
+                "GET /api/v10/channels/2 ",
+                "200 OK",
+                r#"{"id":"2","type":0,"guild_id":"4","permission_overwrites":[{"id":"4","type":0,"allow":"1024","deny":"0"}]}"#,
+            ),
+            ("PUT /api/v10/channels/2/permissions/4", "204 No Content", ""),
+        ])
+        .await;
+        let http = Arc::new(
+            Client::builder()
+                .proxy(addr, true)
+                .ratelimiter(None)
+                .build(),
+        );
+        let delayed = DelayedActions::default();
+
+        lock_channel_action()
+            .execute(&http, None, &delayed)
+            .await
+            .expect("locking the channel failed");
+        assert_eq!(requests.lock().unwrap().len(), 2);
+        assert_eq!(delayed.pending(), 1);
+
+        delayed.flush().await;
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 3);
+        assert!(requests[1].starts_with("PUT /api/v10/channels/2/permissions/4"));
+        assert!(requests[2].starts_with("PUT /api/v10/channels/2/permissions/4"));
+    }
+
+    #[tokio::test]
+    async fn unlocking_deletes_added_overwrite() {
+        let (addr, requests) = serve_discord(&[
+            (
+                "GET /api/v10/channels/2 ",
+                "200 OK",
+                r#"{"id":"2","type":0,"guild_id":"4","permission_overwrites":[]}"#,
+            ),
+            (
+                "PUT /api/v10/channels/2/permissions/4",
+                "204 No Content",
+                "",
+            ),
+            (
+                "DELETE /api/v10/channels/2/permissions/4",
+                "204 No Content",
+                "",
+            ),
+        ])
+        .await;
+        let http = Arc::new(
+            Client::builder()
+                .proxy(addr, true)
+                .ratelimiter(None)
+                .build(),
+        );
+        let delayed = DelayedActions::default();
+
+        lock_channel_action()
+            .execute(&http, None, &delayed)
+            .await
+            .expect("locking the channel failed");
+        delayed.flush().await;
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 3);
+        assert!(requests[1].starts_with("PUT /api/v10/channels/2/permissions/4"));
+        assert!(requests[2].starts_with("DELETE /api/v10/channels/2/permissions/4"));
+    }
+
+    #[tokio::test]
+    async fn locked_channels_are_left_alone() {
+        let (addr, requests) = serve_discord(&[(
+            "GET /api/v10/channels/2 ",
+            "200 OK",
+            r#"{"id":"2","type":0,"guild_id":"4","permission_overwrites":[{"id":"4","type":0,"allow":"0","deny":"2048"}]}"#,
+        )])
+        .await;
+        let http = Arc::new(
+            Client::builder()
+                .proxy(addr, true)
+                .ratelimiter(None)
+                .build(),
+        );
+        let delayed = DelayedActions::default();
+
+        lock_channel_action()
+            .execute(&http, None, &delayed)
+            .await
+            .expect("locking an already locked channel failed");
+
+        assert_eq!(delayed.pending(), 0);
+        assert_eq!(requests.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn reactions_run_before_deletion() {
+        let delete = || MessageAction::Delete {
+            message_id: crate::model::test::MESSAGE_ID,
+            channel_id: Id::new(2),
+        };
+        let react = || MessageAction::React {
+            message_id: crate::model::test::MESSAGE_ID,
+            channel_id: Id::new(2),
+            emoji: ReactionType::Unicode {
+                name: "🤡".to_owned(),
+            },
+        };
+
+        let mut actions = vec![delete(), react()];
+        super::react_first(&mut actions);
+        assert_eq!(actions, vec![react(), delete()]);
+    }
+
+    #[tokio::test]
+    async fn reacting_to_deleted_message_is_skipped() {
+        let (addr, requests) = serve_discord(&[(
+            "PUT /api/v10/channels/2/messages/1/reactions/clown:7/@me",
+            "404 Not Found",
+            r#"{"code":10008,"message":"Unknown Message"}"#,
+        )])
+        .await;
+        let http = Arc::new(
+            Client::builder()
+                .proxy(addr, true)
+                .ratelimiter(None)
+                .build(),
+        );
+
+        let action = MessageAction::React {
+            message_id: Id::new(1),
+            channel_id: Id::new(2),
+            emoji: ReactionType::Custom {
+                animated: false,
+                id: Id::new(7),
+                name: Some("clown".to_owned()),
+            },
+        };
+
+        action
+            .execute(&http, None, &DelayedActions::default())
+            .await
+            .expect("reacting to a deleted message failed the action");
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 1);
+        assert!(requests[0].starts_with("PUT /api/v10/channels/2/messages/1/reactions/clown:7/@me"));
+    }
+
+    #[test]
+    fn embeds_are_fitted_to_discord_limits() {
+        let embed = super::fit_embed(
+            EmbedBuilder::new()
+                .title("t".repeat(300))
+                .description(format!("```{}```", "é".repeat(5_000)))
+                .field(EmbedFieldBuilder::new("n".repeat(300), "v".repeat(2_000)))
+                .build(),
+        );
+
+        assert_eq!(embed.title.as_ref().unwrap().chars().count(), 256);
+        let field = &embed.fields[0];
+        assert_eq!(field.name.chars().count(), 256);
+        assert_eq!(field.value.chars().count(), 1024);
+        assert!(field.value.ends_with('…'));
+
+        let description = embed.description.as_ref().unwrap();
+        assert!(description.starts_with("```é") && description.ends_with("…```"));
+        let total = embed.title.as_ref().unwrap().len()
+            + description.len()
+            + field.name.len()
+            + field.value.len();
+        assert!(total <= 6_000, "{}", total);
+    }
+
+    #[tokio::test]
+    async fn long_messages_are_logged() {
+        let (addr, requests) =
+            serve_discord(&[("POST /api/v10/channels/1/messages", "200 OK", "{}")]).await;
+        let http = Arc::new(
+            Client::builder()
+                .proxy(addr, true)
+                .ratelimiter(None)
+                .build(),
+        );
+
+        let content = "a".repeat(5_000);
+        let action = MessageAction::SendLog {
+            to: Id::new(1),
+            filter_name: "Walls of text".to_owned(),
+            message_channel: Id::new(2),
+            content: content.clone(),
+            previous_content: Some(content.clone()),
+            filter_reason: format!("contains word `{}`", content),
+            author: crate::model::test::USER_ID,
+            context: "message create",
+            actions_taken: Some(vec!["delete"]),
+            coalesce_seconds: None,
+        };
+
+        action
+            .execute(&http, None, &DelayedActions::default())
+            .await
+            .expect("long message wasn't logged");
+        assert_eq!(requests.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn closed_dms_are_skipped() {
+        let (addr, requests) = serve_discord(&[
+            (
+                "POST /api/v10/users/@me/channels",
+                "200 OK",
+                r#"{"id":"5","type":1}"#,
+            ),
+            (
+                "POST /api/v10/channels/5/messages",
+                "403 Forbidden",
+                r#"{"code":50007,"message":"Cannot send messages to this user"}"#,
+            ),
+        ])
+        .await;
+        let http = Arc::new(
+            Client::builder()
+                .proxy(addr, true)
+                .ratelimiter(None)
+                .build(),
+        );
+
+        let action = MessageAction::DmUser {
+            user_id: crate::model::test::USER_ID,
+            content: "Your message was removed".to_owned(),
+            requires_armed: false,
+        };
+
+        action
+            .execute(&http, None, &DelayedActions::default())
+            .await
+            .expect("closed DMs failed the action");
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert!(requests[1].starts_with("POST /api/v10/channels/5/messages"));
+        assert!(
+            requests[1].contains(r#""allowed_mentions":{"parse":[]}"#),
+            "{}",
+            requests[1]
+        );
+    }
+
+    #[tokio::test]
+    async fn webhooks_are_executed_by_id_and_token() {
+        let (addr, requests) =
+            serve_discord(&[("POST /api/v10/webhooks/5/token", "204 No Content", "")]).await;
+        let http = Arc::new(
+            Client::builder()
+                .proxy(addr, true)
+                .ratelimiter(None)
+                .build(),
+        );
+
+        let action = MessageAction::SendWebhook {
+            url: super::WebhookUrl("https://discord.com/api/webhooks/5/token".to_owned()),
+            content: "Message filtered".to_owned(),
+        };
+        assert_eq!(
+            format!("{:?}", action),
+            r#"SendWebhook { url: WebhookUrl(5), content: "Message filtered" }"#
+        );
+
+        action
+            .execute(&http, None, &DelayedActions::default())
+            .await
+            .expect("webhook wasn't executed");
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 1);
+        assert!(requests[0].starts_with("POST /api/v10/webhooks/5/token"));
+        assert!(
+            requests[0].contains(r#""allowed_mentions":{"parse":[]}"#),
+            "{}",
+            requests[0]
+        );
+    }
 }
@@ -0,0 +1,68 @@
+use twilight_model::{
+    channel::message::ReactionType,
+    id::{marker::RoleMarker, Id},
+};
+
+use crate::config::Approval;
+
+/// Whether a reaction with `emoji`, from a member with `roles`, approves the
+/// message it was added to.
+pub(crate) fn is_approval(
+    approval: &Approval,
+    emoji: &ReactionType,
+    roles: &[Id<RoleMarker>],
+) -> bool {
+    let emoji_matches = match emoji {
+        ReactionType::Unicode { name } => *name == approval.emoji,
+        ReactionType::Custom { id, .. } => id.to_string() == approval.emoji,
+    };
+
+    emoji_matches && roles.iter().any(|role| approval.roles.contains(role))
+}
+
+#[cfg(test)]
+mod test {
+    use twilight_model::{channel::message::ReactionType, id::Id};
+
+    use crate::config::Approval;
+
+    fn approval() -> Approval {
+        Approval {
+            emoji: "✅".to_owned(),
+            roles: vec![Id::new(10)],
+        }
+    }
+
+    #[test]
+    fn approvers_approve_with_the_emoji() {
+        let check = ReactionType::Unicode {
+            name: "✅".to_owned(),
+        };
+        let other = ReactionType::Unicode {
+            name: "👍".to_owned(),
+        };
+
+        assert!(super::is_approval(
+            &approval(),
+            &check,
+            &[Id::new(1), Id::new(10)]
+        ));
+        assert!(!super::is_approval(&approval(), &check, &[Id::new(1)]));
+        assert!(!super::is_approval(&approval(), &other, &[Id::new(10)]));
+    }
+
+    #[test]
+    fn custom_emoji_match_by_id() {
+        let approval = Approval {
+            emoji: "123".to_owned(),
+            ..approval()
+        };
+        let custom = ReactionType::Custom {
+            animated: false,
+            id: Id::new(123),
+            name: Some("approved".to_owned()),
+        };
+
+        assert!(super::is_approval(&approval, &custom, &[Id::new(10)]));
+    }
+}
@@ -0,0 +1,190 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::RwLock;
+
+use twilight_model::id::{marker::GuildMarker, Id};
+
+/// How much Chrysanthemum is allowed to do when a filter fails.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub(crate) enum ArmState {
+    /// Actions that require arming are skipped silently.
+    Disarmed = 0,
+    /// Everything is evaluated, and actions that require arming are logged
+    /// as what would have happened instead of being executed.
+    Observe = 1,
+    /// All actions are executed.
+    Armed = 2,
+}
+
+/// What to do with a single action given the current arm state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ActionDisposition {
+    Execute,
+    Observe,
+    Skip,
+}
+
+impl ArmState {
+    pub(crate) fn disposition(self, requires_armed: bool) -> ActionDisposition {
+        match (self, requires_armed) {
+            (_, false) | (ArmState::Armed, true) => ActionDisposition::Execute,
+            (ArmState::Observe, true) => ActionDisposition::Observe,
+            (ArmState::Disarmed, true) => ActionDisposition::Skip,
+        }
+    }
+
+    fn from_u8(value: u8) -> Self {
+        match value {
+            0 => ArmState::Disarmed,
+            1 => ArmState::Observe,
+            _ => ArmState::Armed,
+        }
+    }
+}
+
+impl From<bool> for ArmState {
+    fn from(armed: bool) -> Self {
+        if armed {
+            ArmState::Armed
+        } else {
+            ArmState::Disarmed
+        }
+    }
+}
+
+impl fmt::Display for ArmState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArmState::Disarmed => write!(f, "disarmed"),
+            ArmState::Observe => write!(f, "observing"),
+            ArmState::Armed => write!(f, "armed"),
+        }
+    }
+}
+
+/// An [`ArmState`] that can be shared between tasks.
+#[derive(Debug)]
+pub(crate) struct AtomicArmState(AtomicU8);
+
+impl AtomicArmState {
+    pub(crate) fn new(state: ArmState) -> Self {
+        Self(AtomicU8::new(state as u8))
+    }
+
+    pub(crate) fn load(&self) -> ArmState {
+        ArmState::from_u8(self.0.load(Ordering::Relaxed))
+    }
+
+    pub(crate) fn store(&self, state: ArmState) {
+        self.0.store(state as u8, Ordering::Relaxed);
+    }
+}
+
+/// Each guild's [`ArmState`], so guilds can be armed independently. Guilds
+/// start in the default state until a command changes theirs.
+#[derive(Debug)]
+pub(crate) struct GuildArmStates {
+    default: ArmState,
+    states: RwLock<HashMap<Id<GuildMarker>, AtomicArmState>>,
+}
+
+impl GuildArmStates {
+    pub(crate) fn new(default: ArmState) -> Self {
+        Self {
+            default,
+            states: RwLock::new(HashMap::new()),
+        }
+    }
+
+    pub(crate) fn load(&self, guild_id: Id<GuildMarker>) -> ArmState {
+        self.states
+            .read()
+            .unwrap()
+            .get(&guild_id)
+            .map_or(self.default, AtomicArmState::load)
+    }
+
+    pub(crate) fn store(&self, guild_id: Id<GuildMarker>, state: ArmState) {
+        if let Some(current) = self.states.read().unwrap().get(&guild_id) {
+            current.store(state);
+            return;
+        }
+
+        self.states
+            .write()
+            .unwrap()
+            .insert(guild_id, AtomicArmState::new(state));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+    use twilight_model::id::Id;
+
+    use super::{ActionDisposition, ArmState, AtomicArmState, GuildArmStates};
+
+    #[test]
+    fn disarmed_skips_armed_actions() {
+        assert_eq!(
+            ArmState::Disarmed.disposition(true),
+            ActionDisposition::Skip
+        );
+        assert_eq!(
+            ArmState::Disarmed.disposition(false),
+            ActionDisposition::Execute
+        );
+    }
+
+    #[test]
+    fn observe_reports_armed_actions() {
+        assert_eq!(
+            ArmState::Observe.disposition(true),
+            ActionDisposition::Observe
+        );
+        assert_eq!(
+            ArmState::Observe.disposition(false),
+            ActionDisposition::Execute
+        );
+    }
+
+    #[test]
+    fn armed_executes_everything() {
+        assert_eq!(
+            ArmState::Armed.disposition(true),
+            ActionDisposition::Execute
+        );
+        assert_eq!(
+            ArmState::Armed.disposition(false),
+            ActionDisposition::Execute
+        );
+    }
+
+    #[test]
+    fn atomic_state_round_trips() {
+        let state = AtomicArmState::new(ArmState::from(false));
+        assert_eq!(state.load(), ArmState::Disarmed);
+
+        for arm_state in [ArmState::Observe, ArmState::Armed, ArmState::Disarmed] {
+            state.store(arm_state);
+            assert_eq!(state.load(), arm_state);
+        }
+    }
+
+    #[test]
+    fn guilds_are_armed_independently() {
+        let states = GuildArmStates::new(ArmState::from(true));
+        assert_eq!(states.load(Id::new(1)), ArmState::Armed);
+
+        states.store(Id::new(1), ArmState::Disarmed);
+        assert_eq!(states.load(Id::new(1)), ArmState::Disarmed);
+        assert_eq!(states.load(Id::new(2)), ArmState::Armed);
+
+        states.store(Id::new(1), ArmState::Observe);
+        states.store(Id::new(2), ArmState::Disarmed);
+        assert_eq!(states.load(Id::new(1)), ArmState::Observe);
+        assert_eq!(states.load(Id::new(2)), ArmState::Disarmed);
+    }
+}
@@ -0,0 +1,151 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::Mutex;
+
+use twilight_model::id::{marker::GuildMarker, Id};
+
+/// The window `max_actions_per_minute` counts actions over, in microseconds.
+const CAP_WINDOW: u64 = 60 * 1_000_000;
+
+/// A filter that went over its cap and was disabled.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct TrippedCap {
+    pub(crate) guild_id: Id<GuildMarker>,
+    pub(crate) filter_name: String,
+    pub(crate) cap: u32,
+}
+
+#[derive(Debug, Default)]
+struct CapState {
+    /// When the filter's recent destructive actions were taken.
+    taken_at: HashMap<(Id<GuildMarker>, String), VecDeque<u64>>,
+    disabled: HashSet<(Id<GuildMarker>, String)>,
+    /// Filters that tripped their cap since operators were last told.
+    tripped: Vec<TrippedCap>,
+}
+
+/// Counts each filter's destructive actions, disabling any filter that takes
+/// more than its `max_actions_per_minute` until caps are reset.
+#[derive(Debug, Default)]
+pub(crate) struct ActionCaps {
+    state: Mutex<CapState>,
+}
+
+impl ActionCaps {
+    pub(crate) fn is_disabled(&self, guild_id: Id<GuildMarker>, filter_name: &str) -> bool {
+        self.state
+            .lock()
+            .unwrap()
+            .disabled
+            .contains(&(guild_id, filter_name.to_owned()))
+    }
+
+    /// Records that a filter wants to act at `now`, in microseconds. Returns
+    /// whether it may; if this takes it over `cap`, the filter is disabled
+    /// instead.
+    pub(crate) fn try_act(
+        &self,
+        guild_id: Id<GuildMarker>,
+        filter_name: &str,
+        cap: u32,
+        now: u64,
+    ) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let key = (guild_id, filter_name.to_owned());
+        if state.disabled.contains(&key) {
+            return false;
+        }
+
+        let taken_at = state.taken_at.entry(key.clone()).or_default();
+        while let Some(front) = taken_at.front() {
+            if now.saturating_sub(*front) >= CAP_WINDOW {
+                taken_at.pop_front();
+            } else {
+                break;
+            }
+        }
+
+        if taken_at.len() < cap as usize {
+            taken_at.push_back(now);
+            return true;
+        }
+
+        tracing::warn!(%guild_id, %filter_name, cap, "Filter exceeded its action cap; disabling it");
+        state.taken_at.remove(&key);
+        state.disabled.insert(key);
+        state.tripped.push(TrippedCap {
+            guild_id,
+            filter_name: filter_name.to_owned(),
+            cap,
+        });
+        false
+    }
+
+    /// Takes the filters that have tripped their caps since the last call, so
+    /// operators can be told about each exactly once.
+    pub(crate) fn take_tripped(&self) -> Vec<TrippedCap> {
+        std::mem::take(&mut self.state.lock().unwrap().tripped)
+    }
+
+    /// Re-enables every disabled filter and forgets all counts.
+    pub(crate) fn reset(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.taken_at.clear();
+        state.disabled.clear();
+    }
+
+    /// Re-enables `guild_id`'s disabled filters and forgets its counts.
+    pub(crate) fn reset_guild(&self, guild_id: Id<GuildMarker>) {
+        let mut state = self.state.lock().unwrap();
+        state.taken_at.retain(|(guild, _), _| *guild != guild_id);
+        state.disabled.retain(|(guild, _)| *guild != guild_id);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+    use twilight_model::id::Id;
+
+    use super::{ActionCaps, TrippedCap};
+
+    #[test]
+    fn counts_expire_after_a_minute() {
+        let caps = ActionCaps::default();
+        assert!(caps.try_act(Id::new(1), "filter", 1, 0));
+        assert!(caps.try_act(Id::new(1), "filter", 1, 60 * 1_000_000));
+        assert!(!caps.is_disabled(Id::new(1), "filter"));
+    }
+
+    #[test]
+    fn reset_reenables_filters() {
+        let caps = ActionCaps::default();
+        assert!(caps.try_act(Id::new(1), "filter", 1, 0));
+        assert!(!caps.try_act(Id::new(1), "filter", 1, 1));
+        assert!(caps.is_disabled(Id::new(1), "filter"));
+        assert_eq!(
+            caps.take_tripped(),
+            vec![TrippedCap {
+                guild_id: Id::new(1),
+                filter_name: "filter".to_owned(),
+                cap: 1,
+            }]
+        );
+
+        caps.reset();
+        assert!(!caps.is_disabled(Id::new(1), "filter"));
+        assert!(caps.try_act(Id::new(1), "filter", 1, 2));
+    }
+
+    #[test]
+    fn reset_guild_leaves_other_guilds() {
+        let caps = ActionCaps::default();
+        for guild_id in [Id::new(1), Id::new(2)].iter() {
+            assert!(caps.try_act(*guild_id, "filter", 1, 0));
+            assert!(!caps.try_act(*guild_id, "filter", 1, 1));
+        }
+
+        caps.reset_guild(Id::new(1));
+        assert!(!caps.is_disabled(Id::new(1), "filter"));
+        assert!(caps.is_disabled(Id::new(2), "filter"));
+    }
+}
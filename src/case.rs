@@ -0,0 +1,263 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use eyre::{eyre, Result};
+use once_cell::sync::OnceCell;
+use serde::{Deserialize, Serialize};
+use twilight_http::Client;
+use twilight_mention::Mention;
+use twilight_model::channel::message::AllowedMentions;
+use twilight_model::id::{
+    marker::{ChannelMarker, GuildMarker, MessageMarker, UserMarker},
+    Id,
+};
+
+/// How many times to try opening a case before giving up on it.
+const MAX_ATTEMPTS: u32 = 3;
+/// How long to wait before the first retry. Doubles with each retry.
+const RETRY_DELAY: Duration = Duration::from_millis(500);
+/// How long a single request to the case system may take.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The offense a case is opened for. This is the JSON body `POST`ed to the
+/// case system.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub(crate) struct CaseDetails {
+    pub(crate) guild_id: Id<GuildMarker>,
+    pub(crate) channel_id: Id<ChannelMarker>,
+    pub(crate) user_id: Id<UserMarker>,
+    pub(crate) message_id: Option<Id<MessageMarker>>,
+    pub(crate) filter_name: String,
+    pub(crate) reason: String,
+    pub(crate) content: Option<String>,
+    pub(crate) context: &'static str,
+}
+
+/// The case system's reply: `{ "id": ... }`, where the ID is a string or a
+/// number.
+#[derive(Debug, Deserialize)]
+struct CaseResponse {
+    id: CaseId,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum CaseId {
+    Text(String),
+    Number(u64),
+}
+
+fn client() -> &'static reqwest::Client {
+    static CLIENT: OnceCell<reqwest::Client> = OnceCell::new();
+    CLIENT.get_or_init(|| {
+        reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .expect("couldn't build case system client")
+    })
+}
+
+/// Opens a case at `endpoint`, returning its ID. Connection failures, server
+/// errors and rate limits are retried; other errors aren't, since retrying
+/// won't change the answer.
+pub(crate) async fn open_case(
+    endpoint: &str,
+    authorization: Option<&str>,
+    details: &CaseDetails,
+) -> Result<String> {
+    let body = serde_json::to_string(details)?;
+    let mut delay = RETRY_DELAY;
+
+    for attempt in 1..=MAX_ATTEMPTS {
+        let mut request = client()
+            .post(endpoint)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(body.clone());
+        if let Some(authorization) = authorization {
+            request = request.header(reqwest::header::AUTHORIZATION, authorization);
+        }
+
+        let retryable = match request.send().await {
+            Ok(response) if response.status().is_success() => {
+                let response: CaseResponse = serde_json::from_slice(&response.bytes().await?)?;
+                return Ok(match response.id {
+                    CaseId::Text(id) => id,
+                    CaseId::Number(id) => id.to_string(),
+                });
+            }
+            Ok(response) => {
+                let status = response.status();
+                if !(status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS) {
+                    return Err(eyre!("case system rejected the case with {}", status));
+                }
+
+                eyre!("case system responded with {}", status)
+            }
+            Err(err) => err.into(),
+        };
+
+        tracing::debug!(attempt, err = ?retryable, "Failed to open case");
+        if attempt < MAX_ATTEMPTS {
+            tokio::time::sleep(delay).await;
+            delay *= 2;
+        } else {
+            return Err(retryable.wrap_err(format!("gave up after {} attempts", MAX_ATTEMPTS)));
+        }
+    }
+
+    unreachable!("the last attempt always returns")
+}
+
+/// Opens a case in the background, so a slow or unavailable case system never
+/// holds up handling. If the case can't be opened, it's logged and skipped.
+pub(crate) fn spawn_open_case(
+    http: Arc<Client>,
+    endpoint: String,
+    authorization: Option<String>,
+    log_channel: Option<Id<ChannelMarker>>,
+    details: CaseDetails,
+) {
+    tokio::spawn(async move {
+        let case_id = match open_case(&endpoint, authorization.as_deref(), &details).await {
+            Ok(case_id) => case_id,
+            Err(err) => {
+                tracing::warn!(?err, %endpoint, ?details, "Unable to open case");
+                return;
+            }
+        };
+
+        tracing::debug!(%case_id, ?details, "Opened case");
+
+        if let Some(log_channel) = log_channel {
+            let content = format!(
+                "Opened case `{}` for {} ({}: {})",
+                case_id,
+                details.user_id.mention(),
+                details.filter_name,
+                details.reason
+            );
+
+            let result = match http
+                .create_message(log_channel)
+                .allowed_mentions(Some(&AllowedMentions::default()))
+                .content(&content)
+            {
+                Ok(request) => request.await.map(|_| ()).map_err(eyre::Report::from),
+                Err(err) => Err(err.into()),
+            };
+            if let Err(err) = result {
+                tracing::warn!(?err, %case_id, "Unable to log opened case");
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+
+    use pretty_assertions::assert_eq;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    use twilight_model::id::Id;
+
+    use super::CaseDetails;
+
+    fn details() -> CaseDetails {
+        CaseDetails {
+            guild_id: Id::new(4),
+            channel_id: Id::new(2),
+            user_id: Id::new(3),
+            message_id: Some(Id::new(1)),
+            filter_name: "Scams".to_owned(),
+            reason: "contains word `nitro`".to_owned(),
+            content: Some("free nitro".to_owned()),
+            context: "message create",
+        }
+    }
+
+    /// Reads a whole request: its headers, then as much body as they announce.
+    async fn read_request(socket: &mut tokio::net::TcpStream) -> String {
+        let mut request = Vec::new();
+        let mut buffer = [0; 1024];
+        loop {
+            let read = socket.read(&mut buffer).await.unwrap_or(0);
+            request.extend_from_slice(&buffer[..read]);
+
+            let text = String::from_utf8_lossy(&request).into_owned();
+            if let Some(headers_end) = text.find("\r\n\r\n") {
+                let content_length = text[..headers_end]
+                    .lines()
+                    .find_map(|l| l.strip_prefix("content-length: "))
+                    .and_then(|l| l.trim().parse().ok())
+                    .unwrap_or(0);
+                if read == 0 || text.len() >= headers_end + 4 + content_length {
+                    return text;
+                }
+            } else if read == 0 {
+                return text;
+            }
+        }
+    }
+
+    /// Serves each of `responses` in turn, one per connection, recording the
+    /// requests it receives.
+    async fn serve(
+        responses: Vec<(&'static str, &'static str)>,
+    ) -> (String, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+
+        let recorded = requests.clone();
+        tokio::spawn(async move {
+            for (status, body) in responses {
+                let (mut socket, _) = match listener.accept().await {
+                    Ok(connection) => connection,
+                    Err(_) => break,
+                };
+
+                let request = read_request(&mut socket).await;
+                recorded.lock().unwrap().push(request);
+
+                let response = format!(
+                    "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                );
+                let _ = socket.write_all(response.as_bytes()).await;
+            }
+        });
+
+        (format!("http://{}/cases", addr), requests)
+    }
+
+    #[tokio::test]
+    async fn opens_case_after_retrying_server_errors() {
+        let (endpoint, requests) = serve(vec![
+            ("500 Internal Server Error", "{}"),
+            ("201 Created", r#"{"id": 42}"#),
+        ])
+        .await;
+
+        let case_id = super::open_case(&endpoint, Some("Bearer secret"), &details())
+            .await
+            .expect("case wasn't opened");
+        assert_eq!(case_id, "42");
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert!(requests[1].contains("authorization: Bearer secret"));
+        assert!(requests[1].contains(r#""filter_name":"Scams""#));
+        assert!(requests[1].contains(r#""user_id":"3""#));
+    }
+
+    #[tokio::test]
+    async fn rejected_cases_are_not_retried() {
+        let (endpoint, requests) = serve(vec![("400 Bad Request", "{}")]).await;
+
+        assert!(super::open_case(&endpoint, None, &details()).await.is_err());
+        assert_eq!(requests.lock().unwrap().len(), 1);
+    }
+}
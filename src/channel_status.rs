@@ -0,0 +1,154 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use twilight_model::{
+    channel::StageInstance,
+    id::{marker::ChannelMarker, Id},
+};
+
+use crate::{action::MessageAction, config::ChannelStatusFilter};
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct ChannelStatusFailure {
+    pub(crate) actions: Vec<MessageAction>,
+}
+
+/// Remembers the last topic each stage had that passed the filter, so a
+/// matching topic can be replaced with it.
+#[derive(Debug, Default)]
+pub(crate) struct StageTopics {
+    topics: Mutex<HashMap<Id<ChannelMarker>, String>>,
+}
+
+impl StageTopics {
+    pub(crate) fn accepted(&self, channel_id: Id<ChannelMarker>, topic: &str) {
+        self.topics
+            .lock()
+            .unwrap()
+            .insert(channel_id, topic.to_owned());
+    }
+
+    pub(crate) fn last_accepted(&self, channel_id: Id<ChannelMarker>) -> Option<String> {
+        self.topics.lock().unwrap().get(&channel_id).cloned()
+    }
+
+    /// Forgets a stage that has ended.
+    pub(crate) fn forget(&self, channel_id: Id<ChannelMarker>) {
+        self.topics.lock().unwrap().remove(&channel_id);
+    }
+}
+
+/// Checks a stage's topic, putting back `previous` (or the filter's fallback
+/// topic, for a new stage) if it matches.
+#[tracing::instrument(skip(filter))]
+pub(crate) fn filter_stage_topic(
+    filter: &ChannelStatusFilter,
+    stage: &StageInstance,
+    previous: Option<&str>,
+) -> Result<(), ChannelStatusFailure> {
+    let reason = match filter.filter_topic(&stage.topic) {
+        Ok(()) => return Ok(()),
+        Err(reason) => reason,
+    };
+
+    let mut actions = vec![MessageAction::SetStageTopic {
+        channel_id: stage.channel_id,
+        topic: previous.unwrap_or(&filter.fallback_topic).to_owned(),
+    }];
+
+    if let Some(log_channel) = filter.log_channel {
+        actions.push(MessageAction::SendMessage {
+            to: log_channel,
+            content: format!(
+                "Reverted the topic of the stage in <#{}>, which {}",
+                stage.channel_id, reason
+            ),
+            requires_armed: false,
+            coalesce_seconds: None,
+        });
+    }
+
+    Err(ChannelStatusFailure { actions })
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+    use twilight_model::{
+        channel::{stage_instance::PrivacyLevel, StageInstance},
+        id::Id,
+    };
+
+    use crate::{
+        action::MessageAction,
+        config::{ChannelStatusFilter, MessageFilterRule},
+    };
+
+    use super::ChannelStatusFailure;
+
+    fn filter() -> ChannelStatusFilter {
+        let rule: MessageFilterRule = serde_yaml::from_str("type: words\nwords: [nitro]")
+            .expect("couldn't deserialize MessageFilterRule");
+
+        ChannelStatusFilter {
+            rules: vec![rule],
+            fallback_topic: "Stage".to_owned(),
+            log_channel: Some(Id::new(5)),
+        }
+    }
+
+    fn stage(topic: &str) -> StageInstance {
+        StageInstance {
+            channel_id: crate::model::test::CHANNEL_ID,
+            guild_id: crate::model::test::GUILD_ID,
+            guild_scheduled_event_id: None,
+            id: Id::new(6),
+            privacy_level: PrivacyLevel::GuildOnly,
+            topic: topic.to_owned(),
+        }
+    }
+
+    #[test]
+    fn banned_word_in_stage_topic_is_reverted() {
+        let result =
+            super::filter_stage_topic(&filter(), &stage("free nitro giveaway"), Some("Town hall"));
+
+        assert_eq!(
+            result,
+            Err(ChannelStatusFailure {
+                actions: vec![
+                    MessageAction::SetStageTopic {
+                        channel_id: crate::model::test::CHANNEL_ID,
+                        topic: "Town hall".to_owned(),
+                    },
+                    MessageAction::SendMessage {
+                        to: Id::new(5),
+                        content:
+                            "Reverted the topic of the stage in <#2>, which contains word `nitro`"
+                                .to_owned(),
+                        requires_armed: false,
+                        coalesce_seconds: None,
+                    },
+                ]
+            })
+        );
+    }
+
+    #[test]
+    fn new_stages_fall_back_to_default_topic() {
+        let result = super::filter_stage_topic(&filter(), &stage("nitro"), None);
+        let actions = result.expect_err("topic wasn't filtered").actions;
+        assert_eq!(
+            actions[0],
+            MessageAction::SetStageTopic {
+                channel_id: crate::model::test::CHANNEL_ID,
+                topic: "Stage".to_owned(),
+            }
+        );
+
+        assert_eq!(
+            super::filter_stage_topic(&filter(), &stage("Town hall"), None),
+            Ok(())
+        );
+    }
+}
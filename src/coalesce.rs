@@ -0,0 +1,273 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use twilight_model::{
+    channel::message::ReactionType,
+    id::{marker::ChannelMarker, Id},
+};
+
+use crate::action::{MessageAction, ReactionAction};
+
+/// How much of a held-back message to quote in its summary.
+const MAX_SUMMARY_PREVIEW: usize = 200;
+
+/// Identifies repeats of one rendered log or message: where it's sent and
+/// what it says.
+pub(crate) type CoalesceKey = (Id<ChannelMarker>, String);
+
+/// Holds back repeats of a log or message sent to the same channel while its
+/// window is open, counting them so a single summary can follow.
+#[derive(Debug, Default)]
+pub(crate) struct Coalescer {
+    /// How many repeats of each open window's message were held back.
+    windows: Mutex<HashMap<CoalesceKey, usize>>,
+}
+
+impl Coalescer {
+    /// Whether to send the message `key` identifies. The first one opens a
+    /// window; repeats are counted instead until [`Self::finish`] closes it.
+    pub(crate) fn admit(&self, key: &CoalesceKey) -> bool {
+        let mut windows = self.windows.lock().unwrap();
+        match windows.get_mut(key) {
+            Some(held_back) => {
+                *held_back += 1;
+                false
+            }
+            None => {
+                windows.insert(key.clone(), 0);
+                true
+            }
+        }
+    }
+
+    /// Closes the window for `key`, returning how many repeats were held back.
+    pub(crate) fn finish(&self, key: &CoalesceKey) -> usize {
+        self.windows.lock().unwrap().remove(key).unwrap_or(0)
+    }
+}
+
+/// A coalescing window an action asked for.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct Window {
+    pub(crate) key: CoalesceKey,
+    pub(crate) seconds: u64,
+    /// What was held back: messages, or reactions.
+    noun: &'static str,
+    /// What the summary says the held-back repeats were: the filter for logs,
+    /// or the start of the message itself.
+    summary: String,
+}
+
+impl Window {
+    /// The message announcing `held_back` repeats, if there were any.
+    pub(crate) fn summary(&self, held_back: usize) -> Option<MessageAction> {
+        if held_back == 0 {
+            return None;
+        }
+
+        Some(MessageAction::SendMessage {
+            to: self.key.0,
+            content: format!(
+                "{} more {}{} {} within {} seconds",
+                held_back,
+                self.noun,
+                if held_back == 1 { "" } else { "s" },
+                self.summary,
+                self.seconds
+            ),
+            requires_armed: false,
+            coalesce_seconds: None,
+        })
+    }
+}
+
+/// The window `action` should be coalesced over, if it asked for one.
+pub(crate) fn window(action: &MessageAction) -> Option<Window> {
+    match action {
+        MessageAction::SendLog {
+            to,
+            filter_name,
+            content,
+            filter_reason,
+            coalesce_seconds: Some(seconds),
+            ..
+        } => Some(Window {
+            // Who sent it doesn't matter: a raid is many users sending the
+            // same thing.
+            key: (
+                *to,
+                format!("{}\n{}\n{}", filter_name, filter_reason, content),
+            ),
+            seconds: *seconds,
+            noun: "message",
+            summary: format!("matched `{}`", filter_name),
+        }),
+        MessageAction::SendMessage {
+            to,
+            content,
+            coalesce_seconds: Some(seconds),
+            ..
+        } => Some(Window {
+            key: (*to, content.clone()),
+            seconds: *seconds,
+            noun: "message",
+            summary: format!("like \"{}\" were held back", preview(content)),
+        }),
+        MessageAction::WithFallback { action, .. } => window(action),
+        _ => None,
+    }
+}
+
+/// The window a reaction filter's `action` should be coalesced over, if it
+/// asked for one.
+pub(crate) fn reaction_window(action: &ReactionAction) -> Option<Window> {
+    match action {
+        ReactionAction::SendLog {
+            to,
+            filter_name,
+            filter_reason,
+            reaction,
+            coalesce_seconds: Some(seconds),
+            ..
+        } => {
+            let emoji = match reaction {
+                ReactionType::Custom { id, .. } => id.to_string(),
+                ReactionType::Unicode { name } => name.clone(),
+            };
+
+            Some(Window {
+                key: (
+                    *to,
+                    format!("{}\n{}\n{}", filter_name, filter_reason, emoji),
+                ),
+                seconds: *seconds,
+                noun: "reaction",
+                summary: format!("matched `{}`", filter_name),
+            })
+        }
+        ReactionAction::SendMessage {
+            to,
+            content,
+            coalesce_seconds: Some(seconds),
+            ..
+        } => Some(Window {
+            key: (*to, content.clone()),
+            seconds: *seconds,
+            noun: "message",
+            summary: format!("like \"{}\" were held back", preview(content)),
+        }),
+        ReactionAction::WithFallback { action, .. } => reaction_window(action),
+        _ => None,
+    }
+}
+
+/// The start of `content`, for quoting in a summary.
+fn preview(content: &str) -> String {
+    match content.char_indices().nth(MAX_SUMMARY_PREVIEW) {
+        Some((end, _)) => format!("{}…", &content[..end]),
+        None => content.to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+    use twilight_model::id::Id;
+
+    use super::Coalescer;
+    use crate::action::{MessageAction, ReactionAction};
+
+    fn log(content: &str, author: u64) -> MessageAction {
+        MessageAction::SendLog {
+            to: Id::new(1),
+            filter_name: "Links filter".to_owned(),
+            message_channel: Id::new(2),
+            content: content.to_owned(),
+            previous_content: None,
+            filter_reason: "contains link".to_owned(),
+            author: Id::new(author),
+            context: "message create",
+            actions_taken: None,
+            coalesce_seconds: Some(30),
+        }
+    }
+
+    #[test]
+    fn repeats_are_held_back_until_the_window_closes() {
+        let coalescer = Coalescer::default();
+        let first = super::window(&log("free nitro", 3)).unwrap();
+        let other = super::window(&log("something else", 3)).unwrap();
+
+        assert!(coalescer.admit(&first.key));
+        // The same content from other users is a repeat.
+        for author in 4..16 {
+            assert!(!coalescer.admit(&super::window(&log("free nitro", author)).unwrap().key));
+        }
+        assert!(coalescer.admit(&other.key));
+
+        let held_back = coalescer.finish(&first.key);
+        assert_eq!(held_back, 12);
+        assert_eq!(
+            first.summary(held_back),
+            Some(MessageAction::SendMessage {
+                to: Id::new(1),
+                content: "12 more messages matched `Links filter` within 30 seconds".to_owned(),
+                requires_armed: false,
+                coalesce_seconds: None,
+            })
+        );
+        assert_eq!(other.summary(coalescer.finish(&other.key)), None);
+
+        // A new window opens once the last one closes.
+        assert!(coalescer.admit(&first.key));
+    }
+
+    #[test]
+    fn reaction_logs_are_coalesced() {
+        let log = |author: u64| ReactionAction::SendLog {
+            to: Id::new(1),
+            filter_name: "Emoji filter".to_owned(),
+            message: Id::new(5),
+            channel: Id::new(2),
+            filter_reason: "reacted with denied emoji `🍆`".to_owned(),
+            author: Id::new(author),
+            reaction: crate::model::test::default_reaction("🍆").reaction,
+            coalesce_seconds: Some(30),
+        };
+
+        let coalescer = Coalescer::default();
+        let first = super::reaction_window(&log(3)).unwrap();
+        assert!(coalescer.admit(&first.key));
+        assert!(!coalescer.admit(&super::reaction_window(&log(4)).unwrap().key));
+
+        assert_eq!(
+            first.summary(coalescer.finish(&first.key)),
+            Some(MessageAction::SendMessage {
+                to: Id::new(1),
+                content: "1 more reaction matched `Emoji filter` within 30 seconds".to_owned(),
+                requires_armed: false,
+                coalesce_seconds: None,
+            })
+        );
+    }
+
+    #[test]
+    fn only_actions_asking_for_it_are_coalesced() {
+        let mut action = log("free nitro", 3);
+        if let MessageAction::SendLog {
+            coalesce_seconds, ..
+        } = &mut action
+        {
+            *coalesce_seconds = None;
+        }
+
+        assert_eq!(super::window(&action), None);
+        assert_eq!(
+            super::window(&MessageAction::Delete {
+                message_id: Id::new(1),
+                channel_id: Id::new(2),
+            }),
+            None
+        );
+    }
+}
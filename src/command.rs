@@ -1,5 +1,6 @@
 use color_eyre::eyre::Result;
 use twilight_http::client::InteractionClient;
+use twilight_mention::Mention;
 use twilight_model::application::command::CommandType;
 use twilight_model::application::interaction::InteractionData;
 use twilight_model::{
@@ -10,10 +11,16 @@ use twilight_model::{
             Interaction,
         },
     },
-    channel::{message::MessageFlags, ChannelType},
+    channel::{
+        message::{Embed, Message, MessageFlags},
+        ChannelType,
+    },
     guild::Permissions,
     http::interaction::{InteractionResponse, InteractionResponseType},
-    id::{marker::GuildMarker, Id},
+    id::{
+        marker::{GuildMarker, RoleMarker, UserMarker},
+        Id,
+    },
 };
 use twilight_util::builder::command::CommandBuilder;
 use twilight_util::builder::{
@@ -21,12 +28,19 @@ use twilight_util::builder::{
     InteractionResponseDataBuilder,
 };
 
-use crate::config::SlashCommands;
+use crate::arm::ArmState;
+use crate::config::{MessageFilter, PrefixCommands, RuleMatchMode, SlashCommands, SpamFilter};
+use crate::filter::SpamSummary;
+use crate::scope::ScopeEntry;
 
 const TEST_COMMAND: &str = "chrysanthemum-test";
 const ARM_COMMAND: &str = "chrysanthemum-arm";
 const DISARM_COMMAND: &str = "chrysanthemum-disarm";
+const OBSERVE_COMMAND: &str = "chrysanthemum-observe";
 const RELOAD_COMMAND: &str = "chrysanthemum-reload";
+const SELFTEST_COMMAND: &str = "chrysanthemum-selftest";
+const SCOPE_COMMAND: &str = "chrysanthemum-scope";
+const SPAMINFO_COMMAND: &str = "chrysanthemum-spaminfo";
 
 #[tracing::instrument(skip(http))]
 pub(crate) async fn create_commands_for_guild(
@@ -74,12 +88,106 @@ pub(crate) async fn create_commands_for_guild(
             )
             .default_member_permissions(Permissions::ADMINISTRATOR)
             .build(),
+            CommandBuilder::new(
+                OBSERVE_COMMAND,
+                "Puts Chrysanthemum in observe mode: actions are logged, not taken.",
+                CommandType::ChatInput,
+            )
+            .default_member_permissions(Permissions::ADMINISTRATOR)
+            .build(),
             CommandBuilder::new(
                 RELOAD_COMMAND,
                 "Reloads Chrysanthemum configurations from disk.",
                 CommandType::ChatInput,
             )
             .default_member_permissions(Permissions::ADMINISTRATOR)
+            .option(CommandOption {
+                name: "guild".to_owned(),
+                description:
+                    "The ID of the only guild to reload. Defaults to every guild. Only operators can reload other guilds."
+                        .to_owned(),
+                kind: CommandOptionType::String,
+                required: Some(false),
+                autocomplete: None,
+                channel_types: None,
+                choices: None,
+                description_localizations: None,
+                max_length: Some(20),
+                max_value: None,
+                min_length: Some(1),
+                min_value: None,
+                name_localizations: None,
+                options: None,
+            })
+            .build(),
+            CommandBuilder::new(
+                SCOPE_COMMAND,
+                "Shows which filters apply in a channel, optionally for a given user.",
+                CommandType::ChatInput,
+            )
+            .default_member_permissions(Permissions::ADMINISTRATOR)
+            .option(CommandOption {
+                name: "channel".to_owned(),
+                description: "The channel to check. Defaults to this channel.".to_owned(),
+                kind: CommandOptionType::Channel,
+                required: Some(false),
+                autocomplete: None,
+                channel_types: None,
+                choices: None,
+                description_localizations: None,
+                max_length: None,
+                max_value: None,
+                min_length: None,
+                min_value: None,
+                name_localizations: None,
+                options: None,
+            })
+            .option(CommandOption {
+                name: "user".to_owned(),
+                description: "The user whose roles to check against.".to_owned(),
+                kind: CommandOptionType::User,
+                required: Some(false),
+                autocomplete: None,
+                channel_types: None,
+                choices: None,
+                description_localizations: None,
+                max_length: None,
+                max_value: None,
+                min_length: None,
+                min_value: None,
+                name_localizations: None,
+                options: None,
+            })
+            .build(),
+            CommandBuilder::new(
+                SPAMINFO_COMMAND,
+                "Shows a user's current spam counts and the thresholds they're held to.",
+                CommandType::ChatInput,
+            )
+            .default_member_permissions(Permissions::MANAGE_MESSAGES)
+            .option(CommandOption {
+                name: "user".to_owned(),
+                description: "The user to inspect.".to_owned(),
+                kind: CommandOptionType::User,
+                required: Some(true),
+                autocomplete: None,
+                channel_types: None,
+                choices: None,
+                description_localizations: None,
+                max_length: None,
+                max_value: None,
+                min_length: None,
+                min_value: None,
+                name_localizations: None,
+                options: None,
+            })
+            .build(),
+            CommandBuilder::new(
+                SELFTEST_COMMAND,
+                "Checks that each rule type in this guild's configuration behaves as expected.",
+                CommandType::ChatInput,
+            )
+            .default_member_permissions(Permissions::ADMINISTRATOR)
             .build(),
         ],
     )
@@ -108,6 +216,290 @@ pub(crate) async fn update_guild_commands(
     }
 }
 
+/// A command sent as a message starting with the guild's command prefix.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum PrefixCommand {
+    SetArmState(ArmState),
+    Reload,
+}
+
+/// Parses `content` as a prefix command, if it is one and the author is allowed
+/// to use prefix commands.
+pub(crate) fn parse_prefix_command(
+    config: &PrefixCommands,
+    author_id: Id<UserMarker>,
+    author_roles: &[Id<RoleMarker>],
+    content: &str,
+) -> Option<PrefixCommand> {
+    let rest = content.strip_prefix(config.prefix.as_str())?;
+    // Require whitespace after the prefix, so `!chrys` doesn't match `!chrysalis`.
+    if !rest.starts_with(char::is_whitespace) {
+        return None;
+    }
+
+    let command = match rest.split_whitespace().next()? {
+        "arm" => PrefixCommand::SetArmState(ArmState::Armed),
+        "disarm" => PrefixCommand::SetArmState(ArmState::Disarmed),
+        "observe" => PrefixCommand::SetArmState(ArmState::Observe),
+        "reload" => PrefixCommand::Reload,
+        _ => return None,
+    };
+
+    let permitted =
+        config.users.contains(&author_id) || author_roles.iter().any(|r| config.roles.contains(r));
+    if !permitted {
+        tracing::debug!(%author_id, ?command, "Ignoring prefix command from unpermitted user");
+        return None;
+    }
+
+    Some(command)
+}
+
+/// Changes `guild_id`'s arm state, as the arm, disarm and observe commands do.
+fn set_arm_state(state: &crate::State, guild_id: Id<GuildMarker>, arm_state: ArmState) -> String {
+    state.armed.store(guild_id, arm_state);
+    tracing::info!(%guild_id, %arm_state, "Arm state changed");
+
+    format!("Chrysanthemum **{}**.", arm_state)
+}
+
+/// Reloads configurations, as the reload command run in `invoking_guild_id`
+/// does: only `guild_id`'s if one is given, otherwise every guild's. A
+/// successful reload by command also re-enables filters disabled by their
+/// action caps. Unless `operator` is set, this can only reload the invoking
+/// guild on its own, and only re-enables the invoking guild's filters.
+async fn reload_by_command(
+    state: &crate::State,
+    invoking_guild_id: Id<GuildMarker>,
+    guild_id: Option<Id<GuildMarker>>,
+    operator: bool,
+) -> Result<(), (Option<Id<GuildMarker>>, eyre::Report)> {
+    match guild_id {
+        Some(guild_id) => {
+            if !may_reload(operator, invoking_guild_id, guild_id) {
+                return Err((None, eyre::eyre!("only operators can reload other guilds")));
+            }
+
+            crate::reload_guild_config(state, guild_id)
+                .await
+                .map_err(|(id, report)| (Some(id), report))?;
+            state.action_caps.reset_guild(guild_id);
+        }
+        None => {
+            crate::reload_guild_configs(state)
+                .await
+                .map_err(|(id, report)| (Some(id), report))?;
+            if operator {
+                state.action_caps.reset();
+            } else {
+                state.action_caps.reset_guild(invoking_guild_id);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Whether the reload command run in `invoking_guild_id` may reload
+/// `guild_id` on its own. Only operators may reload other guilds.
+fn may_reload(
+    operator: bool,
+    invoking_guild_id: Id<GuildMarker>,
+    guild_id: Id<GuildMarker>,
+) -> bool {
+    operator || guild_id == invoking_guild_id
+}
+
+/// Whether `user_id` is one of the `operators` in the main configuration.
+fn is_operator(state: &crate::State, user_id: Option<Id<UserMarker>>) -> bool {
+    user_id.map_or(false, |user_id| state.cfg.operators.contains(&user_id))
+}
+
+/// Parses the reload command's `guild` option.
+fn parse_guild_option(value: &str) -> Option<Id<GuildMarker>> {
+    value.trim().parse().ok().and_then(Id::new_checked)
+}
+
+#[tracing::instrument(skip(state, message))]
+pub(crate) async fn handle_prefix_command(
+    state: &crate::State,
+    message: &Message,
+    command: PrefixCommand,
+) -> Result<()> {
+    let response = match command {
+        PrefixCommand::SetArmState(arm_state) => match message.guild_id {
+            Some(guild_id) => set_arm_state(state, guild_id, arm_state),
+            None => return Ok(()),
+        },
+        PrefixCommand::Reload => {
+            let invoking_guild_id = match message.guild_id {
+                Some(guild_id) => guild_id,
+                None => return Ok(()),
+            };
+            let operator = is_operator(state, Some(message.author.id));
+            match reload_by_command(state, invoking_guild_id, None, operator).await {
+                Ok(()) => "Reload successful.".to_owned(),
+                Err((Some(guild_id), report)) => {
+                    format!("Reload failure for guild {}: ```{}```", guild_id, report)
+                }
+                Err((None, report)) => format!("Reload failure: ```{}```", report),
+            }
+        }
+    };
+
+    state
+        .http
+        .create_message(message.channel_id)
+        .reply(message.id)
+        .content(&response)?
+        .await?;
+
+    Ok(())
+}
+
+/// Formats scope preview entries for an embed field, keeping within Discord's
+/// field length limit.
+fn format_scope_entries(entries: &[ScopeEntry]) -> String {
+    const MAX_FIELD_LENGTH: usize = 1024;
+
+    if entries.is_empty() {
+        return "None configured".to_owned();
+    }
+
+    let mut formatted = String::new();
+    for entry in entries {
+        let line = match &entry.skip_reason {
+            None => format!("✅ {}\n", entry.name),
+            Some(reason) => format!("❌ {}: {}\n", entry.name, reason),
+        };
+
+        if formatted.len() + line.len() > MAX_FIELD_LENGTH - "…".len() {
+            formatted.push('…');
+            break;
+        }
+
+        formatted.push_str(&line);
+    }
+
+    formatted
+}
+
+/// Formats a user's spam counts next to the thresholds that apply to them.
+fn format_spam_counts(summary: &SpamSummary, config: &SpamFilter) -> String {
+    let counts = [
+        ("Emoji", summary.emoji.into(), config.emoji.map(u32::from)),
+        ("Links", summary.links.into(), config.links.map(u32::from)),
+        (
+            "Attachments",
+            summary.attachments.into(),
+            config.attachments.map(u32::from),
+        ),
+        (
+            "Spoilers",
+            summary.spoilers.into(),
+            config.spoilers.map(u32::from),
+        ),
+        (
+            "Mentions",
+            summary.mentions.into(),
+            config.mentions.map(u32::from),
+        ),
+        (
+            "Newlines",
+            summary.newlines.into(),
+            config.newlines.map(u32::from),
+        ),
+        ("Characters", summary.chars, config.total_chars),
+        (
+            "Duplicates",
+            summary.duplicates.into(),
+            config.duplicates.map(u32::from),
+        ),
+    ];
+
+    counts
+        .iter()
+        .map(|(name, count, threshold)| match threshold {
+            Some(threshold) => format!("{}: {} / {}", name, count, threshold),
+            None => format!("{}: {} (no limit)", name, count),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Builds the test command's response: whether `message` passes `filters`, and
+/// if not, which filter caught it and why.
+fn test_embed(message: &str, filters: &[MessageFilter]) -> Embed {
+    let result = filters
+        .iter()
+        .map(|f| f.filter_text(message).map_err(|e| (f, e)))
+        .find(Result::is_err)
+        .map(|r| r.unwrap_err());
+
+    let mut builder = EmbedBuilder::new()
+        .title("Test filter")
+        .field(EmbedFieldBuilder::new("Input", format!("```{}```", message)).build());
+
+    match result {
+        Some((filter, filter_match)) => {
+            builder = builder
+                .field(EmbedFieldBuilder::new(
+                    "Status",
+                    format!("❌ Failed: {}", filter_match.rule_description),
+                ))
+                .field(EmbedFieldBuilder::new("Filter", &filter_match.filter_name));
+
+            if let Some(description) = &filter.description {
+                builder = builder.field(EmbedFieldBuilder::new("About this filter", description));
+            }
+        }
+        None => {
+            builder = builder.field(EmbedFieldBuilder::new("Status", "✅ Passed all filters"));
+        }
+    }
+
+    let mut skipped = Vec::new();
+    let mut incomplete = Vec::new();
+    for filter in filters {
+        for rule in &filter.rules {
+            if !rule.rule.needs_message() {
+                continue;
+            }
+
+            if !skipped.contains(&rule.rule.kind()) {
+                skipped.push(rule.rule.kind());
+            }
+            // A skipped rule never matches, so filters needing every rule to
+            // match can't fail the test at all.
+            if filter.match_mode == RuleMatchMode::All
+                && filter.score_threshold.is_none()
+                && !incomplete.contains(&filter.name.as_str())
+            {
+                incomplete.push(filter.name.as_str());
+            }
+        }
+    }
+    if !skipped.is_empty() {
+        let skipped = skipped
+            .iter()
+            .map(|kind| format!("`{}`", kind))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let mut note = format!(
+            "These rules need a real message, so they were skipped: {}",
+            skipped
+        );
+        if !incomplete.is_empty() {
+            note.push_str(&format!(
+                "\nThese filters need all of their rules to match, so they can't fail this test: {}",
+                incomplete.join(", ")
+            ));
+        }
+        builder = builder.field(EmbedFieldBuilder::new("Not tested", note));
+    }
+
+    builder.build()
+}
+
 #[tracing::instrument(skip(state))]
 pub(crate) async fn handle_command(
     state: crate::State,
@@ -148,33 +540,7 @@ pub(crate) async fn handle_command(
 
                     if let Some(guild_config) = guild_cfgs.get(&guild_id) {
                         if let Some(message_filters) = &guild_config.messages {
-                            let result = message_filters
-                                .iter()
-                                .map(|f| f.filter_text(&message[..]).map_err(|e| (f, e)))
-                                .find(Result::is_err)
-                                .map(|r| r.unwrap_err());
-
-                            let mut builder = EmbedBuilder::new().title("Test filter").field(
-                                EmbedFieldBuilder::new("Input", format!("```{}```", message))
-                                    .build(),
-                            );
-
-                            match result {
-                                Some((filter, reason)) => {
-                                    builder = builder
-                                        .field(EmbedFieldBuilder::new(
-                                            "Status",
-                                            format!("❌ Failed: {}", reason),
-                                        ))
-                                        .field(EmbedFieldBuilder::new("Filter", &filter.name));
-                                }
-                                None => {
-                                    builder = builder.field(EmbedFieldBuilder::new(
-                                        "Status",
-                                        "✅ Passed all filters",
-                                    ));
-                                }
-                            }
+                            let embed = test_embed(message, message_filters);
 
                             interaction_http
                                 .create_response(
@@ -185,7 +551,7 @@ pub(crate) async fn handle_command(
                                         data: Some(
                                             InteractionResponseDataBuilder::new()
                                                 .flags(MessageFlags::EPHEMERAL)
-                                                .embeds(vec![builder.build()])
+                                                .embeds(vec![embed])
                                                 .build(),
                                         ),
                                     },
@@ -196,10 +562,15 @@ pub(crate) async fn handle_command(
                     }
                 }
             }
-            ARM_COMMAND => {
-                state
-                    .armed
-                    .store(true, std::sync::atomic::Ordering::Relaxed);
+            ARM_COMMAND | DISARM_COMMAND | OBSERVE_COMMAND => {
+                let arm_state = match cmd_data.name.as_str() {
+                    ARM_COMMAND => ArmState::Armed,
+                    OBSERVE_COMMAND => ArmState::Observe,
+                    _ => ArmState::Disarmed,
+                };
+
+                let content = set_arm_state(&state, guild_id, arm_state);
+
                 interaction_http
                     .create_response(
                         interaction.id,
@@ -209,7 +580,7 @@ pub(crate) async fn handle_command(
                             data: Some(
                                 InteractionResponseDataBuilder::new()
                                     .flags(MessageFlags::EPHEMERAL)
-                                    .content("Chrysanthemum **armed**.".to_owned())
+                                    .content(content)
                                     .build(),
                             ),
                         },
@@ -217,10 +588,47 @@ pub(crate) async fn handle_command(
                     .await
                     .unwrap();
             }
-            DISARM_COMMAND => {
-                state
-                    .armed
-                    .store(false, std::sync::atomic::Ordering::Relaxed);
+            RELOAD_COMMAND => {
+                let guild_option = cmd.options.iter().find_map(|option| {
+                    match (option.name.as_str(), &option.value) {
+                        ("guild", CommandOptionValue::String(value)) => Some(value),
+                        _ => None,
+                    }
+                });
+                let operator = is_operator(&state, interaction.author_id());
+                let result = match guild_option {
+                    Some(value) => match parse_guild_option(value) {
+                        Some(reload_guild_id) => {
+                            reload_by_command(&state, guild_id, Some(reload_guild_id), operator)
+                                .await
+                        }
+                        None => Err((None, eyre::eyre!("`{}` isn't a guild ID", value.trim()))),
+                    },
+                    None => reload_by_command(&state, guild_id, None, operator).await,
+                };
+                let embed = match result {
+                    Ok(()) => EmbedBuilder::new()
+                        .title("Reload successful")
+                        .color(0x32_a8_52)
+                        .build(),
+                    Err((failed_guild_id, report)) => {
+                        let report = report.to_string();
+                        let mut embed = EmbedBuilder::new().title("Reload failure");
+                        if let Some(failed_guild_id) = failed_guild_id {
+                            embed = embed.field(
+                                EmbedFieldBuilder::new("Guild", failed_guild_id.to_string())
+                                    .inline(),
+                            );
+                        }
+                        embed
+                            .field(
+                                EmbedFieldBuilder::new("Reason", format!("```{}```", report))
+                                    .build(),
+                            )
+                            .build()
+                    }
+                };
+
                 interaction_http
                     .create_response(
                         interaction.id,
@@ -230,7 +638,7 @@ pub(crate) async fn handle_command(
                             data: Some(
                                 InteractionResponseDataBuilder::new()
                                     .flags(MessageFlags::EPHEMERAL)
-                                    .content("Chrysanthemum **disarmed**.".to_owned())
+                                    .embeds(vec![embed])
                                     .build(),
                             ),
                         },
@@ -238,25 +646,198 @@ pub(crate) async fn handle_command(
                     .await
                     .unwrap();
             }
-            RELOAD_COMMAND => {
-                let result = crate::reload_guild_configs(&state).await;
-                let embed = match result {
-                    Ok(()) => EmbedBuilder::new()
-                        .title("Reload successful")
-                        .color(0x32_a8_52)
-                        .build(),
-                    Err((_, report)) => {
-                        let report = report.to_string();
-                        EmbedBuilder::new()
-                            .title("Reload failure")
-                            .field(
-                                EmbedFieldBuilder::new("Reason", format!("```{}```", report))
+            SCOPE_COMMAND => {
+                let mut channel_id = interaction.channel_id;
+                let mut user_id = None;
+                for option in &cmd.options {
+                    match (option.name.as_str(), &option.value) {
+                        ("channel", CommandOptionValue::Channel(id)) => channel_id = Some(*id),
+                        ("user", CommandOptionValue::User(id)) => user_id = Some(*id),
+                        _ => {}
+                    }
+                }
+
+                let channel_id = match channel_id {
+                    Some(id) => id,
+                    None => return Ok(()),
+                };
+
+                let roles = user_id
+                    .and_then(|id| cmd.resolved.as_ref()?.members.get(&id))
+                    .map(|m| m.roles.clone())
+                    .unwrap_or_default();
+
+                let guild_cfgs = state.guild_cfgs.read().await;
+                let mut builder = EmbedBuilder::new().title("Scope preview").field(
+                    EmbedFieldBuilder::new(
+                        "Checked",
+                        match user_id {
+                            Some(user_id) => {
+                                format!("{} as {}", channel_id.mention(), user_id.mention())
+                            }
+                            None => format!("{} (no roles)", channel_id.mention()),
+                        },
+                    )
+                    .build(),
+                );
+
+                if let Some(guild_config) = guild_cfgs.get(&guild_id) {
+                    let preview = crate::scope::preview_scopes(
+                        guild_config,
+                        channel_id,
+                        crate::channel_category(&state.cache, channel_id),
+                        user_id,
+                        &roles,
+                    );
+
+                    builder = builder
+                        .field(EmbedFieldBuilder::new(
+                            "Message filters",
+                            format_scope_entries(&preview.messages),
+                        ))
+                        .field(EmbedFieldBuilder::new(
+                            "Reaction filters",
+                            format_scope_entries(&preview.reactions),
+                        ))
+                        .field(EmbedFieldBuilder::new(
+                            "Spam",
+                            format_scope_entries(
+                                preview
+                                    .spam
+                                    .as_ref()
+                                    .map(std::slice::from_ref)
+                                    .unwrap_or_default(),
+                            ),
+                        ));
+                }
+
+                interaction_http
+                    .create_response(
+                        interaction.id,
+                        &interaction.token,
+                        &InteractionResponse {
+                            kind: InteractionResponseType::ChannelMessageWithSource,
+                            data: Some(
+                                InteractionResponseDataBuilder::new()
+                                    .flags(MessageFlags::EPHEMERAL)
+                                    .embeds(vec![builder.build()])
                                     .build(),
-                            )
-                            .build()
+                            ),
+                        },
+                    )
+                    .await
+                    .unwrap();
+            }
+            SPAMINFO_COMMAND => {
+                let user_id = match cmd.options.iter().find(|o| o.name == "user") {
+                    Some(option) => match option.value {
+                        CommandOptionValue::User(id) => id,
+                        _ => return Ok(()),
+                    },
+                    None => return Ok(()),
+                };
+
+                let guild_cfgs = state.guild_cfgs.read().await;
+                let mut builder = EmbedBuilder::new()
+                    .title("Spam tracking")
+                    .field(EmbedFieldBuilder::new("User", user_id.mention().to_string()).build());
+
+                match guild_cfgs
+                    .get(&guild_id)
+                    .and_then(|c| Some((c, c.spam.as_ref()?)))
+                {
+                    Some((guild_config, spam_config)) => {
+                        let now = (chrono::Utc::now().timestamp_millis() as u64) * 1000;
+                        let summary = crate::filter::spam_summary(
+                            &state.spam_history,
+                            user_id,
+                            spam_config.interval,
+                            now,
+                        )
+                        .await;
+
+                        builder = builder.field(EmbedFieldBuilder::new(
+                            format!(
+                                "Last {} seconds ({} messages)",
+                                spam_config.interval, summary.messages
+                            ),
+                            format_spam_counts(&summary, spam_config),
+                        ));
+
+                        if let Some(channel_id) = interaction.channel_id {
+                            let roles = cmd
+                                .resolved
+                                .as_ref()
+                                .and_then(|r| r.members.get(&user_id))
+                                .map(|m| m.roles.clone())
+                                .unwrap_or_default();
+                            let preview = crate::scope::preview_scopes(
+                                guild_config,
+                                channel_id,
+                                crate::channel_category(&state.cache, channel_id),
+                                Some(user_id),
+                                &roles,
+                            );
+
+                            builder = builder.field(EmbedFieldBuilder::new(
+                                "In this channel",
+                                match preview.spam.and_then(|s| s.skip_reason) {
+                                    None => "✅ Spam thresholds apply".to_owned(),
+                                    Some(reason) => format!("❌ Not checked: {}", reason),
+                                },
+                            ));
+                        }
                     }
+                    None => {
+                        builder = builder.description("Spam filtering isn't configured.");
+                    }
+                }
+
+                interaction_http
+                    .create_response(
+                        interaction.id,
+                        &interaction.token,
+                        &InteractionResponse {
+                            kind: InteractionResponseType::ChannelMessageWithSource,
+                            data: Some(
+                                InteractionResponseDataBuilder::new()
+                                    .flags(MessageFlags::EPHEMERAL)
+                                    .embeds(vec![builder.build()])
+                                    .build(),
+                            ),
+                        },
+                    )
+                    .await
+                    .unwrap();
+            }
+            SELFTEST_COMMAND => {
+                let guild_cfgs = state.guild_cfgs.read().await;
+                let filters = guild_cfgs
+                    .get(&guild_id)
+                    .and_then(|c| c.messages.as_deref())
+                    .unwrap_or(&[]);
+                let results = crate::selftest::run_self_test(filters);
+
+                let description = if results.is_empty() {
+                    "No message filter rules are configured.".to_owned()
+                } else {
+                    results
+                        .iter()
+                        .map(|r| match &r.outcome {
+                            Ok(()) => format!("✅ `{}`", r.rule_type),
+                            Err(reason) => format!("❌ `{}`: {}", r.rule_type, reason),
+                        })
+                        .collect::<Vec<_>>()
+                        .join("\n")
                 };
 
+                let passed = results.iter().all(|r| r.outcome.is_ok());
+                let embed = EmbedBuilder::new()
+                    .title("Self-test")
+                    .color(if passed { 0x32_a8_52 } else { 0xd9_34_34 })
+                    .description(description)
+                    .build();
+
                 interaction_http
                     .create_response(
                         interaction.id,
@@ -285,3 +866,226 @@ pub(crate) async fn handle_command(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+    use twilight_model::id::Id;
+
+    use super::{may_reload, parse_guild_option, PrefixCommand};
+    use crate::arm::ArmState;
+    use crate::config::PrefixCommands;
+
+    fn prefix_commands() -> PrefixCommands {
+        PrefixCommands {
+            prefix: "!chrys".to_owned(),
+            users: vec![Id::new(1)],
+            roles: vec![Id::new(10)],
+        }
+    }
+
+    #[test]
+    fn prefix_command_parses_subcommands() {
+        let config = prefix_commands();
+
+        for (content, expected) in [
+            ("!chrys arm", PrefixCommand::SetArmState(ArmState::Armed)),
+            (
+                "!chrys disarm",
+                PrefixCommand::SetArmState(ArmState::Disarmed),
+            ),
+            (
+                "!chrys  observe",
+                PrefixCommand::SetArmState(ArmState::Observe),
+            ),
+            ("!chrys reload now", PrefixCommand::Reload),
+        ] {
+            assert_eq!(
+                super::parse_prefix_command(&config, Id::new(1), &[], content),
+                Some(expected),
+                "{}",
+                content
+            );
+        }
+    }
+
+    #[test]
+    fn prefix_command_ignores_other_messages() {
+        let config = prefix_commands();
+
+        for content in [
+            "chrys arm",
+            "!chrysarm",
+            "!chrys",
+            "!chrys launch",
+            "hi !chrys arm",
+        ] {
+            assert_eq!(
+                super::parse_prefix_command(&config, Id::new(1), &[], content),
+                None,
+                "{}",
+                content
+            );
+        }
+    }
+
+    #[test]
+    fn prefix_command_requires_permission() {
+        let config = prefix_commands();
+
+        assert_eq!(
+            super::parse_prefix_command(&config, Id::new(2), &[Id::new(11)], "!chrys arm"),
+            None
+        );
+        assert_eq!(
+            super::parse_prefix_command(
+                &config,
+                Id::new(2),
+                &[Id::new(11), Id::new(10)],
+                "!chrys arm"
+            ),
+            Some(PrefixCommand::SetArmState(ArmState::Armed))
+        );
+    }
+
+    #[test]
+    fn guild_option_parses_ids() {
+        assert_eq!(parse_guild_option(" 4 "), Some(Id::new(4)));
+        assert_eq!(parse_guild_option("0"), None);
+        assert_eq!(parse_guild_option("my guild"), None);
+    }
+
+    #[test]
+    fn only_operators_reload_other_guilds() {
+        assert!(may_reload(false, Id::new(4), Id::new(4)));
+        assert!(!may_reload(false, Id::new(4), Id::new(5)));
+        assert!(may_reload(true, Id::new(4), Id::new(5)));
+    }
+
+    #[test]
+    fn spam_counts_show_thresholds() {
+        let summary = crate::filter::SpamSummary {
+            messages: 3,
+            emoji: 4,
+            duplicates: 2,
+            ..Default::default()
+        };
+        let config = crate::config::SpamFilter {
+            emoji: Some(5),
+            duplicates: Some(2),
+            interval: 30,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            super::format_spam_counts(&summary, &config),
+            "Emoji: 4 / 5\nLinks: 0 (no limit)\nAttachments: 0 (no limit)\nSpoilers: 0 (no limit)\nMentions: 0 (no limit)\nNewlines: 0 (no limit)\nCharacters: 0 (no limit)\nDuplicates: 2 / 2"
+        );
+    }
+
+    #[test]
+    fn test_embed_shows_filter_description() {
+        let filter: crate::config::MessageFilter = serde_yaml::from_str(
+            r#"
+            name: Scams
+            description: Catches fake Nitro giveaways.
+            rules:
+              - type: words
+                words: [nitro]
+            "#,
+        )
+        .expect("couldn't deserialize MessageFilter");
+
+        let embed = super::test_embed("free nitro", std::slice::from_ref(&filter));
+        let fields: Vec<_> = embed
+            .fields
+            .iter()
+            .map(|f| (f.name.as_str(), f.value.as_str()))
+            .collect();
+        assert_eq!(
+            fields,
+            vec![
+                ("Input", "```free nitro```"),
+                ("Status", "❌ Failed: contains word `nitro`"),
+                ("Filter", "Scams"),
+                ("About this filter", "Catches fake Nitro giveaways."),
+            ]
+        );
+
+        let embed = super::test_embed("hello", std::slice::from_ref(&filter));
+        assert!(embed.fields.iter().all(|f| f.name != "About this filter"));
+    }
+
+    #[test]
+    fn test_embed_notes_skipped_rules() {
+        let filter: crate::config::MessageFilter = serde_yaml::from_str(
+            r#"
+            name: Mass pings
+            rules:
+              - type: mention_count
+                max_total: 5
+            "#,
+        )
+        .expect("couldn't deserialize MessageFilter");
+
+        let embed = super::test_embed("<@1> <@2>", std::slice::from_ref(&filter));
+        let skipped = embed.fields.iter().find(|f| f.name == "Not tested");
+        assert_eq!(
+            skipped.map(|f| f.value.as_str()),
+            Some("These rules need a real message, so they were skipped: `mention_count`")
+        );
+    }
+
+    #[test]
+    fn test_embed_respects_match_mode() {
+        let filter: crate::config::MessageFilter = serde_yaml::from_str(
+            r#"
+            name: Nitro scams
+            match_mode: all
+            rules:
+              - type: words
+                words: [nitro]
+              - type: words
+                words: [free]
+            "#,
+        )
+        .expect("couldn't deserialize MessageFilter");
+
+        let status = |message| {
+            let embed = super::test_embed(message, std::slice::from_ref(&filter));
+            embed
+                .fields
+                .iter()
+                .find(|f| f.name == "Status")
+                .map(|f| f.value.clone())
+        };
+        assert_eq!(
+            status("free nitro").as_deref(),
+            Some("❌ Failed: contains word `nitro` and contains word `free`")
+        );
+        assert_eq!(status("nitro").as_deref(), Some("✅ Passed all filters"));
+    }
+
+    #[test]
+    fn test_embed_notes_untestable_all_filters() {
+        let filter: crate::config::MessageFilter = serde_yaml::from_str(
+            r#"
+            name: Mass ping scams
+            match_mode: all
+            rules:
+              - type: words
+                words: [nitro]
+              - type: mention_count
+                max_total: 5
+            "#,
+        )
+        .expect("couldn't deserialize MessageFilter");
+
+        let embed = super::test_embed("nitro", std::slice::from_ref(&filter));
+        let skipped = embed.fields.iter().find(|f| f.name == "Not tested");
+        assert_eq!(
+            skipped.map(|f| f.value.as_str()),
+            Some("These rules need a real message, so they were skipped: `mention_count`\nThese filters need all of their rules to match, so they can't fail this test: Mass ping scams")
+        );
+    }
+}
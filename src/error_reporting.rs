@@ -0,0 +1,72 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use sentry::{ClientOptions, Hub};
+use twilight_model::id::{marker::GuildMarker, Id};
+
+use crate::config::SentryConfig;
+
+/// How Sentry is set up for a deployment.
+pub(crate) fn client_options(config: &SentryConfig) -> ClientOptions {
+    ClientOptions {
+        release: sentry::release_name!(),
+        environment: config.environment.clone().map(Into::into),
+        traces_sample_rate: config.sample_rate.unwrap_or(0.01),
+        debug: cfg!(debug_assertions),
+        ..Default::default()
+    }
+}
+
+/// A hub for work done on behalf of `guild_id`, so anything reported from it
+/// says which guild it came from, along with the guild's own `sentry_tags`.
+pub(crate) fn guild_hub(guild_id: Id<GuildMarker>, tags: &HashMap<String, String>) -> Arc<Hub> {
+    let hub = Arc::new(Hub::new_from_top(Hub::current()));
+    hub.configure_scope(|scope| {
+        scope.set_tag("guild_id", guild_id);
+        for (key, value) in tags {
+            scope.set_tag(key, value);
+        }
+    });
+    hub
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::{BTreeMap, HashMap};
+
+    use pretty_assertions::assert_eq;
+    use twilight_model::id::Id;
+
+    use crate::config::SentryConfig;
+
+    #[test]
+    fn guild_work_is_tagged() {
+        let config = SentryConfig {
+            url: String::new(),
+            sample_rate: None,
+            environment: Some("staging".to_owned()),
+        };
+        let tags: HashMap<String, String> =
+            std::iter::once(("tenant".to_owned(), "acme".to_owned())).collect();
+
+        let events = sentry::test::with_captured_events_options(
+            || {
+                let hub = super::guild_hub(Id::new(4), &tags);
+                hub.capture_message("filter blew up", sentry::Level::Error);
+                sentry::capture_message("not for any guild", sentry::Level::Error);
+            },
+            super::client_options(&config),
+        );
+
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].environment.as_deref(), Some("staging"));
+        assert_eq!(
+            events[0].tags,
+            BTreeMap::from([
+                ("guild_id".to_owned(), "4".to_owned()),
+                ("tenant".to_owned(), "acme".to_owned()),
+            ])
+        );
+        assert!(events[1].tags.is_empty());
+    }
+}
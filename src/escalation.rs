@@ -0,0 +1,139 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use twilight_model::id::{
+    marker::{GuildMarker, UserMarker},
+    Id,
+};
+
+use crate::config::EscalationTier;
+
+/// How long a violation counts for, if `escalation_decay_seconds` isn't set.
+pub(crate) const DEFAULT_DECAY_SECONDS: u64 = 24 * 60 * 60;
+
+pub(crate) type ViolationKey = (Id<GuildMarker>, Id<UserMarker>);
+
+/// Remembers when each user broke each guild's filters, for `escalate`
+/// actions. Violations are kept in memory until they decay, so this grows
+/// with the number of users who break a guild's filters within its decay
+/// window.
+#[derive(Debug, Default)]
+pub(crate) struct Violations {
+    times: Mutex<HashMap<ViolationKey, Vec<u64>>>,
+}
+
+impl Violations {
+    /// Starts from violations saved before a restart.
+    pub(crate) fn from_saved(times: HashMap<ViolationKey, Vec<u64>>) -> Self {
+        Self {
+            times: Mutex::new(times),
+        }
+    }
+
+    /// A copy of every violation on record, for saving.
+    pub(crate) fn snapshot(&self) -> HashMap<ViolationKey, Vec<u64>> {
+        self.times.lock().unwrap().clone()
+    }
+
+    /// How many violations `user_id` has in `guild_id` that are less than
+    /// `decay` seconds old as of `now`, in seconds.
+    pub(crate) fn count(
+        &self,
+        guild_id: Id<GuildMarker>,
+        user_id: Id<UserMarker>,
+        decay: u64,
+        now: u64,
+    ) -> usize {
+        self.times
+            .lock()
+            .unwrap()
+            .get(&(guild_id, user_id))
+            .map_or(0, |times| {
+                times
+                    .iter()
+                    .filter(|t| now.saturating_sub(**t) < decay)
+                    .count()
+            })
+    }
+
+    /// Records a violation by `user_id` in `guild_id` at `now`, in seconds.
+    pub(crate) fn record(&self, guild_id: Id<GuildMarker>, user_id: Id<UserMarker>, now: u64) {
+        self.times
+            .lock()
+            .unwrap()
+            .entry((guild_id, user_id))
+            .or_default()
+            .push(now);
+    }
+
+    /// Forgets violations older than their guild's decay window, and every
+    /// violation in guilds `decay` returns `None` for. Returns how many users
+    /// still have violations on record.
+    pub(crate) fn prune(&self, decay: impl Fn(Id<GuildMarker>) -> Option<u64>, now: u64) -> usize {
+        let mut times = self.times.lock().unwrap();
+        times.retain(|(guild_id, _), times| match decay(*guild_id) {
+            Some(decay) => {
+                times.retain(|t| now.saturating_sub(*t) < decay);
+                !times.is_empty()
+            }
+            None => false,
+        });
+        times.len()
+    }
+}
+
+/// The tier to run for a user's `violations`th violation: the one with the
+/// highest threshold it reaches. Tiers are sorted by ascending threshold.
+pub(crate) fn tier_for(tiers: &[EscalationTier], violations: usize) -> Option<&EscalationTier> {
+    tiers.iter().rev().find(|tier| tier.threshold <= violations)
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+    use twilight_model::id::Id;
+
+    use crate::config::{EscalationTier, MessageFilterAction};
+
+    use super::Violations;
+
+    #[test]
+    fn violations_decay() {
+        let violations = Violations::default();
+        let user_id = crate::model::test::USER_ID;
+        violations.record(Id::new(1), user_id, 100);
+        violations.record(Id::new(1), user_id, 150);
+        violations.record(Id::new(2), user_id, 150);
+
+        assert_eq!(violations.count(Id::new(1), user_id, 60, 155), 2);
+        assert_eq!(violations.count(Id::new(1), user_id, 60, 200), 1);
+        assert_eq!(violations.count(Id::new(3), user_id, 60, 200), 0);
+
+        assert_eq!(
+            violations.prune(|guild_id| (guild_id == Id::new(1)).then_some(60), 200),
+            1
+        );
+        assert_eq!(violations.count(Id::new(1), user_id, 1_000, 200), 1);
+        assert_eq!(violations.count(Id::new(2), user_id, 1_000, 200), 0);
+    }
+
+    #[test]
+    fn highest_reached_tier_runs() {
+        let tiers = vec![
+            EscalationTier {
+                threshold: 1,
+                actions: vec![MessageFilterAction::Delete],
+            },
+            EscalationTier {
+                threshold: 3,
+                actions: vec![],
+            },
+        ];
+
+        assert!(super::tier_for(&tiers, 0).is_none());
+        assert_eq!(super::tier_for(&tiers, 1).unwrap().threshold, 1);
+        assert_eq!(super::tier_for(&tiers, 2).unwrap().threshold, 1);
+        assert_eq!(super::tier_for(&tiers, 3).unwrap().threshold, 3);
+        assert_eq!(super::tier_for(&tiers, 7).unwrap().threshold, 3);
+    }
+}
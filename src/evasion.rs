@@ -0,0 +1,179 @@
+use twilight_model::id::{
+    marker::{ChannelMarker, MessageMarker, UserMarker},
+    Id,
+};
+
+use crate::{config::DeleteEvasion, message_cache::MessageCache};
+
+/// The most of a deleted message's content to include in a report.
+const MAX_REPORTED_CONTENT: usize = 1000;
+
+/// A message deleted soon after it was posted.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct QuickDelete {
+    pub(crate) author_id: Id<UserMarker>,
+    pub(crate) channel_id: Id<ChannelMarker>,
+    /// How long the message was up, in milliseconds.
+    pub(crate) lifetime_ms: u64,
+    pub(crate) content: String,
+}
+
+impl QuickDelete {
+    pub(crate) fn describe(&self) -> String {
+        let mut content: String = self.content.chars().take(MAX_REPORTED_CONTENT).collect();
+        if content.len() < self.content.len() {
+            content.push('…');
+        }
+
+        format!(
+            "A message from <@{}> in <#{}> was deleted {:.1}s after it was posted. It said:\n>>> {}",
+            self.author_id,
+            self.channel_id,
+            self.lifetime_ms as f64 / 1000.0,
+            content
+        )
+    }
+}
+
+/// Forgets a message deleted at `now_ms`, in milliseconds, returning what the
+/// message cache remembered of it if it was deleted within the window.
+pub(crate) fn check_delete(
+    config: &DeleteEvasion,
+    cache: &MessageCache,
+    message_id: Id<MessageMarker>,
+    channel_id: Id<ChannelMarker>,
+    now_ms: u64,
+) -> Option<QuickDelete> {
+    let (author_id, content) = cache.remove(message_id, now_ms / 1000)?;
+    let lifetime_ms = now_ms.saturating_sub(crate::snowflake::created_at_ms(message_id));
+    if lifetime_ms >= config.window_seconds.saturating_mul(1000) {
+        return None;
+    }
+
+    Some(QuickDelete {
+        author_id: author_id?,
+        channel_id,
+        lifetime_ms,
+        content,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+
+    use super::QuickDelete;
+    use crate::config::{DeleteEvasion, GuildConfig, MessageCacheConfig};
+    use crate::message_cache::MessageCache;
+    use crate::snowflake::{id_created_at, DISCORD_EPOCH_MS};
+
+    const POSTED_AT_MS: u64 = DISCORD_EPOCH_MS + 1_000_000;
+
+    fn cache() -> MessageCache {
+        MessageCache::new(&MessageCacheConfig {
+            capacity: 10,
+            ttl: 60,
+        })
+    }
+
+    #[test]
+    fn fast_post_then_delete_is_reported() {
+        let cache = cache();
+        let config = DeleteEvasion { window_seconds: 5 };
+        let message_id = id_created_at(POSTED_AT_MS);
+        cache.replace(
+            message_id,
+            Some(crate::model::test::USER_ID),
+            "@everyone free nitro",
+            POSTED_AT_MS / 1000,
+        );
+
+        let quick_delete = super::check_delete(
+            &config,
+            &cache,
+            message_id,
+            crate::model::test::CHANNEL_ID,
+            POSTED_AT_MS + 1_500,
+        );
+        assert_eq!(
+            quick_delete,
+            Some(QuickDelete {
+                author_id: crate::model::test::USER_ID,
+                channel_id: crate::model::test::CHANNEL_ID,
+                lifetime_ms: 1_500,
+                content: "@everyone free nitro".to_owned(),
+            })
+        );
+        assert_eq!(
+            quick_delete.unwrap().describe(),
+            "A message from <@3> in <#2> was deleted 1.5s after it was posted. It said:\n>>> @everyone free nitro"
+        );
+    }
+
+    #[test]
+    fn slow_or_unknown_deletes_are_ignored() {
+        let cache = cache();
+        let config = DeleteEvasion { window_seconds: 5 };
+        let message_id = id_created_at(POSTED_AT_MS);
+        cache.replace(
+            message_id,
+            Some(crate::model::test::USER_ID),
+            "hello",
+            POSTED_AT_MS / 1000,
+        );
+
+        let check = |now_ms| {
+            super::check_delete(
+                &config,
+                &cache,
+                message_id,
+                crate::model::test::CHANNEL_ID,
+                now_ms,
+            )
+        };
+        assert_eq!(check(POSTED_AT_MS + 30_000), None);
+        // The first check forgot the message, so there's nothing to report.
+        assert_eq!(check(POSTED_AT_MS + 1_000), None);
+    }
+
+    #[test]
+    fn reported_content_has_secrets_redacted() {
+        let guild_config: GuildConfig = serde_yaml::from_str(
+            r#"
+            messages:
+              - name: Secrets
+                rules:
+                  - type: secret_scan
+                    presets: [discord_token]
+                actions:
+                  - action: delete
+            "#,
+        )
+        .unwrap();
+        let cache = cache();
+        let config = DeleteEvasion { window_seconds: 5 };
+        let message_id = id_created_at(POSTED_AT_MS);
+        // As the message handler caches it.
+        cache.replace(
+            message_id,
+            Some(crate::model::test::USER_ID),
+            &crate::secret::redact(
+                &guild_config.secret_presets(),
+                "my token is MTA5ODc2NTQzMjEwOTg3NjU0Mw.GaBcDe.abcdefghijklmnopqrstuvwxyz0123456789AB",
+            ),
+            POSTED_AT_MS / 1000,
+        );
+
+        let quick_delete = super::check_delete(
+            &config,
+            &cache,
+            message_id,
+            crate::model::test::CHANNEL_ID,
+            POSTED_AT_MS + 1_000,
+        );
+        assert_eq!(
+            quick_delete.unwrap().describe(),
+            "A message from <@3> in <#2> was deleted 1.0s after it was posted. It said:\n>>> my token is [redacted secret]"
+        );
+    }
+}
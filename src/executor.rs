@@ -0,0 +1,339 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use rand::Rng;
+use tokio::sync::{watch, Notify};
+use twilight_model::id::{marker::MessageMarker, Id};
+
+use crate::config::ActionDelay;
+
+/// The most delayed actions that may be waiting at once. During a raid we'd
+/// rather act immediately than let sleeping tasks pile up without bound.
+const MAX_PENDING_ACTIONS: usize = 1000;
+
+impl ActionDelay {
+    /// Picks how long to wait before the next action, including jitter.
+    pub(crate) fn sample(&self) -> Duration {
+        let jitter = if self.jitter_ms > 0 {
+            rand::thread_rng().gen_range(0..=self.jitter_ms)
+        } else {
+            0
+        };
+
+        Duration::from_millis(self.delay_ms + jitter)
+    }
+}
+
+/// Lets every waiting action taken for one message be cancelled at once.
+#[derive(Debug)]
+struct Cancellation {
+    cancelled: watch::Sender<bool>,
+    /// How many actions are still waiting on this.
+    waiting: usize,
+}
+
+/// Runs actions after a delay, and can flush any that are still waiting so
+/// nothing is lost on shutdown.
+#[derive(Debug)]
+pub(crate) struct DelayedActions {
+    pending: Arc<AtomicUsize>,
+    idle: Arc<Notify>,
+    flushing: watch::Sender<bool>,
+    cancellations: Arc<Mutex<HashMap<Id<MessageMarker>, Cancellation>>>,
+    max_pending: usize,
+}
+
+impl Default for DelayedActions {
+    fn default() -> Self {
+        Self::with_max_pending(MAX_PENDING_ACTIONS)
+    }
+}
+
+impl DelayedActions {
+    fn with_max_pending(max_pending: usize) -> Self {
+        let (flushing, _) = watch::channel(false);
+
+        Self {
+            pending: Arc::new(AtomicUsize::new(0)),
+            idle: Arc::new(Notify::new()),
+            flushing,
+            cancellations: Arc::new(Mutex::new(HashMap::new())),
+            max_pending,
+        }
+    }
+
+    pub(crate) fn pending(&self) -> usize {
+        self.pending.load(Ordering::SeqCst)
+    }
+
+    /// Runs `action` once `delay` has passed. If too many actions are already
+    /// waiting, or the queue is being flushed, `action` runs straight away.
+    pub(crate) fn schedule<F>(&self, delay: Duration, action: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        // Checking and taking a place in one step keeps concurrent callers
+        // from both squeezing under the cap.
+        let reserved = self
+            .pending
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |pending| {
+                (pending < self.max_pending).then_some(pending + 1)
+            });
+
+        match reserved {
+            Ok(_) => self.spawn_reserved(delay, action),
+            Err(pending) => {
+                tracing::warn!(
+                    pending,
+                    "Too many delayed actions waiting; executing immediately"
+                );
+                self.schedule_uncapped(Duration::ZERO, action);
+            }
+        }
+    }
+
+    /// Like [`Self::schedule`], but the action is skipped if
+    /// [`Self::cancel`] is called for `message_id` before it runs.
+    pub(crate) fn schedule_for_message<F>(
+        &self,
+        message_id: Id<MessageMarker>,
+        delay: Duration,
+        action: F,
+    ) where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let cancelled = {
+            let mut cancellations = self.cancellations.lock().unwrap();
+            let cancellation = cancellations
+                .entry(message_id)
+                .or_insert_with(|| Cancellation {
+                    cancelled: watch::channel(false).0,
+                    waiting: 0,
+                });
+            cancellation.waiting += 1;
+            cancellation.cancelled.subscribe()
+        };
+
+        let cancellations = self.cancellations.clone();
+        self.schedule(delay, async move {
+            // Cancelling removes the message's entry, so only uncancelled
+            // actions still count towards it.
+            let skip = {
+                let mut cancellations = cancellations.lock().unwrap();
+                let skip = *cancelled.borrow();
+                if !skip {
+                    if let Some(cancellation) = cancellations.get_mut(&message_id) {
+                        cancellation.waiting -= 1;
+                        if cancellation.waiting == 0 {
+                            cancellations.remove(&message_id);
+                        }
+                    }
+                }
+
+                skip
+            };
+
+            if skip {
+                tracing::debug!(%message_id, "Skipping cancelled delayed action");
+            } else {
+                action.await;
+            }
+        });
+    }
+
+    /// Cancels every action still waiting for `message_id`, returning how
+    /// many there were.
+    pub(crate) fn cancel(&self, message_id: Id<MessageMarker>) -> usize {
+        match self.cancellations.lock().unwrap().remove(&message_id) {
+            Some(cancellation) => {
+                cancellation.cancelled.send_replace(true);
+                cancellation.waiting
+            }
+            None => 0,
+        }
+    }
+
+    /// Runs `action` once `delay` has passed, even if many actions are already
+    /// waiting. Used for follow-ups, like undoing a channel lock, that must not
+    /// run early. These still run early when the queue is flushed.
+    pub(crate) fn schedule_uncapped<F>(&self, delay: Duration, action: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        self.pending.fetch_add(1, Ordering::SeqCst);
+        self.spawn_reserved(delay, action);
+    }
+
+    /// Runs `action` after `delay`, for a caller that has already counted it
+    /// as pending.
+    fn spawn_reserved<F>(&self, delay: Duration, action: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let pending = self.pending.clone();
+        let idle = self.idle.clone();
+        let mut flushing = self.flushing.subscribe();
+
+        tokio::spawn(async move {
+            if !*flushing.borrow_and_update() {
+                tokio::select! {
+                    _ = tokio::time::sleep(delay) => {},
+                    _ = flushing.changed() => {},
+                }
+            }
+
+            action.await;
+
+            if pending.fetch_sub(1, Ordering::SeqCst) == 1 {
+                idle.notify_waiters();
+            }
+        });
+    }
+
+    /// Cuts every waiting action's delay short and waits for them all to run.
+    pub(crate) async fn flush(&self) {
+        self.flushing.send_replace(true);
+
+        loop {
+            let idle = self.idle.notified();
+            if self.pending() == 0 {
+                break;
+            }
+
+            idle.await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use pretty_assertions::assert_eq;
+    use twilight_model::id::Id;
+
+    use super::DelayedActions;
+    use crate::config::ActionDelay;
+
+    #[tokio::test(start_paused = true)]
+    async fn delay_applied_before_action() {
+        let queue = DelayedActions::default();
+        let executed = Arc::new(AtomicBool::new(false));
+
+        let flag = executed.clone();
+        queue.schedule(Duration::from_millis(500), async move {
+            flag.store(true, Ordering::SeqCst);
+        });
+
+        tokio::time::sleep(Duration::from_millis(499)).await;
+        assert!(!executed.load(Ordering::SeqCst));
+        assert_eq!(queue.pending(), 1);
+
+        tokio::time::sleep(Duration::from_millis(2)).await;
+        assert!(executed.load(Ordering::SeqCst));
+        assert_eq!(queue.pending(), 0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn flush_runs_waiting_actions() {
+        let queue = DelayedActions::default();
+        let executed = Arc::new(AtomicUsize::new(0));
+
+        for _ in 0..3 {
+            let count = executed.clone();
+            queue.schedule(Duration::from_secs(60), async move {
+                count.fetch_add(1, Ordering::SeqCst);
+            });
+        }
+
+        let start = tokio::time::Instant::now();
+        queue.flush().await;
+
+        assert_eq!(executed.load(Ordering::SeqCst), 3);
+        assert!(start.elapsed() < Duration::from_secs(60));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn saturated_queue_executes_immediately() {
+        let queue = DelayedActions::with_max_pending(1);
+        let executed = Arc::new(AtomicUsize::new(0));
+
+        for _ in 0..2 {
+            let count = executed.clone();
+            queue.schedule(Duration::from_secs(60), async move {
+                count.fetch_add(1, Ordering::SeqCst);
+            });
+        }
+
+        tokio::task::yield_now().await;
+        assert_eq!(executed.load(Ordering::SeqCst), 1);
+        assert_eq!(queue.pending(), 1);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn concurrent_schedules_respect_the_cap() {
+        let queue = Arc::new(DelayedActions::with_max_pending(10));
+
+        let schedulers: Vec<_> = (0..8)
+            .map(|_| {
+                let queue = queue.clone();
+                let runtime = tokio::runtime::Handle::current();
+                std::thread::spawn(move || {
+                    let _runtime = runtime.enter();
+                    for _ in 0..100 {
+                        queue.schedule(Duration::from_secs(3_600), async {});
+                    }
+                })
+            })
+            .collect();
+        for scheduler in schedulers {
+            scheduler.join().unwrap();
+        }
+
+        // Everything over the cap ran straight away, so only the cap's worth
+        // are left waiting.
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(queue.pending(), 10);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn cancelled_actions_are_skipped() {
+        let queue = DelayedActions::default();
+        let executed = Arc::new(AtomicUsize::new(0));
+        let approved = crate::model::test::MESSAGE_ID;
+
+        for message_id in [approved, approved, Id::new(99)] {
+            let count = executed.clone();
+            queue.schedule_for_message(message_id, Duration::from_secs(2), async move {
+                count.fetch_add(1, Ordering::SeqCst);
+            });
+        }
+
+        assert_eq!(queue.cancel(approved), 2);
+        assert_eq!(queue.cancel(approved), 0);
+
+        tokio::time::sleep(Duration::from_secs(3)).await;
+        assert_eq!(executed.load(Ordering::SeqCst), 1);
+        assert_eq!(queue.pending(), 0);
+        assert_eq!(queue.cancel(Id::new(99)), 0);
+    }
+
+    #[test]
+    fn sample_stays_within_jitter() {
+        let delay = ActionDelay {
+            delay_ms: 100,
+            jitter_ms: 50,
+        };
+
+        for _ in 0..100 {
+            let sampled = delay.sample();
+            assert!(sampled >= Duration::from_millis(100));
+            assert!(sampled <= Duration::from_millis(150));
+        }
+    }
+}
@@ -0,0 +1,284 @@
+use std::sync::Mutex;
+use std::time::Duration;
+
+use influxdb::WriteQuery;
+use tokio::sync::{mpsc, watch};
+use tokio::task::JoinHandle;
+
+/// How many points to write at once if `batch_size` isn't set.
+pub(crate) const DEFAULT_BATCH_SIZE: usize = 100;
+/// How often to flush points if `flush_interval_secs` isn't set.
+pub(crate) const DEFAULT_FLUSH_INTERVAL: Duration = Duration::from_secs(10);
+/// How many points may be waiting for the reporter before new ones are
+/// dropped.
+const QUEUE_CAPACITY: usize = 10_000;
+/// How many times to try writing a batch before dropping it.
+const MAX_ATTEMPTS: u32 = 3;
+/// How long to wait before retrying a failed write. Doubles with each retry.
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+
+/// Sends points to Influx from a background task, in batches, so handling
+/// events never waits on Influx.
+#[derive(Debug)]
+pub(crate) struct InfluxReporter {
+    points: mpsc::Sender<WriteQuery>,
+    stop: watch::Sender<bool>,
+    task: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl InfluxReporter {
+    /// Starts a reporter that writes a batch once it has `batch_size` points,
+    /// or once `flush_interval` has passed with any points waiting.
+    pub(crate) fn spawn(
+        client: influxdb::Client,
+        batch_size: usize,
+        flush_interval: Duration,
+    ) -> Self {
+        let (points, receiver) = mpsc::channel(QUEUE_CAPACITY);
+        let (stop, stopped) = watch::channel(false);
+        let task = tokio::spawn(run(
+            client,
+            receiver,
+            stopped,
+            batch_size.max(1),
+            flush_interval,
+        ));
+
+        Self {
+            points,
+            stop,
+            task: Mutex::new(Some(task)),
+        }
+    }
+
+    /// Queues `point` to be written. If the queue is full, because Influx has
+    /// been unreachable for a while, the point is dropped.
+    pub(crate) fn report(&self, point: WriteQuery) {
+        if let Err(err) = self.points.try_send(point) {
+            tracing::warn!(%err, "Dropping Influx point");
+        }
+    }
+
+    /// Writes any points still waiting and stops the reporter.
+    pub(crate) async fn shutdown(&self) {
+        let _ = self.stop.send(true);
+        let task = self.task.lock().unwrap().take();
+        if let Some(task) = task {
+            let _ = task.await;
+        }
+    }
+}
+
+async fn run(
+    client: influxdb::Client,
+    mut receiver: mpsc::Receiver<WriteQuery>,
+    mut stopped: watch::Receiver<bool>,
+    batch_size: usize,
+    flush_interval: Duration,
+) {
+    let mut batch = Vec::with_capacity(batch_size);
+    let mut interval = tokio::time::interval(flush_interval);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+    loop {
+        tokio::select! {
+            point = receiver.recv() => match point {
+                Some(point) => {
+                    batch.push(point);
+                    if batch.len() >= batch_size {
+                        write_batch(&client, std::mem::take(&mut batch)).await;
+                    }
+                }
+                None => break,
+            },
+            _ = interval.tick() => {
+                if !batch.is_empty() {
+                    write_batch(&client, std::mem::take(&mut batch)).await;
+                }
+            }
+            _ = stopped.changed() => break,
+        }
+    }
+
+    while let Ok(point) = receiver.try_recv() {
+        batch.push(point);
+    }
+    if !batch.is_empty() {
+        write_batch(&client, batch).await;
+    }
+}
+
+/// Writes `batch`, retrying with backoff, and drops it if every attempt fails.
+async fn write_batch(client: &influxdb::Client, batch: Vec<WriteQuery>) {
+    let mut backoff = INITIAL_BACKOFF;
+    for attempt in 1..=MAX_ATTEMPTS {
+        match client.query(&batch).await {
+            Ok(_) => return,
+            Err(err) if attempt < MAX_ATTEMPTS => {
+                tracing::debug!(
+                    ?err,
+                    attempt,
+                    ?backoff,
+                    "Error writing Influx points; retrying"
+                );
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+            }
+            Err(err) => {
+                tracing::warn!(
+                    ?err,
+                    points = batch.len(),
+                    "Unable to write Influx points; dropping them"
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use chrono::{TimeZone, Utc};
+    use influxdb::InfluxDbWriteable;
+    use pretty_assertions::assert_eq;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    use super::InfluxReporter;
+
+    #[derive(InfluxDbWriteable)]
+    struct Point {
+        time: chrono::DateTime<Utc>,
+        value: u64,
+        #[influxdb(tag)]
+        guild: String,
+    }
+
+    fn point(value: u64) -> influxdb::WriteQuery {
+        Point {
+            time: Utc.timestamp_millis_opt(1_700_000_000_000).unwrap(),
+            value,
+            guild: "4".to_owned(),
+        }
+        .into_query("event_report")
+    }
+
+    /// Answers each request with `statuses` in turn, recording the requests.
+    async fn serve(statuses: &'static [&'static str]) -> (String, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+
+        let recorded = requests.clone();
+        tokio::spawn(async move {
+            for status in statuses {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut request = Vec::new();
+                let mut buffer = [0; 4096];
+                // Read until the whole body has arrived.
+                loop {
+                    let read = socket.read(&mut buffer).await.unwrap();
+                    request.extend_from_slice(&buffer[..read]);
+                    let text = String::from_utf8_lossy(&request);
+                    if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                        let length = head
+                            .lines()
+                            .find_map(|l| {
+                                let (name, value) = l.split_once(':')?;
+                                if name.eq_ignore_ascii_case("content-length") {
+                                    value.trim().parse::<usize>().ok()
+                                } else {
+                                    None
+                                }
+                            })
+                            .unwrap_or(0);
+                        if body.len() >= length {
+                            break;
+                        }
+                    }
+                    if read == 0 {
+                        break;
+                    }
+                }
+                recorded
+                    .lock()
+                    .unwrap()
+                    .push(String::from_utf8_lossy(&request).into_owned());
+
+                // Influx explains failures in a JSON body.
+                let body = if status.starts_with('5') {
+                    r#"{"error":"unavailable"}"#
+                } else {
+                    ""
+                };
+                let response = format!(
+                    "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                );
+                let _ = socket.write_all(response.as_bytes()).await;
+            }
+        });
+
+        (format!("http://{}", addr), requests)
+    }
+
+    async fn wait_for_requests(requests: &Mutex<Vec<String>>, count: usize) {
+        for _ in 0..200 {
+            if requests.lock().unwrap().len() >= count {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("only got {} requests", requests.lock().unwrap().len());
+    }
+
+    #[tokio::test]
+    async fn writes_full_batches_in_one_request() {
+        let (url, requests) = serve(&["204 No Content"]).await;
+        let reporter = InfluxReporter::spawn(
+            influxdb::Client::new(url, "chrysanthemum"),
+            2,
+            Duration::from_secs(60),
+        );
+
+        reporter.report(point(1));
+        reporter.report(point(2));
+        wait_for_requests(&requests, 1).await;
+
+        let request = requests.lock().unwrap()[0].clone();
+        let (head, body) = request.split_once("\r\n\r\n").unwrap();
+        assert!(head.starts_with("POST /write?"), "{}", head);
+        assert!(head.contains("db=chrysanthemum"), "{}", head);
+        assert_eq!(
+            body.lines().collect::<Vec<_>>(),
+            [
+                "event_report,guild=4 value=1i 1700000000000000000",
+                "event_report,guild=4 value=2i 1700000000000000000",
+            ]
+        );
+
+        reporter.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn retries_failed_writes_and_flushes_on_shutdown() {
+        let (url, requests) = serve(&["500 Internal Server Error", "204 No Content"]).await;
+        let reporter = InfluxReporter::spawn(
+            influxdb::Client::new(url, "chrysanthemum"),
+            10,
+            Duration::from_secs(60),
+        );
+
+        reporter.report(point(1));
+        reporter.shutdown().await;
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0], requests[1]);
+        assert!(requests[1].ends_with("event_report,guild=4 value=1i 1700000000000000000"));
+    }
+}
@@ -0,0 +1,295 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use eyre::{Context, Result};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+use twilight_model::id::{
+    marker::{GuildMarker, UserMarker},
+    Id,
+};
+
+use crate::config::InfractionsConfig;
+
+type InfractionKey = (Id<GuildMarker>, Id<UserMarker>);
+
+/// One user's infractions in one guild, as stored on disk.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+struct StoredInfractions {
+    guild_id: Id<GuildMarker>,
+    user_id: Id<UserMarker>,
+    /// When each infraction happened, in seconds.
+    times: Vec<u64>,
+}
+
+/// How long to wait after an infraction is recorded before writing the file,
+/// so a burst of infractions is written once.
+const FLUSH_DELAY: Duration = Duration::from_secs(2);
+
+/// Counts each user's infractions per guild. Counts are updated in memory and
+/// written to a JSON file in the background shortly after they change, so
+/// they survive restarts without anyone waiting on the write.
+#[derive(Debug)]
+pub(crate) struct InfractionStore {
+    /// How long an infraction counts for, in seconds.
+    retention: u64,
+    file: Arc<StoreFile>,
+    /// Tells the background writer the infractions have changed.
+    changes: mpsc::UnboundedSender<()>,
+}
+
+/// The infractions and the file they're written to, shared with the
+/// background writer.
+#[derive(Debug)]
+struct StoreFile {
+    path: PathBuf,
+    infractions: Mutex<HashMap<InfractionKey, Vec<u64>>>,
+    /// Held while writing the file, so writes land in the order their
+    /// snapshots were taken.
+    writing: tokio::sync::Mutex<()>,
+}
+
+impl InfractionStore {
+    /// Opens the store at the configured path, dropping infractions that are
+    /// older than the retention window as of `now`, in seconds, and starts
+    /// writing changes in the background.
+    pub(crate) fn open(config: &InfractionsConfig, now: u64) -> Result<Self> {
+        let stored: Vec<StoredInfractions> = match std::fs::read_to_string(&config.path) {
+            Ok(contents) => serde_json::from_str(&contents).wrap_err_with(|| {
+                format!("Unable to parse infractions in {}", config.path.display())
+            })?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(err) => {
+                return Err(err).wrap_err_with(|| {
+                    format!("Unable to read infractions from {}", config.path.display())
+                })
+            }
+        };
+
+        let file = Arc::new(StoreFile {
+            path: config.path.clone(),
+            infractions: Mutex::new(
+                stored
+                    .into_iter()
+                    .map(|s| ((s.guild_id, s.user_id), s.times))
+                    .collect(),
+            ),
+            writing: tokio::sync::Mutex::new(()),
+        });
+        let (changes, receiver) = mpsc::unbounded_channel();
+        tokio::spawn(write_changes(file.clone(), receiver));
+
+        let store = Self {
+            retention: config.retention_days.saturating_mul(24 * 60 * 60),
+            file,
+            changes,
+        };
+        // Expired infractions are dropped from the file on the next write.
+        store.forget_expired(now);
+
+        Ok(store)
+    }
+
+    /// How many infractions `user_id` has on record in `guild_id` as of
+    /// `now`, in seconds.
+    pub(crate) fn count(
+        &self,
+        guild_id: Id<GuildMarker>,
+        user_id: Id<UserMarker>,
+        now: u64,
+    ) -> usize {
+        self.file
+            .infractions
+            .lock()
+            .unwrap()
+            .get(&(guild_id, user_id))
+            .map_or(0, |times| {
+                times.iter().filter(|t| self.is_live(**t, now)).count()
+            })
+    }
+
+    /// Records an infraction by `user_id` in `guild_id` at `now`, in seconds,
+    /// returning how many they now have. The file is written shortly after.
+    pub(crate) fn record(
+        &self,
+        guild_id: Id<GuildMarker>,
+        user_id: Id<UserMarker>,
+        now: u64,
+    ) -> usize {
+        let count = {
+            let mut infractions = self.file.infractions.lock().unwrap();
+            let times = infractions.entry((guild_id, user_id)).or_default();
+            times.retain(|t| self.is_live(*t, now));
+            times.push(now);
+            times.len()
+        };
+
+        let _ = self.changes.send(());
+        count
+    }
+
+    /// Forgets infractions older than the retention window, returning how
+    /// many users still have some on record.
+    pub(crate) fn prune(&self, now: u64) -> usize {
+        let (changed, users) = self.forget_expired(now);
+        if changed {
+            let _ = self.changes.send(());
+        }
+
+        users
+    }
+
+    /// Writes the infractions now, without waiting for the background writer.
+    pub(crate) async fn flush(&self) -> Result<()> {
+        self.file.save().await
+    }
+
+    /// Forgets infractions older than the retention window in memory,
+    /// returning whether any were forgotten and how many users still have
+    /// some on record.
+    fn forget_expired(&self, now: u64) -> (bool, usize) {
+        let mut infractions = self.file.infractions.lock().unwrap();
+        let before: usize = infractions.values().map(Vec::len).sum();
+        for times in infractions.values_mut() {
+            times.retain(|t| self.is_live(*t, now));
+        }
+        infractions.retain(|_, times| !times.is_empty());
+
+        let after: usize = infractions.values().map(Vec::len).sum();
+        (after != before, infractions.len())
+    }
+
+    fn is_live(&self, time: u64, now: u64) -> bool {
+        now.saturating_sub(time) < self.retention
+    }
+}
+
+impl StoreFile {
+    /// Writes the infractions to a temporary file and moves it over the
+    /// store, so a crash mid-write can't leave a truncated file behind.
+    async fn save(&self) -> Result<()> {
+        let _writing = self.writing.lock().await;
+        let stored: Vec<StoredInfractions> = self
+            .infractions
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|((guild_id, user_id), times)| StoredInfractions {
+                guild_id: *guild_id,
+                user_id: *user_id,
+                times: times.clone(),
+            })
+            .collect();
+
+        let temp_path = self.path.with_extension("tmp");
+        tokio::fs::write(&temp_path, serde_json::to_string(&stored)?)
+            .await
+            .wrap_err_with(|| format!("Unable to write infractions to {}", temp_path.display()))?;
+        tokio::fs::rename(&temp_path, &self.path)
+            .await
+            .wrap_err_with(|| format!("Unable to replace {}", self.path.display()))?;
+
+        Ok(())
+    }
+}
+
+/// Writes the file `FLUSH_DELAY` after each change, folding in any changes
+/// made in the meantime, until the store is dropped.
+async fn write_changes(file: Arc<StoreFile>, mut changes: mpsc::UnboundedReceiver<()>) {
+    while changes.recv().await.is_some() {
+        tokio::time::sleep(FLUSH_DELAY).await;
+        while changes.try_recv().is_ok() {}
+
+        if let Err(err) = file.save().await {
+            tracing::error!(?err, "Unable to save infractions");
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    use pretty_assertions::assert_eq;
+    use twilight_model::id::Id;
+
+    use super::{InfractionStore, FLUSH_DELAY};
+    use crate::config::InfractionsConfig;
+
+    const DAY: u64 = 24 * 60 * 60;
+
+    fn config(name: &str) -> InfractionsConfig {
+        let path: PathBuf = std::env::temp_dir().join(format!(
+            "chrysanthemum-infractions-{}-{}.json",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+
+        InfractionsConfig {
+            path,
+            retention_days: 7,
+        }
+    }
+
+    #[tokio::test]
+    async fn infractions_survive_restart() {
+        let config = config("restart");
+        let guild_id = crate::model::test::GUILD_ID;
+        let user_id = crate::model::test::USER_ID;
+
+        let store = InfractionStore::open(&config, 0).unwrap();
+        assert_eq!(store.record(guild_id, user_id, 10), 1);
+        assert_eq!(store.record(guild_id, user_id, 20), 2);
+        store.flush().await.unwrap();
+        drop(store);
+
+        let store = InfractionStore::open(&config, 30).unwrap();
+        assert_eq!(store.count(guild_id, user_id, 30), 2);
+        assert_eq!(store.count(guild_id, Id::new(99), 30), 0);
+    }
+
+    #[tokio::test]
+    async fn old_infractions_are_pruned() {
+        let config = config("prune");
+        let guild_id = crate::model::test::GUILD_ID;
+        let user_id = crate::model::test::USER_ID;
+
+        let store = InfractionStore::open(&config, 0).unwrap();
+        store.record(guild_id, user_id, 0);
+        store.record(guild_id, user_id, 5 * DAY);
+        assert_eq!(store.count(guild_id, user_id, 8 * DAY), 1);
+        store.flush().await.unwrap();
+        drop(store);
+
+        let store = InfractionStore::open(&config, 13 * DAY).unwrap();
+        assert_eq!(store.count(guild_id, user_id, 13 * DAY), 0);
+        assert_eq!(store.prune(13 * DAY), 0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn records_are_written_in_the_background() {
+        let config = config("background");
+        let guild_id = crate::model::test::GUILD_ID;
+        let user_id = crate::model::test::USER_ID;
+
+        let store = InfractionStore::open(&config, 0).unwrap();
+        store.record(guild_id, user_id, 10);
+        store.record(guild_id, user_id, 20);
+        assert!(!config.path.exists());
+
+        let mut waited = Duration::ZERO;
+        while !config.path.exists() {
+            assert!(waited < FLUSH_DELAY * 10, "infractions were never written");
+            tokio::time::sleep(FLUSH_DELAY).await;
+            waited += FLUSH_DELAY;
+        }
+        drop(store);
+
+        let store = InfractionStore::open(&config, 30).unwrap();
+        assert_eq!(store.count(guild_id, user_id, 30), 2);
+    }
+}
@@ -0,0 +1,168 @@
+use twilight_model::gateway::Intents;
+
+use crate::config::{Config, GatewayIntent, GuildConfig};
+
+/// Every intent some feature needs, requested unless the configuration says
+/// otherwise.
+pub(crate) const DEFAULT_INTENTS: Intents = Intents::GUILDS
+    .union(Intents::GUILD_MESSAGES)
+    .union(Intents::GUILD_MEMBERS)
+    .union(Intents::GUILD_MESSAGE_REACTIONS)
+    .union(Intents::MESSAGE_CONTENT);
+
+impl GatewayIntent {
+    fn intents(self) -> Intents {
+        match self {
+            GatewayIntent::Guilds => Intents::GUILDS,
+            GatewayIntent::GuildMembers => Intents::GUILD_MEMBERS,
+            GatewayIntent::GuildMessages => Intents::GUILD_MESSAGES,
+            GatewayIntent::GuildMessageReactions => Intents::GUILD_MESSAGE_REACTIONS,
+            GatewayIntent::MessageContent => Intents::MESSAGE_CONTENT,
+        }
+    }
+}
+
+/// The intents to request from the gateway.
+pub(crate) fn configured_intents(cfg: &Config) -> Intents {
+    match &cfg.intents {
+        Some(intents) => intents
+            .iter()
+            .fold(Intents::empty(), |all, intent| all | intent.intents()),
+        None => DEFAULT_INTENTS,
+    }
+}
+
+/// The intents features can need, with their names.
+const INTENT_NAMES: [(Intents, &str); 5] = [
+    (Intents::GUILDS, "GUILDS"),
+    (Intents::GUILD_MEMBERS, "GUILD_MEMBERS"),
+    (Intents::GUILD_MESSAGES, "GUILD_MESSAGES"),
+    (Intents::GUILD_MESSAGE_REACTIONS, "GUILD_MESSAGE_REACTIONS"),
+    (Intents::MESSAGE_CONTENT, "MESSAGE_CONTENT"),
+];
+
+/// Each feature `guild_config` enables, along with the intents it needs to
+/// receive its events.
+pub(crate) fn required_intents(guild_config: &GuildConfig) -> Vec<(&'static str, Intents)> {
+    let messages = Intents::GUILD_MESSAGES | Intents::MESSAGE_CONTENT;
+    let features = [
+        ("message filters", guild_config.messages.is_some(), messages),
+        ("spam filters", guild_config.spam.is_some(), messages),
+        (
+            "prefix commands",
+            guild_config.prefix_commands.is_some(),
+            messages,
+        ),
+        (
+            "reaction filters",
+            guild_config.reactions.is_some(),
+            Intents::GUILD_MESSAGE_REACTIONS,
+        ),
+        (
+            "approval reactions",
+            guild_config.approval.is_some(),
+            Intents::GUILD_MESSAGE_REACTIONS,
+        ),
+        (
+            "username filters",
+            guild_config.usernames.is_some(),
+            Intents::GUILD_MEMBERS,
+        ),
+        (
+            "quick delete reports",
+            guild_config.delete_evasion.is_some(),
+            Intents::GUILD_MESSAGES,
+        ),
+        (
+            "stage topic filters",
+            guild_config.channel_status_filter.is_some(),
+            Intents::GUILDS,
+        ),
+        (
+            "category-scoped filters",
+            guild_config.uses_category_scoping(),
+            Intents::GUILDS,
+        ),
+    ];
+
+    features
+        .iter()
+        .filter(|(_, enabled, _)| *enabled)
+        .map(|(feature, _, intents)| (*feature, *intents))
+        .collect()
+}
+
+/// Checks that `intents` cover every feature `guild_config` enables, naming
+/// each missing intent and the feature that needs it.
+pub(crate) fn check_intents(
+    intents: Intents,
+    guild_config: &GuildConfig,
+) -> Result<(), Vec<String>> {
+    let mut errors = Vec::new();
+    for (feature, required) in required_intents(guild_config) {
+        let missing = required - intents;
+        for (intent, name) in &INTENT_NAMES {
+            if missing.contains(*intent) {
+                errors.push(format!("{} require the {} intent", feature, name));
+            }
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+    use twilight_model::gateway::Intents;
+
+    use crate::config::{GuildConfig, ReactionFilter, UsernameFilter};
+
+    #[test]
+    fn features_map_to_intents() {
+        let guild_config = GuildConfig {
+            messages: Some(vec![]),
+            reactions: Some(vec![]),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            super::required_intents(&guild_config),
+            vec![
+                (
+                    "message filters",
+                    Intents::GUILD_MESSAGES | Intents::MESSAGE_CONTENT
+                ),
+                ("reaction filters", Intents::GUILD_MESSAGE_REACTIONS),
+            ]
+        );
+        assert_eq!(super::required_intents(&GuildConfig::default()), vec![]);
+    }
+
+    #[test]
+    fn missing_intents_are_named() {
+        let guild_config = GuildConfig {
+            reactions: Some(Vec::<ReactionFilter>::new()),
+            usernames: Some(UsernameFilter {
+                rules: vec![],
+                actions: vec![],
+            }),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            super::check_intents(super::DEFAULT_INTENTS, &guild_config),
+            Ok(())
+        );
+        assert_eq!(
+            super::check_intents(Intents::GUILDS | Intents::GUILD_MEMBERS, &guild_config),
+            Err(vec![
+                "reaction filters require the GUILD_MESSAGE_REACTIONS intent".to_owned()
+            ])
+        );
+    }
+}
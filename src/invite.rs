@@ -0,0 +1,226 @@
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+use futures::future;
+use tokio::sync::RwLock;
+use twilight_http::{error::ErrorType, Client as HttpClient};
+use twilight_model::id::{marker::GuildMarker, Id};
+
+use crate::model::ResolvedInvite;
+
+/// How long to remember where an invite goes, if `invite_cache_ttl` isn't set.
+pub(crate) const DEFAULT_CACHE_TTL: Duration = Duration::from_secs(60 * 60);
+/// How many invites to remember before starting over.
+const MAX_CACHED_INVITES: usize = 10_000;
+
+#[derive(Debug)]
+struct CachedInvite {
+    resolved_at: Instant,
+    guild_id: Option<Id<GuildMarker>>,
+}
+
+/// Looks up which guilds invites lead to, remembering each answer for a while
+/// so raids full of the same invite don't run into rate limits.
+#[derive(Debug)]
+pub(crate) struct InviteResolver {
+    ttl: Duration,
+    cache: RwLock<HashMap<String, CachedInvite>>,
+}
+
+impl InviteResolver {
+    pub(crate) fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            cache: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Resolves each distinct invite code in `codes` concurrently. Every code
+    /// is resolved, however many there are, so padding a message with junk
+    /// invites can't hide a real one. Invites that can't be resolved are left
+    /// out, so they can be matched by their code instead.
+    pub(crate) async fn resolve_all<'a>(
+        &self,
+        http: &HttpClient,
+        codes: impl Iterator<Item = &'a str>,
+    ) -> Vec<ResolvedInvite> {
+        let mut seen = HashSet::new();
+        let codes = codes.filter(|code| seen.insert(*code));
+        future::join_all(codes.map(|code| async move {
+            self.resolve(http, code)
+                .await
+                .map(|guild_id| ResolvedInvite {
+                    code: code.to_owned(),
+                    guild_id,
+                })
+        }))
+        .await
+        .into_iter()
+        .flatten()
+        .collect()
+    }
+
+    /// Forgets resolutions older than the cache lifetime. Returns how many
+    /// invites are still cached.
+    pub(crate) async fn prune(&self) -> usize {
+        let mut cache = self.cache.write().await;
+        cache.retain(|_, cached| cached.resolved_at.elapsed() < self.ttl);
+        cache.len()
+    }
+
+    async fn resolve(&self, http: &HttpClient, code: &str) -> Option<Id<GuildMarker>> {
+        if let Some(guild_id) = self.cached(code).await {
+            return guild_id;
+        }
+
+        // Only definite answers are remembered; anything else is tried again
+        // next time.
+        let guild_id = match http.invite(code).await {
+            Ok(response) => match response.model().await {
+                Ok(invite) => invite.guild.map(|guild| guild.id),
+                Err(err) => {
+                    tracing::debug!(%code, ?err, "Unable to deserialize invite");
+                    return None;
+                }
+            },
+            Err(err) if matches!(err.kind(), ErrorType::Response { status, .. } if status.get() == 404) => {
+                None
+            }
+            Err(err) => {
+                tracing::debug!(%code, ?err, "Unable to resolve invite");
+                return None;
+            }
+        };
+
+        self.remember(code, guild_id).await;
+        guild_id
+    }
+
+    /// The guild `code` was last resolved to, if that was recent enough.
+    /// `Some(None)` means it recently turned out not to exist.
+    async fn cached(&self, code: &str) -> Option<Option<Id<GuildMarker>>> {
+        self.cache
+            .read()
+            .await
+            .get(code)
+            .filter(|cached| cached.resolved_at.elapsed() < self.ttl)
+            .map(|cached| cached.guild_id)
+    }
+
+    async fn remember(&self, code: &str, guild_id: Option<Id<GuildMarker>>) {
+        let mut cache = self.cache.write().await;
+        if cache.len() >= MAX_CACHED_INVITES {
+            cache.clear();
+        }
+        cache.insert(
+            code.to_owned(),
+            CachedInvite {
+                resolved_at: Instant::now(),
+                guild_id,
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use pretty_assertions::assert_eq;
+    use twilight_model::id::Id;
+
+    use super::{CachedInvite, HttpClient, InviteResolver};
+    use crate::action::test::serve_discord;
+    use crate::model::ResolvedInvite;
+
+    #[tokio::test]
+    async fn remembers_resolutions_until_they_expire() {
+        let resolver = InviteResolver::new(Duration::from_secs(60));
+        resolver.remember("raid", Some(Id::new(4))).await;
+        resolver.remember("broken", None).await;
+        resolver.cache.write().await.insert(
+            "old".to_owned(),
+            CachedInvite {
+                resolved_at: Instant::now() - Duration::from_secs(120),
+                guild_id: Some(Id::new(5)),
+            },
+        );
+
+        assert_eq!(resolver.cached("raid").await, Some(Some(Id::new(4))));
+        assert_eq!(resolver.cached("broken").await, Some(None));
+        assert_eq!(resolver.cached("old").await, None);
+        assert_eq!(resolver.cached("unknown").await, None);
+
+        assert_eq!(resolver.prune().await, 2);
+    }
+
+    #[tokio::test]
+    async fn resolves_invites_in_order() {
+        let resolver = InviteResolver::new(Duration::from_secs(60));
+        resolver.remember("raid", Some(Id::new(4))).await;
+        resolver.remember("broken", None).await;
+        resolver.remember("friends", Some(Id::new(5))).await;
+
+        // Everything is cached, so nothing is requested.
+        let http = HttpClient::new("token".to_owned());
+        assert_eq!(
+            resolver
+                .resolve_all(&http, ["raid", "broken", "friends"].iter().copied())
+                .await,
+            vec![
+                ResolvedInvite {
+                    code: "raid".to_owned(),
+                    guild_id: Id::new(4),
+                },
+                ResolvedInvite {
+                    code: "friends".to_owned(),
+                    guild_id: Id::new(5),
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn resolves_every_distinct_invite() {
+        let (addr, requests) = serve_discord(&[
+            (
+                "GET /api/v10/invites/raid",
+                "200 OK",
+                r#"{"code":"raid","guild":{"id":"4","name":"Raiders","features":[],"icon":null,"splash":null,"verification_level":0},"type":0}"#,
+            ),
+            (
+                "GET /api/v10/invites/gone",
+                "404 Not Found",
+                r#"{"code":10006,"message":"Unknown Invite"}"#,
+            ),
+            (
+                "GET /api/v10/invites/flaky",
+                "500 Internal Server Error",
+                "{}",
+            ),
+        ])
+        .await;
+        let http = HttpClient::builder()
+            .proxy(addr, true)
+            .ratelimiter(None)
+            .build();
+
+        let resolver = InviteResolver::new(Duration::from_secs(60));
+        for padding in ["a", "b", "c", "d", "e"] {
+            resolver.remember(padding, None).await;
+        }
+        let codes = ["a", "b", "c", "d", "e", "a", "gone", "flaky", "raid"];
+        assert_eq!(
+            resolver.resolve_all(&http, codes.iter().copied()).await,
+            vec![ResolvedInvite {
+                code: "raid".to_owned(),
+                guild_id: Id::new(4),
+            }]
+        );
+        assert_eq!(requests.lock().unwrap().len(), 3);
+
+        // The missing invite is remembered, but the server error isn't.
+        assert_eq!(resolver.cached("gone").await, Some(None));
+        assert_eq!(resolver.cached("flaky").await, None);
+    }
+}
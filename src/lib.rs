@@ -0,0 +1,2517 @@
+//! Chrysanthemum is a Discord bot that filters messages, reactions and
+//! usernames. The crate also exposes its message filters, so rule sets can be
+//! tested offline or used by other tools without running the bot.
+//!
+//! [`config::MessageFilter`] is the same filter guild configurations contain,
+//! and can be built in code as well as deserialized:
+//!
+//! ```
+//! use chrysanthemum::config::{MessageFilter, MessageFilterRule};
+//! use regex::Regex;
+//!
+//! let filter = MessageFilter {
+//!     name: "scams".to_owned(),
+//!     rules: vec![MessageFilterRule::Substring {
+//!         substrings: Regex::new("(?i)free nitro").unwrap().into(),
+//!         whole_message_only: false,
+//!         case_insensitive: true,
+//!     }
+//!     .into()],
+//!     ..Default::default()
+//! };
+//!
+//! let matched = filter.filter_text("Get FREE NITRO here").unwrap_err();
+//! assert_eq!(matched.filter_name, "scams");
+//! assert_eq!(matched.rule_description, "contains substring `FREE NITRO`");
+//! assert!(filter.filter_text("hello there").is_ok());
+//! ```
+//!
+//! To check rules that look at more than text, such as attachments or
+//! mentions, build a [`model::MessageInfo`] and use
+//! [`config::MessageFilter::filter_message`]. Whole guild configurations can
+//! be loaded and validated with [`config::parse_guild_config`].
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use action::{MessageAction, ReactionAction};
+use arm::{ActionDisposition, ArmState, GuildArmStates};
+use chrono::{DateTime, Utc};
+use filter::{PostHistory, RaidHistory, ReactionHistory, SpamHistory};
+use influxdb::{InfluxDbWriteable, WriteQuery};
+use reqwest::header::HeaderValue;
+use sentry::SentryFutureExt;
+use tokio::sync::RwLock;
+
+use futures::stream::StreamExt;
+
+use tracing::Instrument;
+
+use twilight_cache_inmemory::{InMemoryCache, ResourceType};
+use twilight_gateway::Event;
+use twilight_gateway::Shard;
+use twilight_http::Client as HttpClient;
+use twilight_http_ratelimiting::request::Path;
+use twilight_mention::Mention;
+use twilight_model::application::interaction::InteractionData;
+use twilight_model::channel::message::{sticker::MessageSticker, MessageReference};
+use twilight_model::channel::{Message, StageInstance};
+use twilight_model::gateway::payload::incoming::{MessageDelete, MessageUpdate};
+use twilight_model::gateway::GatewayReaction;
+use twilight_model::id::marker::{
+    ApplicationMarker, ChannelMarker, MessageMarker, StickerMarker, UserMarker,
+};
+use twilight_model::id::{marker::GuildMarker, Id};
+use twilight_model::user::User;
+
+use color_eyre::eyre::{Result, WrapErr};
+
+use config::*;
+use model::{MessageInfo, ReactionInfo, StickerMetadata};
+use order::{ChannelOrder, Turn};
+use twilight_util::builder::embed::{EmbedBuilder, EmbedFieldBuilder};
+
+mod action;
+mod approval;
+mod arm;
+mod caps;
+mod case;
+mod channel_status;
+mod coalesce;
+mod command;
+pub mod config;
+mod confusable;
+mod error_reporting;
+mod escalation;
+mod evasion;
+mod executor;
+pub mod filter;
+mod influx;
+mod infractions;
+mod intents;
+mod invite;
+mod message;
+mod message_cache;
+mod metrics;
+pub mod model;
+mod order;
+mod preview;
+mod prometheus;
+mod reaction;
+mod references;
+mod registration;
+mod remote_config;
+mod schema;
+mod scope;
+mod script;
+mod secret;
+mod selftest;
+mod sniff;
+mod snowflake;
+mod state_store;
+mod text;
+mod throttle;
+mod username;
+mod watch;
+
+const DEFAULT_RELOAD_INTERVAL: u64 = 5 * 60;
+const DEFAULT_PRUNE_INTERVAL: u64 = 60;
+const DEFAULT_COMMAND_UPDATE_COOLDOWN: u64 = 60;
+/// How long fetched sticker metadata is reused before it's fetched again.
+const STICKER_CACHE_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// Fetched sticker metadata, and when it was fetched.
+type StickerCache = HashMap<Id<StickerMarker>, (Instant, StickerMetadata)>;
+
+#[derive(Clone, Debug)]
+struct State {
+    cfg: Arc<Config>,
+    guild_cfgs: Arc<RwLock<HashMap<Id<GuildMarker>, GuildConfig>>>,
+    http: Arc<HttpClient>,
+    application_id: Arc<RwLock<Option<Id<ApplicationMarker>>>>,
+    cache: Arc<InMemoryCache>,
+    spam_history: Arc<RwLock<SpamHistory>>,
+    raid_history: Arc<RwLock<RaidHistory>>,
+    reaction_history: Arc<RwLock<ReactionHistory>>,
+    post_history: Arc<RwLock<PostHistory>>,
+    sticker_cache: Arc<RwLock<StickerCache>>,
+    link_previews: Arc<preview::LinkPreviewFetcher>,
+    invites: Arc<invite::InviteResolver>,
+    channel_order: Arc<ChannelOrder>,
+    action_caps: Arc<caps::ActionCaps>,
+    command_registrations: Arc<registration::CommandRegistrations>,
+    stage_topics: Arc<channel_status::StageTopics>,
+    checked_names: Arc<username::CheckedNames>,
+    remote_configs: Arc<Option<remote_config::RemoteConfigs>>,
+    message_cache: Arc<Option<message_cache::MessageCache>>,
+    throttle: Arc<Option<throttle::Throttle>>,
+    infractions: Arc<Option<infractions::InfractionStore>>,
+    violations: Arc<escalation::Violations>,
+    state_store: Arc<Option<state_store::StateStore>>,
+    influx: Arc<Option<influx::InfluxReporter>>,
+    influx_sampler: Arc<metrics::ReportSampler>,
+    counters: Arc<metrics::Counters>,
+    delayed_actions: Arc<executor::DelayedActions>,
+    coalescer: Arc<coalesce::Coalescer>,
+    armed: Arc<GuildArmStates>,
+    /// Held while reloading guild configurations, so reloads run one at a
+    /// time and an older one can't overwrite a newer one.
+    reloading: Arc<tokio::sync::Mutex<()>>,
+}
+
+#[derive(Debug, InfluxDbWriteable)]
+struct EventTimingReport {
+    time: DateTime<Utc>,
+    guild: String,
+    channel: String,
+    time_taken: f64,
+    #[influxdb(tag)]
+    action_kind: &'static str,
+    #[influxdb(tag)]
+    development: bool,
+}
+
+#[derive(Debug, InfluxDbWriteable)]
+struct MessageFilterReport {
+    time: DateTime<Utc>,
+    guild: String,
+    channel: String,
+}
+
+#[derive(Debug, InfluxDbWriteable)]
+struct MessageBudgetExceededReport {
+    time: DateTime<Utc>,
+    guild: String,
+    channel: String,
+}
+
+#[derive(Debug, InfluxDbWriteable)]
+struct TrackingSizeReport {
+    time: DateTime<Utc>,
+    spam_users: u64,
+    raid_guilds: u64,
+    reactions: u64,
+    posts: u64,
+    stickers: u64,
+    link_previews: u64,
+    invites: u64,
+    cached_messages: u64,
+    infraction_users: u64,
+    violation_users: u64,
+}
+
+#[derive(Debug, InfluxDbWriteable)]
+struct ThrottleReport {
+    time: DateTime<Utc>,
+    guild: String,
+    delay_ms: f64,
+}
+
+#[derive(Debug, InfluxDbWriteable)]
+struct ReactionFilterReport {
+    time: DateTime<Utc>,
+    guild: String,
+    channel: String,
+}
+
+#[cfg(debug_assertions)]
+fn init_tracing() {
+    tracing_subscriber::fmt()
+        .pretty()
+        .with_thread_ids(true)
+        .with_thread_names(true)
+        .with_env_filter(
+            tracing_subscriber::EnvFilter::from_default_env()
+                .add_directive("chrysanthemum=trace".parse().unwrap()),
+        )
+        .init();
+}
+
+#[cfg(not(debug_assertions))]
+fn init_tracing() {
+    use tracing_subscriber::prelude::*;
+
+    tracing_subscriber::registry()
+        .with(tracing_subscriber::fmt::layer().json())
+        .with(sentry_tracing::layer())
+        .init();
+}
+
+/// Queues a point for Influx, sampled according to the guild's
+/// `report_every_n` override or the global one.
+fn send_influx_point(
+    state: &State,
+    guild_id: Id<GuildMarker>,
+    guild_metrics: Option<&GuildMetricsConfig>,
+    point: WriteQuery,
+) {
+    if let Some(influx) = state.influx.as_ref() {
+        if let Some(influx_cfg) = state.cfg.influx.as_ref() {
+            let report_every_n = guild_metrics
+                .map(|m| m.report_every_n)
+                .unwrap_or(influx_cfg.report_every_n);
+
+            if state.influx_sampler.should_report(guild_id, report_every_n) {
+                influx.report(point);
+            }
+        }
+    }
+}
+
+/// Holds an action back while its rate limit bucket is nearly empty, reporting
+/// how long it was held.
+async fn throttle_action(
+    state: &State,
+    guild_id: Id<GuildMarker>,
+    guild_metrics: Option<&GuildMetricsConfig>,
+    path: Option<Path>,
+) {
+    let (throttle, path) = match (state.throttle.as_ref(), path) {
+        (Some(throttle), Some(path)) => (throttle, path),
+        _ => return,
+    };
+
+    if let Some(delay) = throttle.wait(state.http.ratelimiter(), &path).await {
+        let report = ThrottleReport {
+            time: Utc::now(),
+            guild: guild_id.to_string(),
+            delay_ms: delay.as_secs_f64() * 1000.0,
+        };
+
+        send_influx_point(
+            state,
+            guild_id,
+            guild_metrics,
+            report.into_query("action_throttled"),
+        );
+    }
+}
+
+/// Checks every guild configuration without connecting to Discord, printing
+/// each invalid one's errors. Looks in each `--config-dir` given, or in the
+/// directories the bot configuration at `chrysanthemum.cfg.yml` names.
+fn validate_configs(mut args: impl Iterator<Item = String>) -> Result<()> {
+    let mut config_dirs = Vec::new();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--config-dir" => match args.next() {
+                Some(dir) => config_dirs.push(PathBuf::from(dir)),
+                None => return Err(eyre::eyre!("--config-dir needs a directory")),
+            },
+            // Older versions took the directory on its own.
+            _ if config_dirs.is_empty() && !arg.starts_with("--") => {
+                config_dirs.push(PathBuf::from(arg))
+            }
+            _ => return Err(eyre::eyre!("Unknown argument `{}`", arg)),
+        }
+    }
+
+    let cfg: Option<Config> = if config_dirs.is_empty() {
+        let cfg_yaml = std::fs::read_to_string("chrysanthemum.cfg.yml")
+            .wrap_err("Unable to read chrysanthemum.cfg.yml; pass --config-dir instead")?;
+        let cfg: Config = serde_yaml::from_str(&cfg_yaml)?;
+        config::set_regex_limits(cfg.regex_limits);
+        config::set_guild_env_vars(cfg.guild_env_vars.clone());
+        Some(cfg)
+    } else {
+        None
+    };
+    let (roots, duplicates) = match &cfg {
+        Some(cfg) => (cfg.guild_config_roots(), cfg.duplicate_guild_configs),
+        None => (
+            config_dirs.iter().map(PathBuf::as_path).collect(),
+            DuplicateGuildConfigs::default(),
+        ),
+    };
+    if roots.is_empty() {
+        return Err(eyre::eyre!(
+            "Remote guild configurations can't be validated; pass --config-dir instead"
+        ));
+    }
+
+    let results = config::check_guild_configs(&roots, duplicates)?;
+    let mut invalid = 0;
+    for (path, result) in &results {
+        if let Err(errors) = result {
+            invalid += 1;
+            println!("{}:", path.display());
+            for error in errors {
+                println!("  - {}", error);
+            }
+        }
+    }
+
+    if invalid > 0 {
+        return Err(eyre::eyre!(
+            "{} of {} guild configurations are invalid",
+            invalid,
+            results.len()
+        ));
+    }
+
+    println!("All {} guild configurations are valid", results.len());
+    Ok(())
+}
+
+/// Runs the bot. With `validate`, checks guild configurations and exits
+/// instead, and with `schema`, prints a JSON Schema for them.
+pub fn run() -> Result<()> {
+    color_eyre::install()?;
+    init_tracing();
+    dotenv::dotenv().ok();
+
+    if std::env::args().nth(1).as_deref() == Some("schema") {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&schema::guild_config_schema())?
+        );
+        return Ok(());
+    }
+
+    if let Some("validate" | "validate-configs") = std::env::args().nth(1).as_deref() {
+        return validate_configs(std::env::args().skip(2));
+    }
+
+    let discord_token = std::env::var("DISCORD_TOKEN")?;
+
+    let config_path = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| "chrysanthemum.cfg.yml".to_owned());
+
+    let cfg_json = std::fs::read_to_string(config_path).expect("couldn't read config file");
+    let mut cfg: Config = serde_yaml::from_str(&cfg_json).expect("Couldn't deserialize config");
+    cfg.expand_env_vars()
+        .map_err(|errors| eyre::eyre!("Couldn't expand config: {}", errors.join(" ")))?;
+    config::set_regex_limits(cfg.regex_limits);
+    config::set_guild_env_vars(cfg.guild_env_vars.clone());
+
+    let _sentry_guard = cfg.sentry.as_ref().map(|sentry_config| {
+        sentry::init((
+            sentry_config.url.clone(),
+            error_reporting::client_options(sentry_config),
+        ))
+    });
+
+    let influx_client = if let Some(influx_cfg) = &cfg.influx {
+        let mut headers = reqwest::header::HeaderMap::new();
+        headers.insert(
+            "Authorization",
+            HeaderValue::from_str(&format!("Token {}", &influx_cfg.token)).unwrap(),
+        );
+        let reqwest_client = reqwest::Client::builder()
+            .default_headers(headers)
+            .build()
+            .unwrap();
+        let influx_client = influxdb::Client::new(&influx_cfg.url, &influx_cfg.database)
+            .with_http_client(reqwest_client);
+        Some(influx_client)
+    } else {
+        None
+    };
+
+    let intents = intents::configured_intents(&cfg);
+
+    tokio::runtime::Builder::new_multi_thread().enable_all().build().unwrap().block_on(async {
+
+    let (shard, mut events) = Shard::builder(discord_token.clone(), intents).build();
+    shard.start().await?;
+
+    let http = Arc::new(HttpClient::new(discord_token));
+    let mut resource_types = ResourceType::MESSAGE
+        | ResourceType::MEMBER
+        | ResourceType::USER
+        | ResourceType::USER_CURRENT
+        | ResourceType::CHANNEL;
+    if cfg.check_guild_references {
+        resource_types |= ResourceType::ROLE;
+    }
+    let cache = InMemoryCache::builder()
+        .resource_types(resource_types)
+        .build();
+
+    let cfg = Arc::new(cfg);
+    // Anything saved that has since expired is dropped by the first prune,
+    // which runs as soon as the event loop starts.
+    let state_store = cfg.state_db_path.clone().map(state_store::StateStore::new);
+    let saved_state = match &state_store {
+        Some(store) => store.load()?,
+        None => state_store::SavedState::default(),
+    };
+    let spam_history = Arc::new(RwLock::new(saved_state.spam_history));
+    let remote_configs = match &cfg.guild_config_dir {
+        config::ConfigSource::Remote { url, authorization } => Some(
+            remote_config::RemoteConfigs::new(url, authorization.as_deref()),
+        ),
+        config::ConfigSource::Local(_) => None,
+    };
+    let initial_guild_configs = load_guild_configs(&cfg, remote_configs.as_ref(), &cfg.active_guilds)
+        .await
+        .map_err(|(_, e)| e)?;
+
+    if initial_guild_configs.is_empty() {
+        tracing::warn!(guild_config_dir = ?cfg.guild_config_dir, "No guild configurations were loaded");
+    }
+
+    let infraction_store = cfg
+        .infractions
+        .as_ref()
+        .map(|c| infractions::InfractionStore::open(c, Utc::now().timestamp() as u64))
+        .transpose()?;
+
+    let influx = cfg.influx.as_ref().zip(influx_client).map(|(influx_cfg, client)| {
+        influx::InfluxReporter::spawn(
+            client,
+            influx_cfg.batch_size.unwrap_or(influx::DEFAULT_BATCH_SIZE),
+            influx_cfg
+                .flush_interval_secs
+                .map_or(influx::DEFAULT_FLUSH_INTERVAL, Duration::from_secs),
+        )
+    });
+
+    let state = State {
+        armed: Arc::new(GuildArmStates::new(ArmState::from(cfg.armed_by_default))),
+        http,
+        spam_history,
+        raid_history: Arc::new(RwLock::new(saved_state.raid_history)),
+        reaction_history: Arc::new(RwLock::new(ReactionHistory::new())),
+        post_history: Arc::new(RwLock::new(PostHistory::new())),
+        sticker_cache: Arc::new(RwLock::new(HashMap::new())),
+        link_previews: Arc::new(preview::LinkPreviewFetcher::default()),
+        invites: Arc::new(invite::InviteResolver::new(
+            cfg.invite_cache_ttl
+                .map_or(invite::DEFAULT_CACHE_TTL, Duration::from_secs),
+        )),
+        channel_order: Arc::new(ChannelOrder::default()),
+        action_caps: Arc::new(caps::ActionCaps::default()),
+        command_registrations: Arc::new(registration::CommandRegistrations::default()),
+        stage_topics: Arc::new(channel_status::StageTopics::default()),
+        checked_names: Arc::new(username::CheckedNames::default()),
+        remote_configs: Arc::new(remote_configs),
+        message_cache: Arc::new(
+            cfg.message_cache
+                .as_ref()
+                .map(message_cache::MessageCache::new),
+        ),
+        throttle: Arc::new(cfg.throttle.as_ref().map(throttle::Throttle::new)),
+        infractions: Arc::new(infraction_store),
+        violations: Arc::new(saved_state.violations),
+        state_store: Arc::new(state_store),
+        cfg,
+        cache: Arc::new(cache),
+        application_id: Arc::new(RwLock::new(None)),
+        guild_cfgs: Arc::new(RwLock::new(initial_guild_configs)),
+        influx: Arc::new(influx),
+        influx_sampler: Arc::new(metrics::ReportSampler::default()),
+        counters: Arc::new(metrics::Counters::default()),
+        delayed_actions: Arc::new(executor::DelayedActions::default()),
+        coalescer: Arc::new(coalesce::Coalescer::default()),
+        reloading: Arc::new(tokio::sync::Mutex::new(())),
+    };
+
+    tracing::info!("About to enter main event loop; Chrysanthemum is now online.");
+
+    for (guild_id, _) in state.guild_cfgs.read().await.iter() {
+        let result = send_notification_to_guild(
+            &state,
+            *guild_id,
+            "Chrysanthemum online",
+            "Chrysanthemum is now online.",
+        )
+        .await;
+        if let Err(err) = result {
+            tracing::error!(?err, %guild_id, "Error sending up notification");
+        }
+    }
+
+    let mut interval = tokio::time::interval(Duration::from_secs(
+        state.cfg.reload_interval.unwrap_or(DEFAULT_RELOAD_INTERVAL),
+    ));
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+    let mut prune_interval = tokio::time::interval(Duration::from_secs(
+        state.cfg.prune_interval.unwrap_or(DEFAULT_PRUNE_INTERVAL),
+    ));
+    prune_interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+    let mut config_watcher = watch_config_dirs(&state.cfg);
+    let metrics_server = match state.cfg.prometheus.as_ref() {
+        Some(prometheus_cfg) => {
+            let listener = tokio::net::TcpListener::bind(&prometheus_cfg.bind_addr)
+                .await
+                .wrap_err_with(|| {
+                    format!("Unable to serve metrics on {}", prometheus_cfg.bind_addr)
+                })?;
+            tracing::info!(bind_addr = %prometheus_cfg.bind_addr, "Serving Prometheus metrics");
+
+            let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+            let server = tokio::spawn(prometheus::serve(
+                listener,
+                state.counters.clone(),
+                async {
+                    let _ = stopped.await;
+                },
+            ));
+            Some((stop, server))
+        }
+        None => None,
+    };
+    let shutdown = shutdown_signal();
+    tokio::pin!(shutdown);
+    loop {
+        tokio::select! {
+            _ = &mut shutdown => {
+                tracing::info!(pending = state.delayed_actions.pending(), "Shutting down; flushing delayed actions");
+                shard.shutdown();
+                state.delayed_actions.flush().await;
+                if let Some(influx) = state.influx.as_ref() {
+                    influx.shutdown().await;
+                }
+                save_tracking_state(&state).await;
+                if let Some(store) = state.infractions.as_ref() {
+                    if let Err(err) = store.flush().await {
+                        tracing::error!(?err, "Unable to save infractions");
+                    }
+                }
+                break;
+            },
+            Some(event) = events.next() => {
+                state.cache.update(&event);
+                // Take each message's turn in its channel now, while events are
+                // still in arrival order, so its actions run in that order.
+                let turn = match &event {
+                    Event::MessageCreate(message) => Some(state.channel_order.turn(message.channel_id)),
+                    Event::MessageUpdate(update) => Some(state.channel_order.turn(update.channel_id)),
+                    _ => None,
+                };
+                tokio::spawn(handle_event_wrapper(event, state.clone(), turn).instrument(tracing::debug_span!("Handling event")));
+            },
+            _ = interval.tick() => {
+                spawn_reload(&state);
+            }
+            _ = config_changed(&mut config_watcher) => {
+                tracing::debug!("Guild configuration files changed");
+                spawn_reload(&state);
+            }
+            _ = prune_interval.tick() => {
+                if let Err(err) = prune_tracking_state(&state).await {
+                    tracing::error!(?err, "Error reporting tracking state sizes");
+                }
+            }
+        }
+    }
+
+    if let Some((stop, server)) = metrics_server {
+        let _ = stop.send(());
+        server.await?;
+    }
+
+    Ok(())
+    })
+}
+
+/// Waits for Ctrl-C, or for SIGTERM, which is how systemd and Docker stop the
+/// bot.
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => {},
+                    _ = terminate.recv() => {},
+                }
+                return;
+            }
+            Err(err) => tracing::warn!(?err, "Unable to listen for SIGTERM"),
+        }
+    }
+
+    if let Err(err) = tokio::signal::ctrl_c().await {
+        tracing::error!(?err, "Unable to listen for Ctrl-C");
+        std::future::pending::<()>().await;
+    }
+}
+
+async fn handle_event_wrapper(event: Event, state: State, turn: Option<Turn>) {
+    let start = Instant::now();
+    // Errors are logged inside the guild's hub, so Sentry reports them with
+    // the guild's tags.
+    let handled = async {
+        if let Err(report) = handle_event(&event, state.clone(), turn).await {
+            tracing::error!(result = ?report, event = ?event, "Error handling event");
+        }
+    };
+    match event.guild_id() {
+        Some(guild_id) => {
+            let hub = guild_sentry_hub(&state, guild_id).await;
+            handled.bind_hub(hub).await
+        }
+        None => handled.await,
+    };
+    let end = Instant::now();
+    let time = end - start;
+
+    let (guild_id, channel_id, action_kind) = match event {
+        Event::MessageCreate(message) => {
+            let message = message.0;
+
+            (
+                message.guild_id.unwrap(),
+                message.channel_id,
+                "message create",
+            )
+        }
+        Event::MessageUpdate(update) => (
+            update.guild_id.unwrap(),
+            update.channel_id,
+            "message update",
+        ),
+        Event::ReactionAdd(rxn) => {
+            let rxn = rxn.0;
+            (rxn.guild_id.unwrap(), rxn.channel_id, "reaction")
+        }
+        _ => return,
+    };
+
+    let development = cfg!(debug_assertions);
+    let report = EventTimingReport {
+        time: Utc::now(),
+        time_taken: time.as_secs_f64(),
+        guild: guild_id.to_string(),
+        channel: channel_id.to_string(),
+        action_kind,
+        development,
+    };
+
+    let guild_metrics = state
+        .guild_cfgs
+        .read()
+        .await
+        .get(&guild_id)
+        .and_then(|c| c.metrics.clone());
+
+    send_influx_point(
+        &state,
+        guild_id,
+        guild_metrics.as_ref(),
+        report.into_query("event_report"),
+    );
+}
+
+/// A Sentry hub that tags what's reported from it with `guild_id` and the
+/// guild's configured tags.
+async fn guild_sentry_hub(state: &State, guild_id: Id<GuildMarker>) -> Arc<sentry::Hub> {
+    let guild_cfgs = state.guild_cfgs.read().await;
+    let tags = guild_cfgs.get(&guild_id).map(|c| &c.sentry_tags);
+    error_reporting::guild_hub(guild_id, tags.unwrap_or(&HashMap::new()))
+}
+
+#[tracing::instrument(skip(state))]
+async fn handle_event(event: &Event, state: State, turn: Option<Turn>) -> Result<()> {
+    match event {
+        Event::MessageCreate(message) => {
+            let message = &message.0;
+            filter_message(message, state, turn).await?;
+        }
+        Event::MessageUpdate(update) => {
+            filter_message_edit(update, &state, turn).await?;
+        }
+        Event::ReactionAdd(rxn) => {
+            let rxn = &rxn.0;
+            check_approval(rxn, &state).await;
+            filter_reaction(rxn, state).await?;
+        }
+        Event::MemberAdd(member) => {
+            filter_member_names(
+                member.guild_id,
+                &member.user,
+                member.nick.as_deref(),
+                &state,
+            )
+            .await?;
+        }
+        Event::MemberUpdate(update) => {
+            filter_member_names(
+                update.guild_id,
+                &update.user,
+                update.nick.as_deref(),
+                &state,
+            )
+            .await?;
+        }
+        Event::GuildCreate(guild) if state.cfg.check_guild_references => {
+            check_guild_references(guild.id, &state).await;
+        }
+        Event::MemberRemove(remove) => {
+            state.checked_names.forget(remove.guild_id, remove.user.id);
+        }
+        Event::MessageDelete(delete) => {
+            check_message_delete(delete, &state).await?;
+        }
+        Event::StageInstanceCreate(stage) => {
+            filter_stage_topic(&stage.0, &state).await?;
+        }
+        Event::StageInstanceUpdate(stage) => {
+            filter_stage_topic(&stage.0, &state).await?;
+        }
+        Event::StageInstanceDelete(stage) => {
+            state.stage_topics.forget(stage.0.channel_id);
+        }
+        Event::ReactionRemove(rxn) => {
+            let rxn = &rxn.0;
+            crate::filter::forget_reaction(
+                &state.reaction_history,
+                rxn.message_id,
+                rxn.user_id,
+                &rxn.emoji,
+            )
+            .await;
+        }
+        Event::Ready(ready) => {
+            {
+                *state.application_id.write().await = Some(ready.application.id);
+            }
+
+            let interaction_http = state.http.interaction(ready.application.id);
+            let guild_cfgs = state.guild_cfgs.read().await;
+
+            for (guild_id, guild_config) in guild_cfgs.iter() {
+                command::update_guild_commands(
+                    &interaction_http,
+                    *guild_id,
+                    guild_config.slash_commands.as_ref(),
+                )
+                .await?;
+                state.command_registrations.registered(
+                    *guild_id,
+                    guild_config.slash_commands.is_some(),
+                    tokio::time::Instant::now(),
+                );
+            }
+        }
+        Event::InteractionCreate(interaction) => {
+            let interaction = &interaction.0;
+            if let Some(InteractionData::ApplicationCommand(cmd)) = &interaction.data {
+                command::handle_command(state.clone(), interaction, cmd.as_ref()).await?;
+            }
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
+
+/// Warns about channels and roles `guild_id`'s configuration refers to that
+/// aren't in the cache, which is only complete once the guild is available.
+async fn check_guild_references(guild_id: Id<GuildMarker>, state: &State) {
+    let guild_cfgs = state.guild_cfgs.read().await;
+    let guild_config = match guild_cfgs.get(&guild_id) {
+        Some(guild_config) => guild_config,
+        None => return,
+    };
+
+    let unresolved = references::unresolved(
+        guild_config,
+        |channel_id| {
+            state
+                .cache
+                .channel(channel_id)
+                .map_or(false, |c| c.guild_id == Some(guild_id))
+        },
+        |role_id| {
+            state
+                .cache
+                .role(role_id)
+                .map_or(false, |r| r.guild_id() == guild_id)
+        },
+    );
+    for problem in unresolved {
+        tracing::warn!(%guild_id, %problem, "Guild configuration refers to something missing");
+    }
+}
+
+/// Forgets spam, raid, reaction, sticker and link preview tracking that has
+/// expired, and reports how much tracking state is left so that leaks show up
+/// in metrics.
+#[tracing::instrument(skip(state))]
+async fn prune_tracking_state(state: &State) -> Result<()> {
+    let now = (Utc::now().timestamp_millis() as u64) * 1000;
+    let (spam_intervals, longest_min_interval, escalation_decays) = {
+        let guild_cfgs = state.guild_cfgs.read().await;
+        let spam_intervals: HashMap<Id<GuildMarker>, u16> = guild_cfgs
+            .iter()
+            .filter_map(|(guild_id, c)| Some((*guild_id, c.spam.as_ref()?.interval)))
+            .collect();
+        let longest_min_interval = guild_cfgs
+            .values()
+            .filter_map(GuildConfig::longest_min_interval)
+            .max()
+            .unwrap_or(0);
+        let escalation_decays: HashMap<Id<GuildMarker>, u64> = guild_cfgs
+            .iter()
+            .filter(|(_, c)| c.needs_violation_counts())
+            .map(|(guild_id, c)| (*guild_id, c.escalation_decay()))
+            .collect();
+        (spam_intervals, longest_min_interval, escalation_decays)
+    };
+
+    // Spam history isn't kept per guild, so keep records as long as any guild
+    // might look at them.
+    let longest_interval = spam_intervals.values().copied().max().unwrap_or(0);
+    let report = TrackingSizeReport {
+        time: Utc::now(),
+        spam_users: filter::prune_spam_history(&state.spam_history, longest_interval, now).await
+            as u64,
+        raid_guilds: filter::prune_raid_history(
+            &state.raid_history,
+            |guild_id| spam_intervals.get(&guild_id).copied(),
+            now,
+        )
+        .await as u64,
+        reactions: filter::prune_reaction_history(&state.reaction_history, now).await as u64,
+        posts: filter::prune_post_history(&state.post_history, longest_min_interval, now).await
+            as u64,
+        stickers: {
+            let mut sticker_cache = state.sticker_cache.write().await;
+            sticker_cache.retain(|_, (fetched_at, _)| fetched_at.elapsed() < STICKER_CACHE_TTL);
+            sticker_cache.len() as u64
+        },
+        link_previews: state.link_previews.prune().await as u64,
+        invites: state.invites.prune().await as u64,
+        cached_messages: state
+            .message_cache
+            .as_ref()
+            .as_ref()
+            .map_or(0, |cache| cache.prune(Utc::now().timestamp() as u64))
+            as u64,
+        infraction_users: match state.infractions.as_ref() {
+            Some(store) => store.prune(Utc::now().timestamp() as u64) as u64,
+            None => 0,
+        },
+        violation_users: state.violations.prune(
+            |guild_id| escalation_decays.get(&guild_id).copied(),
+            Utc::now().timestamp() as u64,
+        ) as u64,
+    };
+
+    tracing::debug!(?report, "Pruned tracking state");
+    save_tracking_state(state).await;
+
+    if let Some(influx) = state.influx.as_ref() {
+        influx.report(report.into_query("tracking_sizes"));
+    }
+
+    Ok(())
+}
+
+/// Saves spam windows and violations to the state store, if there is one.
+async fn save_tracking_state(state: &State) {
+    if let Some(store) = state.state_store.as_ref() {
+        if let Err(err) = store
+            .save(&state.spam_history, &state.raid_history, &state.violations)
+            .await
+        {
+            tracing::error!(?err, "Unable to save tracking state");
+        }
+    }
+}
+
+/// Starts watching the guild configuration directories if `auto_reload` is
+/// on. Watching is given up on, rather than failing to start, if it errors.
+fn watch_config_dirs(cfg: &Config) -> Option<watch::ConfigWatcher> {
+    if !cfg.auto_reload {
+        return None;
+    }
+
+    let roots = cfg.guild_config_roots();
+    if roots.is_empty() {
+        tracing::warn!("auto_reload only works with local configuration directories; ignoring it");
+        return None;
+    }
+
+    match watch::ConfigWatcher::new(&roots) {
+        Ok(watcher) => Some(watcher),
+        Err(err) => {
+            tracing::error!(?err, "Unable to watch guild configuration directories");
+            None
+        }
+    }
+}
+
+/// Waits for `watcher` to see a change, or forever if there's no watcher.
+async fn config_changed(watcher: &mut Option<watch::ConfigWatcher>) {
+    match watcher {
+        Some(watcher) => watcher.changed().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Reloads guild configurations, reporting failures to the guild whose
+/// configuration failed. Every guild keeps its current configuration if one
+/// fails to load.
+async fn reload_and_report(state: &State) -> Result<()> {
+    let result = reload_guild_configs(state).await;
+    if let Err((guild_id, report)) = result {
+        sentry::Hub::run(guild_sentry_hub(state, guild_id).await, || {
+            tracing::error!(?guild_id, ?report, "Error reloading guild configuration");
+        });
+        send_notification_to_guild(
+            state,
+            guild_id,
+            "Configuration reload failed",
+            &format!(
+                "Failure reason:\n```{:#?}```\nConfiguration changes have **not** been applied.",
+                report
+            ),
+        )
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Reloads guild configurations in the background, so slow fetches don't hold
+/// up events.
+fn spawn_reload(state: &State) {
+    let state = state.clone();
+    tokio::spawn(
+        async move {
+            if let Err(err) = reload_and_report(&state).await {
+                tracing::error!(?err, "Unable to report configuration reload failure");
+            }
+        }
+        .instrument(tracing::debug_span!("Reloading guild configurations")),
+    );
+}
+
+/// Loads the configurations of `guild_ids` from wherever `cfg` says they
+/// live, checking that the gateway intents we request cover them.
+async fn load_guild_configs(
+    cfg: &Config,
+    remote_configs: Option<&remote_config::RemoteConfigs>,
+    guild_ids: &[Id<GuildMarker>],
+) -> Result<HashMap<Id<GuildMarker>, GuildConfig>, (Id<GuildMarker>, eyre::Report)> {
+    let guild_configs = match remote_configs {
+        Some(remote_configs) => {
+            remote_configs
+                .load_guild_configs(guild_ids, cfg.allow_missing_guild_configs)
+                .await?
+        }
+        None => config::load_guild_configs(
+            &cfg.guild_config_roots(),
+            guild_ids,
+            cfg.allow_missing_guild_configs,
+            cfg.duplicate_guild_configs,
+        )?,
+    };
+
+    // Features whose events we never receive would silently do nothing.
+    let intents = intents::configured_intents(cfg);
+    for (guild_id, guild_config) in &guild_configs {
+        if let Err(errs) = intents::check_intents(intents, guild_config) {
+            return Err((
+                *guild_id,
+                eyre::eyre!(
+                    "Configuration for guild {} needs intents that aren't requested: {}",
+                    guild_id,
+                    errs.join("; ")
+                ),
+            ));
+        }
+    }
+
+    Ok(guild_configs)
+}
+
+#[tracing::instrument(skip(state))]
+async fn reload_guild_configs(state: &State) -> Result<(), (Id<GuildMarker>, eyre::Report)> {
+    let _reloading = state.reloading.lock().await;
+    tracing::debug!("Reloading guild configurations");
+    let new_guild_configs = load_guild_configs(
+        &state.cfg,
+        state.remote_configs.as_ref().as_ref(),
+        &state.cfg.active_guilds,
+    )
+    .await?;
+    let mut guild_cfgs = state.guild_cfgs.write().await;
+    update_commands_for_configs(state, &new_guild_configs).await?;
+    *guild_cfgs = new_guild_configs;
+
+    Ok(())
+}
+
+/// Reloads only `guild_id`'s configuration, leaving every other guild's
+/// alone. The guild's current configuration is kept if the new one fails to
+/// load.
+#[tracing::instrument(skip(state))]
+async fn reload_guild_config(
+    state: &State,
+    guild_id: Id<GuildMarker>,
+) -> Result<(), (Id<GuildMarker>, eyre::Report)> {
+    if !state.cfg.active_guilds.contains(&guild_id) {
+        return Err((
+            guild_id,
+            eyre::eyre!("Guild {} isn't in active_guilds", guild_id),
+        ));
+    }
+
+    let _reloading = state.reloading.lock().await;
+    tracing::debug!("Reloading guild configuration");
+    let mut new_guild_configs = load_guild_configs(
+        &state.cfg,
+        state.remote_configs.as_ref().as_ref(),
+        &[guild_id],
+    )
+    .await?;
+    let mut guild_cfgs = state.guild_cfgs.write().await;
+    update_commands_for_configs(state, &new_guild_configs).await?;
+
+    // A guild skipped by allow_missing_guild_configs goes away, as it would
+    // in a full reload.
+    match new_guild_configs.remove(&guild_id) {
+        Some(guild_config) => guild_cfgs.insert(guild_id, guild_config),
+        None => guild_cfgs.remove(&guild_id),
+    };
+
+    Ok(())
+}
+
+/// Registers or removes slash commands for guilds whose new configurations
+/// change whether they're enabled.
+async fn update_commands_for_configs(
+    state: &State,
+    new_guild_configs: &HashMap<Id<GuildMarker>, GuildConfig>,
+) -> Result<(), (Id<GuildMarker>, eyre::Report)> {
+    let application_id = *state.application_id.read().await;
+
+    // We can't interact with commands until we have an application ID from the
+    // gateway. Don't try if we don't have one yet.
+    if let Some(application_id) = application_id {
+        let interaction_http = state.http.interaction(application_id);
+
+        let cooldown = Duration::from_secs(
+            state
+                .cfg
+                .command_update_cooldown
+                .unwrap_or(DEFAULT_COMMAND_UPDATE_COOLDOWN),
+        );
+
+        for (guild_id, new_guild_config) in new_guild_configs {
+            let enabled = new_guild_config.slash_commands.is_some();
+            let now = tokio::time::Instant::now();
+
+            match state
+                .command_registrations
+                .plan(*guild_id, enabled, cooldown, now)
+            {
+                registration::RegistrationPlan::Now => {
+                    tracing::trace!(%guild_id, "Updating guild commands");
+
+                    let result = command::update_guild_commands(
+                        &interaction_http,
+                        *guild_id,
+                        new_guild_config.slash_commands.as_ref(),
+                    )
+                    .await;
+                    if let Err(err) = result {
+                        state.command_registrations.forget(*guild_id);
+                        return Err((*guild_id, err));
+                    }
+
+                    state
+                        .command_registrations
+                        .registered(*guild_id, enabled, now);
+                }
+                registration::RegistrationPlan::After(delay) => {
+                    tracing::debug!(%guild_id, ?delay, "Deferring guild command update");
+                    tokio::spawn(deferred_command_update(
+                        state.clone(),
+                        application_id,
+                        *guild_id,
+                        delay,
+                    ));
+                }
+                registration::RegistrationPlan::Unchanged
+                | registration::RegistrationPlan::Scheduled => {}
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Updates a guild's commands once its command update cooldown has passed,
+/// using whichever configuration is loaded by then.
+async fn deferred_command_update(
+    state: State,
+    application_id: Id<ApplicationMarker>,
+    guild_id: Id<GuildMarker>,
+    delay: Duration,
+) {
+    tokio::time::sleep(delay).await;
+
+    let guild_cfgs = state.guild_cfgs.read().await;
+    let slash_commands = match guild_cfgs.get(&guild_id) {
+        Some(guild_config) => guild_config.slash_commands.as_ref(),
+        None => {
+            state.command_registrations.forget(guild_id);
+            return;
+        }
+    };
+
+    let interaction_http = state.http.interaction(application_id);
+    tracing::trace!(%guild_id, "Updating guild commands");
+    match command::update_guild_commands(&interaction_http, guild_id, slash_commands).await {
+        Ok(()) => state.command_registrations.registered(
+            guild_id,
+            slash_commands.is_some(),
+            tokio::time::Instant::now(),
+        ),
+        Err(err) => {
+            tracing::error!(?err, %guild_id, "Error updating guild commands");
+            state.command_registrations.forget(guild_id);
+        }
+    }
+}
+
+/// Looks up descriptions and tags for stickers, fetching any we haven't seen
+/// before. Stickers that can't be fetched are skipped.
+async fn fetch_sticker_metadata(
+    state: &State,
+    stickers: &[MessageSticker],
+) -> Vec<StickerMetadata> {
+    let mut metadata = Vec::with_capacity(stickers.len());
+
+    for sticker in stickers {
+        let cached = state
+            .sticker_cache
+            .read()
+            .await
+            .get(&sticker.id)
+            .filter(|(fetched_at, _)| fetched_at.elapsed() < STICKER_CACHE_TTL)
+            .map(|(_, cached)| cached.clone());
+        if let Some(cached) = cached {
+            metadata.push(cached);
+            continue;
+        }
+
+        let fetched = match state.http.sticker(sticker.id).await {
+            Ok(response) => response.model().await,
+            Err(err) => {
+                tracing::warn!(?err, sticker_id = %sticker.id, "Unable to fetch sticker");
+                continue;
+            }
+        };
+
+        match fetched {
+            Ok(fetched) => {
+                let fetched = StickerMetadata {
+                    id: fetched.id,
+                    description: fetched.description,
+                    tags: fetched.tags,
+                };
+                state
+                    .sticker_cache
+                    .write()
+                    .await
+                    .insert(sticker.id, (Instant::now(), fetched.clone()));
+                metadata.push(fetched);
+            }
+            Err(err) => {
+                tracing::warn!(?err, sticker_id = %sticker.id, "Unable to deserialize sticker");
+            }
+        }
+    }
+
+    metadata
+}
+
+/// The category `channel_id` is in, as far as the cache knows. Threads are in
+/// their parent channel's category.
+pub(crate) fn channel_category(
+    cache: &InMemoryCache,
+    channel_id: Id<ChannelMarker>,
+) -> Option<Id<ChannelMarker>> {
+    let channel = cache.channel(channel_id)?;
+    if channel.kind.is_thread() {
+        let parent_id = channel.parent_id?;
+        cache.channel(parent_id)?.parent_id
+    } else {
+        channel.parent_id
+    }
+}
+
+/// The author of the message `reference` replies to, if it's cached.
+fn reply_author(state: &State, reference: Option<&MessageReference>) -> Option<Id<UserMarker>> {
+    let message_id = reference?.message_id?;
+    state.cache.message(message_id).map(|m| m.author())
+}
+
+#[tracing::instrument(skip(state, turn))]
+async fn filter_message_info<'msg>(
+    guild_id: Id<GuildMarker>,
+    message_info: &'msg MessageInfo<'_>,
+    state: &'msg State,
+    context: &'static str,
+    mut turn: Option<Turn>,
+) -> Result<()> {
+    // What to fetch is decided while holding the configuration, but fetching
+    // happens without it: a queued reload would otherwise hold up every other
+    // event until the slowest request finished.
+    let (invite_codes, link_urls, fetch_stickers, sniff_attachments, metrics) = {
+        let guild_cfgs = state.guild_cfgs.read().await;
+        let guild_config = match guild_cfgs.get(&guild_id) {
+            Some(guild_config) => guild_config,
+            None => return Ok(()),
+        };
+
+        if message_info.author_is_bot && !guild_config.include_bots {
+            tracing::trace!(?guild_id, author = %message_info.author_id, "Skipping message filtration because message was sent by a bot and include_bots is false for this guild");
+            return Ok(());
+        }
+
+        if guild_config.messages.is_none() {
+            return Ok(());
+        }
+
+        let invite_codes: Vec<_> = if guild_config.needs_invite_resolution(message_info) {
+            crate::filter::invite_codes(message_info.content).collect()
+        } else {
+            Vec::new()
+        };
+        let link_urls = guild_config.link_preview_urls(message_info);
+        (
+            invite_codes,
+            link_urls,
+            guild_config.needs_sticker_metadata() && !message_info.stickers.is_empty(),
+            guild_config.needs_attachment_sniffing() && !message_info.attachments.is_empty(),
+            guild_config.metrics.clone(),
+        )
+    };
+
+    state.counters.message_scanned();
+    let evaluation = async {
+        let (resolved_invites, link_previews, sticker_metadata, sniffed_attachments) = tokio::join!(
+            async {
+                if invite_codes.is_empty() {
+                    Vec::new()
+                } else {
+                    state
+                        .invites
+                        .resolve_all(&state.http, invite_codes.into_iter())
+                        .await
+                }
+            },
+            async {
+                if link_urls.is_empty() {
+                    Vec::new()
+                } else {
+                    state.link_previews.fetch_all(link_urls.into_iter()).await
+                }
+            },
+            async {
+                if fetch_stickers {
+                    fetch_sticker_metadata(state, message_info.stickers).await
+                } else {
+                    Vec::new()
+                }
+            },
+            async {
+                if sniff_attachments {
+                    Some(crate::sniff::sniff_attachments(message_info.attachments).await)
+                } else {
+                    None
+                }
+            },
+        );
+
+        let guild_cfgs = state.guild_cfgs.read().await;
+        let guild_config = guild_cfgs.get(&guild_id)?;
+        let prior_infractions = match state.infractions.as_ref() {
+            Some(store) if guild_config.needs_prior_infractions() => Some(store.count(
+                guild_id,
+                message_info.author_id,
+                Utc::now().timestamp() as u64,
+            )),
+            _ => None,
+        };
+        let prior_violations = guild_config.needs_violation_counts().then(|| {
+            state.violations.count(
+                guild_id,
+                message_info.author_id,
+                guild_config.escalation_decay(),
+                Utc::now().timestamp() as u64,
+            )
+        });
+        let message_info = &MessageInfo {
+            attachments: sniffed_attachments
+                .as_deref()
+                .unwrap_or(message_info.attachments),
+            sticker_metadata: &sticker_metadata,
+            link_previews: &link_previews,
+            resolved_invites: &resolved_invites,
+            prior_infractions,
+            prior_violations,
+            ..*message_info
+        };
+
+        tracing::trace!(?message_info, "Filtering message");
+
+        let now = (Utc::now().timestamp_millis() as u64) * 1000;
+        let result = crate::message::filter_and_spam_check_message(
+            guild_config,
+            state.spam_history.clone(),
+            state.raid_history.clone(),
+            &state.action_caps,
+            message_info,
+            context,
+            now,
+        )
+        .await;
+        Some((guild_cfgs, result))
+    };
+
+    let budget = state
+        .cfg
+        .message_latency_budget_ms
+        .map(Duration::from_millis);
+    let (guild_cfgs, result) = match crate::message::within_budget(budget, evaluation).await {
+        Some(Some(evaluated)) => evaluated,
+        // The guild's configuration was removed while fetching.
+        Some(None) => return Ok(()),
+        None => {
+            tracing::warn!(%message_info.id, %message_info.channel_id, ?budget, "Message evaluation exceeded its latency budget; letting it through");
+
+            let report = MessageBudgetExceededReport {
+                time: Utc::now(),
+                guild: guild_id.to_string(),
+                channel: message_info.channel_id.to_string(),
+            };
+
+            send_influx_point(
+                state,
+                guild_id,
+                metrics.as_ref(),
+                report.into_query("message_budget_exceeded"),
+            );
+
+            return Ok(());
+        }
+    };
+    // Still there, since it was there when the evaluation took the lock.
+    let guild_config = &guild_cfgs[&guild_id];
+
+    let tripped = state.action_caps.take_tripped();
+    if !tripped.is_empty() {
+        tokio::spawn(notify_tripped_caps(state.clone(), tripped));
+    }
+
+    let mut failure = match result {
+        Ok(()) => return Ok(()),
+        Err(failure) => failure,
+    };
+
+    tracing::trace!(%message_info.id, %message_info.channel_id, %message_info.author_id, ?failure, "Message filtered");
+    state.counters.filter_hit(&failure.filter_name);
+
+    if guild_config.needs_violation_counts() {
+        state.violations.record(
+            guild_id,
+            message_info.author_id,
+            Utc::now().timestamp() as u64,
+        );
+    }
+
+    if let Some(store) = state.infractions.as_ref() {
+        store.record(
+            guild_id,
+            message_info.author_id,
+            Utc::now().timestamp() as u64,
+        );
+    }
+
+    let action_delay = guild_config.action_delay.clone();
+    let log_config = guild_config.log.clone();
+    let metrics = guild_config.metrics.clone();
+    // Release the configuration before waiting our turn, so a reload queued
+    // behind us can't block an earlier message we're waiting on.
+    drop(guild_cfgs);
+
+    if let Some(turn) = turn.as_mut() {
+        turn.wait().await;
+    }
+
+    action::react_first(&mut failure.actions);
+    let (logs, actions): (Vec<_>, Vec<_>) = failure
+        .actions
+        .into_iter()
+        .partition(MessageAction::is_combined_log);
+    let mut taken = crate::message::ActionsTaken::default();
+    let mut deleted = false;
+
+    for action in actions {
+        tracing::trace!(?action, "Executing action");
+
+        // We only want to execute Delete actions once per message,
+        // since we'll get a 404 on subsequent requests.
+        if let MessageAction::Delete { .. } = action {
+            if deleted {
+                tracing::trace!(?action, "Skipping duplicate delete action");
+                continue;
+            }
+
+            deleted = true;
+        }
+
+        if !should_execute_action(
+            state,
+            guild_id,
+            &action,
+            action.kind(),
+            action.requires_armed(),
+        ) {
+            continue;
+        }
+
+        let action_delay = action_delay
+            .as_ref()
+            .filter(|_| action.requires_armed())
+            .map(|action_delay| action_delay.sample());
+
+        if !coalesce_action(
+            state,
+            &action,
+            coalesce::window(&action),
+            action_delay.unwrap_or_default(),
+            log_config.as_ref(),
+        ) {
+            continue;
+        }
+
+        if let Some(action_delay) = action_delay {
+            let state = state.clone();
+            let log_config = log_config.clone();
+            let metrics = metrics.clone();
+            let ran = taken.delayed();
+            state.delayed_actions.clone().schedule_for_message(
+                message_info.id,
+                action_delay,
+                async move {
+                    throttle_action(&state, guild_id, metrics.as_ref(), action.rate_limit_path())
+                        .await;
+                    match execute_message_action(&state, &action, log_config.as_ref()).await {
+                        Ok(()) => {
+                            state.counters.action_taken(action.kind());
+                            let _ = ran.send(action.kind());
+                        }
+                        Err(action_err) => {
+                            tracing::warn!(?action, ?action_err, "Error executing delayed action");
+                        }
+                    }
+                },
+            );
+        } else {
+            throttle_action(state, guild_id, metrics.as_ref(), action.rate_limit_path()).await;
+            match execute_message_action(state, &action, log_config.as_ref()).await {
+                Ok(()) => {
+                    state.counters.action_taken(action.kind());
+                    taken.ran(action.kind());
+                }
+                Err(action_err) => {
+                    tracing::warn!(?action, ?action_err, "Error executing action");
+                }
+            }
+        }
+    }
+
+    if !logs.is_empty() {
+        if taken.has_delayed() {
+            // Held back until the delayed actions have run or been cancelled,
+            // without holding up the next message in the channel.
+            let state = state.clone();
+            let log_config = log_config.clone();
+            let metrics = metrics.clone();
+            tokio::spawn(async move {
+                send_combined_logs(
+                    &state,
+                    guild_id,
+                    logs,
+                    taken,
+                    log_config.as_ref(),
+                    metrics.as_ref(),
+                )
+                .await;
+            });
+        } else {
+            send_combined_logs(
+                state,
+                guild_id,
+                logs,
+                taken,
+                log_config.as_ref(),
+                metrics.as_ref(),
+            )
+            .await;
+        }
+    }
+
+    // Let the next message in this channel act.
+    drop(turn);
+
+    tracing::trace!(%message_info.id, %message_info.channel_id, %message_info.author_id, "Filtration completed, all actions executed");
+
+    let report = MessageFilterReport {
+        time: Utc::now(),
+        guild: guild_id.to_string(),
+        channel: message_info.channel_id.to_string(),
+    };
+
+    send_influx_point(
+        state,
+        guild_id,
+        metrics.as_ref(),
+        report.into_query(context),
+    );
+    tracing::trace!(%message_info.id, %message_info.channel_id, %message_info.author_id, "Influx point queued");
+
+    Ok(())
+}
+
+/// Sends a message's combined logs once its other actions have run, listing
+/// the ones that did.
+async fn send_combined_logs(
+    state: &State,
+    guild_id: Id<GuildMarker>,
+    logs: Vec<MessageAction>,
+    taken: crate::message::ActionsTaken,
+    log_config: Option<&LogConfig>,
+    metrics: Option<&GuildMetricsConfig>,
+) {
+    let taken = taken.finish().await;
+
+    for mut log in logs {
+        log.set_actions_taken(&taken);
+
+        if !should_execute_action(state, guild_id, &log, log.kind(), log.requires_armed())
+            || !coalesce_action(
+                state,
+                &log,
+                coalesce::window(&log),
+                Duration::ZERO,
+                log_config,
+            )
+        {
+            continue;
+        }
+
+        throttle_action(state, guild_id, metrics, log.rate_limit_path()).await;
+        match execute_message_action(state, &log, log_config).await {
+            Ok(()) => state.counters.action_taken(log.kind()),
+            Err(action_err) => {
+                tracing::warn!(action = ?log, ?action_err, "Error executing action");
+            }
+        }
+    }
+}
+
+/// Executes a message filter action. A message being deleted is forgotten
+/// from the message cache first, since our own deletions aren't evasion, and
+/// remembered again if it couldn't be deleted.
+async fn execute_message_action(
+    state: &State,
+    action: &MessageAction,
+    log_config: Option<&LogConfig>,
+) -> Result<()> {
+    let forgotten = match (action, state.message_cache.as_ref()) {
+        (MessageAction::Delete { message_id, .. }, Some(message_cache)) => message_cache
+            .remove(*message_id, Utc::now().timestamp() as u64)
+            .map(|cached| (message_cache, *message_id, cached)),
+        _ => None,
+    };
+
+    let result = action
+        .execute(&state.http, log_config, &state.delayed_actions)
+        .await;
+    if let (Err(_), Some((message_cache, message_id, (author_id, content)))) = (&result, forgotten)
+    {
+        message_cache.replace(
+            message_id,
+            author_id,
+            &content,
+            Utc::now().timestamp() as u64,
+        );
+    }
+
+    result
+}
+
+#[tracing::instrument(skip(state, turn))]
+async fn filter_message(message: &Message, state: State, turn: Option<Turn>) -> Result<()> {
+    let guild_id = match message.guild_id {
+        Some(id) => id,
+        None => return Ok(()),
+    };
+
+    cache_message_content(
+        &state,
+        guild_id,
+        message.id,
+        Some(message.author.id),
+        &message.content,
+    )
+    .await;
+
+    let member = match message.member.as_ref() {
+        Some(member) => member,
+        None => {
+            // For non-bot users, this should always be set.
+            if !message.author.bot {
+                sentry::capture_message(
+                    "No `member` field attached to non-bot message",
+                    sentry::Level::Error,
+                );
+                tracing::error!(?message.id, "No `member` field attached to message");
+            }
+
+            return Ok(());
+        }
+    };
+
+    let prefix_command = state
+        .guild_cfgs
+        .read()
+        .await
+        .get(&guild_id)
+        .and_then(|c| c.prefix_commands.as_ref())
+        .and_then(|p| {
+            command::parse_prefix_command(p, message.author.id, &member.roles, &message.content)
+        });
+    if let Some(prefix_command) = prefix_command {
+        return command::handle_prefix_command(&state, message, prefix_command).await;
+    }
+
+    let clean_message_content = crate::message::clean_mentions(&message.content, &message.mentions);
+    let mentioned_users: Vec<_> = message.mentions.iter().map(|m| m.id).collect();
+    let targeted_users = crate::message::targeted_users(
+        mentioned_users.iter().copied(),
+        message
+            .referenced_message
+            .as_ref()
+            .map(|m| m.author.id)
+            .or_else(|| reply_author(&state, message.reference.as_ref())),
+    );
+
+    let records_posts = state
+        .guild_cfgs
+        .read()
+        .await
+        .get(&guild_id)
+        .map_or(false, |c| c.longest_min_interval().is_some());
+    let previous_post = if records_posts {
+        crate::filter::record_post(
+            &state.post_history,
+            message.channel_id,
+            message.author.id,
+            message.timestamp,
+        )
+        .await
+    } else {
+        None
+    };
+
+    let message_info = MessageInfo {
+        id: message.id,
+        author_id: message.author.id,
+        channel_id: message.channel_id,
+        category_id: channel_category(&state.cache, message.channel_id),
+        // We can assume guild_id exists since the DM intent is disabled
+        guild_id: message.guild_id.unwrap(),
+        timestamp: message.timestamp,
+        author_is_bot: message.author.bot,
+        author_roles: &member.roles,
+        content: &clean_message_content,
+        kind: message.kind,
+        attachments: &message.attachments,
+        stickers: &message.sticker_items,
+        components: &message.components,
+        embeds: &message.embeds,
+        previous_content: None,
+        previous_post,
+        prior_infractions: None,
+        prior_violations: None,
+        targeted_users: &targeted_users,
+        mentioned_users: &mentioned_users,
+        mentioned_roles: &message.mention_roles,
+        sticker_metadata: &[],
+        link_previews: &[],
+        resolved_invites: &[],
+    };
+
+    filter_message_info(guild_id, &message_info, &state, "message create", turn).await
+}
+
+#[tracing::instrument(skip(state))]
+async fn filter_reaction(rxn: &GatewayReaction, state: State) -> Result<()> {
+    if rxn.guild_id.is_none() {
+        tracing::trace!("A reaction was added, but no guild ID is present. Ignoring.");
+        return Ok(());
+    }
+
+    let guild_id = rxn.guild_id.unwrap();
+
+    if rxn.member.is_none() {
+        tracing::trace!("A reaction was added, but no member information is present. Ignoring.");
+        return Ok(());
+    }
+
+    let member = rxn.member.as_ref().unwrap();
+
+    let guild_cfgs = state.guild_cfgs.read().await;
+    if let Some(guild_config) = guild_cfgs.get(&guild_id) {
+        if member.user.bot && !guild_config.include_bots {
+            tracing::trace!("A reaction was added by a bot and include_bots is not set. Ignoring.");
+            return Ok(());
+        }
+
+        if guild_config.is_ignored_channel(rxn.channel_id) {
+            tracing::trace!("A reaction was added in an ignored channel. Ignoring.");
+            return Ok(());
+        }
+
+        if let Some(reaction_filters) = &guild_config.reactions {
+            let tracks_counts = reaction_filters.iter().any(|f| {
+                f.rules
+                    .iter()
+                    .any(|r| matches!(r, ReactionFilterRule::Count { .. }))
+            });
+
+            let distinct_reactions = if tracks_counts {
+                crate::filter::record_reaction(
+                    &state.reaction_history,
+                    rxn.message_id,
+                    rxn.user_id,
+                    &rxn.emoji,
+                )
+                .await
+            } else {
+                1
+            };
+
+            let reaction_info = ReactionInfo {
+                author_is_bot: member.user.bot,
+                author_roles: &member.roles,
+                author_id: rxn.user_id,
+                channel_id: rxn.channel_id,
+                category_id: channel_category(&state.cache, rxn.channel_id),
+                message_id: rxn.message_id,
+                // We can assume guild_id exists since the DM intent is disabled
+                guild_id: rxn.guild_id.unwrap(),
+                reaction: rxn.emoji.clone(),
+                distinct_reactions,
+                prior_violations: guild_config.needs_violation_counts().then(|| {
+                    state.violations.count(
+                        guild_id,
+                        rxn.user_id,
+                        guild_config.escalation_decay(),
+                        Utc::now().timestamp() as u64,
+                    )
+                }),
+            };
+
+            let filter_result = crate::reaction::filter_reaction(
+                reaction_filters,
+                guild_config.default_scoping.as_ref(),
+                guild_config.default_actions.as_deref(),
+                &reaction_info,
+            );
+
+            if let Err(failure) = filter_result {
+                state.counters.filter_hit(&failure.filter_name);
+                if guild_config.needs_violation_counts() {
+                    state
+                        .violations
+                        .record(guild_id, rxn.user_id, Utc::now().timestamp() as u64);
+                }
+                let mut deleted = false;
+
+                for action in failure.actions {
+                    if matches!(action, ReactionAction::Delete { .. }) {
+                        if deleted {
+                            continue;
+                        }
+
+                        deleted = true;
+                    }
+
+                    if !should_execute_action(
+                        &state,
+                        guild_id,
+                        &action,
+                        action.kind(),
+                        action.requires_armed(),
+                    ) {
+                        continue;
+                    }
+
+                    let action_delay = guild_config
+                        .action_delay
+                        .as_ref()
+                        .filter(|_| action.requires_armed())
+                        .map(|action_delay| action_delay.sample());
+
+                    if !coalesce_action(
+                        &state,
+                        &action,
+                        coalesce::reaction_window(&action),
+                        action_delay.unwrap_or_default(),
+                        guild_config.log.as_ref(),
+                    ) {
+                        continue;
+                    }
+
+                    if let Some(action_delay) = action_delay {
+                        let state = state.clone();
+                        let log_config = guild_config.log.clone();
+                        let metrics = guild_config.metrics.clone();
+                        state
+                            .delayed_actions
+                            .clone()
+                            .schedule(action_delay, async move {
+                                throttle_action(
+                                    &state,
+                                    guild_id,
+                                    metrics.as_ref(),
+                                    action.rate_limit_path(),
+                                )
+                                .await;
+                                match action
+                                    .execute(
+                                        &state.http,
+                                        log_config.as_ref(),
+                                        &state.delayed_actions,
+                                    )
+                                    .await
+                                {
+                                    Ok(()) => state.counters.action_taken(action.kind()),
+                                    Err(action_err) => {
+                                        tracing::warn!(
+                                            ?action_err,
+                                            ?action,
+                                            "Error executing delayed reaction action"
+                                        );
+                                    }
+                                }
+                            });
+                    } else {
+                        throttle_action(
+                            &state,
+                            guild_id,
+                            guild_config.metrics.as_ref(),
+                            action.rate_limit_path(),
+                        )
+                        .await;
+                        match action
+                            .execute(
+                                &state.http,
+                                guild_config.log.as_ref(),
+                                &state.delayed_actions,
+                            )
+                            .await
+                        {
+                            Ok(()) => state.counters.action_taken(action.kind()),
+                            Err(action_err) => {
+                                tracing::warn!(
+                                    ?action_err,
+                                    ?action,
+                                    "Error executing reaction action"
+                                );
+                            }
+                        }
+                    }
+                }
+
+                let report = ReactionFilterReport {
+                    time: Utc::now(),
+                    guild: guild_id.to_string(),
+                    channel: rxn.channel_id.to_string(),
+                };
+
+                send_influx_point(
+                    &state,
+                    guild_id,
+                    guild_config.metrics.as_ref(),
+                    report.into_query("reaction_filter"),
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Decides whether an action should be executed under `guild_id`'s arm state.
+/// In observe mode, actions that would have been executed are logged and
+/// counted in the metrics instead.
+fn should_execute_action(
+    state: &State,
+    guild_id: Id<GuildMarker>,
+    action: &impl std::fmt::Debug,
+    kind: &'static str,
+    requires_armed: bool,
+) -> bool {
+    match state.armed.load(guild_id).disposition(requires_armed) {
+        ActionDisposition::Execute => true,
+        ActionDisposition::Observe => {
+            tracing::info!(
+                observe = true,
+                ?action,
+                "[OBSERVE] Would have executed action"
+            );
+            state.counters.action_observed(kind);
+            false
+        }
+        ActionDisposition::Skip => {
+            tracing::trace!(?action, "Skipping execution because we are not armed");
+            false
+        }
+    }
+}
+
+/// Decides whether to send a log or message that asked to be coalesced, or
+/// hold it back as a repeat. The first one of a window sends, and arranges
+/// for a summary of the repeats to follow once the window closes. The window
+/// is counted from when the first one sends, `send_delay` from now, so the
+/// summary can't arrive before it.
+fn coalesce_action(
+    state: &State,
+    action: &impl std::fmt::Debug,
+    window: Option<coalesce::Window>,
+    send_delay: Duration,
+    log_config: Option<&LogConfig>,
+) -> bool {
+    let window = match window {
+        Some(window) => window,
+        None => return true,
+    };
+
+    if !state.coalescer.admit(&window.key) {
+        tracing::debug!(?action, "Holding back repeated action");
+        return false;
+    }
+
+    let state = state.clone();
+    let log_config = log_config.cloned();
+    state.delayed_actions.clone().schedule_uncapped(
+        send_delay + Duration::from_secs(window.seconds),
+        async move {
+            let summary = match window.summary(state.coalescer.finish(&window.key)) {
+                Some(summary) => summary,
+                None => return,
+            };
+            if let Err(err) = summary
+                .execute(&state.http, log_config.as_ref(), &state.delayed_actions)
+                .await
+            {
+                tracing::warn!(?err, "Unable to send summary of held back actions");
+            }
+        },
+    );
+
+    true
+}
+
+#[tracing::instrument(skip(state))]
+async fn filter_member_names(
+    guild_id: Id<GuildMarker>,
+    user: &User,
+    nick: Option<&str>,
+    state: &State,
+) -> Result<()> {
+    let guild_cfgs = state.guild_cfgs.read().await;
+    if let Some(guild_config) = guild_cfgs.get(&guild_id) {
+        if user.bot && !guild_config.include_bots {
+            tracing::trace!("A bot's names changed and include_bots is not set. Ignoring.");
+            return Ok(());
+        }
+
+        if state.cache.current_user().map(|current| current.id) == Some(user.id) {
+            tracing::trace!("Our own names changed. Ignoring.");
+            return Ok(());
+        }
+
+        if let Some(username_filter) = &guild_config.usernames {
+            // With no nickname, the member is shown by their username, which
+            // is always checked.
+            let mut names = vec![user.name.as_str()];
+            if let Some(nick) = nick {
+                names.push(nick);
+            }
+
+            if !state.checked_names.changed(guild_id, user.id, &names) {
+                tracing::trace!("Member update didn't change any names. Ignoring.");
+                return Ok(());
+            }
+
+            let filter_result = crate::username::filter_names(username_filter, user.id, &names);
+
+            if let Err(failure) = filter_result {
+                for action in failure.actions {
+                    if !should_execute_action(
+                        state,
+                        guild_id,
+                        &action,
+                        action.kind(),
+                        action.requires_armed(),
+                    ) {
+                        continue;
+                    }
+
+                    match action
+                        .execute(
+                            &state.http,
+                            guild_config.log.as_ref(),
+                            &state.delayed_actions,
+                        )
+                        .await
+                    {
+                        Ok(()) => state.counters.action_taken(action.kind()),
+                        Err(action_err) => {
+                            tracing::warn!(?action_err, ?action, "Error executing username action");
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Cancels a message's delayed actions if `rxn` is an approver approving it.
+#[tracing::instrument(skip(state))]
+async fn check_approval(rxn: &GatewayReaction, state: &State) {
+    let (guild_id, member) = match (rxn.guild_id, rxn.member.as_ref()) {
+        (Some(guild_id), Some(member)) => (guild_id, member),
+        _ => return,
+    };
+
+    let approved = state
+        .guild_cfgs
+        .read()
+        .await
+        .get(&guild_id)
+        .and_then(|c| c.approval.as_ref())
+        .map_or(false, |approval| {
+            approval::is_approval(approval, &rxn.emoji, &member.roles)
+        });
+
+    if approved {
+        let cancelled = state.delayed_actions.cancel(rxn.message_id);
+        tracing::debug!(%rxn.message_id, approver = %rxn.user_id, cancelled, "Message approved");
+    }
+}
+
+/// Remembers what message `id` says in the message cache, if there is one,
+/// returning what it said before. Secrets the guild's `secret_scan` rules look
+/// for are redacted first, so a delete-evasion report can't repost them.
+async fn cache_message_content(
+    state: &State,
+    guild_id: Id<GuildMarker>,
+    id: Id<MessageMarker>,
+    author_id: Option<Id<UserMarker>>,
+    content: &str,
+) -> Option<String> {
+    let message_cache = state.message_cache.as_ref().as_ref()?;
+    let presets = state
+        .guild_cfgs
+        .read()
+        .await
+        .get(&guild_id)
+        .map(|c| c.secret_presets())
+        .unwrap_or_default();
+
+    message_cache.replace(
+        id,
+        author_id,
+        &secret::redact(&presets, content),
+        Utc::now().timestamp() as u64,
+    )
+}
+
+/// Forgets a deleted message, reporting it if it was deleted soon enough after
+/// it was posted that the guild's `delete_evasion` wants to know.
+#[tracing::instrument(skip(state))]
+async fn check_message_delete(delete: &MessageDelete, state: &State) -> Result<()> {
+    let (message_cache, guild_id) = match (state.message_cache.as_ref(), delete.guild_id) {
+        (Some(message_cache), Some(guild_id)) => (message_cache, guild_id),
+        _ => return Ok(()),
+    };
+
+    let now_ms = Utc::now().timestamp_millis() as u64;
+    let quick_delete = {
+        let guild_cfgs = state.guild_cfgs.read().await;
+        match guild_cfgs
+            .get(&guild_id)
+            .and_then(|c| c.delete_evasion.as_ref())
+        {
+            Some(config) => {
+                evasion::check_delete(config, message_cache, delete.id, delete.channel_id, now_ms)
+            }
+            None => {
+                message_cache.remove(delete.id, now_ms / 1000);
+                None
+            }
+        }
+    };
+
+    if let Some(quick_delete) = quick_delete {
+        tracing::debug!(?quick_delete, "Message deleted soon after it was posted");
+        send_notification_to_guild(
+            state,
+            guild_id,
+            "Message deleted soon after posting",
+            &quick_delete.describe(),
+        )
+        .await?;
+    }
+
+    Ok(())
+}
+
+#[tracing::instrument(skip(state))]
+async fn filter_stage_topic(stage: &StageInstance, state: &State) -> Result<()> {
+    let guild_cfgs = state.guild_cfgs.read().await;
+    let filter = match guild_cfgs
+        .get(&stage.guild_id)
+        .and_then(|c| c.channel_status_filter.as_ref())
+    {
+        Some(filter) => filter,
+        None => return Ok(()),
+    };
+
+    let previous = state.stage_topics.last_accepted(stage.channel_id);
+    let failure = match channel_status::filter_stage_topic(filter, stage, previous.as_deref()) {
+        Ok(()) => {
+            state.stage_topics.accepted(stage.channel_id, &stage.topic);
+            return Ok(());
+        }
+        Err(failure) => failure,
+    };
+
+    let log_config = guild_cfgs.get(&stage.guild_id).and_then(|c| c.log.as_ref());
+    for action in failure.actions {
+        if !should_execute_action(
+            state,
+            stage.guild_id,
+            &action,
+            action.kind(),
+            action.requires_armed(),
+        ) {
+            continue;
+        }
+
+        match action
+            .execute(&state.http, log_config, &state.delayed_actions)
+            .await
+        {
+            Ok(()) => state.counters.action_taken(action.kind()),
+            Err(action_err) => {
+                tracing::warn!(?action_err, ?action, "Error executing stage topic action");
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[tracing::instrument(skip(state, turn))]
+async fn filter_message_edit_http(
+    update: &MessageUpdate,
+    state: &State,
+    previous_content: Option<&str>,
+    turn: Option<Turn>,
+) -> Result<()> {
+    let guild_id = match update.guild_id {
+        Some(id) => id,
+        None => return Ok(()),
+    };
+
+    let (author_id, author_is_bot) = match &update.author {
+        Some(author) => (author.id, author.bot),
+        None => return Ok(()),
+    };
+
+    let http_message = state
+        .http
+        .message(update.channel_id, update.id)
+        .await?
+        .model()
+        .await?;
+
+    let author_roles = {
+        let cached_member = state.cache.member(guild_id, author_id);
+        match cached_member.as_ref() {
+            Some(member) => member.roles().to_owned(),
+            None => state
+                .http
+                .guild_member(guild_id, author_id)
+                .await?
+                .model()
+                .await?
+                .roles
+                .clone(),
+        }
+    };
+
+    let mentioned_users: Vec<_> = http_message.mentions.iter().map(|m| m.id).collect();
+    let targeted_users = crate::message::targeted_users(
+        mentioned_users.iter().copied(),
+        http_message
+            .referenced_message
+            .as_ref()
+            .map(|m| m.author.id)
+            .or_else(|| reply_author(state, http_message.reference.as_ref())),
+    );
+
+    let message_info = MessageInfo {
+        id: http_message.id,
+        channel_id: http_message.channel_id,
+        category_id: channel_category(&state.cache, http_message.channel_id),
+        // We can assume guild_id exists since the DM intent is disabled
+        guild_id: http_message.guild_id.unwrap(),
+        timestamp: http_message.timestamp,
+        author_roles: &author_roles[..],
+        content: &http_message.content,
+        kind: http_message.kind,
+        attachments: &http_message.attachments,
+        stickers: &http_message.sticker_items,
+        components: &http_message.components,
+        embeds: &http_message.embeds,
+        previous_content,
+        previous_post: None,
+        prior_infractions: None,
+        prior_violations: None,
+        targeted_users: &targeted_users,
+        mentioned_users: &mentioned_users,
+        mentioned_roles: &http_message.mention_roles,
+        sticker_metadata: &[],
+        link_previews: &[],
+        resolved_invites: &[],
+        author_id,
+        author_is_bot,
+    };
+
+    filter_message_info(guild_id, &message_info, state, "message edit", turn).await
+}
+
+#[tracing::instrument(skip(state, turn))]
+async fn filter_message_edit(
+    update: &MessageUpdate,
+    state: &State,
+    turn: Option<Turn>,
+) -> Result<()> {
+    let guild_id = match update.guild_id {
+        Some(id) => id,
+        None => return Ok(()),
+    };
+
+    let previous_content = match update.content.as_deref() {
+        Some(content) => {
+            cache_message_content(
+                state,
+                guild_id,
+                update.id,
+                update.author.as_ref().map(|a| a.id),
+                content,
+            )
+            .await
+        }
+        None => None,
+    };
+
+    // Updates without content are usually Discord adding link embeds; the
+    // content was already checked when it was posted or last edited.
+    if update.content.is_none() && update.embeds.is_none() {
+        tracing::trace!("Skipping message update without content or embeds");
+        return Ok(());
+    }
+
+    // Check scoping before the message or its author might be fetched.
+    let in_scope = state
+        .guild_cfgs
+        .read()
+        .await
+        .get(&guild_id)
+        .map_or(false, |guild_config| {
+            guild_config.filters_messages_in(
+                update.channel_id,
+                channel_category(&state.cache, update.channel_id),
+            )
+        });
+    if !in_scope {
+        tracing::trace!(%update.channel_id, "Skipping message update outside every filter's scope");
+        return Ok(());
+    }
+
+    let cached_message = state.cache.message(update.id);
+
+    match (cached_message, update.content.as_deref()) {
+        (Some(message), Some(content)) => {
+            tracing::trace!("Got message from cache and content from update");
+
+            let (author_id, author_is_bot) = match update.author.as_ref() {
+                Some(author) => (author.id, author.bot),
+                None => {
+                    let cached_author = state.cache.user(message.author());
+                    match cached_author {
+                        Some(author) => (author.id, author.bot),
+                        None => {
+                            // Drop the reference to the cached data. In general, updating the
+                            // Twilight cache can deadlock when a message gets deleted while
+                            // another thread holds a reference to the cached message. Dropping
+                            // the cached reference prevents this.
+                            drop(message);
+                            return filter_message_edit_http(
+                                update,
+                                state,
+                                previous_content.as_deref(),
+                                turn,
+                            )
+                            .await;
+                        }
+                    }
+                }
+            };
+
+            let timestamp = message.timestamp();
+            let kind = message.kind();
+            let attachments = message.attachments().to_owned();
+            let sticker_items = message.sticker_items().to_owned();
+            let components = message.components().to_owned();
+            let embeds = update
+                .embeds
+                .clone()
+                .unwrap_or_else(|| message.embeds().to_owned());
+            let mentioned_users: Vec<_> = match update.mentions.as_ref() {
+                Some(mentions) => mentions.iter().map(|m| m.id).collect(),
+                None => message.mentions().to_owned(),
+            };
+            let mentioned_roles = update
+                .mention_roles
+                .clone()
+                .unwrap_or_else(|| message.mention_roles().to_owned());
+            let reference = message.reference().cloned();
+
+            // For the same reason as above, we drop the message here.
+            drop(message);
+            let targeted_users = crate::message::targeted_users(
+                mentioned_users.iter().copied(),
+                reply_author(state, reference.as_ref()),
+            );
+
+            let author_roles = {
+                let cached_member = state.cache.member(guild_id, author_id);
+                match cached_member.as_ref() {
+                    Some(member) => member.roles().to_owned(),
+                    None => {
+                        return filter_message_edit_http(
+                            update,
+                            state,
+                            previous_content.as_deref(),
+                            turn,
+                        )
+                        .await
+                    }
+                }
+            };
+
+            let clean_message_content =
+                crate::message::clean_mentions(content, update.mentions.as_deref().unwrap_or(&[]));
+
+            let message_info = MessageInfo {
+                id: update.id,
+                author_id,
+                author_is_bot,
+                // We can assume guild_id exists since the DM intent is disabled
+                guild_id: update.guild_id.unwrap(),
+                author_roles: &author_roles[..],
+                content: &clean_message_content,
+                kind,
+                channel_id: update.channel_id,
+                category_id: channel_category(&state.cache, update.channel_id),
+                timestamp,
+                attachments: &attachments[..],
+                stickers: &sticker_items[..],
+                components: &components[..],
+                embeds: &embeds[..],
+                previous_content: previous_content.as_deref(),
+                previous_post: None,
+                prior_infractions: None,
+                prior_violations: None,
+                targeted_users: &targeted_users,
+                mentioned_users: &mentioned_users,
+                mentioned_roles: &mentioned_roles,
+                sticker_metadata: &[],
+                link_previews: &[],
+                resolved_invites: &[],
+            };
+
+            filter_message_info(guild_id, &message_info, state, "message edit", turn).await
+        }
+        _ => filter_message_edit_http(update, state, previous_content.as_deref(), turn).await,
+    }
+}
+
+/// Tells each guild about its filters that were disabled for going over their
+/// action caps.
+async fn notify_tripped_caps(state: State, tripped: Vec<caps::TrippedCap>) {
+    for cap in tripped {
+        let body = format!(
+            "Filter `{}` tried to take more than {} actions in a minute, so it has been disabled. It will stay disabled until configurations are reloaded with `chrysanthemum-reload`.",
+            cap.filter_name, cap.cap
+        );
+
+        if let Err(err) =
+            send_notification_to_guild(&state, cap.guild_id, "Filter disabled", &body).await
+        {
+            tracing::error!(?err, guild_id = %cap.guild_id, "Error sending action cap notification");
+        }
+    }
+}
+
+/// Sends an embed to the guild's notification channel, mentioning its
+/// notification roles. Does nothing if the guild has no notifications set up.
+#[tracing::instrument(skip(state))]
+async fn send_notification_to_guild(
+    state: &State,
+    guild_id: Id<GuildMarker>,
+    title: &str,
+    body: &str,
+) -> Result<()> {
+    let guild_configs = state.guild_cfgs.read().await;
+    if let Some(guild_config) = guild_configs.get(&guild_id) {
+        if let Some(notification_config) = &guild_config.notifications {
+            let mut builder = EmbedBuilder::new().title(title).description(body);
+
+            if let Some(ping_roles) = &notification_config.ping_roles {
+                let mut cc_body = String::new();
+                for role in ping_roles {
+                    cc_body += &role.mention().to_string();
+                    cc_body += " ";
+                }
+
+                builder = builder.field(EmbedFieldBuilder::new("CC", cc_body).build());
+            }
+
+            state
+                .http
+                .create_message(notification_config.channel)
+                .embeds(&[builder.build()])?
+                .await?;
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,188 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+
+use twilight_model::id::{
+    marker::{MessageMarker, UserMarker},
+    Id,
+};
+
+use crate::config::MessageCacheConfig;
+
+#[derive(Debug)]
+struct CachedMessage {
+    author_id: Option<Id<UserMarker>>,
+    content: String,
+    /// When the content was stored, in seconds.
+    stored_at: u64,
+    /// Where the message is in `CacheState::recency`.
+    use_order: u64,
+}
+
+#[derive(Debug, Default)]
+struct CacheState {
+    messages: HashMap<Id<MessageMarker>, CachedMessage>,
+    /// Cached messages, least recently stored first.
+    recency: BTreeMap<u64, Id<MessageMarker>>,
+    next_use: u64,
+}
+
+/// Remembers the content of recent messages, so an edit can be logged with what
+/// the message said before.
+///
+/// Holds at most `capacity` messages, dropping the least recently stored when
+/// full, and forgets messages after `ttl` seconds.
+#[derive(Debug)]
+pub(crate) struct MessageCache {
+    capacity: usize,
+    ttl: u64,
+    state: Mutex<CacheState>,
+}
+
+impl MessageCache {
+    pub(crate) fn new(config: &MessageCacheConfig) -> Self {
+        Self {
+            capacity: config.capacity,
+            ttl: config.ttl,
+            state: Mutex::new(CacheState::default()),
+        }
+    }
+
+    /// Stores `content` as what message `id` says at `now`, in seconds,
+    /// returning what it said before if that's still remembered. If
+    /// `author_id` isn't known, the one stored before is kept.
+    pub(crate) fn replace(
+        &self,
+        id: Id<MessageMarker>,
+        author_id: Option<Id<UserMarker>>,
+        content: &str,
+        now: u64,
+    ) -> Option<String> {
+        if self.capacity == 0 {
+            return None;
+        }
+
+        let mut state = self.state.lock().unwrap();
+        let use_order = state.next_use;
+        state.next_use += 1;
+
+        let previous = state.messages.remove(&id);
+        if let Some(previous) = &previous {
+            state.recency.remove(&previous.use_order);
+        }
+        state.messages.insert(
+            id,
+            CachedMessage {
+                author_id: author_id.or_else(|| previous.as_ref()?.author_id),
+                content: content.to_owned(),
+                stored_at: now,
+                use_order,
+            },
+        );
+        state.recency.insert(use_order, id);
+
+        while state.messages.len() > self.capacity {
+            let (_, oldest) = match state.recency.pop_first() {
+                Some(oldest) => oldest,
+                None => break,
+            };
+            state.messages.remove(&oldest);
+        }
+
+        previous
+            .filter(|previous| now.saturating_sub(previous.stored_at) < self.ttl)
+            .map(|previous| previous.content)
+    }
+
+    /// Forgets message `id`, returning who sent it and what it said if that's
+    /// still remembered at `now`, in seconds.
+    pub(crate) fn remove(
+        &self,
+        id: Id<MessageMarker>,
+        now: u64,
+    ) -> Option<(Option<Id<UserMarker>>, String)> {
+        let mut state = self.state.lock().unwrap();
+        let message = state.messages.remove(&id)?;
+        state.recency.remove(&message.use_order);
+
+        if now.saturating_sub(message.stored_at) < self.ttl {
+            Some((message.author_id, message.content))
+        } else {
+            None
+        }
+    }
+
+    /// Forgets messages older than the TTL, returning how many are left.
+    pub(crate) fn prune(&self, now: u64) -> usize {
+        let mut state = self.state.lock().unwrap();
+        let CacheState {
+            messages, recency, ..
+        } = &mut *state;
+
+        messages.retain(|_, message| {
+            let fresh = now.saturating_sub(message.stored_at) < self.ttl;
+            if !fresh {
+                recency.remove(&message.use_order);
+            }
+            fresh
+        });
+
+        messages.len()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+    use twilight_model::id::Id;
+
+    use super::MessageCache;
+    use crate::config::MessageCacheConfig;
+
+    fn cache(capacity: usize) -> MessageCache {
+        MessageCache::new(&MessageCacheConfig { capacity, ttl: 60 })
+    }
+
+    #[test]
+    fn replace_returns_previous_content() {
+        let cache = cache(10);
+        assert_eq!(cache.replace(Id::new(1), None, "first", 0), None);
+        assert_eq!(
+            cache.replace(Id::new(1), None, "second", 1),
+            Some("first".to_owned())
+        );
+        assert_eq!(
+            cache.replace(Id::new(1), None, "third", 2),
+            Some("second".to_owned())
+        );
+    }
+
+    #[test]
+    fn least_recent_messages_are_evicted() {
+        let cache = cache(2);
+        cache.replace(Id::new(1), None, "one", 0);
+        cache.replace(Id::new(2), None, "two", 0);
+        // Touching the first message makes the second the least recent.
+        cache.replace(Id::new(1), None, "one, edited", 0);
+        cache.replace(Id::new(3), None, "three", 0);
+
+        assert_eq!(
+            cache.replace(Id::new(1), None, "one, edited again", 0),
+            Some("one, edited".to_owned())
+        );
+        assert_eq!(cache.replace(Id::new(2), None, "two, edited", 0), None);
+    }
+
+    #[test]
+    fn expired_messages_are_forgotten() {
+        let cache = cache(10);
+        cache.replace(Id::new(1), None, "old", 0);
+        cache.replace(Id::new(2), None, "new", 30);
+
+        assert_eq!(cache.prune(60), 1);
+        assert_eq!(cache.replace(Id::new(1), None, "old, edited", 60), None);
+        assert_eq!(
+            cache.replace(Id::new(2), None, "new, edited", 61),
+            Some("new".to_owned())
+        );
+    }
+}
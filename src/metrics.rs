@@ -0,0 +1,170 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use twilight_model::id::{marker::GuildMarker, Id};
+
+/// Decides which metrics points get reported. Each guild has its own counter,
+/// so a guild that overrides `report_every_n` gets its own cadence regardless
+/// of how busy other guilds are.
+#[derive(Debug, Default)]
+pub(crate) struct ReportSampler {
+    counts: Mutex<HashMap<Id<GuildMarker>, usize>>,
+}
+
+impl ReportSampler {
+    pub(crate) fn should_report(&self, guild_id: Id<GuildMarker>, report_every_n: usize) -> bool {
+        let mut counts = self.counts.lock().unwrap();
+        let count = counts.entry(guild_id).or_insert(0);
+        let report = *count % report_every_n.max(1) == 0;
+        *count = count.wrapping_add(1);
+        report
+    }
+}
+
+/// Running totals of what Chrysanthemum has done since it started, counted
+/// where the matching Influx points are sent.
+#[derive(Debug, Default)]
+pub(crate) struct Counters {
+    messages_scanned: AtomicU64,
+    filter_hits: Mutex<BTreeMap<String, u64>>,
+    actions_taken: Mutex<BTreeMap<&'static str, u64>>,
+    /// Actions observe mode held back, by type.
+    actions_observed: Mutex<BTreeMap<&'static str, u64>>,
+}
+
+impl Counters {
+    pub(crate) fn message_scanned(&self) {
+        self.messages_scanned.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn filter_hit(&self, filter_name: &str) {
+        let mut filter_hits = self.filter_hits.lock().unwrap();
+        match filter_hits.get_mut(filter_name) {
+            Some(count) => *count += 1,
+            None => {
+                filter_hits.insert(filter_name.to_owned(), 1);
+            }
+        }
+    }
+
+    pub(crate) fn action_taken(&self, kind: &'static str) {
+        *self.actions_taken.lock().unwrap().entry(kind).or_insert(0) += 1;
+    }
+
+    pub(crate) fn action_observed(&self, kind: &'static str) {
+        *self
+            .actions_observed
+            .lock()
+            .unwrap()
+            .entry(kind)
+            .or_insert(0) += 1;
+    }
+
+    /// Renders the counters in Prometheus' text format.
+    pub(crate) fn render(&self) -> String {
+        let mut text = String::new();
+
+        text.push_str(
+            "# HELP chrysanthemum_messages_scanned_total Messages checked against filters.\n",
+        );
+        text.push_str("# TYPE chrysanthemum_messages_scanned_total counter\n");
+        let _ = writeln!(
+            text,
+            "chrysanthemum_messages_scanned_total {}",
+            self.messages_scanned.load(Ordering::Relaxed)
+        );
+
+        text.push_str("# HELP chrysanthemum_filter_hits_total Messages each filter matched.\n");
+        text.push_str("# TYPE chrysanthemum_filter_hits_total counter\n");
+        for (filter_name, count) in self.filter_hits.lock().unwrap().iter() {
+            let _ = writeln!(
+                text,
+                "chrysanthemum_filter_hits_total{{filter=\"{}\"}} {}",
+                escape_label(filter_name),
+                count
+            );
+        }
+
+        text.push_str("# HELP chrysanthemum_actions_taken_total Actions taken, by type.\n");
+        text.push_str("# TYPE chrysanthemum_actions_taken_total counter\n");
+        for (kind, count) in self.actions_taken.lock().unwrap().iter() {
+            let _ = writeln!(
+                text,
+                "chrysanthemum_actions_taken_total{{action=\"{}\"}} {}",
+                kind, count
+            );
+        }
+
+        text.push_str(
+            "# HELP chrysanthemum_actions_observed_total Actions observe mode held back, by type.\n",
+        );
+        text.push_str("# TYPE chrysanthemum_actions_observed_total counter\n");
+        for (kind, count) in self.actions_observed.lock().unwrap().iter() {
+            let _ = writeln!(
+                text,
+                "chrysanthemum_actions_observed_total{{action=\"{}\"}} {}",
+                kind, count
+            );
+        }
+
+        text
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+    use twilight_model::id::Id;
+
+    use super::{Counters, ReportSampler};
+
+    #[test]
+    fn guild_override_changes_cadence() {
+        let sampler = ReportSampler::default();
+
+        let default_guild: Vec<_> = (0..6)
+            .map(|_| sampler.should_report(Id::new(1), 3))
+            .collect();
+        let overridden_guild: Vec<_> = (0..6)
+            .map(|_| sampler.should_report(Id::new(2), 2))
+            .collect();
+
+        assert_eq!(default_guild, [true, false, false, true, false, false]);
+        assert_eq!(overridden_guild, [true, false, true, false, true, false]);
+    }
+
+    #[test]
+    fn report_every_point_when_n_is_one() {
+        let sampler = ReportSampler::default();
+        assert!((0..5).all(|_| sampler.should_report(Id::new(1), 1)));
+    }
+
+    #[test]
+    fn renders_counters() {
+        let counters = Counters::default();
+        counters.message_scanned();
+        counters.message_scanned();
+        counters.filter_hit("Scams");
+        counters.filter_hit("Scams");
+        counters.filter_hit("say \"hi\"");
+        counters.action_taken("delete");
+        counters.action_observed("ban");
+
+        let text = counters.render();
+        assert!(text.contains("\nchrysanthemum_messages_scanned_total 2\n"));
+        assert!(text.contains("\nchrysanthemum_filter_hits_total{filter=\"Scams\"} 2\n"));
+        assert!(text.contains("\nchrysanthemum_filter_hits_total{filter=\"say \\\"hi\\\"\"} 1\n"));
+        assert!(text.contains("\nchrysanthemum_actions_taken_total{action=\"delete\"} 1\n"));
+        assert!(text.contains("\nchrysanthemum_actions_observed_total{action=\"ban\"} 1\n"));
+        assert_eq!(text.matches("# TYPE").count(), 4);
+    }
+}
@@ -1,24 +1,102 @@
 use twilight_model::{
-    channel::{message::sticker::MessageSticker, message::ReactionType, Attachment},
+    channel::{
+        message::sticker::MessageSticker, message::Component, message::Embed, message::MessageType,
+        message::ReactionType, Attachment,
+    },
     id::{
-        marker::{ChannelMarker, GuildMarker, MessageMarker, RoleMarker, UserMarker},
+        marker::{
+            ChannelMarker, GuildMarker, MessageMarker, RoleMarker, StickerMarker, UserMarker,
+        },
         Id,
     },
     util::datetime::Timestamp,
 };
 
+/// A message, along with everything message filters may need to know about it
+/// that Discord doesn't send with the message itself.
 #[derive(Debug, PartialEq, Eq)]
-pub(crate) struct MessageInfo<'a> {
-    pub(crate) author_is_bot: bool,
-    pub(crate) id: Id<MessageMarker>,
-    pub(crate) author_id: Id<UserMarker>,
-    pub(crate) channel_id: Id<ChannelMarker>,
-    pub(crate) guild_id: Id<GuildMarker>,
-    pub(crate) author_roles: &'a [Id<RoleMarker>],
-    pub(crate) content: &'a str,
-    pub(crate) timestamp: Timestamp,
-    pub(crate) attachments: &'a [Attachment],
-    pub(crate) stickers: &'a [MessageSticker],
+pub struct MessageInfo<'a> {
+    pub author_is_bot: bool,
+    pub id: Id<MessageMarker>,
+    pub author_id: Id<UserMarker>,
+    pub channel_id: Id<ChannelMarker>,
+    /// The category the channel is in, if it has one and it's cached.
+    pub category_id: Option<Id<ChannelMarker>>,
+    pub guild_id: Id<GuildMarker>,
+    pub author_roles: &'a [Id<RoleMarker>],
+    pub content: &'a str,
+    pub kind: MessageType,
+    pub timestamp: Timestamp,
+    pub attachments: &'a [Attachment],
+    pub stickers: &'a [MessageSticker],
+    pub components: &'a [Component],
+    pub embeds: &'a [Embed],
+    /// What the message said before this edit, if it's an edit and the message
+    /// cache remembers it.
+    pub previous_content: Option<&'a str>,
+    /// When the author last posted in this channel before this message, if
+    /// it's a new message and a `min_interval` rule asked for it.
+    pub previous_post: Option<Timestamp>,
+    /// How many infractions the author has on record, if the infraction store
+    /// is enabled and a `prior_infractions` rule asked for it.
+    pub prior_infractions: Option<usize>,
+    /// How many times the author has broken this guild's filters recently,
+    /// not counting this message, if an `escalate` action asked for it.
+    pub prior_violations: Option<usize>,
+    /// Users the message mentions, along with the author of the message it
+    /// replies to, if known.
+    pub targeted_users: &'a [Id<UserMarker>],
+    /// Users and roles the message mentions.
+    pub mentioned_users: &'a [Id<UserMarker>],
+    pub mentioned_roles: &'a [Id<RoleMarker>],
+    /// Descriptions and tags for the message's stickers. Only fetched when a
+    /// sticker rule asks for them.
+    pub sticker_metadata: &'a [StickerMetadata],
+    /// Preview titles and descriptions of the pages the message links to.
+    /// Only fetched when a link rule asks for them.
+    pub link_previews: &'a [LinkPreview],
+    /// The guilds the message's invites lead to. Only resolved when an invite
+    /// rule asks for them.
+    pub resolved_invites: &'a [ResolvedInvite],
+}
+
+impl MessageInfo<'_> {
+    /// Whether Discord generated this message, such as for a pin or a member
+    /// joining, rather than someone writing it. Only types known to be
+    /// written by a user or an app count as written; anything else, including
+    /// types Discord adds later, is treated as generated.
+    pub(crate) fn is_system(&self) -> bool {
+        !matches!(
+            self.kind,
+            MessageType::Regular
+                | MessageType::Reply
+                | MessageType::ChatInputCommand
+                | MessageType::ContextMenuCommand
+        )
+    }
+}
+
+/// The parts of a sticker that aren't sent with the message.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StickerMetadata {
+    pub id: Id<StickerMarker>,
+    pub description: Option<String>,
+    pub tags: String,
+}
+
+/// What a link would show in its embed, taken from the page's OpenGraph tags.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LinkPreview {
+    pub url: String,
+    pub title: Option<String>,
+    pub description: Option<String>,
+}
+
+/// An invite code, and the guild it leads to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ResolvedInvite {
+    pub code: String,
+    pub guild_id: Id<GuildMarker>,
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -28,15 +106,24 @@ pub(crate) struct ReactionInfo<'a> {
     pub(crate) author_id: Id<UserMarker>,
     pub(crate) message_id: Id<MessageMarker>,
     pub(crate) channel_id: Id<ChannelMarker>,
+    /// The category the channel is in, if it has one and it's cached.
+    pub(crate) category_id: Option<Id<ChannelMarker>>,
     pub(crate) guild_id: Id<GuildMarker>,
     pub(crate) reaction: ReactionType,
+    /// How many distinct reactions the author has on this message, including
+    /// this one. Only tracked when a reaction count rule is configured.
+    pub(crate) distinct_reactions: usize,
+    /// How many times the author has broken this guild's filters recently,
+    /// not counting this reaction, if an `escalate` action asked for it.
+    pub(crate) prior_violations: Option<usize>,
 }
 
 #[cfg(test)]
+#[allow(clippy::redundant_static_lifetimes, clippy::redundant_field_names)]
 pub(crate) mod test {
     use twilight_model::{
         channel::message::Mention,
-        channel::message::ReactionType,
+        channel::message::{MessageType, ReactionType},
         id::{
             marker::{ChannelMarker, GuildMarker, MessageMarker, UserMarker},
             Id,
@@ -79,9 +166,23 @@ pub(crate) mod test {
             guild_id: GUILD_ID,
             author_roles: &[],
             content: content,
+            kind: MessageType::Regular,
             timestamp: Timestamp::from_secs(100).unwrap(),
             attachments: &[],
             stickers: &[],
+            components: &[],
+            embeds: &[],
+            previous_content: None,
+            previous_post: None,
+            prior_infractions: None,
+            prior_violations: None,
+            targeted_users: &[],
+            category_id: None,
+            mentioned_users: &[],
+            mentioned_roles: &[],
+            sticker_metadata: &[],
+            link_previews: &[],
+            resolved_invites: &[],
         }
     }
 
@@ -97,11 +198,14 @@ pub(crate) mod test {
             author_roles: &[],
             author_id: USER_ID,
             channel_id: CHANNEL_ID,
+            category_id: None,
             message_id: MESSAGE_ID,
             guild_id: GUILD_ID,
             reaction: ReactionType::Unicode {
                 name: rxn.to_string(),
             },
+            distinct_reactions: 1,
+            prior_violations: None,
         }
     }
 }
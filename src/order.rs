@@ -0,0 +1,130 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use tokio::sync::oneshot;
+use twilight_model::id::{marker::ChannelMarker, Id};
+
+/// Keeps actions within a channel in the order their messages arrived, while
+/// letting channels proceed independently of each other.
+///
+/// Each message takes a [`Turn`] when it arrives. Evaluation can happen in any
+/// order, but a turn only finishes waiting once every earlier turn in the same
+/// channel has been dropped.
+#[derive(Debug, Default)]
+pub(crate) struct ChannelOrder {
+    tails: Mutex<HashMap<Id<ChannelMarker>, oneshot::Receiver<()>>>,
+}
+
+impl ChannelOrder {
+    /// Takes the next turn in `channel`. Must be called in arrival order.
+    pub(crate) fn turn(&self, channel: Id<ChannelMarker>) -> Turn {
+        let (done, next) = oneshot::channel();
+        let previous = self.tails.lock().unwrap().insert(channel, next);
+
+        Turn {
+            previous,
+            done: Some(done),
+        }
+    }
+}
+
+/// A message's place in its channel's queue. Dropping it lets the next message
+/// in the channel go ahead.
+#[derive(Debug)]
+pub(crate) struct Turn {
+    previous: Option<oneshot::Receiver<()>>,
+    done: Option<oneshot::Sender<()>>,
+}
+
+impl Turn {
+    /// Waits until every earlier message in the channel has finished.
+    pub(crate) async fn wait(&mut self) {
+        if let Some(previous) = self.previous.as_mut() {
+            // An error just means the previous turn was dropped, which is what
+            // we're waiting for.
+            let _ = previous.await;
+            self.previous = None;
+        }
+    }
+}
+
+impl Drop for Turn {
+    fn drop(&mut self) {
+        // If we never waited, later turns must still wait for earlier ones, so
+        // hand our place on once the previous turn is done.
+        if let Some(previous) = self.previous.take() {
+            let done = self.done.take();
+            tokio::spawn(async move {
+                let _ = previous.await;
+                drop(done);
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use pretty_assertions::assert_eq;
+    use twilight_model::id::Id;
+
+    use super::ChannelOrder;
+
+    #[tokio::test(start_paused = true)]
+    async fn actions_run_in_arrival_order() {
+        let order = ChannelOrder::default();
+        let executed = Arc::new(Mutex::new(Vec::new()));
+
+        let mut tasks = Vec::new();
+        // The first message takes longest to evaluate, but must still act first.
+        for (message, evaluation_ms) in [(1, 300), (2, 10)] {
+            let mut turn = order.turn(Id::new(1));
+            let executed = executed.clone();
+            tasks.push(tokio::spawn(async move {
+                tokio::time::sleep(Duration::from_millis(evaluation_ms)).await;
+                turn.wait().await;
+                executed.lock().unwrap().push(message);
+            }));
+        }
+
+        for task in tasks {
+            task.await.unwrap();
+        }
+
+        assert_eq!(*executed.lock().unwrap(), vec![1, 2]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn channels_are_independent() {
+        let order = ChannelOrder::default();
+        let _slow = order.turn(Id::new(1));
+        let mut other = order.turn(Id::new(2));
+
+        tokio::time::timeout(Duration::from_millis(1), other.wait())
+            .await
+            .expect("a turn in another channel shouldn't wait");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn dropped_turns_keep_order() {
+        let order = ChannelOrder::default();
+        let mut first = order.turn(Id::new(1));
+        // The second message needs no actions, so never waits.
+        let second = order.turn(Id::new(1));
+        let mut third = order.turn(Id::new(1));
+        drop(second);
+
+        first.wait().await;
+        assert!(
+            tokio::time::timeout(Duration::from_millis(10), third.wait())
+                .await
+                .is_err(),
+            "the third turn went ahead of the first"
+        );
+
+        drop(first);
+        third.wait().await;
+    }
+}
@@ -0,0 +1,428 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use eyre::{eyre, Result};
+use once_cell::sync::OnceCell;
+use regex::{Regex, RegexBuilder};
+use tokio::sync::RwLock;
+
+use crate::model::LinkPreview;
+
+/// How much of a page to download when looking for its preview tags. They
+/// belong in `<head>`, so this comfortably covers well-formed pages.
+const MAX_PAGE_BYTES: usize = 256 * 1024;
+/// How long a single page fetch may take.
+const FETCH_TIMEOUT: Duration = Duration::from_secs(3);
+/// How many links in one message we'll fetch previews for.
+const MAX_PREVIEWS_PER_MESSAGE: usize = 3;
+/// How long to remember a page's preview, or that it couldn't be fetched.
+const CACHE_TTL: Duration = Duration::from_secs(60 * 60);
+/// How many pages to remember before starting over.
+const MAX_CACHED_PREVIEWS: usize = 10_000;
+
+fn meta_tag_regex() -> &'static Regex {
+    static REGEX: OnceCell<Regex> = OnceCell::new();
+    REGEX.get_or_init(|| {
+        RegexBuilder::new(r"<meta\s[^>]*>")
+            .case_insensitive(true)
+            .build()
+            .unwrap()
+    })
+}
+
+fn attribute_regex() -> &'static Regex {
+    static REGEX: OnceCell<Regex> = OnceCell::new();
+    REGEX.get_or_init(|| Regex::new(r#"([\w:-]+)\s*=\s*(?:"([^"]*)"|'([^']*)')"#).unwrap())
+}
+
+fn title_regex() -> &'static Regex {
+    static REGEX: OnceCell<Regex> = OnceCell::new();
+    REGEX.get_or_init(|| {
+        RegexBuilder::new(r"<title[^>]*>([^<]*)</title>")
+            .case_insensitive(true)
+            .build()
+            .unwrap()
+    })
+}
+
+fn decode_entities(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&#x27;", "'")
+        .replace("&amp;", "&")
+        .trim()
+        .to_owned()
+}
+
+/// Pulls the title and description a link embed would show out of a page's
+/// HTML, preferring OpenGraph tags over the plain `<title>` and description.
+pub(crate) fn parse_preview(url: &str, html: &str) -> LinkPreview {
+    let mut tags = HashMap::new();
+    for tag in meta_tag_regex().find_iter(html) {
+        let mut key = None;
+        let mut content = None;
+        for attribute in attribute_regex().captures_iter(tag.as_str()) {
+            let value = attribute.get(2).or_else(|| attribute.get(3)).unwrap();
+            match attribute[1].to_ascii_lowercase().as_str() {
+                "property" | "name" => key = Some(value.as_str().to_ascii_lowercase()),
+                "content" => content = Some(value.as_str()),
+                _ => {}
+            }
+        }
+
+        if let (Some(key), Some(content)) = (key, content) {
+            tags.entry(key).or_insert_with(|| decode_entities(content));
+        }
+    }
+
+    let title = tags
+        .remove("og:title")
+        .or_else(|| title_regex().captures(html).map(|c| decode_entities(&c[1])));
+    let description = tags
+        .remove("og:description")
+        .or_else(|| tags.remove("description"));
+
+    LinkPreview {
+        url: url.to_owned(),
+        title: title.filter(|t| !t.is_empty()),
+        description: description.filter(|d| !d.is_empty()),
+    }
+}
+
+/// Whether `ip` is reachable on the public internet. Anything else could let a
+/// message make us probe the network we run on.
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                // "This network", 0.0.0.0/8, which reaches the local host.
+                || a == 0
+                || ip.is_multicast()
+                // Carrier-grade NAT, 100.64.0.0/10.
+                || (a == 100 && (64..128).contains(&b)))
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public(IpAddr::V4(ip)),
+            None => {
+                let segments = ip.segments();
+                let first = segments[0];
+                !(ip.is_loopback()
+                    || ip.is_unspecified()
+                    || ip.is_multicast()
+                    // Unique local, fc00::/7.
+                    || (first & 0xfe00) == 0xfc00
+                    // Link local, fe80::/10.
+                    || (first & 0xffc0) == 0xfe80
+                    // NAT64, 64:ff9b::/96, and 6to4, 2002::/16, both of which
+                    // embed an IPv4 address that could be private.
+                    || segments[..6] == [0x64, 0xff9b, 0, 0, 0, 0]
+                    || first == 0x2002)
+            }
+        },
+    }
+}
+
+/// Resolves hosts for the preview client, refusing any that resolve to an
+/// address that isn't public. The client connects to the addresses checked
+/// here, so a host can't pass the check and then resolve somewhere else.
+struct PublicResolver;
+
+impl reqwest::dns::Resolve for PublicResolver {
+    fn resolve(&self, name: hyper::client::connect::dns::Name) -> reqwest::dns::Resolving {
+        Box::pin(async move {
+            let addrs: Vec<_> = tokio::net::lookup_host((name.as_str(), 0)).await?.collect();
+            if let Some(addr) = addrs.iter().find(|a| !is_public(a.ip())) {
+                return Err(
+                    format!("{} resolves to non-public address {}", name, addr.ip()).into(),
+                );
+            }
+
+            Ok(Box::new(addrs.into_iter()) as reqwest::dns::Addrs)
+        })
+    }
+}
+
+/// The client previews are fetched with. Redirects aren't followed, since they
+/// could lead anywhere.
+fn client() -> &'static reqwest::Client {
+    static CLIENT: OnceCell<reqwest::Client> = OnceCell::new();
+    CLIENT.get_or_init(|| {
+        reqwest::Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .timeout(FETCH_TIMEOUT)
+            .dns_resolver(Arc::new(PublicResolver))
+            .build()
+            .expect("couldn't build link preview client")
+    })
+}
+
+#[derive(Debug)]
+struct CachedPreview {
+    fetched_at: Instant,
+    preview: Option<LinkPreview>,
+}
+
+/// Fetches and caches link previews, refusing to connect to anything that
+/// isn't a public address.
+#[derive(Debug, Default)]
+pub(crate) struct LinkPreviewFetcher {
+    cache: RwLock<HashMap<String, CachedPreview>>,
+    /// Lets tests fetch from a server on localhost.
+    allow_private_addresses: bool,
+}
+
+impl LinkPreviewFetcher {
+    /// Fetches previews for the first few links in `urls`. Links that can't be
+    /// fetched are skipped, so the message is judged on what we have.
+    pub(crate) async fn fetch_all<'a>(
+        &self,
+        urls: impl Iterator<Item = &'a str>,
+    ) -> Vec<LinkPreview> {
+        let mut previews = Vec::new();
+        for url in urls.take(MAX_PREVIEWS_PER_MESSAGE) {
+            if let Some(preview) = self.fetch(url).await {
+                previews.push(preview);
+            }
+        }
+
+        previews
+    }
+
+    /// Forgets previews older than the cache lifetime. Returns how many pages
+    /// are still cached.
+    pub(crate) async fn prune(&self) -> usize {
+        let mut cache = self.cache.write().await;
+        cache.retain(|_, cached| cached.fetched_at.elapsed() < CACHE_TTL);
+        cache.len()
+    }
+
+    async fn fetch(&self, url: &str) -> Option<LinkPreview> {
+        if let Some(cached) = self.cache.read().await.get(url) {
+            if cached.fetched_at.elapsed() < CACHE_TTL {
+                return cached.preview.clone();
+            }
+        }
+
+        let preview = match self.fetch_uncached(url).await {
+            Ok(preview) => Some(preview),
+            Err(err) => {
+                tracing::debug!(%url, ?err, "Unable to fetch link preview");
+                None
+            }
+        };
+
+        let mut cache = self.cache.write().await;
+        if cache.len() >= MAX_CACHED_PREVIEWS {
+            cache.clear();
+        }
+        cache.insert(
+            url.to_owned(),
+            CachedPreview {
+                fetched_at: Instant::now(),
+                preview: preview.clone(),
+            },
+        );
+
+        preview
+    }
+
+    async fn fetch_uncached(&self, url: &str) -> Result<LinkPreview> {
+        let parsed = url::Url::parse(url)?;
+        if !matches!(parsed.scheme(), "http" | "https") {
+            return Err(eyre!("unsupported scheme {}", parsed.scheme()));
+        }
+
+        // Hosts given by name are checked as they're resolved; addresses
+        // written into the link never go through the resolver.
+        let ip = match parsed.host() {
+            Some(url::Host::Domain(_)) => None,
+            Some(url::Host::Ipv4(ip)) => Some(IpAddr::V4(ip)),
+            Some(url::Host::Ipv6(ip)) => Some(IpAddr::V6(ip)),
+            None => return Err(eyre!("link has no host")),
+        };
+        if let Some(ip) = ip.filter(|ip| !self.allow_private_addresses && !is_public(*ip)) {
+            return Err(eyre!("link is to non-public address {}", ip));
+        }
+
+        let mut response = client()
+            .get(parsed)
+            .header(reqwest::header::ACCEPT, "text/html")
+            .send()
+            .await?
+            .error_for_status()?;
+
+        let mut body = Vec::new();
+        while let Some(chunk) = response.chunk().await? {
+            body.extend_from_slice(&chunk);
+            if body.len() >= MAX_PAGE_BYTES {
+                body.truncate(MAX_PAGE_BYTES);
+                break;
+            }
+        }
+
+        Ok(parse_preview(url, &String::from_utf8_lossy(&body)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    use std::time::{Duration, Instant};
+
+    use super::{CachedPreview, LinkPreviewFetcher, CACHE_TTL};
+    use crate::model::LinkPreview;
+
+    const PAGE: &str = r#"<html><head>
+        <title>Fallback title</title>
+        <meta property="og:title" content="Free Nitro &amp; more">
+        <meta content='Claim your free nitro now' property='og:description'>
+        </head><body></body></html>"#;
+
+    /// Serves `PAGE` to every connection.
+    async fn serve_page() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let mut request = [0; 1024];
+                let _ = socket.read(&mut request).await;
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    PAGE.len(),
+                    PAGE
+                );
+                let _ = socket.write_all(response.as_bytes()).await;
+            }
+        });
+
+        format!("http://{}/invite", addr)
+    }
+
+    #[test]
+    fn parses_open_graph_tags() {
+        assert_eq!(
+            super::parse_preview("https://example.com/", PAGE),
+            LinkPreview {
+                url: "https://example.com/".to_owned(),
+                title: Some("Free Nitro & more".to_owned()),
+                description: Some("Claim your free nitro now".to_owned()),
+            }
+        );
+    }
+
+    #[test]
+    fn falls_back_to_plain_tags() {
+        let html = r#"<title>Plain</title><meta name="Description" content="Just a page">"#;
+
+        assert_eq!(
+            super::parse_preview("https://example.com/", html),
+            LinkPreview {
+                url: "https://example.com/".to_owned(),
+                title: Some("Plain".to_owned()),
+                description: Some("Just a page".to_owned()),
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn fetches_preview_from_server() {
+        let url = serve_page().await;
+        let fetcher = LinkPreviewFetcher {
+            allow_private_addresses: true,
+            ..Default::default()
+        };
+
+        let previews = fetcher.fetch_all(std::iter::once(url.as_str())).await;
+        assert_eq!(
+            previews,
+            vec![LinkPreview {
+                url: url.clone(),
+                title: Some("Free Nitro & more".to_owned()),
+                description: Some("Claim your free nitro now".to_owned()),
+            }]
+        );
+        assert!(fetcher.cache.read().await.contains_key(&url));
+    }
+
+    #[tokio::test]
+    async fn refuses_private_addresses() {
+        let url = serve_page().await;
+        let fetcher = LinkPreviewFetcher::default();
+
+        assert_eq!(
+            fetcher.fetch_all(std::iter::once(url.as_str())).await,
+            vec![]
+        );
+
+        // Names are checked when they're resolved.
+        let named = url.replace("127.0.0.1", "localhost");
+        assert_eq!(
+            fetcher.fetch_all(std::iter::once(named.as_str())).await,
+            vec![]
+        );
+    }
+
+    #[tokio::test]
+    async fn prune_forgets_expired_previews() {
+        let fetcher = LinkPreviewFetcher::default();
+        {
+            let mut cache = fetcher.cache.write().await;
+            for (url, age) in [
+                ("https://old.example/", CACHE_TTL * 2),
+                ("https://new.example/", Duration::ZERO),
+            ] {
+                cache.insert(
+                    url.to_owned(),
+                    CachedPreview {
+                        fetched_at: Instant::now() - age,
+                        preview: None,
+                    },
+                );
+            }
+        }
+
+        assert_eq!(fetcher.prune().await, 1);
+        assert!(fetcher
+            .cache
+            .read()
+            .await
+            .contains_key("https://new.example/"));
+    }
+
+    #[test]
+    fn classifies_addresses() {
+        for private in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "192.168.0.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "::1",
+            "fd00::1",
+            "::ffff:10.0.0.1",
+            "0.0.0.0",
+            "0.1.2.3",
+            "64:ff9b::a00:1",
+            "2002:a00:1::1",
+        ] {
+            assert!(!super::is_public(private.parse().unwrap()), "{}", private);
+        }
+
+        for public in ["1.1.1.1", "2606:4700:4700::1111"] {
+            assert!(super::is_public(public.parse().unwrap()), "{}", public);
+        }
+    }
+}
@@ -1,5 +1,6 @@
 use crate::{
-    action::ReactionAction,
+    action::{ReactionAction, WebhookUrl},
+    case::CaseDetails,
     config::{MessageFilterAction, ReactionFilter, Scoping},
     model::ReactionInfo,
 };
@@ -10,13 +11,45 @@ pub(crate) struct ReactionFilterFailure {
     pub(crate) actions: Vec<ReactionAction>,
 }
 
+/// Maps a configured action to the actions to take, running the reached tier's
+/// actions for `escalate`.
+fn map_filter_action_to_actions(
+    filter_action: &MessageFilterAction,
+    reaction: &ReactionInfo,
+    filter_name: &str,
+    filter_reason: &str,
+) -> Vec<ReactionAction> {
+    match filter_action {
+        MessageFilterAction::Escalate { tiers } => {
+            let violations = reaction.prior_violations.unwrap_or(0) + 1;
+            crate::escalation::tier_for(tiers, violations)
+                .map_or(&[][..], |tier| &tier.actions)
+                .iter()
+                .flat_map(|a| map_filter_action_to_actions(a, reaction, filter_name, filter_reason))
+                .collect()
+        }
+        filter_action => vec![map_filter_action_to_action(
+            filter_action,
+            reaction,
+            filter_name,
+            filter_reason,
+        )],
+    }
+}
+
+/// Fills in an action's `$USER_ID` and `$FILTER_REASON` template variables.
+fn render_template(template: &str, reaction: &ReactionInfo, filter_reason: &str) -> String {
+    let rendered = template.replace("$USER_ID", &reaction.author_id.to_string());
+    rendered.replace("$FILTER_REASON", filter_reason)
+}
+
 fn map_filter_action_to_action(
     filter_action: &MessageFilterAction,
     reaction: &ReactionInfo,
     filter_name: &str,
     filter_reason: &str,
 ) -> ReactionAction {
-    match filter_action {
+    let action = match filter_action {
         MessageFilterAction::Delete => ReactionAction::Delete {
             message_id: reaction.message_id,
             channel_id: reaction.channel_id,
@@ -26,19 +59,47 @@ fn map_filter_action_to_action(
             channel_id,
             content,
             requires_armed,
+            coalesce_seconds,
+            ..
         } => {
-            let formatted_content = content.replace("$USER_ID", &reaction.author_id.to_string());
-            let formatted_content = formatted_content.replace("$FILTER_REASON", filter_reason);
+            let formatted_content = render_template(content, reaction, filter_reason);
 
             ReactionAction::SendMessage {
                 to: *channel_id,
                 content: formatted_content,
                 requires_armed: *requires_armed,
+                coalesce_seconds: *coalesce_seconds,
+            }
+        }
+        MessageFilterAction::DmUser {
+            content,
+            requires_armed,
+        } => {
+            let formatted_content = render_template(content, reaction, filter_reason);
+
+            ReactionAction::DmUser {
+                user_id: reaction.author_id,
+                content: formatted_content,
+                requires_armed: *requires_armed,
             }
         }
+        MessageFilterAction::SendWebhook { url, content } => {
+            let formatted_content = render_template(content, reaction, filter_reason);
+
+            ReactionAction::SendWebhook {
+                url: WebhookUrl(url.clone()),
+                content: formatted_content,
+            }
+        }
+        MessageFilterAction::Escalate { .. } => {
+            unreachable!(
+                "escalations are expanded by map_filter_action_to_actions and skipped as fallbacks"
+            )
+        }
         MessageFilterAction::Ban {
             delete_message_seconds,
             reason,
+            ..
         } => {
             let formatted_reason = reason.replace("$FILTER_REASON", filter_reason);
             let formatted_reason = formatted_reason.replace("$FILTER_REASON", filter_reason);
@@ -50,7 +111,7 @@ fn map_filter_action_to_action(
                 reason: formatted_reason,
             }
         }
-        MessageFilterAction::Kick { reason } => {
+        MessageFilterAction::Kick { reason, .. } => {
             let formatted_reason = reason.replace("$FILTER_REASON", filter_reason);
             let formatted_reason = formatted_reason.replace("$FILTER_REASON", filter_reason);
 
@@ -60,7 +121,9 @@ fn map_filter_action_to_action(
                 reason: formatted_reason,
             }
         }
-        MessageFilterAction::Timeout { duration, reason } => {
+        MessageFilterAction::Timeout {
+            duration, reason, ..
+        } => {
             let formatted_reason = reason.replace("$FILTER_REASON", filter_reason);
             let formatted_reason = formatted_reason.replace("$FILTER_REASON", filter_reason);
 
@@ -71,7 +134,10 @@ fn map_filter_action_to_action(
                 duration: *duration,
             }
         }
-        MessageFilterAction::SendLog { channel_id } => ReactionAction::SendLog {
+        MessageFilterAction::SendLog {
+            channel_id,
+            coalesce_seconds,
+        } => ReactionAction::SendLog {
             to: *channel_id,
             filter_name: filter_name.to_string(),
             message: reaction.message_id,
@@ -79,7 +145,72 @@ fn map_filter_action_to_action(
             author: reaction.author_id,
             filter_reason: filter_reason.to_string(),
             reaction: reaction.reaction.clone(),
+            coalesce_seconds: *coalesce_seconds,
         },
+        MessageFilterAction::LockChannel {
+            duration_seconds, ..
+        } => ReactionAction::LockChannel {
+            guild_id: reaction.guild_id,
+            channel_id: reaction.channel_id,
+            duration_seconds: *duration_seconds,
+        },
+        MessageFilterAction::AddRole {
+            role_id,
+            requires_armed,
+        } => ReactionAction::AddRole {
+            user_id: reaction.author_id,
+            guild_id: reaction.guild_id,
+            role_id: *role_id,
+            requires_armed: *requires_armed,
+        },
+        MessageFilterAction::RemoveRole { role_id } => ReactionAction::RemoveRole {
+            user_id: reaction.author_id,
+            guild_id: reaction.guild_id,
+            role_id: *role_id,
+        },
+        MessageFilterAction::React { emoji } => ReactionAction::React {
+            message_id: reaction.message_id,
+            channel_id: reaction.channel_id,
+            emoji: emoji.clone(),
+        },
+        MessageFilterAction::CreateCase {
+            endpoint,
+            authorization,
+            log_channel,
+        } => ReactionAction::CreateCase {
+            endpoint: endpoint.clone(),
+            authorization: authorization.clone(),
+            log_channel: *log_channel,
+            details: CaseDetails {
+                guild_id: reaction.guild_id,
+                channel_id: reaction.channel_id,
+                user_id: reaction.author_id,
+                message_id: Some(reaction.message_id),
+                filter_name: filter_name.to_string(),
+                reason: filter_reason.to_string(),
+                content: None,
+                context: "reaction",
+            },
+        },
+    };
+
+    match filter_action.on_failure() {
+        // Validation rejects these, since an escalation isn't one action that
+        // can stand in for another.
+        Some(MessageFilterAction::Escalate { .. }) => {
+            tracing::warn!(%filter_name, "Skipping escalate fallback, which isn't supported");
+            action
+        }
+        Some(fallback) => ReactionAction::WithFallback {
+            action: Box::new(action),
+            on_failure: Box::new(map_filter_action_to_action(
+                fallback,
+                reaction,
+                filter_name,
+                filter_reason,
+            )),
+        },
+        None => action,
     }
 }
 
@@ -92,19 +223,26 @@ pub(crate) fn filter_reaction(
 ) -> Result<(), ReactionFilterFailure> {
     for filter in filters {
         if let Some(scoping) = filter.scoping.as_ref().or(default_scoping) {
-            if !scoping.is_included(reaction.channel_id, reaction.author_roles) {
+            if !scoping.is_included(
+                reaction.channel_id,
+                reaction.category_id,
+                Some(reaction.author_id),
+                reaction.author_roles,
+            ) {
                 continue;
             }
         }
 
-        if let Err(reason) = filter.filter_reaction(&reaction.reaction) {
+        if let Err(filter_match) = filter.filter_reaction(reaction) {
+            tracing::debug!(?filter_match, "Reaction failed filter");
+            let reason = &filter_match.rule_description;
             let actions = filter
                 .actions
                 .as_deref()
                 .or(default_actions)
                 .unwrap_or(&[])
                 .iter()
-                .map(|a| map_filter_action_to_action(a, reaction, &filter.name, &reason))
+                .flat_map(|a| map_filter_action_to_actions(a, reaction, &filter.name, reason))
                 .collect();
 
             return Err(ReactionFilterFailure {
@@ -120,11 +258,13 @@ pub(crate) fn filter_reaction(
 #[cfg(test)]
 mod test {
     use pretty_assertions::assert_eq;
-    use twilight_model::id::Id;
+    use tokio::sync::RwLock;
+    use twilight_model::{channel::message::ReactionType, id::Id};
 
     use crate::{
         action::ReactionAction,
         config::{FilterMode, MessageFilterAction, ReactionFilter, ReactionFilterRule, Scoping},
+        filter::{prune_reaction_history, record_reaction, ReactionHistory},
         reaction::ReactionFilterFailure,
     };
 
@@ -142,21 +282,27 @@ mod test {
                 MessageFilterAction::Ban {
                     delete_message_seconds: 0,
                     reason: "$FILTER_REASON".to_string(),
+                    on_failure: None,
                 },
                 MessageFilterAction::Kick {
                     reason: "$FILTER_REASON".to_string(),
+                    on_failure: None,
                 },
                 MessageFilterAction::Timeout {
                     duration: 60_000,
                     reason: "$FILTER_REASON".to_string(),
+                    on_failure: None,
                 },
                 MessageFilterAction::SendLog {
                     channel_id: Id::new(3),
+                    coalesce_seconds: None,
                 },
                 MessageFilterAction::SendMessage {
                     channel_id: Id::new(3),
                     content: "$USER_ID $FILTER_REASON".to_string(),
                     requires_armed: false,
+                    on_failure: None,
+                    coalesce_seconds: None,
                 },
             ]),
         }];
@@ -198,11 +344,13 @@ mod test {
                         filter_reason: "reacted with denied emoji `🍆`".to_string(),
                         author: crate::model::test::USER_ID,
                         reaction: rxn.reaction.clone(),
+                        coalesce_seconds: None,
                     },
                     ReactionAction::SendMessage {
                         to: Id::new(3),
                         content: "3 reacted with denied emoji `🍆`".to_string(),
                         requires_armed: false,
+                        coalesce_seconds: None,
                     },
                 ]
             })
@@ -309,6 +457,7 @@ mod test {
 
         let default_actions = vec![MessageFilterAction::SendLog {
             channel_id: Id::new(2),
+            coalesce_seconds: None,
         }];
 
         let rxn = crate::model::test::default_reaction("🍆");
@@ -417,4 +566,61 @@ mod test {
         let result = super::filter_reaction(&filters, None, None, &rxn);
         assert_eq!(result, Ok(()));
     }
+
+    #[tokio::test]
+    async fn filter_stacked_reactions() {
+        let filters = vec![ReactionFilter {
+            name: "first".to_string(),
+            rules: vec![ReactionFilterRule::Count { max_distinct: 2 }],
+            scoping: None,
+            actions: Some(vec![MessageFilterAction::Delete]),
+        }];
+
+        let history = RwLock::new(ReactionHistory::new());
+        let mut results = vec![];
+
+        for emoji in ["🍆", "💜", "💜", "🔥"] {
+            let mut rxn = crate::model::test::default_reaction(emoji);
+            rxn.distinct_reactions =
+                record_reaction(&history, rxn.message_id, rxn.author_id, &rxn.reaction).await;
+            results.push(super::filter_reaction(&filters, None, None, &rxn));
+        }
+
+        assert_eq!(results[0], Ok(()));
+        assert_eq!(results[1], Ok(()));
+        // Reacting with the same emoji twice doesn't count as a new reaction.
+        assert_eq!(results[2], Ok(()));
+        assert_eq!(
+            results[3],
+            Err(ReactionFilterFailure {
+                filter_name: "first".to_string(),
+                actions: vec![ReactionAction::Delete {
+                    message_id: crate::model::test::MESSAGE_ID,
+                    channel_id: crate::model::test::CHANNEL_ID,
+                    reaction: ReactionType::Unicode {
+                        name: "🔥".to_string()
+                    },
+                }]
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn old_reactions_are_pruned() {
+        const DAY_MS: u64 = 24 * 60 * 60 * 1000;
+        let now_ms = crate::snowflake::DISCORD_EPOCH_MS + 10 * DAY_MS;
+        let history = RwLock::new(ReactionHistory::new());
+        let reaction = ReactionType::Unicode {
+            name: "🔥".to_string(),
+        };
+
+        let old = crate::snowflake::id_created_at(now_ms - 2 * DAY_MS);
+        let fresh = crate::snowflake::id_created_at(now_ms - 60 * 1000);
+        record_reaction(&history, old, Id::new(1), &reaction).await;
+        record_reaction(&history, fresh, Id::new(1), &reaction).await;
+        record_reaction(&history, fresh, Id::new(2), &reaction).await;
+
+        assert_eq!(prune_reaction_history(&history, now_ms * 1000).await, 2);
+        assert!(!history.read().await.contains_key(&(old, Id::new(1))));
+    }
 }
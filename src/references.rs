@@ -0,0 +1,211 @@
+use twilight_model::id::{
+    marker::{ChannelMarker, RoleMarker},
+    Id,
+};
+
+use crate::config::{GuildConfig, MessageFilterAction, Scoping, UsernameFilterAction};
+
+/// The channels and roles a guild configuration refers to, each with where
+/// it's referred to.
+#[derive(Debug, Default)]
+struct References {
+    channels: Vec<(String, Id<ChannelMarker>)>,
+    roles: Vec<(String, Id<RoleMarker>)>,
+}
+
+impl References {
+    fn channels<'a>(
+        &mut self,
+        context: &str,
+        channels: impl IntoIterator<Item = &'a Id<ChannelMarker>>,
+    ) {
+        self.channels
+            .extend(channels.into_iter().map(|id| (context.to_owned(), *id)));
+    }
+
+    fn roles<'a>(&mut self, context: &str, roles: impl IntoIterator<Item = &'a Id<RoleMarker>>) {
+        self.roles
+            .extend(roles.into_iter().map(|id| (context.to_owned(), *id)));
+    }
+
+    fn scoping(&mut self, context: &str, scoping: Option<&Scoping>) {
+        let scoping = match scoping {
+            Some(scoping) => scoping,
+            None => return,
+        };
+
+        let context = format!("{} scoping", context);
+        let channels = [
+            &scoping.exclude_channels,
+            &scoping.include_channels,
+            &scoping.exclude_categories,
+            &scoping.include_categories,
+        ];
+        for channels in channels.iter() {
+            self.channels(&context, channels.iter().flatten());
+        }
+        for roles in [&scoping.exclude_roles, &scoping.include_roles].iter() {
+            self.roles(&context, roles.iter().flatten());
+        }
+    }
+
+    /// Adds what `actions` refer to, including their fallbacks and
+    /// escalation tiers.
+    fn actions(&mut self, context: &str, actions: Option<&[MessageFilterAction]>) {
+        for action in actions.into_iter().flatten() {
+            let context = format!("{} {}", context, action.name());
+            match action {
+                MessageFilterAction::SendMessage { channel_id, .. }
+                | MessageFilterAction::SendLog { channel_id, .. } => {
+                    self.channels(&context, [channel_id]);
+                }
+                MessageFilterAction::CreateCase { log_channel, .. } => {
+                    self.channels(&context, log_channel);
+                }
+                MessageFilterAction::AddRole { role_id, .. }
+                | MessageFilterAction::RemoveRole { role_id } => {
+                    self.roles(&context, [role_id]);
+                }
+                MessageFilterAction::Escalate { tiers } => {
+                    for (i, tier) in tiers.iter().enumerate() {
+                        self.actions(&format!("{} tier {}", context, i), Some(&tier.actions));
+                    }
+                }
+                _ => {}
+            }
+
+            if let Some(fallback) = action.on_failure() {
+                self.actions(&context, Some(std::slice::from_ref(fallback)));
+            }
+        }
+    }
+}
+
+fn references(guild: &GuildConfig) -> References {
+    let mut references = References::default();
+
+    if let Some(notifications) = &guild.notifications {
+        references.channels("notifications", [&notifications.channel]);
+        references.roles("notifications", notifications.ping_roles.iter().flatten());
+    }
+    references.channels("ignore_channels", guild.ignore_channels.iter().flatten());
+    references.scoping("default", guild.default_scoping.as_ref());
+    references.actions("default_actions", guild.default_actions.as_deref());
+
+    for (i, filter) in guild.messages.iter().flatten().enumerate() {
+        let context = format!("message filter {}", i);
+        references.scoping(&context, filter.scoping.as_ref());
+        references.actions(&context, filter.actions.as_deref());
+    }
+    for (i, filter) in guild.reactions.iter().flatten().enumerate() {
+        let context = format!("reaction filter {}", i);
+        references.scoping(&context, filter.scoping.as_ref());
+        references.actions(&context, filter.actions.as_deref());
+    }
+    if let Some(spam) = &guild.spam {
+        references.scoping("spam filter", spam.scoping.as_ref());
+        references.actions("spam filter", spam.actions.as_deref());
+    }
+    if let Some(usernames) = &guild.usernames {
+        for action in &usernames.actions {
+            let UsernameFilterAction::SendMessage { channel_id, .. } = action;
+            references.channels("usernames SendMessage", [channel_id]);
+        }
+    }
+    if let Some(channel_status) = &guild.channel_status_filter {
+        references.channels("channel_status_filter", &channel_status.log_channel);
+    }
+    if let Some(approval) = &guild.approval {
+        references.roles("approval", &approval.roles);
+    }
+    if let Some(prefix_commands) = &guild.prefix_commands {
+        references.roles("prefix_commands", &prefix_commands.roles);
+    }
+
+    references
+}
+
+/// Describes every channel and role `guild` refers to that doesn't exist,
+/// going by `channel_exists` and `role_exists`.
+pub(crate) fn unresolved(
+    guild: &GuildConfig,
+    channel_exists: impl Fn(Id<ChannelMarker>) -> bool,
+    role_exists: impl Fn(Id<RoleMarker>) -> bool,
+) -> Vec<String> {
+    let references = references(guild);
+
+    let channels = references
+        .channels
+        .into_iter()
+        .filter(|(_, id)| !channel_exists(*id))
+        .map(|(context, id)| format!("{} refers to channel {}, which doesn't exist", context, id));
+    let roles = references
+        .roles
+        .into_iter()
+        .filter(|(_, id)| !role_exists(*id))
+        .map(|(context, id)| format!("{} refers to role {}, which doesn't exist", context, id));
+
+    channels.chain(roles).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+    use twilight_model::id::Id;
+
+    use crate::config::GuildConfig;
+
+    #[test]
+    fn reports_channels_and_roles_that_dont_exist() {
+        let guild: GuildConfig = serde_yaml::from_str(
+            r#"
+            notifications:
+              channel: 10
+            default_scoping:
+              exclude_roles: [20, 21]
+            default_actions:
+              - action: send_log
+                channel_id: 11
+            messages:
+              - name: ban
+                rules:
+                  - type: words
+                    words: [bad]
+                scoping:
+                  include_channels: [10]
+                actions:
+                  - action: escalate
+                    tiers:
+                      - threshold: 1
+                        actions:
+                          - action: ban
+                            reason: bad
+                            delete_message_seconds: 0
+                            on_failure:
+                              action: send_message
+                              channel_id: 12
+                              content: couldn't ban
+                              requires_armed: false
+                          - action: add_role
+                            role_id: 22
+                            requires_armed: true
+            "#,
+        )
+        .expect("couldn't deserialize GuildConfig");
+
+        let existing_channels = [Id::new(10), Id::new(11)];
+        let existing_roles = [Id::new(20)];
+        assert_eq!(
+            super::unresolved(
+                &guild,
+                |id| existing_channels.contains(&id),
+                |id| existing_roles.contains(&id)
+            ),
+            vec![
+                "message filter 0 escalate tier 0 ban send_message refers to channel 12, which doesn't exist",
+                "default scoping refers to role 21, which doesn't exist",
+                "message filter 0 escalate tier 0 add_role refers to role 22, which doesn't exist",
+            ]
+        );
+    }
+}
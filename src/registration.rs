@@ -0,0 +1,172 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use tokio::time::Instant;
+use twilight_model::id::{marker::GuildMarker, Id};
+
+/// What a reload should do about a guild's slash commands.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum RegistrationPlan {
+    /// The commands are already registered as configured.
+    Unchanged,
+    /// Register the commands now.
+    Now,
+    /// Register the commands once this much time has passed, using whatever
+    /// configuration is current then.
+    After(Duration),
+    /// An earlier reload already scheduled a registration, which will pick up
+    /// this reload's configuration.
+    Scheduled,
+}
+
+#[derive(Debug)]
+struct Registered {
+    /// Whether commands were registered, as opposed to removed.
+    enabled: bool,
+    at: Instant,
+    pending: bool,
+}
+
+/// Remembers when each guild's slash commands were last registered, so rapid
+/// reloads coalesce into at most one registration per cooldown.
+#[derive(Debug, Default)]
+pub(crate) struct CommandRegistrations {
+    guilds: Mutex<HashMap<Id<GuildMarker>, Registered>>,
+}
+
+impl CommandRegistrations {
+    /// Decides what to do about a reload that wants `guild_id`'s commands to be
+    /// registered (`enabled`) or removed. Unless this returns
+    /// [`RegistrationPlan::Unchanged`] or [`RegistrationPlan::Scheduled`], the
+    /// caller must call [`Self::registered`] once it has registered them, or
+    /// [`Self::forget`] if that failed.
+    pub(crate) fn plan(
+        &self,
+        guild_id: Id<GuildMarker>,
+        enabled: bool,
+        cooldown: Duration,
+        now: Instant,
+    ) -> RegistrationPlan {
+        let mut guilds = self.guilds.lock().unwrap();
+        let registered = match guilds.get_mut(&guild_id) {
+            Some(registered) => registered,
+            None => return RegistrationPlan::Now,
+        };
+
+        if registered.pending {
+            return RegistrationPlan::Scheduled;
+        }
+
+        if registered.enabled == enabled {
+            return RegistrationPlan::Unchanged;
+        }
+
+        let elapsed = now.saturating_duration_since(registered.at);
+        if elapsed >= cooldown {
+            return RegistrationPlan::Now;
+        }
+
+        registered.pending = true;
+        RegistrationPlan::After(cooldown - elapsed)
+    }
+
+    /// Records that `guild_id`'s commands were just registered or removed.
+    pub(crate) fn registered(&self, guild_id: Id<GuildMarker>, enabled: bool, now: Instant) {
+        self.guilds.lock().unwrap().insert(
+            guild_id,
+            Registered {
+                enabled,
+                at: now,
+                pending: false,
+            },
+        );
+    }
+
+    /// Forgets `guild_id`'s registration, so the next reload registers its
+    /// commands straight away.
+    pub(crate) fn forget(&self, guild_id: Id<GuildMarker>) {
+        self.guilds.lock().unwrap().remove(&guild_id);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use pretty_assertions::assert_eq;
+    use tokio::time::Instant;
+    use twilight_model::id::Id;
+
+    use super::{CommandRegistrations, RegistrationPlan};
+
+    const COOLDOWN: Duration = Duration::from_secs(30);
+
+    #[test]
+    fn rapid_reloads_update_commands_once() {
+        let registrations = CommandRegistrations::default();
+        let guild_id = Id::new(1);
+        let start = Instant::now();
+        registrations.registered(guild_id, true, start);
+
+        // Two reloads a second apart both turn slash commands off.
+        let plans: Vec<_> = (1..=2)
+            .map(|secs| {
+                registrations.plan(guild_id, false, COOLDOWN, start + Duration::from_secs(secs))
+            })
+            .collect();
+        let updates = plans
+            .iter()
+            .filter(|plan| matches!(plan, RegistrationPlan::Now | RegistrationPlan::After(_)))
+            .count();
+        assert_eq!(updates, 1);
+        assert_eq!(
+            plans,
+            vec![
+                RegistrationPlan::After(Duration::from_secs(29)),
+                RegistrationPlan::Scheduled
+            ]
+        );
+    }
+
+    #[test]
+    fn unchanged_commands_are_not_updated() {
+        let registrations = CommandRegistrations::default();
+        let guild_id = Id::new(1);
+        let start = Instant::now();
+        assert_eq!(
+            registrations.plan(guild_id, true, COOLDOWN, start),
+            RegistrationPlan::Now
+        );
+        registrations.registered(guild_id, true, start);
+        assert_eq!(
+            registrations.plan(guild_id, true, COOLDOWN, start + COOLDOWN),
+            RegistrationPlan::Unchanged
+        );
+    }
+
+    #[test]
+    fn changes_within_cooldown_are_deferred() {
+        let registrations = CommandRegistrations::default();
+        let guild_id = Id::new(1);
+        let start = Instant::now();
+        registrations.registered(guild_id, true, start);
+
+        let soon = start + Duration::from_secs(10);
+        assert_eq!(
+            registrations.plan(guild_id, false, COOLDOWN, soon),
+            RegistrationPlan::After(Duration::from_secs(20))
+        );
+        assert_eq!(
+            registrations.plan(guild_id, true, COOLDOWN, soon),
+            RegistrationPlan::Scheduled
+        );
+
+        let later = start + COOLDOWN;
+        registrations.registered(guild_id, true, later);
+        assert_eq!(
+            registrations.plan(guild_id, false, COOLDOWN, later + COOLDOWN),
+            RegistrationPlan::Now
+        );
+    }
+}
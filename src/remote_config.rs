@@ -0,0 +1,257 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use futures::future;
+use twilight_model::id::{marker::GuildMarker, Id};
+
+use crate::config::{GuildConfig, LoadConfigError};
+
+/// How long fetching a single guild's configuration may take.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Fetches guild configurations from `<url>/<guild_id>.json`, remembering the
+/// last one fetched for each guild so a reload survives the server being
+/// unavailable. The `authorization` header is left out of `Debug` output.
+pub(crate) struct RemoteConfigs {
+    client: reqwest::Client,
+    url: String,
+    authorization: Option<String>,
+    last_good: Mutex<HashMap<Id<GuildMarker>, String>>,
+}
+
+impl std::fmt::Debug for RemoteConfigs {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RemoteConfigs")
+            .field("url", &self.url)
+            .field(
+                "authorization",
+                &self.authorization.as_ref().map(|_| "<redacted>"),
+            )
+            .finish_non_exhaustive()
+    }
+}
+
+impl RemoteConfigs {
+    pub(crate) fn new(url: &str, authorization: Option<&str>) -> Self {
+        Self {
+            client: reqwest::Client::builder()
+                .timeout(REQUEST_TIMEOUT)
+                .build()
+                .expect("couldn't build remote configuration client"),
+            url: url.trim_end_matches('/').to_owned(),
+            authorization: authorization.map(ToOwned::to_owned),
+            last_good: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn guild_url(&self, guild_id: Id<GuildMarker>) -> String {
+        format!("{}/{}.json", self.url, guild_id)
+    }
+
+    /// Fetches the text of `guild_id`'s configuration.
+    async fn fetch(&self, guild_id: Id<GuildMarker>) -> Result<String, LoadConfigError> {
+        let url = self.guild_url(guild_id);
+        let mut request = self.client.get(&url);
+        if let Some(authorization) = &self.authorization {
+            request = request.header(reqwest::header::AUTHORIZATION, authorization);
+        }
+
+        let fetch_error =
+            |err: reqwest::Error| LoadConfigError::Fetch(url.clone(), err.to_string());
+        let response = request.send().await.map_err(fetch_error)?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Err(LoadConfigError::MissingRemoteGuildConfig(guild_id, url));
+        }
+
+        response
+            .error_for_status()
+            .map_err(fetch_error)?
+            .text()
+            .await
+            .map_err(fetch_error)
+    }
+
+    /// Fetches and validates `guild_id`'s configuration. If it can't be
+    /// fetched, the last configuration fetched for the guild is used instead.
+    pub(crate) async fn load_config(
+        &self,
+        guild_id: Id<GuildMarker>,
+    ) -> Result<GuildConfig, LoadConfigError> {
+        let config_string = match self.fetch(guild_id).await {
+            Ok(config_string) => config_string,
+            Err(err @ LoadConfigError::Fetch(..)) => {
+                match self.last_good.lock().unwrap().get(&guild_id) {
+                    Some(last_good) => {
+                        tracing::warn!(%guild_id, %err, "Using the last configuration fetched for this guild");
+                        last_good.clone()
+                    }
+                    None => return Err(err),
+                }
+            }
+            Err(err) => return Err(err),
+        };
+
+        let guild_config = crate::config::parse_guild_config(&config_string)?;
+        self.last_good
+            .lock()
+            .unwrap()
+            .insert(guild_id, config_string);
+
+        Ok(guild_config)
+    }
+
+    /// Loads the configurations for every guild in `guild_ids`, like
+    /// [`crate::config::load_guild_configs`] does for local directories.
+    /// Every guild's configuration is fetched at once.
+    pub(crate) async fn load_guild_configs(
+        &self,
+        guild_ids: &[Id<GuildMarker>],
+        allow_missing: bool,
+    ) -> Result<HashMap<Id<GuildMarker>, GuildConfig>, (Id<GuildMarker>, eyre::Report)> {
+        let loaded =
+            future::join_all(guild_ids.iter().map(|guild_id| self.load_config(*guild_id))).await;
+        let mut configs = HashMap::new();
+
+        for (guild_id, loaded) in guild_ids.iter().zip(loaded) {
+            let guild_id = *guild_id;
+
+            let guild_config = match loaded {
+                Ok(guild_config) => guild_config,
+                Err(err @ LoadConfigError::MissingRemoteGuildConfig(..)) if allow_missing => {
+                    tracing::warn!(%guild_id, %err, "Skipping guild without a configuration");
+                    continue;
+                }
+                Err(err) => {
+                    return Err((
+                        guild_id,
+                        eyre::Report::new(err).wrap_err(format!(
+                            "Unable to load configuration for guild {}",
+                            guild_id
+                        )),
+                    ))
+                }
+            };
+
+            configs.insert(guild_id, guild_config);
+        }
+
+        Ok(configs)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+
+    use pretty_assertions::assert_eq;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    use super::RemoteConfigs;
+    use crate::config::LoadConfigError;
+
+    /// Serves one response per connection, giving `body` to
+    /// `GET /configs/4.json` and 404 to anything else, until `responses` runs
+    /// out. Later connections are refused.
+    async fn serve(body: &'static str, responses: usize) -> (String, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+
+        let recorded = requests.clone();
+        tokio::spawn(async move {
+            for _ in 0..responses {
+                let (mut socket, _) = match listener.accept().await {
+                    Ok(connection) => connection,
+                    Err(_) => break,
+                };
+
+                let mut buffer = [0; 4096];
+                let read = socket.read(&mut buffer).await.unwrap_or(0);
+                let request = String::from_utf8_lossy(&buffer[..read]).into_owned();
+                let (status, body) = if request.starts_with("GET /configs/4.json ") {
+                    ("200 OK", body)
+                } else {
+                    ("404 Not Found", "")
+                };
+                recorded.lock().unwrap().push(request);
+
+                let response = format!(
+                    "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                );
+                let _ = socket.write_all(response.as_bytes()).await;
+            }
+        });
+
+        (format!("http://{}/configs/", addr), requests)
+    }
+
+    #[tokio::test]
+    async fn loads_remote_guild_config() {
+        let (url, requests) = serve(
+            r#"{ "messages": [{ "name": "Scams", "rules": [{ "type": "words", "words": ["nitro"] }], "actions": [{ "action": "delete" }] }] }"#,
+            3,
+        )
+        .await;
+        let remote = RemoteConfigs::new(&url, Some("Bearer secret"));
+        let guild_id = crate::model::test::GUILD_ID;
+
+        let configs = remote
+            .load_guild_configs(&[guild_id], false)
+            .await
+            .expect("couldn't load remote configuration");
+        let filters = configs[&guild_id].messages.as_ref().unwrap();
+        assert_eq!(filters[0].name, "Scams");
+        assert!(filters[0].filter_text("free nitro").is_err());
+        assert!(requests.lock().unwrap()[0]
+            .to_lowercase()
+            .contains("authorization: bearer secret"));
+
+        // Guilds the server doesn't know about are missing, not broken.
+        let configs = remote
+            .load_guild_configs(&[guild_id, twilight_model::id::Id::new(5)], true)
+            .await
+            .expect("couldn't skip missing configuration");
+        assert_eq!(configs.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn last_good_config_survives_fetch_failures() {
+        let (url, _) = serve(r#"{ "include_bots": true }"#, 1).await;
+        let remote = RemoteConfigs::new(&url, None);
+        let guild_id = crate::model::test::GUILD_ID;
+
+        assert!(remote.load_config(guild_id).await.unwrap().include_bots);
+        // The server is gone now, so this uses the copy fetched above.
+        assert!(remote.load_config(guild_id).await.unwrap().include_bots);
+
+        let other = RemoteConfigs::new(&url, None);
+        assert!(matches!(
+            other.load_config(guild_id).await,
+            Err(LoadConfigError::Fetch(..))
+        ));
+    }
+
+    #[tokio::test]
+    async fn remote_configs_are_validated() {
+        let (url, _) = serve(r#"{ "ignore_channels": [] }"#, 1).await;
+        let remote = RemoteConfigs::new(&url, None);
+
+        assert!(matches!(
+            remote.load_config(crate::model::test::GUILD_ID).await,
+            Err(LoadConfigError::Validate(_))
+        ));
+    }
+
+    #[test]
+    fn debug_output_redacts_authorization() {
+        let remote = RemoteConfigs::new("http://localhost/configs/", Some("Bearer secret"));
+        let debug = format!("{:?}", remote);
+        assert!(!debug.contains("secret"));
+        assert!(debug.contains("<redacted>"));
+    }
+}
@@ -0,0 +1,795 @@
+use serde_json::{json, Map, Value};
+
+/// A Discord ID. Twilight accepts them as strings or integers.
+fn snowflake() -> Value {
+    json!({ "type": ["string", "integer"], "pattern": "^[0-9]+$" })
+}
+
+fn snowflakes() -> Value {
+    json!({ "type": "array", "items": snowflake() })
+}
+
+fn strings() -> Value {
+    json!({ "type": "array", "items": { "type": "string" } })
+}
+
+fn boolean() -> Value {
+    json!({ "type": "boolean" })
+}
+
+fn integer() -> Value {
+    json!({ "type": "integer", "minimum": 0 })
+}
+
+fn number() -> Value {
+    json!({ "type": "number" })
+}
+
+fn string() -> Value {
+    json!({ "type": "string" })
+}
+
+fn reference(name: &str) -> Value {
+    json!({ "$ref": format!("#/$defs/{}", name) })
+}
+
+fn array_of(items: Value) -> Value {
+    json!({ "type": "array", "items": items })
+}
+
+/// An object with `properties`, of which `required` must be present. Unknown
+/// properties are allowed, as serde ignores them.
+fn object(properties: &[(&str, Value)], required: &[&str]) -> Value {
+    let properties: Map<String, Value> = properties
+        .iter()
+        .map(|(name, schema)| (name.to_string(), schema.clone()))
+        .collect();
+
+    json!({
+        "type": "object",
+        "properties": properties,
+        "required": required,
+    })
+}
+
+/// Like [`object`], but for types that deny unknown fields.
+fn strict_object(properties: &[(&str, Value)], required: &[&str]) -> Value {
+    let mut schema = object(properties, required);
+    schema["additionalProperties"] = json!(false);
+    schema
+}
+
+/// An internally tagged enum: one object per variant, told apart by `tag`.
+fn tagged(tag: &str, variants: Vec<(&str, Value)>) -> Value {
+    let variants = variants
+        .into_iter()
+        .map(|(name, mut schema)| {
+            schema["properties"][tag] = json!({ "const": name });
+            let required = schema["required"].as_array_mut().unwrap();
+            required.insert(0, json!(tag));
+            schema
+        })
+        .collect::<Vec<_>>();
+
+    json!({ "oneOf": variants })
+}
+
+fn filter_mode() -> Value {
+    json!({ "enum": ["allow", "deny"] })
+}
+
+fn message_action() -> Value {
+    let on_failure = || ("on_failure", reference("message_action"));
+
+    tagged(
+        "action",
+        vec![
+            ("delete", object(&[], &[])),
+            (
+                "send_message",
+                object(
+                    &[
+                        ("channel_id", snowflake()),
+                        ("content", string()),
+                        ("requires_armed", boolean()),
+                        ("coalesce_seconds", integer()),
+                        on_failure(),
+                    ],
+                    &["channel_id", "content", "requires_armed"],
+                ),
+            ),
+            (
+                "dm_user",
+                object(
+                    &[("content", string()), ("requires_armed", boolean())],
+                    &["content", "requires_armed"],
+                ),
+            ),
+            (
+                "send_webhook",
+                object(
+                    &[("url", string()), ("content", string())],
+                    &["url", "content"],
+                ),
+            ),
+            (
+                "escalate",
+                object(
+                    &[(
+                        "tiers",
+                        array_of(object(
+                            &[
+                                ("threshold", json!({ "type": "integer", "minimum": 1 })),
+                                ("actions", array_of(reference("message_action"))),
+                            ],
+                            &["threshold", "actions"],
+                        )),
+                    )],
+                    &["tiers"],
+                ),
+            ),
+            (
+                "ban",
+                object(
+                    &[
+                        ("reason", string()),
+                        ("delete_message_seconds", integer()),
+                        on_failure(),
+                    ],
+                    &["reason", "delete_message_seconds"],
+                ),
+            ),
+            (
+                "kick",
+                object(&[("reason", string()), on_failure()], &["reason"]),
+            ),
+            ("timeout", {
+                let mut timeout = object(
+                    &[
+                        ("reason", string()),
+                        ("duration", integer()),
+                        ("duration_secs", integer()),
+                        on_failure(),
+                    ],
+                    &["reason"],
+                );
+                timeout["anyOf"] = json!([
+                    { "required": ["duration"] },
+                    { "required": ["duration_secs"] },
+                ]);
+                timeout
+            }),
+            (
+                "send_log",
+                object(
+                    &[("channel_id", snowflake()), ("coalesce_seconds", integer())],
+                    &["channel_id"],
+                ),
+            ),
+            (
+                "add_role",
+                object(
+                    &[("role_id", snowflake()), ("requires_armed", boolean())],
+                    &["role_id", "requires_armed"],
+                ),
+            ),
+            (
+                "remove_role",
+                object(&[("role_id", snowflake())], &["role_id"]),
+            ),
+            (
+                "lock_channel",
+                object(
+                    &[("duration_seconds", integer()), on_failure()],
+                    &["duration_seconds"],
+                ),
+            ),
+            (
+                "create_case",
+                object(
+                    &[
+                        ("endpoint", string()),
+                        ("authorization", string()),
+                        ("log_channel", snowflake()),
+                    ],
+                    &["endpoint"],
+                ),
+            ),
+            ("react", object(&[("emoji", string())], &["emoji"])),
+        ],
+    )
+}
+
+fn message_rule() -> Value {
+    let text_rule = |list: &'static str| {
+        object(
+            &[
+                (list, strings()),
+                ("whole_message_only", boolean()),
+                ("case_insensitive", boolean()),
+            ],
+            &[list],
+        )
+    };
+
+    tagged(
+        "type",
+        vec![
+            ("words", text_rule("words")),
+            ("substring", text_rule("substrings")),
+            (
+                "regex",
+                object(
+                    &[
+                        ("regexes", strings()),
+                        (
+                            "flags",
+                            strict_object(
+                                &[
+                                    ("case_insensitive", boolean()),
+                                    ("multiline", boolean()),
+                                    ("dot_all", boolean()),
+                                    ("unicode", boolean()),
+                                ],
+                                &[],
+                            ),
+                        ),
+                    ],
+                    &["regexes"],
+                ),
+            ),
+            (
+                "zalgo",
+                object(
+                    &[
+                        ("max_combining_ratio", number()),
+                        ("max_combining_run", integer()),
+                    ],
+                    &[],
+                ),
+            ),
+            (
+                "mime_type",
+                object(
+                    &[
+                        ("mode", filter_mode()),
+                        ("types", strings()),
+                        ("allow_unknown", boolean()),
+                        ("sniff", boolean()),
+                    ],
+                    &["mode", "types", "allow_unknown"],
+                ),
+            ),
+            (
+                "attachment_extension",
+                object(
+                    &[("mode", filter_mode()), ("extensions", strings())],
+                    &["mode", "extensions"],
+                ),
+            ),
+            (
+                "attachment_size",
+                object(&[("max_bytes", integer())], &["max_bytes"]),
+            ),
+            (
+                "invite",
+                object(
+                    &[
+                        ("mode", filter_mode()),
+                        ("invites", strings()),
+                        ("resolve_guilds", boolean()),
+                        ("guilds", snowflakes()),
+                    ],
+                    &["mode", "invites"],
+                ),
+            ),
+            (
+                "link",
+                object(
+                    &[
+                        ("mode", filter_mode()),
+                        ("domains", strings()),
+                        ("path_patterns", strings()),
+                        ("preview_words", strings()),
+                        ("match_subdomains", boolean()),
+                    ],
+                    &["mode", "domains"],
+                ),
+            ),
+            (
+                "sticker_id",
+                object(
+                    &[("mode", filter_mode()), ("stickers", snowflakes())],
+                    &["mode", "stickers"],
+                ),
+            ),
+            (
+                "sticker_name",
+                object(
+                    &[("stickers", strings()), ("include_metadata", boolean())],
+                    &["stickers"],
+                ),
+            ),
+            ("emoji_name", object(&[("names", strings())], &["names"])),
+            ("has_components", object(&[("max", integer())], &["max"])),
+            (
+                "system_message",
+                object(
+                    &[(
+                        "types",
+                        array_of(json!({
+                            "enum": ["user_join", "pin", "guild_boost", "thread_created", "channel_follow"]
+                        })),
+                    )],
+                    &["types"],
+                ),
+            ),
+            (
+                "min_interval",
+                object(&[("seconds", integer())], &["seconds"]),
+            ),
+            (
+                "prior_infractions",
+                object(&[("count", integer())], &["count"]),
+            ),
+            (
+                "protected_users",
+                object(&[("users", snowflakes())], &["users"]),
+            ),
+            (
+                "max_length",
+                object(
+                    &[("chars", integer()), ("count_newlines_as", integer())],
+                    &["chars"],
+                ),
+            ),
+            (
+                "capitalization",
+                object(
+                    &[("max_uppercase_ratio", number()), ("min_length", integer())],
+                    &["max_uppercase_ratio", "min_length"],
+                ),
+            ),
+            ("invisible_chars", object(&[("max_count", integer())], &[])),
+            (
+                "mixed_script",
+                object(&[("min_length", integer())], &["min_length"]),
+            ),
+            (
+                "repeated_chars",
+                object(&[("max_run", integer())], &["max_run"]),
+            ),
+            (
+                "secret_scan",
+                object(
+                    &[(
+                        "presets",
+                        array_of(json!({
+                            "enum": ["discord_token", "aws_access_key", "high_entropy"]
+                        })),
+                    )],
+                    &["presets"],
+                ),
+            ),
+            (
+                "mention_count",
+                object(
+                    &[
+                        ("max_users", integer()),
+                        ("max_roles", integer()),
+                        ("max_total", integer()),
+                    ],
+                    &[],
+                ),
+            ),
+            (
+                "not",
+                object(&[("rule", reference("message_rule"))], &["rule"]),
+            ),
+        ],
+    )
+}
+
+fn reaction_rule() -> Value {
+    tagged(
+        "type",
+        vec![
+            (
+                "default",
+                object(
+                    &[("mode", filter_mode()), ("emoji", strings())],
+                    &["mode", "emoji"],
+                ),
+            ),
+            (
+                "custom_id",
+                object(
+                    &[("mode", filter_mode()), ("emoji", snowflakes())],
+                    &["mode", "emoji"],
+                ),
+            ),
+            ("custom_name", object(&[("names", strings())], &["names"])),
+            (
+                "count",
+                object(&[("max_distinct", integer())], &["max_distinct"]),
+            ),
+        ],
+    )
+}
+
+fn scoping() -> Value {
+    let window = object(
+        &[
+            (
+                "start",
+                json!({ "type": "string", "pattern": "^[0-9]{1,2}:[0-9]{2}$" }),
+            ),
+            (
+                "end",
+                json!({ "type": "string", "pattern": "^[0-9]{1,2}:[0-9]{2}$" }),
+            ),
+            ("days", strings()),
+        ],
+        &["start", "end"],
+    );
+
+    object(
+        &[
+            ("exclude_channels", snowflakes()),
+            ("include_channels", snowflakes()),
+            ("exclude_categories", snowflakes()),
+            ("include_categories", snowflakes()),
+            ("exclude_roles", snowflakes()),
+            ("include_roles", snowflakes()),
+            ("min_account_age_secs", integer()),
+            (
+                "schedule",
+                object(
+                    &[
+                        (
+                            "timezone",
+                            json!({ "type": "string", "examples": ["Europe/London"] }),
+                        ),
+                        ("windows", array_of(window)),
+                    ],
+                    &["timezone", "windows"],
+                ),
+            ),
+        ],
+        &[],
+    )
+}
+
+fn message_filter() -> Value {
+    let weighted_rule = json!({
+        "allOf": [reference("message_rule")],
+        "properties": { "weight": number() },
+    });
+
+    object(
+        &[
+            ("name", string()),
+            ("description", string()),
+            ("rules", array_of(weighted_rule)),
+            ("match_mode", json!({ "enum": ["any", "all"] })),
+            ("score_threshold", number()),
+            (
+                "scan",
+                strict_object(
+                    &[
+                        ("content", boolean()),
+                        ("embeds", boolean()),
+                        ("components", boolean()),
+                        ("attachment_descriptions", boolean()),
+                    ],
+                    &[],
+                ),
+            ),
+            ("scan_attachment_descriptions", boolean()),
+            ("scan_embeds", boolean()),
+            ("trusted_mime_types", strings()),
+            ("log_sample_rate", number()),
+            ("max_actions_per_minute", integer()),
+            ("leetspeak", boolean()),
+            ("normalize", boolean()),
+            (
+                "leetspeak_substitutions",
+                json!({
+                    "type": "object",
+                    "additionalProperties": { "type": "string", "minLength": 1, "maxLength": 1 },
+                }),
+            ),
+            ("scoping", reference("scoping")),
+            ("actions", array_of(reference("message_action"))),
+        ],
+        &["name", "rules"],
+    )
+}
+
+fn guild_config() -> Value {
+    let actions = || array_of(reference("message_action"));
+    // Neither of these enums are renamed, so their names are as in the code.
+    let username_rule = tagged(
+        "type",
+        vec![
+            (
+                "Substring",
+                object(&[("substrings", strings())], &["substrings"]),
+            ),
+            ("Regex", object(&[("regexes", strings())], &["regexes"])),
+            (
+                "Invite",
+                object(
+                    &[("mode", filter_mode()), ("invites", strings())],
+                    &["mode", "invites"],
+                ),
+            ),
+        ],
+    );
+    let username_action = object(
+        &[(
+            "SendMessage",
+            object(
+                &[("channel_id", snowflake()), ("content", string())],
+                &["channel_id", "content"],
+            ),
+        )],
+        &["SendMessage"],
+    );
+
+    object(
+        &[
+            (
+                "notifications",
+                object(
+                    &[("channel", snowflake()), ("ping_roles", snowflakes())],
+                    &["channel"],
+                ),
+            ),
+            ("ignore_channels", snowflakes()),
+            (
+                "slash_commands",
+                object(&[("enabled", boolean())], &["enabled"]),
+            ),
+            (
+                "prefix_commands",
+                object(
+                    &[
+                        ("prefix", string()),
+                        ("users", snowflakes()),
+                        ("roles", snowflakes()),
+                    ],
+                    &["prefix"],
+                ),
+            ),
+            ("default_scoping", reference("scoping")),
+            ("default_actions", actions()),
+            ("messages", array_of(reference("message_filter"))),
+            (
+                "reactions",
+                array_of(object(
+                    &[
+                        ("name", string()),
+                        ("rules", array_of(reaction_rule())),
+                        ("scoping", reference("scoping")),
+                        ("actions", actions()),
+                    ],
+                    &["name", "rules"],
+                )),
+            ),
+            (
+                "spam",
+                object(
+                    &[
+                        ("emoji", integer()),
+                        ("duplicates", integer()),
+                        ("crosspost", integer()),
+                        ("links", integer()),
+                        ("attachments", integer()),
+                        ("spoilers", integer()),
+                        ("mentions", integer()),
+                        ("newlines", integer()),
+                        ("total_chars", integer()),
+                        ("raid_users", integer()),
+                        ("interval", integer()),
+                        ("actions", actions()),
+                        ("scoping", reference("scoping")),
+                    ],
+                    &["interval"],
+                ),
+            ),
+            (
+                "usernames",
+                object(
+                    &[
+                        ("rules", array_of(username_rule)),
+                        ("actions", array_of(username_action)),
+                    ],
+                    &["rules", "actions"],
+                ),
+            ),
+            (
+                "channel_status_filter",
+                object(
+                    &[
+                        ("rules", array_of(reference("message_rule"))),
+                        ("fallback_topic", string()),
+                        ("log_channel", snowflake()),
+                    ],
+                    &["rules"],
+                ),
+            ),
+            (
+                "approval",
+                object(
+                    &[("emoji", string()), ("roles", snowflakes())],
+                    &["emoji", "roles"],
+                ),
+            ),
+            (
+                "delete_evasion",
+                object(&[("window_seconds", integer())], &["window_seconds"]),
+            ),
+            (
+                "log",
+                object(
+                    &[
+                        ("title", string()),
+                        ("color", integer()),
+                        (
+                            "fields",
+                            array_of(object(
+                                &[
+                                    ("name", string()),
+                                    ("value", string()),
+                                    ("inline", boolean()),
+                                ],
+                                &["name", "value"],
+                            )),
+                        ),
+                        ("include_content", boolean()),
+                    ],
+                    &["fields"],
+                ),
+            ),
+            (
+                "metrics",
+                object(&[("report_every_n", integer())], &["report_every_n"]),
+            ),
+            (
+                "multi_match",
+                json!({ "enum": ["first", "union", "most_severe"] }),
+            ),
+            ("combine_logs", boolean()),
+            ("escalation_decay_seconds", integer()),
+            (
+                "action_delay",
+                object(
+                    &[("delay_ms", integer()), ("jitter_ms", integer())],
+                    &["delay_ms"],
+                ),
+            ),
+            ("include_bots", boolean()),
+            (
+                "sentry_tags",
+                json!({ "type": "object", "additionalProperties": string() }),
+            ),
+        ],
+        &[],
+    )
+}
+
+/// A JSON Schema describing guild configuration files, for editors to
+/// autocomplete and check them with. It only describes their shape; files
+/// matching it can still fail validation.
+pub(crate) fn guild_config_schema() -> Value {
+    let mut schema = guild_config();
+    schema["$schema"] = json!("https://json-schema.org/draft/2020-12/schema");
+    schema["title"] = json!("Chrysanthemum guild configuration");
+    schema["$defs"] = json!({
+        "message_action": message_action(),
+        "message_filter": message_filter(),
+        "message_rule": message_rule(),
+        "scoping": scoping(),
+    });
+    schema
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::{json, Value};
+
+    use crate::config::{GuildConfig, MessageFilterAction, MessageFilterRule};
+
+    /// Builds the smallest value `schema` allows, resolving references against
+    /// `root`.
+    fn example(schema: &Value, root: &Value) -> Value {
+        if let Some(reference) = schema["$ref"].as_str() {
+            let name = reference.trim_start_matches("#/$defs/");
+            return example(&root["$defs"][name], root);
+        }
+        if let Some(all) = schema["allOf"].as_array() {
+            return example(&all[0], root);
+        }
+        if let Some(one) = schema["oneOf"].as_array() {
+            return example(&one[0], root);
+        }
+        if let Some(constant) = schema.get("const") {
+            return constant.clone();
+        }
+        if let Some(values) = schema["enum"].as_array() {
+            return values[0].clone();
+        }
+        if let Some(examples) = schema["examples"].as_array() {
+            return examples[0].clone();
+        }
+        if schema["pattern"] == "^[0-9]+$" {
+            return json!("1");
+        }
+        if schema["pattern"].is_string() {
+            return json!("12:00");
+        }
+
+        match schema["type"].as_str() {
+            Some("object") => {
+                let mut object = serde_json::Map::new();
+                // A variant with alternative required fields needs one of them.
+                let alternatives = schema["anyOf"][0]["required"].as_array();
+                let required = schema["required"]
+                    .as_array()
+                    .into_iter()
+                    .chain(alternatives)
+                    .flatten();
+                for name in required {
+                    let name = name.as_str().unwrap();
+                    object.insert(name.to_owned(), example(&schema["properties"][name], root));
+                }
+                Value::Object(object)
+            }
+            Some("array") => json!([example(&schema["items"], root)]),
+            Some("string") => json!("x"),
+            Some("integer") => json!(1),
+            Some("number") => json!(0.5),
+            Some("boolean") => json!(false),
+            other => panic!("no example for {:?} in {}", other, schema),
+        }
+    }
+
+    /// Every variant of the `oneOf` at `pointer`, as the smallest value the
+    /// schema allows.
+    fn variant_examples(root: &Value, pointer: &str) -> Vec<Value> {
+        root.pointer(pointer).unwrap()["oneOf"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|variant| example(variant, root))
+            .collect()
+    }
+
+    #[test]
+    fn schema_examples_deserialize() {
+        let schema = super::guild_config_schema();
+
+        for action in variant_examples(&schema, "/$defs/message_action") {
+            serde_json::from_value::<MessageFilterAction>(action.clone())
+                .unwrap_or_else(|err| panic!("{} doesn't deserialize: {}", action, err));
+        }
+        for rule in variant_examples(&schema, "/$defs/message_rule") {
+            serde_json::from_value::<MessageFilterRule>(rule.clone())
+                .unwrap_or_else(|err| panic!("{} doesn't deserialize: {}", rule, err));
+        }
+
+        let mut guild = serde_json::Map::new();
+        for (name, property) in schema["properties"].as_object().unwrap() {
+            let mut property = property.clone();
+            // Fill in optional fields too, so every section is checked.
+            if let Some(properties) = property["properties"].as_object() {
+                property["required"] = json!(properties.keys().collect::<Vec<_>>());
+            }
+            guild.insert(name.clone(), example(&property, &schema));
+        }
+        serde_json::from_value::<GuildConfig>(Value::Object(guild.clone())).unwrap_or_else(|err| {
+            panic!("{:#} doesn't deserialize: {}", Value::Object(guild), err)
+        });
+    }
+}
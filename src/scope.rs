@@ -0,0 +1,174 @@
+use twilight_model::id::{
+    marker::{ChannelMarker, RoleMarker, UserMarker},
+    Id,
+};
+
+use crate::config::{GuildConfig, Scoping};
+
+/// Whether a single filter applies in a given place, and if not, why.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct ScopeEntry {
+    pub(crate) name: String,
+    pub(crate) skip_reason: Option<String>,
+}
+
+/// Which filters in each subsystem apply to a channel and set of roles.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct ScopePreview {
+    pub(crate) messages: Vec<ScopeEntry>,
+    pub(crate) reactions: Vec<ScopeEntry>,
+    pub(crate) spam: Option<ScopeEntry>,
+}
+
+/// Evaluates the scoping of every filter in `guild_config` as if content were
+/// posted in `channel`, in `category`, by `author` with `roles`.
+pub(crate) fn preview_scopes(
+    guild_config: &GuildConfig,
+    channel: Id<ChannelMarker>,
+    category: Option<Id<ChannelMarker>>,
+    author: Option<Id<UserMarker>>,
+    roles: &[Id<RoleMarker>],
+) -> ScopePreview {
+    let ignored = guild_config.is_ignored_channel(channel);
+    let default_scoping = guild_config.default_scoping.as_ref();
+
+    let entry = |name: &str, scoping: Option<&Scoping>| {
+        let skip_reason = if ignored {
+            Some("channel is in ignore_channels".to_owned())
+        } else {
+            scoping
+                .or(default_scoping)
+                .and_then(|s| s.exclusion_reason(channel, category, author, roles))
+        };
+
+        ScopeEntry {
+            name: name.to_owned(),
+            skip_reason,
+        }
+    };
+
+    let messages = guild_config
+        .messages
+        .iter()
+        .flatten()
+        .map(|f| entry(&f.name, f.scoping.as_ref()))
+        .collect();
+
+    let reactions = guild_config
+        .reactions
+        .iter()
+        .flatten()
+        .map(|f| entry(&f.name, f.scoping.as_ref()))
+        .collect();
+
+    let spam = guild_config
+        .spam
+        .as_ref()
+        .map(|s| entry("Spam", s.scoping.as_ref()));
+
+    ScopePreview {
+        messages,
+        reactions,
+        spam,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+    use twilight_model::id::Id;
+
+    use crate::config::{GuildConfig, MessageFilter, ReactionFilter, Scoping, SpamFilter};
+
+    use super::{ScopeEntry, ScopePreview};
+
+    fn guild_config() -> GuildConfig {
+        GuildConfig {
+            default_scoping: Some(Scoping {
+                exclude_roles: Some(vec![Id::new(10)]),
+                ..Default::default()
+            }),
+            messages: Some(vec![
+                MessageFilter {
+                    name: "everywhere".to_owned(),
+                    ..Default::default()
+                },
+                MessageFilter {
+                    name: "only general".to_owned(),
+                    scoping: Some(Scoping {
+                        include_channels: Some(vec![Id::new(1)]),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                },
+            ]),
+            reactions: Some(vec![ReactionFilter {
+                name: "reactions".to_owned(),
+                rules: vec![],
+                scoping: Some(Scoping {
+                    exclude_channels: Some(vec![Id::new(2)]),
+                    ..Default::default()
+                }),
+                actions: None,
+            }]),
+            spam: Some(SpamFilter::default()),
+            ..Default::default()
+        }
+    }
+
+    fn entry(name: &str, skip_reason: Option<&str>) -> ScopeEntry {
+        ScopeEntry {
+            name: name.to_owned(),
+            skip_reason: skip_reason.map(str::to_owned),
+        }
+    }
+
+    #[test]
+    fn preview_reports_skip_reasons() {
+        let preview = super::preview_scopes(&guild_config(), Id::new(2), None, None, &[]);
+
+        assert_eq!(
+            preview,
+            ScopePreview {
+                messages: vec![
+                    entry("everywhere", None),
+                    entry("only general", Some("channel is not in include_channels")),
+                ],
+                reactions: vec![entry("reactions", Some("channel is in exclude_channels"))],
+                spam: Some(entry("Spam", None)),
+            }
+        );
+    }
+
+    #[test]
+    fn preview_uses_default_scoping_for_roles() {
+        let preview =
+            super::preview_scopes(&guild_config(), Id::new(1), None, None, &[Id::new(10)]);
+
+        assert_eq!(
+            preview.messages,
+            vec![
+                entry("everywhere", Some("author has excluded role 10")),
+                entry("only general", None),
+            ]
+        );
+        assert_eq!(
+            preview.spam,
+            Some(entry("Spam", Some("author has excluded role 10")))
+        );
+    }
+
+    #[test]
+    fn preview_respects_ignored_channels() {
+        let mut guild_config = guild_config();
+        guild_config.ignore_channels = Some(vec![Id::new(1)]);
+
+        let preview = super::preview_scopes(&guild_config, Id::new(1), None, None, &[]);
+        assert!(preview
+            .messages
+            .iter()
+            .chain(&preview.reactions)
+            .chain(&preview.spam)
+            .all(|e| e.skip_reason.as_deref() == Some("channel is in ignore_channels")));
+    }
+}
@@ -0,0 +1,97 @@
+/// The writing systems `mixed_script` rules tell apart. Han, kana and Hangul
+/// are grouped together, since Japanese and Korean words mix them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Script {
+    Latin,
+    Greek,
+    Cyrillic,
+    Armenian,
+    Hebrew,
+    Arabic,
+    Devanagari,
+    Bengali,
+    Thai,
+    Georgian,
+    Cjk,
+}
+
+impl Script {
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Script::Latin => "Latin",
+            Script::Greek => "Greek",
+            Script::Cyrillic => "Cyrillic",
+            Script::Armenian => "Armenian",
+            Script::Hebrew => "Hebrew",
+            Script::Arabic => "Arabic",
+            Script::Devanagari => "Devanagari",
+            Script::Bengali => "Bengali",
+            Script::Thai => "Thai",
+            Script::Georgian => "Georgian",
+            Script::Cjk => "CJK",
+        }
+    }
+}
+
+/// The script `c` is written in, going by the Unicode blocks each script's
+/// letters live in. Characters that aren't letters, such as digits,
+/// punctuation and combining marks, belong to no script, and neither do
+/// letters from scripts not listed in [`Script`].
+pub(crate) fn script(c: char) -> Option<Script> {
+    if !c.is_alphabetic() {
+        return None;
+    }
+
+    let script = match c as u32 {
+        0x41..=0x5A
+        | 0x61..=0x7A
+        | 0xAA
+        | 0xBA
+        | 0xC0..=0x24F
+        | 0x250..=0x2AF
+        | 0x1D00..=0x1D7F
+        | 0x1E00..=0x1EFF
+        | 0x2C60..=0x2C7F
+        | 0xA720..=0xA7FF
+        | 0xFF21..=0xFF3A
+        | 0xFF41..=0xFF5A => Script::Latin,
+        0x370..=0x3FF | 0x1F00..=0x1FFF => Script::Greek,
+        0x400..=0x52F | 0x1C80..=0x1C8F | 0x2DE0..=0x2DFF | 0xA640..=0xA69F => Script::Cyrillic,
+        0x530..=0x58F | 0xFB13..=0xFB17 => Script::Armenian,
+        0x590..=0x5FF | 0xFB1D..=0xFB4F => Script::Hebrew,
+        0x600..=0x6FF | 0x750..=0x77F | 0x8A0..=0x8FF | 0xFB50..=0xFDFF | 0xFE70..=0xFEFE => {
+            Script::Arabic
+        }
+        0x900..=0x97F => Script::Devanagari,
+        0x980..=0x9FF => Script::Bengali,
+        0xE00..=0xE7F => Script::Thai,
+        0x10A0..=0x10FF | 0x2D00..=0x2D2F => Script::Georgian,
+        0x1100..=0x11FF
+        | 0x3040..=0x30FF
+        | 0x3100..=0x318F
+        | 0x31F0..=0x31FF
+        | 0x3400..=0x4DBF
+        | 0x4E00..=0x9FFF
+        | 0xAC00..=0xD7AF
+        | 0xF900..=0xFAFF
+        | 0xFF66..=0xFFDC
+        | 0x20000..=0x3134F => Script::Cjk,
+        _ => return None,
+    };
+
+    Some(script)
+}
+
+/// Finds the first word in `text` at least `min_length` characters long that
+/// mixes letters from more than one script. Returns the word and the first
+/// two scripts in it.
+pub(crate) fn find_mixed_word(text: &str, min_length: usize) -> Option<(&str, Script, Script)> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| word.chars().count() >= min_length)
+        .find_map(|word| {
+            let mut scripts = word.chars().filter_map(script);
+            let first = scripts.next()?;
+            let second = scripts.find(|script| *script != first)?;
+            Some((word, first, second))
+        })
+}
@@ -0,0 +1,220 @@
+use std::time::Duration;
+
+use eyre::{eyre, Result};
+use futures::future;
+use once_cell::sync::OnceCell;
+use twilight_model::channel::Attachment;
+
+/// How much of an attachment to download when detecting its type. Every
+/// signature we know of is well within this.
+const MAX_SNIFF_BYTES: usize = 8 * 1024;
+/// How long downloading the start of one attachment may take.
+const SNIFF_TIMEOUT: Duration = Duration::from_secs(3);
+/// How many attachments in one message we'll download the start of.
+const MAX_SNIFFED_PER_MESSAGE: usize = 5;
+
+/// File signatures, as the offset they're found at, the bytes found there, and
+/// the MIME type they mean. More specific signatures come first.
+const SIGNATURES: &[(usize, &[u8], &str)] = &[
+    (0, b"\x89PNG\r\n\x1a\n", "image/png"),
+    (0, b"\xff\xd8\xff", "image/jpeg"),
+    (0, b"GIF87a", "image/gif"),
+    (0, b"GIF89a", "image/gif"),
+    (8, b"WEBP", "image/webp"),
+    (8, b"WAVE", "audio/wav"),
+    (8, b"AVI ", "video/x-msvideo"),
+    (0, b"BM", "image/bmp"),
+    (4, b"ftyp", "video/mp4"),
+    (0, b"\x1a\x45\xdf\xa3", "video/webm"),
+    (0, b"OggS", "audio/ogg"),
+    (0, b"ID3", "audio/mpeg"),
+    (0, b"fLaC", "audio/flac"),
+    (0, b"%PDF-", "application/pdf"),
+    (0, b"PK\x03\x04", "application/zip"),
+    (0, b"Rar!\x1a\x07", "application/vnd.rar"),
+    (0, b"7z\xbc\xaf\x27\x1c", "application/x-7z-compressed"),
+    (0, b"\x1f\x8b", "application/gzip"),
+    (0, b"MZ", "application/vnd.microsoft.portable-executable"),
+    (0, b"\x7fELF", "application/x-elf"),
+    (0, b"\xcf\xfa\xed\xfe", "application/x-mach-binary"),
+    (0, b"\xce\xfa\xed\xfe", "application/x-mach-binary"),
+    (0, b"\xca\xfe\xba\xbe", "application/x-mach-binary"),
+    (0, b"#!", "text/x-shellscript"),
+];
+
+/// Detects the type of a file from its first bytes, if it has a signature we
+/// know.
+pub(crate) fn detect(bytes: &[u8]) -> Option<&'static str> {
+    SIGNATURES
+        .iter()
+        .find(|(offset, signature, _)| {
+            bytes
+                .get(*offset..offset + signature.len())
+                .map_or(false, |found| found == *signature)
+        })
+        .map(|(_, _, mime_type)| *mime_type)
+}
+
+fn client() -> &'static reqwest::Client {
+    static CLIENT: OnceCell<reqwest::Client> = OnceCell::new();
+    CLIENT.get_or_init(|| {
+        reqwest::Client::builder()
+            .timeout(SNIFF_TIMEOUT)
+            .build()
+            .expect("couldn't build attachment sniffing client")
+    })
+}
+
+/// Downloads up to the first [`MAX_SNIFF_BYTES`] of the attachment at `url`.
+async fn download_start(url: &str) -> Result<Vec<u8>> {
+    let mut response = client()
+        .get(url)
+        .header(
+            reqwest::header::RANGE,
+            format!("bytes=0-{}", MAX_SNIFF_BYTES - 1),
+        )
+        .send()
+        .await?
+        .error_for_status()?;
+
+    let mut body = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        body.extend_from_slice(&chunk);
+        if body.len() >= MAX_SNIFF_BYTES {
+            body.truncate(MAX_SNIFF_BYTES);
+            break;
+        }
+    }
+
+    if body.is_empty() {
+        return Err(eyre!("attachment is empty"));
+    }
+    Ok(body)
+}
+
+/// Copies `attachments`, replacing the type of each one with the type its
+/// start says it is. Discord picks types from file extensions, so this is what
+/// catches a renamed file. Attachments are downloaded concurrently, and ones
+/// that can't be downloaded or recognized keep the type Discord gave, if any.
+pub(crate) async fn sniff_attachments(attachments: &[Attachment]) -> Vec<Attachment> {
+    let detected = future::join_all(attachments.iter().take(MAX_SNIFFED_PER_MESSAGE).map(
+        |attachment| async move {
+            match download_start(&attachment.url).await {
+                Ok(bytes) => detect(&bytes),
+                Err(err) => {
+                    tracing::debug!(url = %attachment.url, ?err, "Unable to sniff attachment type");
+                    None
+                }
+            }
+        },
+    ))
+    .await;
+
+    let mut sniffed = attachments.to_vec();
+    for (attachment, detected) in sniffed.iter_mut().zip(detected) {
+        if let Some(content_type) = detected {
+            attachment.content_type = Some(content_type.to_owned());
+        }
+    }
+
+    sniffed
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    use twilight_model::channel::Attachment;
+    use twilight_model::id::Id;
+
+    /// Serves `body` to every connection, after an HTTP header.
+    async fn serve(body: &'static [u8]) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let mut request = [0; 1024];
+                let _ = socket.read(&mut request).await;
+                let header = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    body.len()
+                );
+                let _ = socket.write_all(header.as_bytes()).await;
+                let _ = socket.write_all(body).await;
+            }
+        });
+
+        format!("http://{}/free-nitro.png", addr)
+    }
+
+    fn attachment(url: String, content_type: Option<&str>) -> Attachment {
+        Attachment {
+            content_type: content_type.map(str::to_owned),
+            ephemeral: false,
+            filename: "free-nitro.png".to_owned(),
+            description: None,
+            height: None,
+            id: Id::new(1),
+            proxy_url: url.clone(),
+            size: 1024,
+            url,
+            width: None,
+        }
+    }
+
+    #[test]
+    fn detects_signatures() {
+        assert_eq!(super::detect(b"\x89PNG\r\n\x1a\n rest"), Some("image/png"));
+        assert_eq!(
+            super::detect(b"RIFF\x00\x00\x00\x00WEBPVP8 "),
+            Some("image/webp")
+        );
+        assert_eq!(
+            super::detect(b"\x00\x00\x00\x20ftypisom"),
+            Some("video/mp4")
+        );
+        assert_eq!(
+            super::detect(b"MZ\x90\x00"),
+            Some("application/vnd.microsoft.portable-executable")
+        );
+        assert_eq!(super::detect(b"just some text"), None);
+        assert_eq!(super::detect(b""), None);
+    }
+
+    #[tokio::test]
+    async fn renamed_executable_is_sniffed() {
+        let url = serve(b"MZ\x90\x00\x03\x00\x00\x00This program cannot be run in DOS mode").await;
+        let text_url = serve(b"just some text").await;
+        let attachments = [
+            attachment(url.clone(), None),
+            // Discord types attachments by extension, so a renamed executable
+            // arrives as an image.
+            attachment(url, Some("image/png")),
+            attachment(text_url, Some("text/plain")),
+        ];
+
+        let sniffed = super::sniff_attachments(&attachments).await;
+        assert_eq!(
+            sniffed[0].content_type.as_deref(),
+            Some("application/vnd.microsoft.portable-executable")
+        );
+        assert_eq!(
+            sniffed[1].content_type.as_deref(),
+            Some("application/vnd.microsoft.portable-executable")
+        );
+        // Types Discord gave are kept when the contents aren't recognized.
+        assert_eq!(sniffed[2].content_type.as_deref(), Some("text/plain"));
+    }
+
+    #[tokio::test]
+    async fn unreachable_attachment_keeps_no_type() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/gone.png", listener.local_addr().unwrap());
+        drop(listener);
+
+        let sniffed = super::sniff_attachments(&[attachment(url, None)]).await;
+        assert_eq!(sniffed[0].content_type, None);
+    }
+}
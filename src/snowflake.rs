@@ -0,0 +1,81 @@
+use twilight_model::id::Id;
+
+/// The first millisecond of 2015, which Discord snowflakes count from.
+pub(crate) const DISCORD_EPOCH_MS: u64 = 1_420_070_400_000;
+
+/// How far the timestamp is shifted within a snowflake. The low bits hold the
+/// worker, process and increment, which we never need.
+const TIMESTAMP_SHIFT: u32 = 22;
+
+/// When an ID of any kind was created, in milliseconds since the Unix epoch.
+pub(crate) fn created_at_ms<T>(id: Id<T>) -> u64 {
+    (id.get() >> TIMESTAMP_SHIFT) + DISCORD_EPOCH_MS
+}
+
+/// Builds the smallest ID created at `timestamp_ms`, in milliseconds since the
+/// Unix epoch. Useful for fabricating IDs with a known age.
+///
+/// # Panics
+/// Panics if `timestamp_ms` is not after the Discord epoch.
+#[cfg(test)]
+pub(crate) fn id_created_at<T>(timestamp_ms: u64) -> Id<T> {
+    let since_epoch = timestamp_ms
+        .checked_sub(DISCORD_EPOCH_MS)
+        .filter(|ms| *ms > 0)
+        .expect("timestamp must be after the Discord epoch");
+
+    Id::new(since_epoch << TIMESTAMP_SHIFT)
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+    use twilight_model::id::{
+        marker::{ChannelMarker, GuildMarker, UserMarker},
+        Id,
+    };
+
+    use super::{created_at_ms, id_created_at, DISCORD_EPOCH_MS};
+
+    // The example snowflake from Discord's API reference.
+    const EXAMPLE_ID: u64 = 175928847299117063;
+    const EXAMPLE_TIMESTAMP_MS: u64 = 1462015105796;
+
+    #[test]
+    fn decodes_known_snowflake() {
+        let id: Id<UserMarker> = Id::new(EXAMPLE_ID);
+        assert_eq!(created_at_ms(id), EXAMPLE_TIMESTAMP_MS);
+    }
+
+    #[test]
+    fn decodes_identically_across_id_types() {
+        let user: Id<UserMarker> = Id::new(EXAMPLE_ID);
+        let channel: Id<ChannelMarker> = user.cast();
+        let guild: Id<GuildMarker> = user.cast();
+
+        assert_eq!(created_at_ms(user), EXAMPLE_TIMESTAMP_MS);
+        assert_eq!(created_at_ms(channel), EXAMPLE_TIMESTAMP_MS);
+        assert_eq!(created_at_ms(guild), EXAMPLE_TIMESTAMP_MS);
+    }
+
+    #[test]
+    fn fabricated_ids_round_trip() {
+        let timestamp_ms = DISCORD_EPOCH_MS + 86_400_000;
+
+        let user: Id<UserMarker> = id_created_at(timestamp_ms);
+        let channel: Id<ChannelMarker> = id_created_at(timestamp_ms);
+        let guild: Id<GuildMarker> = id_created_at(timestamp_ms);
+
+        assert_eq!(created_at_ms(user), timestamp_ms);
+        assert_eq!(created_at_ms(channel), timestamp_ms);
+        assert_eq!(created_at_ms(guild), timestamp_ms);
+        assert_eq!(user.get(), channel.get());
+        assert_eq!(user.get(), guild.get());
+    }
+
+    #[test]
+    #[should_panic]
+    fn fabricating_before_epoch_panics() {
+        let _: Id<UserMarker> = id_created_at(DISCORD_EPOCH_MS);
+    }
+}
@@ -0,0 +1,208 @@
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use eyre::{Context, Result};
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+use twilight_model::id::{
+    marker::{GuildMarker, UserMarker},
+    Id,
+};
+
+use crate::escalation::Violations;
+use crate::filter::{RaidHistory, RaidRecord, SpamHistory, SpamRecord};
+
+/// One user's spam window, as stored on disk.
+#[derive(Serialize, Deserialize, Debug)]
+struct StoredSpamWindow {
+    user_id: Id<UserMarker>,
+    records: Vec<SpamRecord>,
+}
+
+/// One guild's raid window, as stored on disk.
+#[derive(Serialize, Deserialize, Debug)]
+struct StoredRaidWindow {
+    guild_id: Id<GuildMarker>,
+    records: Vec<RaidRecord>,
+}
+
+/// One user's `escalate` violations in one guild, as stored on disk.
+#[derive(Serialize, Deserialize, Debug)]
+struct StoredViolations {
+    guild_id: Id<GuildMarker>,
+    user_id: Id<UserMarker>,
+    /// When each violation happened, in seconds.
+    times: Vec<u64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct StoredState {
+    spam: Vec<StoredSpamWindow>,
+    raids: Vec<StoredRaidWindow>,
+    violations: Vec<StoredViolations>,
+}
+
+/// Tracking state loaded from the state store, or empty if there's none.
+#[derive(Debug, Default)]
+pub(crate) struct SavedState {
+    pub(crate) spam_history: SpamHistory,
+    pub(crate) raid_history: RaidHistory,
+    pub(crate) violations: Violations,
+}
+
+/// Saves spam and raid windows and `escalate` violations to a JSON file, so a
+/// restart in the middle of a raid doesn't reset them.
+#[derive(Debug)]
+pub(crate) struct StateStore {
+    path: PathBuf,
+}
+
+impl StateStore {
+    pub(crate) fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    /// Loads the state saved at the store's path. Expired entries are left for
+    /// the next prune, which knows each guild's intervals.
+    pub(crate) fn load(&self) -> Result<SavedState> {
+        let stored: StoredState = match std::fs::read_to_string(&self.path) {
+            Ok(contents) => serde_json::from_str(&contents).wrap_err_with(|| {
+                format!("Unable to parse saved state in {}", self.path.display())
+            })?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => StoredState::default(),
+            Err(err) => {
+                return Err(err).wrap_err_with(|| {
+                    format!("Unable to read saved state from {}", self.path.display())
+                })
+            }
+        };
+
+        Ok(SavedState {
+            spam_history: stored
+                .spam
+                .into_iter()
+                .map(|w| (w.user_id, Arc::new(Mutex::new(VecDeque::from(w.records)))))
+                .collect(),
+            raid_history: stored
+                .raids
+                .into_iter()
+                .map(|w| (w.guild_id, Arc::new(Mutex::new(VecDeque::from(w.records)))))
+                .collect(),
+            violations: Violations::from_saved(
+                stored
+                    .violations
+                    .into_iter()
+                    .map(|v| ((v.guild_id, v.user_id), v.times))
+                    .collect(),
+            ),
+        })
+    }
+
+    /// Writes the current state to a temporary file and moves it over the
+    /// store, so a crash mid-write can't leave a truncated file behind.
+    pub(crate) async fn save(
+        &self,
+        spam_history: &RwLock<SpamHistory>,
+        raid_history: &RwLock<RaidHistory>,
+        violations: &Violations,
+    ) -> Result<()> {
+        let stored = StoredState {
+            spam: spam_history
+                .read()
+                .await
+                .iter()
+                .map(|(user_id, records)| StoredSpamWindow {
+                    user_id: *user_id,
+                    records: records.lock().unwrap().iter().cloned().collect(),
+                })
+                .collect(),
+            raids: raid_history
+                .read()
+                .await
+                .iter()
+                .map(|(guild_id, records)| StoredRaidWindow {
+                    guild_id: *guild_id,
+                    records: records.lock().unwrap().iter().cloned().collect(),
+                })
+                .collect(),
+            violations: violations
+                .snapshot()
+                .into_iter()
+                .map(|((guild_id, user_id), times)| StoredViolations {
+                    guild_id,
+                    user_id,
+                    times,
+                })
+                .collect(),
+        };
+
+        let temp_path = self.path.with_extension("tmp");
+        tokio::fs::write(&temp_path, serde_json::to_string(&stored)?)
+            .await
+            .wrap_err_with(|| format!("Unable to write state to {}", temp_path.display()))?;
+        tokio::fs::rename(&temp_path, &self.path)
+            .await
+            .wrap_err_with(|| format!("Unable to replace {}", self.path.display()))?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+    use std::path::PathBuf;
+    use std::sync::{Arc, Mutex};
+
+    use pretty_assertions::assert_eq;
+    use tokio::sync::RwLock;
+    use twilight_model::id::Id;
+
+    use super::StateStore;
+    use crate::escalation::Violations;
+    use crate::filter::SpamRecord;
+    use crate::model::test::{message_at_time, BAD_CONTENT, GUILD_ID, USER_ID};
+
+    fn store(name: &str) -> StateStore {
+        let path: PathBuf = std::env::temp_dir().join(format!(
+            "chrysanthemum-state-{}-{}.json",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+
+        StateStore::new(path)
+    }
+
+    #[tokio::test]
+    async fn state_survives_restart() {
+        let store = store("restart");
+        let saved = store.load().unwrap();
+        assert!(saved.spam_history.is_empty());
+
+        let spam_history = RwLock::new(HashMap::new());
+        let raid_history = RwLock::new(HashMap::new());
+        let violations = Violations::default();
+        let message = message_at_time(BAD_CONTENT, 100);
+        spam_history.write().await.insert(
+            USER_ID,
+            Arc::new(Mutex::new(
+                std::iter::once(SpamRecord::from_message(&message)).collect(),
+            )),
+        );
+        violations.record(GUILD_ID, USER_ID, 100);
+        violations.record(Id::new(9), USER_ID, 150);
+
+        store
+            .save(&spam_history, &raid_history, &violations)
+            .await
+            .unwrap();
+
+        let saved = store.load().unwrap();
+        assert_eq!(saved.spam_history[&USER_ID].lock().unwrap().len(), 1);
+        assert!(saved.raid_history.is_empty());
+        assert_eq!(saved.violations.count(GUILD_ID, USER_ID, 60, 120), 1);
+        assert_eq!(saved.violations.count(Id::new(9), USER_ID, 60, 160), 1);
+    }
+}
@@ -0,0 +1,230 @@
+use twilight_model::channel::message::{Component, Embed};
+
+use crate::config::TextSurfaces;
+use crate::model::MessageInfo;
+
+/// A part of a message that people can read.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum TextSurface {
+    Content,
+    Embed,
+    Component,
+    AttachmentDescription,
+}
+
+impl TextSurface {
+    /// Says where a rule found what it matched, for anything but the content.
+    pub(crate) fn describe(self, reason: String) -> String {
+        match self {
+            TextSurface::Content => reason,
+            TextSurface::Embed => format!("embed {}", reason),
+            TextSurface::Component => format!("component {}", reason),
+            TextSurface::AttachmentDescription => format!("attachment description {}", reason),
+        }
+    }
+}
+
+fn embed_text(embed: &Embed) -> impl Iterator<Item = &str> {
+    embed
+        .title
+        .as_deref()
+        .into_iter()
+        .chain(embed.description.as_deref())
+        .chain(
+            embed
+                .fields
+                .iter()
+                .flat_map(|f| [f.name.as_str(), f.value.as_str()]),
+        )
+        .chain(embed.author.as_ref().map(|a| a.name.as_str()))
+        .chain(embed.footer.as_ref().map(|f| f.text.as_str()))
+}
+
+fn component_text<'a>(component: &'a Component, text: &mut Vec<&'a str>) {
+    match component {
+        Component::ActionRow(row) => {
+            for component in &row.components {
+                component_text(component, text);
+            }
+        }
+        Component::Button(button) => text.extend(button.label.as_deref()),
+        Component::SelectMenu(menu) => {
+            text.extend(menu.placeholder.as_deref());
+            for option in &menu.options {
+                text.push(&option.label);
+                text.extend(option.description.as_deref());
+            }
+        }
+        Component::TextInput(input) => {
+            text.push(&input.label);
+            text.extend(input.placeholder.as_deref());
+            text.extend(input.value.as_deref());
+        }
+        Component::Unknown(_) => {}
+    }
+}
+
+/// Collects each piece of text in the parts of `message` that `surfaces` asks
+/// for, such as an embed's title and each of its fields. Pieces are kept
+/// apart, rather than joined, so that no rule can match across two of them.
+pub(crate) fn extract_text<'a>(
+    message: &MessageInfo<'a>,
+    surfaces: TextSurfaces,
+) -> Vec<(TextSurface, &'a str)> {
+    let mut text = Vec::new();
+
+    if surfaces.content {
+        text.push((TextSurface::Content, message.content));
+    }
+
+    if surfaces.embeds {
+        let embeds: &'a [Embed] = message.embeds;
+        text.extend(
+            embeds
+                .iter()
+                .flat_map(embed_text)
+                .map(|piece| (TextSurface::Embed, piece)),
+        );
+    }
+
+    if surfaces.components {
+        let mut pieces = Vec::new();
+        for component in message.components {
+            component_text(component, &mut pieces);
+        }
+        text.extend(
+            pieces
+                .into_iter()
+                .map(|piece| (TextSurface::Component, piece)),
+        );
+    }
+
+    if surfaces.attachment_descriptions {
+        text.extend(
+            message
+                .attachments
+                .iter()
+                .filter_map(|a| a.description.as_deref())
+                .map(|piece| (TextSurface::AttachmentDescription, piece)),
+        );
+    }
+
+    text.retain(|(_, text)| !text.is_empty());
+    text
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+    use twilight_model::channel::message::component::{ActionRow, Button, ButtonStyle};
+    use twilight_model::channel::message::Component;
+    use twilight_model::channel::Attachment;
+    use twilight_model::id::Id;
+    use twilight_util::builder::embed::{EmbedBuilder, EmbedFieldBuilder, EmbedFooterBuilder};
+
+    use super::{extract_text, TextSurface};
+    use crate::config::TextSurfaces;
+    use crate::model::test::message;
+
+    #[test]
+    fn each_surface_follows_its_flag() {
+        let embeds = [EmbedBuilder::new()
+            .title("embed title")
+            .description("embed description")
+            .field(EmbedFieldBuilder::new("field name", "field value"))
+            .footer(EmbedFooterBuilder::new("footer"))
+            .build()];
+        let components = [Component::ActionRow(ActionRow {
+            components: vec![Component::Button(Button {
+                custom_id: Some("button".to_owned()),
+                disabled: false,
+                emoji: None,
+                label: Some("button label".to_owned()),
+                style: ButtonStyle::Primary,
+                url: None,
+            })],
+        })];
+        let attachments = [Attachment {
+            content_type: Some("image/png".to_owned()),
+            ephemeral: false,
+            filename: "cat.png".to_owned(),
+            description: Some("alt text".to_owned()),
+            height: None,
+            id: Id::new(1),
+            proxy_url: String::new(),
+            size: 1,
+            url: String::new(),
+            width: None,
+        }];
+
+        let mut message = message("content");
+        message.embeds = &embeds;
+        message.components = &components;
+        message.attachments = &attachments;
+
+        let content = vec![(TextSurface::Content, "content")];
+        let embed: Vec<_> = [
+            "embed title",
+            "embed description",
+            "field name",
+            "field value",
+            "footer",
+        ]
+        .iter()
+        .map(|piece| (TextSurface::Embed, *piece))
+        .collect();
+        let component = vec![(TextSurface::Component, "button label")];
+        let description = vec![(TextSurface::AttachmentDescription, "alt text")];
+
+        let only = |surfaces: TextSurfaces| extract_text(&message, surfaces);
+        assert_eq!(only(TextSurfaces::default()), content);
+        assert_eq!(
+            only(TextSurfaces {
+                content: false,
+                embeds: true,
+                ..Default::default()
+            }),
+            embed
+        );
+        assert_eq!(
+            only(TextSurfaces {
+                content: false,
+                components: true,
+                ..Default::default()
+            }),
+            component
+        );
+        assert_eq!(
+            only(TextSurfaces {
+                content: false,
+                attachment_descriptions: true,
+                ..Default::default()
+            }),
+            description
+        );
+        assert_eq!(
+            only(TextSurfaces {
+                content: true,
+                embeds: true,
+                components: true,
+                attachment_descriptions: true,
+            }),
+            [content, embed, component, description].concat()
+        );
+    }
+
+    #[test]
+    fn empty_surfaces_are_skipped() {
+        let surfaces = TextSurfaces {
+            embeds: true,
+            components: true,
+            attachment_descriptions: true,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            extract_text(&message(""), surfaces),
+            Vec::<(TextSurface, &str)>::new()
+        );
+    }
+}
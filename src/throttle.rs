@@ -0,0 +1,159 @@
+use std::convert::TryFrom;
+use std::time::Duration;
+
+use twilight_http_ratelimiting::{request::Path, Bucket, Ratelimiter};
+
+use crate::config::ThrottleConfig;
+
+/// Spaces out actions whose rate limit bucket is nearly empty, so a burst of
+/// actions during a raid slows down gradually instead of running into 429s.
+#[derive(Debug)]
+pub(crate) struct Throttle {
+    min_remaining: u64,
+    max_delay: Duration,
+}
+
+impl Throttle {
+    pub(crate) fn new(config: &ThrottleConfig) -> Self {
+        Self {
+            min_remaining: config.min_remaining,
+            max_delay: Duration::from_millis(config.max_delay_ms),
+        }
+    }
+
+    /// How long to wait before using `bucket`, if at all. What's left of the
+    /// bucket is spread evenly over the time until it refills.
+    pub(crate) fn delay_for(&self, bucket: &Bucket) -> Option<Duration> {
+        if bucket.remaining() > self.min_remaining {
+            return None;
+        }
+
+        let time_remaining = bucket.time_remaining()?;
+        let remaining = u32::try_from(bucket.remaining()).unwrap_or(u32::MAX);
+        Some((time_remaining / remaining.saturating_add(1)).min(self.max_delay))
+    }
+
+    /// Waits until a request to `path` can go ahead without draining its
+    /// bucket, returning how long that took if it had to wait at all.
+    pub(crate) async fn wait(
+        &self,
+        ratelimiter: Option<&dyn Ratelimiter>,
+        path: &Path,
+    ) -> Option<Duration> {
+        let bucket = match ratelimiter?.bucket(path).await {
+            Ok(bucket) => bucket?,
+            Err(err) => {
+                tracing::debug!(?err, ?path, "Unable to get rate limit bucket");
+                return None;
+            }
+        };
+
+        let delay = self.delay_for(&bucket)?;
+        tracing::debug!(
+            ?path,
+            ?delay,
+            remaining = bucket.remaining(),
+            "Throttling action"
+        );
+        tokio::time::sleep(delay).await;
+        Some(delay)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use futures::future;
+    use pretty_assertions::assert_eq;
+    use twilight_http_ratelimiting::{
+        request::Path, Bucket, GetBucketFuture, GetTicketFuture, HasBucketFuture,
+        IsGloballyLockedFuture, Ratelimiter,
+    };
+
+    use super::Throttle;
+    use crate::config::ThrottleConfig;
+
+    fn throttle() -> Throttle {
+        Throttle::new(&ThrottleConfig {
+            min_remaining: 2,
+            max_delay_ms: 5_000,
+        })
+    }
+
+    fn bucket(remaining: u64) -> Bucket {
+        Bucket::new(5, remaining, Duration::from_secs(3), Some(Instant::now()))
+    }
+
+    /// A rate limiter that always reports the same bucket.
+    #[derive(Debug)]
+    struct FixedRatelimiter {
+        remaining: u64,
+    }
+
+    impl Ratelimiter for FixedRatelimiter {
+        fn bucket(&self, _path: &Path) -> GetBucketFuture {
+            Box::pin(future::ok(Some(bucket(self.remaining))))
+        }
+
+        fn is_globally_locked(&self) -> IsGloballyLockedFuture {
+            Box::pin(future::ok(false))
+        }
+
+        fn has(&self, _path: &Path) -> HasBucketFuture {
+            Box::pin(future::ok(true))
+        }
+
+        /// Throttling only looks at buckets, so taking a ticket is a bug.
+        fn ticket(&self, _path: Path) -> GetTicketFuture {
+            Box::pin(future::err("throttling never takes tickets".into()))
+        }
+    }
+
+    #[test]
+    fn delay_spreads_remaining_budget() {
+        let throttle = throttle();
+
+        assert_eq!(throttle.delay_for(&bucket(3)), None);
+
+        // Two requests left, three seconds to go: wait about a second each.
+        let delay = throttle.delay_for(&bucket(2)).unwrap();
+        assert!(delay > Duration::from_millis(900) && delay <= Duration::from_secs(1));
+
+        let delay = throttle.delay_for(&bucket(0)).unwrap();
+        assert!(delay > Duration::from_millis(2_900) && delay <= Duration::from_secs(3));
+
+        // Buckets that haven't started counting down have nothing to wait for.
+        let fresh = Bucket::new(5, 0, Duration::from_secs(3), None);
+        assert_eq!(throttle.delay_for(&fresh), None);
+    }
+
+    #[test]
+    fn delay_is_capped() {
+        let throttle = Throttle::new(&ThrottleConfig {
+            min_remaining: 2,
+            max_delay_ms: 100,
+        });
+
+        assert_eq!(
+            throttle.delay_for(&bucket(0)),
+            Some(Duration::from_millis(100))
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn low_budget_throttles_requests() {
+        let throttle = throttle();
+        let path = Path::ChannelsIdMessages(1);
+
+        let low = FixedRatelimiter { remaining: 1 };
+        let start = tokio::time::Instant::now();
+        let delay = throttle.wait(Some(&low), &path).await;
+        assert!(delay.is_some());
+        assert!(start.elapsed() >= delay.unwrap());
+
+        let plenty = FixedRatelimiter { remaining: 5 };
+        assert_eq!(throttle.wait(Some(&plenty), &path).await, None);
+        assert_eq!(throttle.wait(None, &path).await, None);
+    }
+}
@@ -0,0 +1,191 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use twilight_model::id::{
+    marker::{GuildMarker, UserMarker},
+    Id,
+};
+
+use crate::{
+    action::MessageAction,
+    config::{UsernameFilter, UsernameFilterAction},
+};
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct UsernameFilterFailure {
+    pub(crate) actions: Vec<MessageAction>,
+}
+
+/// How many members' names to remember before starting over.
+const MAX_REMEMBERED_MEMBERS: usize = 100_000;
+
+type MemberKey = (Id<GuildMarker>, Id<UserMarker>);
+
+/// Remembers the names each member had when they were last checked, so member
+/// updates that don't change a name, like new roles, aren't checked again.
+#[derive(Debug, Default)]
+pub(crate) struct CheckedNames {
+    names: Mutex<HashMap<MemberKey, Vec<String>>>,
+}
+
+impl CheckedNames {
+    /// Records `names` as the member's current names, returning whether they
+    /// differ from the names last recorded for them.
+    pub(crate) fn changed(
+        &self,
+        guild_id: Id<GuildMarker>,
+        user_id: Id<UserMarker>,
+        names: &[&str],
+    ) -> bool {
+        let mut remembered = self.names.lock().unwrap();
+        if remembered
+            .get(&(guild_id, user_id))
+            .map_or(false, |previous| previous.iter().eq(names))
+        {
+            return false;
+        }
+
+        if remembered.len() >= MAX_REMEMBERED_MEMBERS {
+            remembered.clear();
+        }
+        remembered.insert(
+            (guild_id, user_id),
+            names.iter().map(|name| (*name).to_owned()).collect(),
+        );
+        true
+    }
+
+    /// Forgets a member who left the guild.
+    pub(crate) fn forget(&self, guild_id: Id<GuildMarker>, user_id: Id<UserMarker>) {
+        self.names.lock().unwrap().remove(&(guild_id, user_id));
+    }
+}
+
+fn map_filter_action_to_action(
+    filter_action: &UsernameFilterAction,
+    user_id: Id<UserMarker>,
+    name: &str,
+    filter_reason: &str,
+) -> MessageAction {
+    match filter_action {
+        UsernameFilterAction::SendMessage {
+            channel_id,
+            content,
+        } => {
+            let formatted_content = content.replace("$USER_ID", &user_id.to_string());
+            let formatted_content = formatted_content.replace("$USERNAME", name);
+            let formatted_content = formatted_content.replace("$FILTER_REASON", filter_reason);
+
+            MessageAction::SendMessage {
+                to: *channel_id,
+                content: formatted_content,
+                requires_armed: false,
+                coalesce_seconds: None,
+            }
+        }
+    }
+}
+
+/// Checks each of a member's names (username and nickname) against the
+/// username filter, stopping at the first name that fails.
+#[tracing::instrument(skip(filter))]
+pub(crate) fn filter_names(
+    filter: &UsernameFilter,
+    user_id: Id<UserMarker>,
+    names: &[&str],
+) -> Result<(), UsernameFilterFailure> {
+    for name in names {
+        if let Err(reason) = filter.filter_name(name) {
+            let actions = filter
+                .actions
+                .iter()
+                .map(|a| map_filter_action_to_action(a, user_id, name, &reason))
+                .collect();
+
+            return Err(UsernameFilterFailure { actions });
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+    use twilight_model::id::Id;
+
+    use crate::{
+        action::MessageAction,
+        config::{FilterMode, UsernameFilter, UsernameFilterAction, UsernameFilterRule},
+    };
+
+    use super::{CheckedNames, UsernameFilterFailure};
+
+    fn invite_filter() -> UsernameFilter {
+        UsernameFilter {
+            rules: vec![UsernameFilterRule::Invite {
+                mode: FilterMode::AllowList,
+                invites: vec!["roblox".to_owned()],
+            }],
+            actions: vec![UsernameFilterAction::SendMessage {
+                channel_id: Id::new(1),
+                content: "$USER_ID ($USERNAME): $FILTER_REASON".to_owned(),
+            }],
+        }
+    }
+
+    #[test]
+    fn filter_invite_in_username() {
+        let result = super::filter_names(
+            &invite_filter(),
+            crate::model::test::USER_ID,
+            &["free nitro discord.gg/evilserver"],
+        );
+
+        assert_eq!(
+            result,
+            Err(UsernameFilterFailure {
+                actions: vec![MessageAction::SendMessage {
+                    to: Id::new(1),
+                    content: "3 (free nitro discord.gg/evilserver): contains unallowed invite in name `evilserver`".to_owned(),
+                    requires_armed: false,
+                    coalesce_seconds: None,
+                }]
+            })
+        );
+    }
+
+    #[test]
+    fn filter_checks_every_name() {
+        let result = super::filter_names(
+            &invite_filter(),
+            crate::model::test::USER_ID,
+            &["innocent", "discord.gg/evilserver"],
+        );
+        assert!(result.is_err());
+
+        let result = super::filter_names(
+            &invite_filter(),
+            crate::model::test::USER_ID,
+            &["innocent", "discord.gg/roblox"],
+        );
+        assert_eq!(result, Ok(()));
+    }
+
+    #[test]
+    fn only_changed_names_are_checked_again() {
+        let checked = CheckedNames::default();
+        let guild_id = Id::new(1);
+        let user_id = crate::model::test::USER_ID;
+
+        assert!(checked.changed(guild_id, user_id, &["innocent"]));
+        assert!(!checked.changed(guild_id, user_id, &["innocent"]));
+        assert!(checked.changed(guild_id, user_id, &["innocent", "discord.gg/evilserver"]));
+        // Clearing the nickname leaves only the username to check.
+        assert!(checked.changed(guild_id, user_id, &["innocent"]));
+        assert!(checked.changed(Id::new(2), user_id, &["innocent"]));
+
+        checked.forget(guild_id, user_id);
+        assert!(checked.changed(guild_id, user_id, &["innocent"]));
+    }
+}
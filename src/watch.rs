@@ -0,0 +1,118 @@
+use std::path::Path;
+use std::time::Duration;
+
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use tokio::sync::mpsc::{self, UnboundedReceiver};
+
+/// How long the configuration directories must stay quiet after a change
+/// before reloading, so an editor writing a file more than once only causes one
+/// reload.
+const DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// Watches guild configuration directories for changed configuration files.
+pub(crate) struct ConfigWatcher {
+    // Dropping the watcher stops it.
+    _watcher: RecommendedWatcher,
+    changes: UnboundedReceiver<()>,
+}
+
+impl ConfigWatcher {
+    pub(crate) fn new(roots: &[&Path]) -> notify::Result<Self> {
+        let (tx, changes) = mpsc::unbounded_channel();
+        let mut watcher =
+            notify::recommended_watcher(move |event: notify::Result<Event>| match event {
+                Ok(event) if is_guild_config_change(&event) => {
+                    let _ = tx.send(());
+                }
+                Ok(_) => {}
+                Err(err) => tracing::warn!(?err, "Error watching guild configurations"),
+            })?;
+
+        for root in roots {
+            watcher.watch(root, RecursiveMode::NonRecursive)?;
+        }
+
+        Ok(Self {
+            _watcher: watcher,
+            changes,
+        })
+    }
+
+    /// Waits until a guild configuration file changes and then stays
+    /// unchanged for a moment.
+    pub(crate) async fn changed(&mut self) {
+        if self.changes.recv().await.is_none() {
+            return std::future::pending().await;
+        }
+
+        debounce(&mut self.changes, DEBOUNCE).await;
+    }
+}
+
+/// Waits until nothing has been sent on `changes` for `quiet`, discarding
+/// everything sent in the meantime.
+async fn debounce(changes: &mut UnboundedReceiver<()>, quiet: Duration) {
+    while let Ok(Some(())) = tokio::time::timeout(quiet, changes.recv()).await {}
+}
+
+fn is_guild_config_change(event: &Event) -> bool {
+    let changes_files = matches!(
+        event.kind,
+        EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)
+    );
+
+    changes_files
+        && event
+            .paths
+            .iter()
+            .any(|path| crate::config::is_guild_config_file(path))
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use notify::event::{AccessKind, CreateKind, ModifyKind};
+    use notify::{Event, EventKind};
+    use tokio::sync::mpsc;
+
+    #[test]
+    fn only_config_file_changes_count() {
+        let modify =
+            |path: &str| Event::new(EventKind::Modify(ModifyKind::Any)).add_path(path.into());
+
+        assert!(super::is_guild_config_change(&modify("configs/123.yml")));
+        assert!(super::is_guild_config_change(
+            &Event::new(EventKind::Create(CreateKind::File)).add_path("configs/123.yml".into())
+        ));
+        assert!(!super::is_guild_config_change(&modify(
+            "configs/.123.yml.swp"
+        )));
+        assert!(!super::is_guild_config_change(
+            &Event::new(EventKind::Access(AccessKind::Any)).add_path("configs/123.yml".into())
+        ));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn rapid_changes_are_debounced() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let quiet = Duration::from_millis(500);
+
+        // Two saves in quick succession, then a third after things settled.
+        tx.send(()).unwrap();
+        tx.send(()).unwrap();
+        let sender = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_secs(2)).await;
+            tx.send(()).unwrap();
+        });
+
+        assert_eq!(rx.recv().await, Some(()));
+        let started = tokio::time::Instant::now();
+        super::debounce(&mut rx, quiet).await;
+        assert_eq!(started.elapsed(), quiet);
+
+        sender.await.unwrap();
+        assert_eq!(rx.recv().await, Some(()));
+        assert!(rx.try_recv().is_err());
+    }
+}